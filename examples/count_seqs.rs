@@ -27,7 +27,7 @@ fn main() -> Result<(), Box<dyn Error>> {
         total_bases += seq.seq.len() as u64;
     }
 
-    print!("{}\t{}\n", total_records, total_bases);
+    println!("{}\t{}", total_records, total_bases);
 
     Ok(())
 }
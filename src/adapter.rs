@@ -0,0 +1,276 @@
+use crate::errors::*;
+use crate::kmer::*;
+#[cfg(feature = "presets")]
+use crate::oligos::*;
+use crate::reader::*;
+use std::collections::HashMap;
+use std::io::BufRead;
+
+/// Options of inferring adapters from reads.
+#[derive(Debug, Clone, Copy)]
+pub struct AdapterInferenceOptions {
+    /// Number of reads sampled from the start of the input.
+    pub sample_reads: usize,
+    /// Size of seed k-mers.
+    pub k: usize,
+    /// Minimum fraction of sampled reads containing the seed k-mer of a candidate.
+    pub min_fraction: f64,
+    /// Minimum fraction of reads agreeing on a base to extend a candidate.
+    pub min_consensus: f64,
+    /// Maximum length of candidates.
+    pub max_len: usize,
+    /// Maximum number of candidates.
+    pub max_candidates: usize,
+}
+
+impl Default for AdapterInferenceOptions {
+    fn default() -> Self {
+        Self {
+            sample_reads: 200_000,
+            k: 10,
+            min_fraction: 0.01,
+            min_consensus: 0.8,
+            max_len: 64,
+            max_candidates: 3,
+        }
+    }
+}
+
+/// An adapter sequence inferred from reads.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdapterCandidate {
+    pub seq: Vec<u8>,
+    /// Number of sampled reads containing the seed k-mer.
+    pub reads: u64,
+    /// Fraction of sampled reads containing the seed k-mer.
+    pub fraction: f64,
+    /// The adapter of the embedded catalog sharing most k-mers with the candidate, if any.
+    pub known: Option<&'static str>,
+}
+
+// so that candidates can be passed to `SeqGrep::new` and `AhoCorasick::new` directly
+impl AsRef<[u8]> for AdapterCandidate {
+    fn as_ref(&self) -> &[u8] {
+        &self.seq
+    }
+}
+
+/// Infers adapters from enriched sequences of reads, like the adapter detection of fastp.
+///
+/// K-mers of the first `sample_reads` reads are counted once per read, and low-complexity k-mers
+/// (fewer than 3 distinct bases or dominated by one base, e.g., polyG or dinucleotide repeats)
+/// are ignored. The most frequent
+/// k-mer is extended on both sides with the consensus of the reads containing it, until the bases
+/// of the reads diverge (the inserts before adapters) or run out (the 3' ends of reads).
+/// K-mers of the candidate are then removed, and the next seed is searched for, skipping those
+/// extended to variants of earlier candidates.
+///
+/// Candidates are annotated with adapters of the catalog in `oligos` (feature `presets`),
+/// a candidate matching none is a novel adapter or another overrepresented sequence worth checking.
+pub fn infer_adapters<R: BufRead>(
+    reader: &mut Reader<R>,
+    opts: &AdapterInferenceOptions,
+) -> Result<Vec<AdapterCandidate>, FastxErr> {
+    let k = opts.k;
+    let mut reads: Vec<Vec<u8>> = Vec::new();
+    let mut counts: HashMap<u64, (u32, u32)> = HashMap::new(); // k-mer -> (reads, last read)
+    while reads.len() < opts.sample_reads
+        && let Some(res) = reader.next()
+    {
+        let seq = res?;
+        let idx = reads.len() as u32 + 1; // 0 means no read
+        for (_, code) in KmerIter::new(seq.seq, k, false) {
+            let e = counts.entry(code).or_insert((0, 0));
+            if e.1 != idx {
+                e.0 += 1;
+                e.1 = idx;
+            }
+        }
+        reads.push(seq.seq.to_ascii_uppercase());
+    }
+
+    let min_reads = ((reads.len() as f64 * opts.min_fraction).ceil() as u32).max(3);
+    let mut candidates = Vec::new();
+    while candidates.len() < opts.max_candidates {
+        let Some((&seed, &(n, _))) = counts
+            .iter()
+            .filter(|(code, (n, _))| *n >= min_reads && !is_low_complexity(**code, k))
+            .max_by_key(|(code, (n, _))| (*n, std::cmp::Reverse(**code)))
+        else {
+            break;
+        };
+
+        let seq = extend_seed(&reads, &decode_kmer(seed, k), opts);
+        for (_, code) in KmerIter::new(&seq, k, false) {
+            counts.remove(&code);
+        }
+        counts.remove(&seed);
+
+        // seeds spanning the start of an adapter and the bases before it are enriched too
+        if candidates
+            .iter()
+            .any(|c: &AdapterCandidate| shared_kmers(&c.seq, &seq, k) * 2 >= seq.len() + 1 - k)
+        {
+            continue;
+        }
+
+        candidates.push(AdapterCandidate {
+            known: annotate(&seq, k),
+            seq,
+            reads: n as u64,
+            fraction: n as f64 / reads.len() as f64,
+        });
+    }
+    Ok(candidates)
+}
+
+// whether a k-mer has fewer than 3 distinct bases, or one base making up over 2/3 of it
+fn is_low_complexity(code: u64, k: usize) -> bool {
+    let mut freq = [0usize; 4];
+    for i in 0..k {
+        freq[((code >> (2 * i)) & 3) as usize] += 1;
+    }
+    freq.iter().filter(|&&n| n > 0).count() < 3 || freq.iter().any(|&n| n * 3 > k * 2)
+}
+
+// extends a seed with the consensus bases of the reads containing it
+fn extend_seed(reads: &[Vec<u8>], seed: &[u8], opts: &AdapterInferenceOptions) -> Vec<u8> {
+    let finder = memchr::memmem::Finder::new(seed);
+    let hits: Vec<(&[u8], usize)> = reads
+        .iter()
+        .filter_map(|r| finder.find(r).map(|p| (r.as_slice(), p)))
+        .collect();
+
+    // reads disagreeing with the consensus are dropped as random matches
+    let extend = |forward: bool, max: usize| -> Vec<u8> {
+        let mut alive = vec![true; hits.len()];
+        let mut ext = Vec::new();
+        while ext.len() < max {
+            let i = ext.len();
+            let base_at = |(r, p): (&[u8], usize)| {
+                if forward {
+                    r.get(p + seed.len() + i).copied()
+                } else {
+                    (p > i).then(|| r[p - 1 - i])
+                }
+            };
+            let mut freq = [0usize; 4];
+            for (h, _) in hits.iter().zip(&alive).filter(|(_, a)| **a) {
+                if let Some(b) = base_at(*h)
+                    && let c @ 0..=3 = BASE2BIT[b as usize]
+                {
+                    freq[c as usize] += 1;
+                }
+            }
+            let total: usize = freq.iter().sum();
+            let (best, &n) = freq.iter().enumerate().max_by_key(|x| x.1).unwrap();
+            if total < 3 || (n as f64) < total as f64 * opts.min_consensus {
+                break;
+            }
+            let base = b"ACGT"[best];
+            for (h, a) in hits.iter().zip(alive.iter_mut()) {
+                if *a && base_at(*h) != Some(base) {
+                    *a = false;
+                }
+            }
+            ext.push(base);
+        }
+        ext
+    };
+
+    let max = opts.max_len.saturating_sub(seed.len());
+    let mut left = extend(false, max);
+    let right = extend(true, max - left.len());
+    left.reverse();
+    left.extend_from_slice(seed);
+    left.extend_from_slice(&right);
+    left
+}
+
+// the catalog adapter sharing most k-mers with the sequence
+#[cfg(feature = "presets")]
+fn annotate(seq: &[u8], k: usize) -> Option<&'static str> {
+    ADAPTERS
+        .iter()
+        .map(|a| (shared_kmers(a.seq, seq, k), a.name))
+        .filter(|(shared, _)| *shared > 0)
+        .max_by_key(|(shared, _)| *shared)
+        .map(|(_, name)| name)
+}
+
+#[cfg(not(feature = "presets"))]
+fn annotate(_seq: &[u8], _k: usize) -> Option<&'static str> {
+    None
+}
+
+// the number of k-mers of a found in b
+fn shared_kmers(a: &[u8], b: &[u8], k: usize) -> usize {
+    a.windows(k)
+        .filter(|w| b.windows(k).any(|s| s == *w))
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::*;
+
+    fn random_seq(rng: &mut Rng, len: usize) -> Vec<u8> {
+        (0..len).map(|_| b"ACGT"[rng.below(4)]).collect()
+    }
+
+    #[test]
+    fn test_infer_adapters() {
+        let mut rng = Rng::new(11);
+        let truseq_read1 = b"AGATCGGAAGAGCACACGTCTGAACTCCAGTCA";
+        let mut adapter = truseq_read1.to_vec();
+        adapter.extend_from_slice(b"CACGTATGCCGTCTTCTGCTTG");
+        let mut input = Vec::new();
+        for i in 0..2000 {
+            // a fifth of reads have short inserts, read through into the adapter
+            let read = if i % 5 == 0 {
+                let insert = 20 + rng.below(40);
+                let mut read = random_seq(&mut rng, insert);
+                read.extend_from_slice(&adapter);
+                read.truncate(100);
+                read
+            } else if i % 7 == 0 {
+                let mut read = random_seq(&mut rng, 70);
+                read.extend_from_slice(&[b'G'; 30]);
+                read
+            } else {
+                random_seq(&mut rng, 100)
+            };
+            input.extend_from_slice(format!(">r{i}\n").as_bytes());
+            input.extend_from_slice(&read);
+            input.push(b'\n');
+        }
+
+        let candidates =
+            infer_adapters(&mut Reader::from_bytes(&input), &Default::default()).unwrap();
+        assert_eq!(candidates.len(), 1);
+        let c = &candidates[0];
+        assert!(c.seq.starts_with(truseq_read1), "{:?}", c);
+        assert!(adapter.starts_with(&c.seq));
+        #[cfg(feature = "presets")]
+        assert_eq!(c.known, Some("TruSeq_Read1"));
+        assert!(c.fraction > 0.19 && c.fraction < 0.21);
+    }
+
+    #[test]
+    fn test_infer_adapters_none() {
+        let mut rng = Rng::new(1);
+        let mut input = Vec::new();
+        for i in 0..500 {
+            input.extend_from_slice(format!(">r{i}\n").as_bytes());
+            input.extend_from_slice(&random_seq(&mut rng, 80));
+            input.push(b'\n');
+        }
+        let candidates =
+            infer_adapters(&mut Reader::from_bytes(&input), &Default::default()).unwrap();
+        assert!(candidates.is_empty());
+        assert!(is_low_complexity(encode_kmer(b"ACACACACAC").unwrap(), 10));
+        assert!(is_low_complexity(encode_kmer(b"CTGGGGGGGG").unwrap(), 10));
+        assert!(!is_low_complexity(encode_kmer(b"ACGAAAAATT").unwrap(), 10));
+    }
+}
@@ -0,0 +1,268 @@
+use crate::errors::*;
+use crate::input::*;
+use crate::qc::*;
+use crate::reader::*;
+use crate::samplesheet::*;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// How input files are grouped into samples.
+#[derive(Debug, Clone)]
+pub enum GroupBy {
+    /// Every file is a sample, named by its path.
+    File,
+    /// Files are grouped by sample names derived from file names, see `sample_name_from_path`.
+    FileName,
+    /// Files are grouped by the `r1`/`r2` columns of a sample sheet, matched by path or file name.
+    /// Files not in the sheet are an error.
+    SampleSheet(SampleSheet),
+}
+
+/// Statistics of a sample.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SampleStats {
+    pub sample: String,
+    /// Files of the sample, in input order.
+    pub files: Vec<PathBuf>,
+    pub stats: QcStats,
+}
+
+/// Derives a sample name from a file name, by removing the directory, FASTA/Q and compression
+/// extensions, and Illumina-style suffixes of lanes and reads, e.g., `x/A_S1_L001_R1_001.fastq.gz`,
+/// `A_R2.fq` and `A_1.fq.gz` all become `A`.
+pub fn sample_name_from_path<P: AsRef<Path>>(path: P) -> String {
+    let name = path
+        .as_ref()
+        .file_name()
+        .map_or_else(String::new, |s| s.to_string_lossy().to_string());
+    let mut stem = name.as_str();
+    for _ in 0..2 {
+        if let Some((s, ext)) = stem.rsplit_once('.')
+            && !s.is_empty()
+            && (is_one_of(ext, DEFAULT_EXTENSIONS) || is_one_of(ext, COMPRESSION_EXTENSIONS))
+        {
+            stem = s;
+        }
+    }
+
+    // suffixes from the end: _001, _R1/_R2 or _1/_2, _L001, _S1
+    let mut fields: Vec<&str> = stem.split('_').collect();
+    let digits = |s: &str, n: usize| s.len() == n && s.bytes().all(|b| b.is_ascii_digit());
+    if fields.len() > 2
+        && digits(fields[fields.len() - 1], 3)
+        && fields[fields.len() - 2].starts_with(['R', 'I'])
+    {
+        fields.pop();
+    }
+    if fields.len() > 1
+        && matches!(
+            fields[fields.len() - 1],
+            "R1" | "R2" | "1" | "2" | "I1" | "I2"
+        )
+    {
+        fields.pop();
+    }
+    if let Some(f) = fields.last()
+        && fields.len() > 1
+        && f.starts_with('L')
+        && digits(&f[1..], 3)
+    {
+        fields.pop();
+    }
+    if let Some(f) = fields.last()
+        && fields.len() > 1
+        && f.len() > 1
+        && f.starts_with('S')
+        && f[1..].bytes().all(|b| b.is_ascii_digit())
+    {
+        fields.pop();
+    }
+    fields.join("_")
+}
+
+fn is_one_of(ext: &str, extensions: &[&str]) -> bool {
+    extensions.iter().any(|e| e.eq_ignore_ascii_case(ext))
+}
+
+// the sample of a file in a sample sheet
+fn sheet_sample(sheet: &SampleSheet, file: &Path) -> Option<String> {
+    let name = file.file_name();
+    sheet
+        .samples
+        .iter()
+        .find(|s| {
+            [&s.r1, &s.r2].into_iter().flatten().any(|f| {
+                let f = Path::new(f);
+                f == file || (f.file_name().is_some() && f.file_name() == name)
+            })
+        })
+        .map(|s| s.name.clone())
+}
+
+/// Computes QC statistics of files in parallel, and aggregates them by sample, in the order of
+/// first occurrence of samples in the files. `threads` of 0 means the number of CPUs.
+/// The first error, in file order, is returned.
+pub fn aggregate_samples<P: AsRef<Path> + Sync>(
+    files: &[P],
+    group_by: &GroupBy,
+    threads: usize,
+) -> Result<Vec<SampleStats>, FastxErr> {
+    // group files first, so sample sheet errors come before the work
+    let mut samples: Vec<SampleStats> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    let mut file_sample = Vec::with_capacity(files.len());
+    for file in files {
+        let file = file.as_ref();
+        let name = match group_by {
+            GroupBy::File => file.to_string_lossy().to_string(),
+            GroupBy::FileName => sample_name_from_path(file),
+            GroupBy::SampleSheet(sheet) => sheet_sample(sheet, file).ok_or_else(|| {
+                FastxErr::InvalidSampleSheet(format!("no sample of file {}", file.display()))
+            })?,
+        };
+        let i = *index.entry(name.clone()).or_insert_with(|| {
+            samples.push(SampleStats {
+                sample: name,
+                files: Vec::new(),
+                stats: QcStats::new(),
+            });
+            samples.len() - 1
+        });
+        samples[i].files.push(file.to_path_buf());
+        file_sample.push(i);
+    }
+
+    let threads = match threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    }
+    .min(files.len().max(1));
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<Result<QcStats, FastxErr>>>> =
+        Mutex::new((0..files.len()).map(|_| None).collect());
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    if i >= files.len() {
+                        break;
+                    }
+                    let res = file_stats(files[i].as_ref());
+                    results.lock().unwrap()[i] = Some(res);
+                }
+            });
+        }
+    });
+
+    for (res, i) in results.into_inner().unwrap().into_iter().zip(file_sample) {
+        samples[i]
+            .stats
+            .merge(&res.expect("every file is processed")?);
+    }
+    Ok(samples)
+}
+
+fn file_stats(file: &Path) -> Result<QcStats, FastxErr> {
+    let mut stats = QcStats::new();
+    let mut reader = Reader::new(file)?;
+    while let Some(res) = reader.next() {
+        stats.add(&res?);
+    }
+    Ok(stats)
+}
+
+/// Writes statistics of samples as a tab-delimited table with a header line.
+pub fn write_sample_table<W: Write>(samples: &[SampleStats], w: &mut W) -> io::Result<()> {
+    writeln!(
+        w,
+        "sample\tfiles\treads\tbases\tq20_rate\tq30_rate\tgc_content\tmean_length\tmin_length\tmax_length"
+    )?;
+    for s in samples {
+        let st = &s.stats;
+        writeln!(
+            w,
+            "{}\t{}\t{}\t{}\t{:.4}\t{:.4}\t{:.4}\t{:.2}\t{}\t{}",
+            s.sample,
+            s.files.len(),
+            st.reads,
+            st.bases,
+            st.q20_rate(),
+            st.q30_rate(),
+            st.gc_content(),
+            st.mean_len(),
+            st.min_len(),
+            st.max_len(),
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_sample_name_from_path() {
+        for (path, name) in [
+            ("x/A_S1_L001_R1_001.fastq.gz", "A"),
+            ("A_S12_L002_R2_001.fq", "A"),
+            ("A_R2.fq", "A"),
+            ("A_B_1.fq.gz", "A_B"),
+            ("A.fa", "A"),
+            ("R1.fq", "R1"),
+            ("A_L001.fq", "A"),
+        ] {
+            assert_eq!(sample_name_from_path(path), name, "{path}");
+        }
+    }
+
+    #[test]
+    fn test_aggregate_samples() {
+        let dir = std::env::temp_dir().join(format!("fastseq-aggregate-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let files: Vec<PathBuf> = ["A_R1.fq", "B_R1.fq", "A_R2.fq"]
+            .iter()
+            .map(|f| dir.join(f))
+            .collect();
+        fs::write(&files[0], "@r\nACGT\n+\nIIII\n").unwrap();
+        fs::write(&files[1], "@r\nAC\n+\n##\n@s\nGC\n+\nII\n").unwrap();
+        fs::write(&files[2], "@r\nGGCCAA\n+\nIIIIII\n").unwrap();
+
+        let samples = aggregate_samples(&files, &GroupBy::FileName, 2).unwrap();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0].sample, "A");
+        assert_eq!(samples[0].files, vec![files[0].clone(), files[2].clone()]);
+        assert_eq!((samples[0].stats.reads, samples[0].stats.bases), (2, 10));
+        assert_eq!((samples[1].stats.reads, samples[1].stats.q30_bases), (2, 2));
+
+        let mut out = Vec::new();
+        write_sample_table(&samples, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(
+            out.lines().nth(1).unwrap(),
+            "A\t2\t2\t10\t1.0000\t1.0000\t0.6000\t5.00\t4\t6"
+        );
+
+        let per_file = aggregate_samples(&files, &GroupBy::File, 0).unwrap();
+        assert_eq!(per_file.len(), 3);
+
+        let sheet = SampleSheet::parse("sample,r1,r2\nS1,A_R1.fq,A_R2.fq\n".as_bytes()).unwrap();
+        let by_sheet = GroupBy::SampleSheet(sheet);
+        let samples = aggregate_samples(&files[..1], &by_sheet, 1).unwrap();
+        assert_eq!(samples[0].sample, "S1");
+        assert!(matches!(
+            aggregate_samples(&files, &by_sheet, 1),
+            Err(FastxErr::InvalidSampleSheet(_))
+        ));
+
+        fs::remove_file(&files[2]).unwrap();
+        assert!(aggregate_samples(&files, &GroupBy::File, 2).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
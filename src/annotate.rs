@@ -0,0 +1,249 @@
+use crate::errors::*;
+use crate::reader::*;
+use crate::seq::*;
+use crate::writer::*;
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+/// How annotated regions are rendered in output sequences.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlayStyle {
+    /// Annotated bases in uppercase and the rest in lowercase, e.g., exons and introns.
+    Upper,
+    /// Annotated bases in lowercase and the rest in uppercase, i.e., soft-masking of repeats.
+    Lower,
+    /// Annotated regions enclosed in a pair of brackets, with case unchanged.
+    /// Quality scores are dropped as sequence lengths change.
+    Brackets(u8, u8),
+}
+
+/// Per-record interval annotations, 0-based and half-open like BED.
+#[derive(Debug, Clone, Default)]
+pub struct Annotations {
+    intervals: HashMap<Vec<u8>, Vec<(usize, usize)>>,
+}
+
+impl Annotations {
+    /// Creates an empty annotation set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of annotated records.
+    pub fn len(&self) -> usize {
+        self.intervals.len()
+    }
+
+    /// Returns whether no record is annotated.
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    /// Adds an interval `start..end` of a record. Empty intervals are ignored.
+    pub fn add(&mut self, id: &[u8], start: usize, end: usize) {
+        if start >= end {
+            return;
+        }
+        self.intervals
+            .entry(id.to_vec())
+            .or_default()
+            .push((start, end));
+    }
+
+    /// Returns intervals of a record, in the order they were added.
+    pub fn get(&self, id: &[u8]) -> Option<&[(usize, usize)]> {
+        self.intervals.get(id).map(|v| v.as_slice())
+    }
+
+    /// Reads intervals from the first three columns of a BED file.
+    /// Empty lines and lines starting with `#`, `track` or `browser` are skipped.
+    pub fn from_bed<R: BufRead>(reader: R) -> Result<Self, FastxErr> {
+        let mut annots = Self::new();
+        for line in reader.split(b'\n') {
+            let line = line?;
+            let line = line.strip_suffix(b"\r").unwrap_or(&line);
+            if line.is_empty()
+                || line.starts_with(b"#")
+                || line.starts_with(b"track")
+                || line.starts_with(b"browser")
+            {
+                continue;
+            }
+            let mut fields = line.split(|&b| b == b'\t');
+            let (Some(id), Some(start), Some(end)) = (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(FastxErr::InvalidBed(
+                    String::from_utf8_lossy(line).into_owned(),
+                ));
+            };
+            let (Some(start), Some(end)) = (parse_usize(start), parse_usize(end)) else {
+                return Err(FastxErr::InvalidBed(
+                    String::from_utf8_lossy(line).into_owned(),
+                ));
+            };
+            annots.add(id, start, end);
+        }
+        Ok(annots)
+    }
+}
+
+fn parse_usize(s: &[u8]) -> Option<usize> {
+    std::str::from_utf8(s).ok()?.trim().parse().ok()
+}
+
+/// Renders intervals onto a sequence, writing the result to `out`.
+/// Overlapping and adjacent intervals are merged, and intervals are clipped to the sequence.
+pub fn overlay(seq: &[u8], intervals: &[(usize, usize)], style: OverlayStyle, out: &mut Vec<u8>) {
+    out.clear();
+
+    let mut merged: Vec<(usize, usize)> = intervals
+        .iter()
+        .map(|&(s, e)| (s.min(seq.len()), e.min(seq.len())))
+        .filter(|(s, e)| s < e)
+        .collect();
+    merged.sort_unstable();
+    let mut n = 0;
+    for i in 0..merged.len() {
+        if n > 0 && merged[i].0 <= merged[n - 1].1 {
+            merged[n - 1].1 = merged[n - 1].1.max(merged[i].1);
+        } else {
+            merged[n] = merged[i];
+            n += 1;
+        }
+    }
+    merged.truncate(n);
+
+    match style {
+        OverlayStyle::Upper | OverlayStyle::Lower => {
+            let upper = style == OverlayStyle::Upper;
+            out.extend(seq.iter().map(|b| {
+                if upper {
+                    b.to_ascii_lowercase()
+                } else {
+                    b.to_ascii_uppercase()
+                }
+            }));
+            for (s, e) in merged {
+                if upper {
+                    out[s..e].make_ascii_uppercase();
+                } else {
+                    out[s..e].make_ascii_lowercase();
+                }
+            }
+        }
+        OverlayStyle::Brackets(open, close) => {
+            out.reserve(seq.len() + 2 * merged.len());
+            let mut prev = 0;
+            for (s, e) in merged {
+                out.extend_from_slice(&seq[prev..s]);
+                out.push(open);
+                out.extend_from_slice(&seq[s..e]);
+                out.push(close);
+                prev = e;
+            }
+            out.extend_from_slice(&seq[prev..]);
+        }
+    }
+}
+
+/// Writes all records with their annotations rendered, and returns the number of annotated records.
+/// Records without annotations are rendered as having no annotated region.
+pub fn overlay_records<R: BufRead, W: Write>(
+    reader: &mut Reader<R>,
+    writer: &mut Writer<W>,
+    annots: &Annotations,
+    style: OverlayStyle,
+) -> Result<u64, FastxErr> {
+    let mut n = 0u64;
+    let mut buf = Vec::with_capacity(1 << 20);
+    while let Some(res) = reader.next() {
+        let record = res?;
+        let intervals = annots.get(record.id).unwrap_or_default();
+        if !intervals.is_empty() {
+            n += 1;
+        }
+        overlay(record.seq, intervals, style, &mut buf);
+        writer.write(&Seq {
+            id: record.id,
+            desc: record.desc,
+            seq: &buf,
+            qual: match style {
+                OverlayStyle::Brackets(..) => None,
+                _ => record.qual,
+            },
+        })?;
+    }
+    writer.flush()?;
+    Ok(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_overlay_styles() {
+        let mut out = Vec::new();
+        let intervals = [(6, 8), (2, 4), (3, 5), (20, 30)];
+
+        overlay(b"ACGTACGTAC", &intervals, OverlayStyle::Upper, &mut out);
+        assert_eq!(out, b"acGTAcGTac");
+
+        overlay(b"acgtacgtac", &intervals, OverlayStyle::Lower, &mut out);
+        assert_eq!(out, b"ACgtaCgtAC");
+
+        overlay(
+            b"ACGTACGTAC",
+            &intervals,
+            OverlayStyle::Brackets(b'[', b']'),
+            &mut out,
+        );
+        assert_eq!(out, b"AC[GTA]C[GT]AC");
+
+        overlay(b"ACGT", &[], OverlayStyle::Brackets(b'[', b']'), &mut out);
+        assert_eq!(out, b"ACGT");
+    }
+
+    #[test]
+    fn test_annotations_from_bed() {
+        let bed = "track name=x\n# comment\nchr1\t0\t2\tgene1\nchr1\t5\t5\nchr2\t1\t3\n\n";
+        let annots = Annotations::from_bed(Cursor::new(bed)).unwrap();
+        assert_eq!(annots.len(), 2);
+        assert_eq!(annots.get(b"chr1"), Some(&[(0, 2)][..]));
+        assert_eq!(annots.get(b"chr3"), None);
+
+        assert!(matches!(
+            Annotations::from_bed(Cursor::new("chr1\tx\t2\n")),
+            Err(FastxErr::InvalidBed(_))
+        ));
+        assert!(Annotations::from_bed(Cursor::new("chr1\t1\n")).is_err());
+    }
+
+    #[test]
+    fn test_overlay_records() {
+        let mut annots = Annotations::new();
+        annots.add(b"r1", 1, 3);
+
+        let input = "@r1\nACGT\n+\nABCD\n@r2\nACGT\n+\nABCD\n";
+        let mut reader = Reader::from_reader(Cursor::new(input.as_bytes()));
+        let mut writer = Writer::from_writer(Vec::new());
+        let n = overlay_records(&mut reader, &mut writer, &annots, OverlayStyle::Upper).unwrap();
+        assert_eq!(n, 1);
+        assert_eq!(
+            writer.into_inner(),
+            b"@r1\naCGt\n+\nABCD\n@r2\nacgt\n+\nABCD\n"
+        );
+
+        let mut reader = Reader::from_reader(Cursor::new(input.as_bytes()));
+        let mut writer = Writer::from_writer(Vec::new());
+        overlay_records(
+            &mut reader,
+            &mut writer,
+            &annots,
+            OverlayStyle::Brackets(b'{', b'}'),
+        )
+        .unwrap();
+        assert_eq!(writer.into_inner(), b">r1\nA{CG}T\n>r2\nACGT\n");
+    }
+}
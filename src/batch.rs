@@ -0,0 +1,161 @@
+use crate::errors::*;
+use crate::reader::*;
+use crate::seq::*;
+
+// location of a record in the arena: id, desc, seq and qual are stored back to back from start
+#[derive(Debug, Clone, Copy)]
+struct Span {
+    start: usize,
+    id_len: usize,
+    desc_len: usize,
+    seq_len: usize,
+    qual_len: Option<usize>,
+}
+
+/// A batch of records stored in one buffer (an arena) with offsets, instead of one
+/// allocation per record, for handing records over to worker threads.
+///
+/// A batch owns its data, so it can be sent to other threads, and records are borrowed from it
+/// as `Seq`. Clearing a batch keeps its buffers, so batches can be recycled without allocations.
+#[derive(Debug, Clone, Default)]
+pub struct RecordBatch {
+    data: Vec<u8>,
+    spans: Vec<Span>,
+}
+
+impl RecordBatch {
+    /// Creates an empty batch.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an empty batch with room for `records` records of `bytes` bytes in total.
+    pub fn with_capacity(records: usize, bytes: usize) -> Self {
+        Self {
+            data: Vec::with_capacity(bytes),
+            spans: Vec::with_capacity(records),
+        }
+    }
+
+    /// Returns the number of records.
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
+
+    /// Returns whether the batch is empty.
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    /// Returns the number of bytes of all records.
+    pub fn bytes(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Removes all records, keeping the allocated memory.
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.spans.clear();
+    }
+
+    /// Appends a copy of a record.
+    pub fn push(&mut self, seq: &Seq) {
+        self.spans.push(Span {
+            start: self.data.len(),
+            id_len: seq.id.len(),
+            desc_len: seq.desc.len(),
+            seq_len: seq.seq.len(),
+            qual_len: seq.qual.map(|q| q.len()),
+        });
+        self.data.extend_from_slice(seq.id);
+        self.data.extend_from_slice(seq.desc);
+        self.data.extend_from_slice(seq.seq);
+        if let Some(qual) = seq.qual {
+            self.data.extend_from_slice(qual);
+        }
+    }
+
+    /// Returns the i-th record.
+    pub fn get(&self, i: usize) -> Option<Seq<'_>> {
+        let s = self.spans.get(i)?;
+        let id_end = s.start + s.id_len;
+        let desc_end = id_end + s.desc_len;
+        let seq_end = desc_end + s.seq_len;
+        Some(Seq {
+            id: &self.data[s.start..id_end],
+            desc: &self.data[id_end..desc_end],
+            seq: &self.data[desc_end..seq_end],
+            qual: s.qual_len.map(|n| &self.data[seq_end..seq_end + n]),
+        })
+    }
+
+    /// Returns an iterator of all records.
+    pub fn iter(&self) -> impl Iterator<Item = Seq<'_>> {
+        (0..self.len()).map(|i| self.get(i).unwrap())
+    }
+
+    /// Appends records from the source until the batch has `max_records` records or
+    /// `max_bytes` bytes (0 for no limit), and returns the number of records appended.
+    /// 0 means the source is exhausted.
+    pub fn fill<S: RecordSource>(
+        &mut self,
+        source: &mut S,
+        max_records: usize,
+        max_bytes: usize,
+    ) -> Result<usize, FastxErr> {
+        let mut n = 0;
+        while self.len() < max_records && (max_bytes == 0 || self.bytes() < max_bytes) {
+            match source.next_record() {
+                Some(res) => self.push(&res?),
+                None => break,
+            }
+            n += 1;
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_record_batch() {
+        let input = "@r1 d\nACGT\n+\nIIII\n@r2\nAC\n+\nII\n@r3\nA\n+\nI\n";
+        let mut reader = Reader::from_reader(Cursor::new(input.as_bytes()));
+        let mut batch = RecordBatch::with_capacity(2, 1024);
+        assert_eq!(batch.fill(&mut reader, 2, 0).unwrap(), 2);
+        assert_eq!(batch.len(), 2);
+        let first = batch.get(0).unwrap();
+        assert_eq!(first.id, b"r1");
+        assert_eq!(first.desc, b"d");
+        assert_eq!(first.qual, Some(&b"IIII"[..]));
+        assert_eq!(batch.get(1).unwrap().seq, b"AC");
+        assert!(batch.get(2).is_none());
+
+        // batches can be sent to other threads
+        let batch = std::thread::spawn(move || {
+            assert_eq!(batch.iter().map(|s| s.seq.len()).sum::<usize>(), 6);
+            batch
+        })
+        .join()
+        .unwrap();
+
+        let mut batch = batch;
+        batch.clear();
+        assert_eq!(batch.fill(&mut reader, 2, 0).unwrap(), 1);
+        assert_eq!(batch.get(0).unwrap().id, b"r3");
+        batch.clear();
+        assert_eq!(batch.fill(&mut reader, 2, 0).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_record_batch_max_bytes() {
+        let mut batch = RecordBatch::new();
+        let mut reader = Reader::from_reader(Cursor::new(">a\nACGTACGT\n>b\nAC\n>c\nA\n"));
+        assert_eq!(batch.fill(&mut reader, 100, 10).unwrap(), 2);
+        assert_eq!(batch.bytes(), 12);
+        assert_eq!(batch.get(1).unwrap().qual, None);
+    }
+}
@@ -0,0 +1,451 @@
+use crate::errors::*;
+use crate::reader::SizeLimits;
+use crate::seq::*;
+use crate::xopen::*;
+use std::io::{self, BufRead, Read, Write};
+use std::path::Path;
+
+/// Magic bytes at the beginning of a binary record stream.
+pub const BINARY_MAGIC: &[u8; 4] = b"FSQB";
+
+/// Version of the binary record format.
+pub const BINARY_VERSION: u8 = 1;
+
+// stream flag: the writer was allowed to 2-bit pack sequences
+const STREAM_FLAG_PACKED: u8 = 1;
+
+// record flags
+const RECORD_FLAG_QUAL: u8 = 1; // the record has quality scores
+const RECORD_FLAG_PACKED: u8 = 1 << 1; // the sequence is stored 2-bit packed
+
+/// A writer of the compact length-prefixed binary record format.
+///
+/// Each record is stored as a flag byte, the lengths of id, desc and seq as little-endian u32,
+/// and then the raw bytes. Sequences consisting only of `ACGT` can optionally be 2-bit packed.
+/// Use a `.zst` (or any other supported) file extension with `BinaryWriter::new` to compress the stream.
+pub struct BinaryWriter<W: Write> {
+    writer: W,
+    packed: bool,
+    pack_buf: Vec<u8>,
+}
+
+impl BinaryWriter<Box<dyn FinishWrite>> {
+    /// Creates a new BinaryWriter to a file path, "-" for stdout.
+    /// The compression format is detected from the file extension.
+    pub fn new<P: AsRef<Path>>(file: P, packed: bool) -> Result<Self, FastxErr> {
+        let w = xwrite(file, 65536).map_err(FastxErr::IOError)?;
+        Self::from_writer(w, packed)
+    }
+}
+
+impl<W: Write> BinaryWriter<W> {
+    /// Creates a new BinaryWriter from any Write, and writes the stream header.
+    pub fn from_writer(mut writer: W, packed: bool) -> Result<Self, FastxErr> {
+        let flags = if packed { STREAM_FLAG_PACKED } else { 0 };
+        writer.write_all(BINARY_MAGIC)?;
+        writer.write_all(&[BINARY_VERSION, flags])?;
+        Ok(Self {
+            writer,
+            packed,
+            pack_buf: Vec::with_capacity(1024),
+        })
+    }
+
+    /// Writes one record.
+    pub fn write(&mut self, seq: &Seq) -> Result<(), FastxErr> {
+        let mut flags = 0u8;
+        if let Some(qual) = seq.qual {
+            if qual.len() != seq.seq.len() {
+                return Err(FastxErr::UnequalSeqAndQual(seq.seq.len(), qual.len()));
+            }
+            flags |= RECORD_FLAG_QUAL;
+        }
+        let packed = self.packed && is_packable(seq.seq);
+        if packed {
+            flags |= RECORD_FLAG_PACKED;
+        }
+
+        // all lengths are checked before writing, so errors never leave half a record
+        let mut header = [0u8; 13];
+        header[0] = flags;
+        for (i, len) in [seq.id.len(), seq.desc.len(), seq.seq.len()]
+            .into_iter()
+            .enumerate()
+        {
+            header[1 + 4 * i..5 + 4 * i].copy_from_slice(&field_len(len)?.to_le_bytes());
+        }
+        self.writer.write_all(&header)?;
+        self.writer.write_all(seq.id)?;
+        self.writer.write_all(seq.desc)?;
+        if packed {
+            pack_2bit(seq.seq, &mut self.pack_buf);
+            self.writer.write_all(&self.pack_buf)?;
+        } else {
+            self.writer.write_all(seq.seq)?;
+        }
+        if let Some(qual) = seq.qual {
+            self.writer.write_all(qual)?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&mut self) -> Result<(), FastxErr> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Returns a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: FinishWrite> BinaryWriter<W> {
+    /// Flushes and finalizes the output, see `Writer::finish`.
+    pub fn finish(&mut self) -> Result<(), FastxErr> {
+        self.writer.finish()?;
+        Ok(())
+    }
+}
+
+/// A reader of the binary record format written by `BinaryWriter`.
+pub struct BinaryReader<R: Read> {
+    reader: R,
+    record_buf: Vec<u8>,
+    pack_buf: Vec<u8>,
+    limits: SizeLimits,
+}
+
+impl BinaryReader<Box<dyn BufRead>> {
+    /// Creates a new BinaryReader from a file path, "-" for stdin.
+    /// Compressed streams are detected automatically.
+    pub fn new<P: AsRef<Path>>(file: P) -> Result<Self, FastxErr> {
+        let r = xopen(file, 65536).map_err(FastxErr::IOError)?;
+        Self::from_reader(r)
+    }
+}
+
+impl<R: Read> BinaryReader<R> {
+    /// Creates a new BinaryReader from any Read, and checks the stream header.
+    pub fn from_reader(mut reader: R) -> Result<Self, FastxErr> {
+        let mut header = [0u8; 6];
+        reader.read_exact(&mut header).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => FastxErr::InvalidBinaryFormat("missing header"),
+            _ => FastxErr::IOError(e),
+        })?;
+        if &header[..4] != BINARY_MAGIC {
+            return Err(FastxErr::InvalidBinaryFormat("bad magic bytes"));
+        }
+        if header[4] != BINARY_VERSION {
+            return Err(FastxErr::InvalidBinaryFormat("unsupported version"));
+        }
+        Ok(Self {
+            reader,
+            record_buf: Vec::with_capacity(1 << 20),
+            pack_buf: Vec::with_capacity(1024),
+            limits: SizeLimits::default(),
+        })
+    }
+
+    /// Sets limits of header and sequence lengths, see `Reader::set_limits`.
+    /// Records exceeding them fail with `SizeLimitExceeded` before their fields are read.
+    /// Without limits, buffers only grow as field bytes are actually read, so corrupt lengths
+    /// fail as truncated records.
+    pub fn set_limits(&mut self, limits: SizeLimits) {
+        self.limits = limits;
+    }
+
+    // returns None if EOF is reached, otherwise returns Some(Ok(Seq)) or Some(Err(e))
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Result<Seq<'_>, FastxErr>> {
+        let mut flags = [0u8; 1];
+        loop {
+            match self.reader.read(&mut flags) {
+                Ok(0) => return None,
+                Ok(_) => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Some(Err(FastxErr::IOError(e))),
+            }
+        }
+        Some(self.read_record(flags[0]))
+    }
+
+    fn read_record(&mut self, flags: u8) -> Result<Seq<'_>, FastxErr> {
+        let id_len = read_len(&mut self.reader)?;
+        let desc_len = read_len(&mut self.reader)?;
+        let seq_len = read_len(&mut self.reader)?;
+        let has_qual = flags & RECORD_FLAG_QUAL != 0;
+        // the header line is the ID, a space and the description
+        let header_len = id_len + desc_len + (desc_len > 0) as usize;
+        if header_len > self.limits.max_header_len {
+            return Err(FastxErr::SizeLimitExceeded(
+                "header",
+                self.limits.max_header_len,
+            ));
+        }
+        if seq_len > self.limits.max_seq_len {
+            return Err(FastxErr::SizeLimitExceeded(
+                "sequence",
+                self.limits.max_seq_len,
+            ));
+        }
+
+        self.record_buf.clear();
+        read_n(&mut self.reader, &mut self.record_buf, id_len + desc_len)?;
+        if flags & RECORD_FLAG_PACKED != 0 {
+            self.pack_buf.clear();
+            read_n(&mut self.reader, &mut self.pack_buf, seq_len.div_ceil(4))?;
+            unpack_2bit(&self.pack_buf, seq_len, &mut self.record_buf);
+        } else {
+            read_n(&mut self.reader, &mut self.record_buf, seq_len)?;
+        }
+        if has_qual {
+            read_n(&mut self.reader, &mut self.record_buf, seq_len)?;
+        }
+
+        let id_end = id_len;
+        let desc_end = id_end + desc_len;
+        let seq_end = desc_end + seq_len;
+        Ok(Seq {
+            id: &self.record_buf[..id_end],
+            desc: &self.record_buf[id_end..desc_end],
+            seq: &self.record_buf[desc_end..seq_end],
+            qual: if has_qual {
+                Some(&self.record_buf[seq_end..])
+            } else {
+                None
+            },
+        })
+    }
+}
+
+// length of the stream header: magic bytes, version and flags
+pub(crate) const BINARY_HEADER_LEN: usize = 6;
+
+// parses an unpacked record at the start of buf without copying,
+// and returns it with the number of bytes it takes
+pub(crate) fn parse_record(buf: &[u8]) -> Result<(Seq<'_>, usize), FastxErr> {
+    let err = FastxErr::InvalidBinaryFormat("truncated record");
+    if buf.len() < 13 {
+        return Err(err);
+    }
+    let flags = buf[0];
+    if flags & RECORD_FLAG_PACKED != 0 {
+        return Err(FastxErr::InvalidBinaryFormat("unexpected packed record"));
+    }
+    let len = |i: usize| u32::from_le_bytes(buf[1 + 4 * i..5 + 4 * i].try_into().unwrap()) as usize;
+    let (id_len, desc_len, seq_len) = (len(0), len(1), len(2));
+    let qual_len = if flags & RECORD_FLAG_QUAL != 0 {
+        seq_len
+    } else {
+        0
+    };
+    let id_end = 13 + id_len;
+    let desc_end = id_end + desc_len;
+    let seq_end = desc_end + seq_len;
+    let end = seq_end + qual_len;
+    if buf.len() < end {
+        return Err(err);
+    }
+    let seq = Seq {
+        id: &buf[13..id_end],
+        desc: &buf[id_end..desc_end],
+        seq: &buf[desc_end..seq_end],
+        qual: if flags & RECORD_FLAG_QUAL != 0 {
+            Some(&buf[seq_end..end])
+        } else {
+            None
+        },
+    };
+    Ok((seq, end))
+}
+
+#[inline]
+fn field_len(len: usize) -> Result<u32, FastxErr> {
+    u32::try_from(len).map_err(|_| FastxErr::InvalidBinaryFormat("field too long"))
+}
+
+#[inline]
+fn read_len<R: Read>(r: &mut R) -> Result<usize, FastxErr> {
+    let mut b = [0u8; 4];
+    r.read_exact(&mut b).map_err(truncated)?;
+    Ok(u32::from_le_bytes(b) as usize)
+}
+
+// append exactly n bytes from the reader to buf, growing it as data arrives,
+// so a corrupt length from the input fails as a truncated record instead of allocating it
+#[inline]
+fn read_n<R: Read>(r: &mut R, buf: &mut Vec<u8>, n: usize) -> Result<(), FastxErr> {
+    let start = buf.len();
+    r.take(n as u64).read_to_end(buf)?;
+    if buf.len() - start < n {
+        return Err(FastxErr::InvalidBinaryFormat("truncated record"));
+    }
+    Ok(())
+}
+
+fn truncated(e: io::Error) -> FastxErr {
+    match e.kind() {
+        io::ErrorKind::UnexpectedEof => FastxErr::InvalidBinaryFormat("truncated record"),
+        _ => FastxErr::IOError(e),
+    }
+}
+
+// only uppercase ACGT can be packed losslessly
+#[inline]
+fn is_packable(seq: &[u8]) -> bool {
+    seq.iter().all(|b| matches!(b, b'A' | b'C' | b'G' | b'T'))
+}
+
+const PACK_TABLE: [u8; 4] = [b'A', b'C', b'G', b'T'];
+
+#[inline]
+fn base_to_2bit(b: u8) -> u8 {
+    match b {
+        b'A' => 0,
+        b'C' => 1,
+        b'G' => 2,
+        _ => 3,
+    }
+}
+
+// pack 4 bases per byte, the first base in the highest bits
+fn pack_2bit(seq: &[u8], out: &mut Vec<u8>) {
+    out.clear();
+    for chunk in seq.chunks(4) {
+        let mut byte = 0u8;
+        for (i, &b) in chunk.iter().enumerate() {
+            byte |= base_to_2bit(b) << (6 - 2 * i);
+        }
+        out.push(byte);
+    }
+}
+
+fn unpack_2bit(packed: &[u8], len: usize, out: &mut Vec<u8>) {
+    out.reserve(len);
+    for i in 0..len {
+        let byte = packed[i >> 2];
+        out.push(PACK_TABLE[((byte >> (6 - 2 * (i & 3))) & 3) as usize]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn records() -> Vec<Seq<'static>> {
+        vec![
+            Seq {
+                id: b"read1",
+                desc: b"desc 1",
+                seq: b"ACGTACGTA",
+                qual: Some(b"IIIIIIII#"),
+            },
+            Seq {
+                id: b"read2",
+                desc: b"",
+                seq: b"ACGNnacgt",
+                qual: Some(b"#########"),
+            },
+            Seq {
+                id: b"chr1",
+                desc: b"",
+                seq: b"",
+                qual: None,
+            },
+        ]
+    }
+
+    fn roundtrip(packed: bool) {
+        let mut writer = BinaryWriter::from_writer(Vec::new(), packed).unwrap();
+        for r in records() {
+            writer.write(&r).unwrap();
+        }
+        let data = writer.into_inner();
+
+        let mut reader = BinaryReader::from_reader(Cursor::new(data)).unwrap();
+        let mut i = 0;
+        while let Some(res) = reader.next() {
+            let seq = res.unwrap();
+            let expected = records()[i];
+            assert_eq!(seq.id, expected.id);
+            assert_eq!(seq.desc, expected.desc);
+            assert_eq!(seq.seq, expected.seq);
+            assert_eq!(seq.qual, expected.qual);
+            i += 1;
+        }
+        assert_eq!(i, 3);
+    }
+
+    #[test]
+    fn test_binary_roundtrip_raw() {
+        roundtrip(false);
+    }
+
+    #[test]
+    fn test_binary_roundtrip_packed() {
+        roundtrip(true);
+    }
+
+    #[test]
+    fn test_binary_packed_is_smaller() {
+        let seq = Seq {
+            id: b"r",
+            desc: b"",
+            seq: b"ACGTACGTACGTACGTACGT",
+            qual: None,
+        };
+        let mut raw = BinaryWriter::from_writer(Vec::new(), false).unwrap();
+        raw.write(&seq).unwrap();
+        let mut packed = BinaryWriter::from_writer(Vec::new(), true).unwrap();
+        packed.write(&seq).unwrap();
+        assert_eq!(raw.into_inner().len() - packed.into_inner().len(), 15);
+    }
+
+    #[test]
+    fn test_binary_invalid_stream() {
+        assert!(matches!(
+            BinaryReader::from_reader(Cursor::new(b">seq1\nACGT\n".to_vec())),
+            Err(FastxErr::InvalidBinaryFormat(_))
+        ));
+
+        let mut writer = BinaryWriter::from_writer(Vec::new(), false).unwrap();
+        writer.write(&records()[0]).unwrap();
+        let mut data = writer.into_inner();
+        data.truncate(data.len() - 3);
+        let mut reader = BinaryReader::from_reader(Cursor::new(data)).unwrap();
+        assert!(matches!(
+            reader.next(),
+            Some(Err(FastxErr::InvalidBinaryFormat(_)))
+        ));
+
+        // a corrupt sequence length fails without allocating it
+        let mut writer = BinaryWriter::from_writer(Vec::new(), false).unwrap();
+        writer.write(&records()[2]).unwrap();
+        let mut data = writer.into_inner();
+        // flags, then lengths of the ID, description and sequence
+        let i = BINARY_HEADER_LEN + 9;
+        data[i..i + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let mut reader = BinaryReader::from_reader(Cursor::new(data.clone())).unwrap();
+        assert!(matches!(
+            reader.next(),
+            Some(Err(FastxErr::InvalidBinaryFormat(_)))
+        ));
+        let mut reader = BinaryReader::from_reader(Cursor::new(data)).unwrap();
+        reader.set_limits(SizeLimits {
+            max_seq_len: 1 << 20,
+            ..Default::default()
+        });
+        assert!(matches!(
+            reader.next(),
+            Some(Err(FastxErr::SizeLimitExceeded("sequence", _)))
+        ));
+    }
+}
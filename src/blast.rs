@@ -0,0 +1,284 @@
+use crate::errors::*;
+use crate::reader::*;
+use crate::seq::*;
+use crate::writer::*;
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, Write};
+
+/// Options for normalizing FASTA files for makeblastdb/DIAMOND ingestion.
+#[derive(Debug, Clone)]
+pub struct BlastOptions {
+    /// Maximum length of sequence IDs, makeblastdb rejects IDs longer than 50 characters.
+    /// 0 is taken as 1.
+    pub max_id_len: usize,
+    /// Line width of output sequences.
+    pub line_width: usize,
+    /// Whether the sequences are proteins, nucleotides otherwise.
+    pub protein: bool,
+    /// Whether to keep the descriptions in headers.
+    pub keep_desc: bool,
+}
+
+impl Default for BlastOptions {
+    fn default() -> Self {
+        Self {
+            max_id_len: 50,
+            line_width: 80,
+            protein: false,
+            keep_desc: true,
+        }
+    }
+}
+
+/// Statistics of a normalization run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlastStats {
+    /// Number of records written.
+    pub records: usize,
+    /// Number of records whose IDs were altered.
+    pub renamed: usize,
+    /// Number of illegal characters removed from sequences.
+    pub removed_chars: usize,
+}
+
+/// Reads all records from the reader and writes makeblastdb-ready FASTA records to the writer:
+/// IDs are made unique and shortened to `max_id_len`, illegal characters are replaced in IDs and
+/// removed from sequences, sequences are uppercased and wrapped at `line_width`.
+/// For every altered ID, a tab-delimited line of the new and the original ID is written to `mapping`.
+/// It fails with `SizeLimitExceeded` if `max_id_len` is too short to keep IDs unique.
+pub fn normalize_for_blast<R: BufRead, W: Write, M: Write>(
+    reader: &mut Reader<R>,
+    writer: &mut Writer<W>,
+    mapping: &mut M,
+    opts: &BlastOptions,
+) -> Result<BlastStats, FastxErr> {
+    writer.set_line_width(opts.line_width);
+    let max_id_len = opts.max_id_len.max(1);
+
+    let mut stats = BlastStats::default();
+    let mut seen: HashSet<Vec<u8>> = HashSet::new();
+    let mut next_suffix: HashMap<Vec<u8>, usize> = HashMap::new();
+    let mut id = Vec::with_capacity(64);
+    let mut seq_buf = Vec::with_capacity(1 << 20);
+
+    while let Some(res) = reader.next() {
+        let record = res?;
+
+        sanitize_blast_id(record.id, max_id_len, &mut id);
+        make_unique(&mut id, max_id_len, &seen, &mut next_suffix)?;
+        if id != record.id {
+            stats.renamed += 1;
+            mapping.write_all(&id)?;
+            mapping.write_all(b"\t")?;
+            mapping.write_all(record.id)?;
+            mapping.write_all(b"\n")?;
+        }
+
+        seq_buf.clear();
+        for &b in record.seq {
+            let b = b.to_ascii_uppercase();
+            let legal = if opts.protein {
+                b.is_ascii_uppercase() || b == b'*'
+            } else {
+                matches!(
+                    b,
+                    b'A' | b'C'
+                        | b'G'
+                        | b'T'
+                        | b'U'
+                        | b'R'
+                        | b'Y'
+                        | b'K'
+                        | b'M'
+                        | b'S'
+                        | b'W'
+                        | b'B'
+                        | b'D'
+                        | b'H'
+                        | b'V'
+                        | b'N'
+                )
+            };
+            if legal {
+                seq_buf.push(b);
+            } else {
+                stats.removed_chars += 1;
+            }
+        }
+
+        writer.write(&Seq {
+            id: &id,
+            desc: if opts.keep_desc { record.desc } else { &[] },
+            seq: &seq_buf,
+            qual: None,
+        })?;
+        stats.records += 1;
+        seen.insert(id.clone());
+    }
+    writer.flush()?;
+    mapping.flush()?;
+
+    Ok(stats)
+}
+
+// keep alphanumerics and ._-| in IDs, replace others with '_', and truncate
+fn sanitize_blast_id(id: &[u8], max_len: usize, out: &mut Vec<u8>) {
+    out.clear();
+    for &b in id.iter().take(max_len) {
+        if b.is_ascii_alphanumeric() || matches!(b, b'.' | b'_' | b'-' | b'|') {
+            out.push(b);
+        } else {
+            out.push(b'_');
+        }
+    }
+    if out.is_empty() {
+        out.extend_from_slice(&b"seq"[..max_len.min(3)]);
+    }
+}
+
+// append the smallest "_<n>" suffix that makes the ID unique, truncating the ID first if needed,
+// so the result is never longer than max_len. The '_' is dropped if the suffix is too long,
+// and it fails if the number itself is.
+// Suffixes of an ID are tried from the one after the last used, kept in next_suffix,
+// so many copies of an ID take linear time.
+fn make_unique(
+    id: &mut Vec<u8>,
+    max_len: usize,
+    seen: &HashSet<Vec<u8>>,
+    next_suffix: &mut HashMap<Vec<u8>, usize>,
+) -> Result<(), FastxErr> {
+    if !seen.contains(id.as_slice()) {
+        return Ok(());
+    }
+    let base = id.clone();
+    let mut n = next_suffix.get(&base).copied().unwrap_or(2);
+    loop {
+        let suffix = format!("_{n}");
+        let suffix = if suffix.len() > max_len {
+            &suffix[1..]
+        } else {
+            &suffix[..]
+        };
+        if suffix.len() > max_len {
+            return Err(FastxErr::SizeLimitExceeded("deduplicated ID", max_len));
+        }
+        let keep = base.len().min(max_len.saturating_sub(suffix.len()));
+        id.clear();
+        id.extend_from_slice(&base[..keep]);
+        id.extend_from_slice(suffix.as_bytes());
+        if !seen.contains(id.as_slice()) {
+            next_suffix.insert(base, n + 1);
+            return Ok(());
+        }
+        n += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn normalize(input: &str, opts: &BlastOptions) -> (String, String, BlastStats) {
+        let mut reader = Reader::from_reader(Cursor::new(input.as_bytes()));
+        let mut writer = Writer::from_writer(Vec::new());
+        let mut mapping = Vec::new();
+        let stats = normalize_for_blast(&mut reader, &mut writer, &mut mapping, opts).unwrap();
+        (
+            String::from_utf8(writer.into_inner()).unwrap(),
+            String::from_utf8(mapping).unwrap(),
+            stats,
+        )
+    }
+
+    #[test]
+    fn test_normalize_for_blast() {
+        let input = "\
+>seq1 desc
+ac-gt.NN
+>seq1
+ACGT
+>sp:P1[x]
+ACGT
+";
+        let (out, mapping, stats) = normalize(input, &BlastOptions::default());
+        assert_eq!(out, ">seq1 desc\nACGTNN\n>seq1_2\nACGT\n>sp_P1_x_\nACGT\n");
+        assert_eq!(mapping, "seq1_2\tseq1\nsp_P1_x_\tsp:P1[x]\n");
+        assert_eq!(
+            stats,
+            BlastStats {
+                records: 3,
+                renamed: 2,
+                removed_chars: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_normalize_for_blast_long_ids_and_wrapping() {
+        let opts = BlastOptions {
+            max_id_len: 6,
+            line_width: 4,
+            protein: true,
+            keep_desc: false,
+        };
+        let input = ">abcdefgh d\nmkv*lx1\n>abcdefgh\nMK\n";
+        let (out, mapping, _) = normalize(input, &opts);
+        assert_eq!(out, ">abcdef\nMKV*\nLX\n>abcd_2\nMK\n");
+        assert_eq!(mapping, "abcdef\tabcdefgh\nabcd_2\tabcdefgh\n");
+    }
+
+    #[test]
+    fn test_normalize_for_blast_many_duplicates() {
+        let mut input = String::from(">r_3\nACGT\n");
+        for _ in 0..1000 {
+            input.push_str(">r\nACGT\n");
+        }
+        let (out, _, stats) = normalize(&input, &BlastOptions::default());
+        let ids: Vec<&str> = out.lines().filter_map(|l| l.strip_prefix('>')).collect();
+        assert_eq!(ids[..4], ["r_3", "r", "r_2", "r_4"]);
+        assert_eq!(ids[1000], "r_1001");
+        assert_eq!(ids.iter().collect::<HashSet<_>>().len(), 1001);
+        assert_eq!(stats.renamed, 999);
+    }
+
+    #[test]
+    fn test_normalize_for_blast_tiny_max_id_len() {
+        let ids = |out: &str| -> Vec<String> {
+            out.lines()
+                .filter_map(|l| l.strip_prefix('>'))
+                .map(|id| id.to_string())
+                .collect()
+        };
+        let input: String = (0..9).map(|_| ">abc\nACGT\n").collect();
+        for (max_id_len, expected) in [
+            (3, ["abc", "a_2", "a_3"]),
+            (2, ["ab", "_2", "_3"]),
+            (1, ["a", "2", "3"]),
+            (0, ["a", "2", "3"]),
+        ] {
+            let opts = BlastOptions {
+                max_id_len,
+                ..Default::default()
+            };
+            let (out, _, _) = normalize(&input, &opts);
+            let ids = ids(&out);
+            assert_eq!(ids[..3], expected);
+            assert!(ids.iter().all(|id| id.len() <= max_id_len.max(1)));
+            assert_eq!(ids.iter().collect::<HashSet<_>>().len(), 9);
+        }
+
+        // "_10" fits in neither 1 nor 2 bytes
+        let input: String = (0..10).map(|_| ">abc\nACGT\n").collect();
+        let mut reader = Reader::from_reader(Cursor::new(input.as_bytes()));
+        let mut writer = Writer::from_writer(Vec::new());
+        let opts = BlastOptions {
+            max_id_len: 1,
+            ..Default::default()
+        };
+        assert!(matches!(
+            normalize_for_blast(&mut reader, &mut writer, &mut Vec::new(), &opts),
+            Err(FastxErr::SizeLimitExceeded("deduplicated ID", 1))
+        ));
+    }
+}
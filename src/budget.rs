@@ -0,0 +1,236 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct Inner {
+    limit: usize,
+    used: AtomicUsize,
+}
+
+/// A memory budget shared by spill-capable operations, e.g., `ExactKmerCounter` and
+/// `RecordCache`, so one knob bounds the memory of all of them.
+///
+/// Operations reserve working memory from the budget as their estimates grow, and spill to disk
+/// or evict entries when a reservation fails. Clones share the same budget, also across threads.
+/// The budget only accounts for reservations; it does not measure the memory of the process.
+#[derive(Clone)]
+pub struct MemoryBudget {
+    inner: Arc<Inner>,
+}
+
+impl std::fmt::Debug for MemoryBudget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MemoryBudget")
+            .field("limit", &self.limit())
+            .field("used", &self.used())
+            .finish()
+    }
+}
+
+impl Default for MemoryBudget {
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
+
+impl MemoryBudget {
+    /// Creates a budget of `limit` bytes.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                limit,
+                used: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Creates a budget without a limit.
+    pub fn unlimited() -> Self {
+        Self::new(usize::MAX)
+    }
+
+    /// Parses a size like "4096", "512M", "8G" or "1.5g", in binary units (K, M, G, T),
+    /// with an optional "B" or "iB" suffix.
+    pub fn parse(size: &str) -> Option<Self> {
+        let s = size.trim().to_ascii_uppercase();
+        let s = s
+            .strip_suffix("IB")
+            .or_else(|| s.strip_suffix('B'))
+            .unwrap_or(&s);
+        let (num, unit) = match s.char_indices().last()? {
+            (i, 'K') => (&s[..i], 1u64 << 10),
+            (i, 'M') => (&s[..i], 1 << 20),
+            (i, 'G') => (&s[..i], 1 << 30),
+            (i, 'T') => (&s[..i], 1 << 40),
+            _ => (s, 1),
+        };
+        let num: f64 = num.trim().parse().ok()?;
+        if !num.is_finite() || num < 0.0 {
+            return None;
+        }
+        Some(Self::new(
+            (num * unit as f64).min(usize::MAX as f64) as usize
+        ))
+    }
+
+    /// Creates a budget of a fraction of the physical memory, None if it can not be determined.
+    /// Supported on Linux (/proc/meminfo) and macOS (sysctl).
+    pub fn fraction_of_system(fraction: f64) -> Option<Self> {
+        let total = system_memory()?;
+        Some(Self::new(
+            (total as f64 * fraction.clamp(0.0, 1.0)) as usize,
+        ))
+    }
+
+    /// Returns the limit in bytes.
+    pub fn limit(&self) -> usize {
+        self.inner.limit
+    }
+
+    /// Returns the reserved bytes.
+    pub fn used(&self) -> usize {
+        self.inner.used.load(Ordering::Relaxed)
+    }
+
+    /// Returns the bytes left.
+    pub fn available(&self) -> usize {
+        self.limit().saturating_sub(self.used())
+    }
+
+    /// Creates an empty reservation, grown by the operation as it uses memory.
+    pub fn reservation(&self) -> Reservation {
+        Reservation {
+            budget: self.clone(),
+            bytes: 0,
+        }
+    }
+
+    fn try_take(&self, n: usize) -> bool {
+        let limit = self.inner.limit;
+        self.inner
+            .used
+            .fetch_update(Ordering::AcqRel, Ordering::Relaxed, |used| {
+                used.checked_add(n).filter(|&u| u <= limit)
+            })
+            .is_ok()
+    }
+
+    fn give_back(&self, n: usize) {
+        self.inner.used.fetch_sub(n, Ordering::AcqRel);
+    }
+}
+
+/// Memory reserved from a `MemoryBudget`, returned to the budget on drop.
+#[derive(Debug)]
+pub struct Reservation {
+    budget: MemoryBudget,
+    bytes: usize,
+}
+
+impl Reservation {
+    /// Returns the reserved bytes.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Reserves `n` more bytes, returns false if the budget is exhausted.
+    pub fn try_grow(&mut self, n: usize) -> bool {
+        if self.budget.try_take(n) {
+            self.bytes += n;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Grows or shrinks the reservation to `n` bytes, returns false if growing fails.
+    pub fn try_resize(&mut self, n: usize) -> bool {
+        if n > self.bytes {
+            self.try_grow(n - self.bytes)
+        } else {
+            self.shrink(self.bytes - n);
+            true
+        }
+    }
+
+    /// Returns `n` bytes to the budget.
+    pub fn shrink(&mut self, n: usize) {
+        let n = n.min(self.bytes);
+        self.budget.give_back(n);
+        self.bytes -= n;
+    }
+
+    /// Returns all bytes to the budget.
+    pub fn release(&mut self) {
+        self.shrink(self.bytes);
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn system_memory() -> Option<u64> {
+    let info = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let line = info.lines().find(|l| l.starts_with("MemTotal:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+#[cfg(target_os = "macos")]
+fn system_memory() -> Option<u64> {
+    let out = std::process::Command::new("sysctl")
+        .args(["-n", "hw.memsize"])
+        .output()
+        .ok()?;
+    String::from_utf8(out.stdout).ok()?.trim().parse().ok()
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn system_memory() -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_budget_reservations() {
+        let budget = MemoryBudget::new(100);
+        let mut a = budget.reservation();
+        let mut b = budget.clone().reservation();
+        assert!(a.try_grow(60));
+        assert!(!b.try_grow(50));
+        assert!(b.try_grow(40));
+        assert_eq!((budget.used(), budget.available()), (100, 0));
+
+        a.shrink(30);
+        assert!(b.try_resize(70));
+        assert!(!b.try_resize(71));
+        drop(b);
+        assert_eq!(budget.used(), 30);
+        a.release();
+        assert_eq!(budget.used(), 0);
+
+        assert_eq!(MemoryBudget::default().limit(), usize::MAX);
+        if cfg!(target_os = "linux") {
+            assert!(MemoryBudget::fraction_of_system(0.5).unwrap().limit() > 0);
+        }
+    }
+
+    #[test]
+    fn test_memory_budget_parse() {
+        let limit = |s| MemoryBudget::parse(s).map(|b| b.limit());
+        assert_eq!(limit("4096"), Some(4096));
+        assert_eq!(limit("512M"), Some(512 << 20));
+        assert_eq!(limit("8g"), Some(8 << 30));
+        assert_eq!(limit("1.5GiB"), Some(3 << 29));
+        assert_eq!(limit(" 2 KB "), Some(2048));
+        assert_eq!(limit("x"), None);
+        assert_eq!(limit("-1G"), None);
+        assert_eq!(limit(""), None);
+    }
+}
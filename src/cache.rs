@@ -0,0 +1,371 @@
+use crate::budget::*;
+use crate::errors::*;
+use crate::seq::*;
+use crate::store::*;
+use crate::twobit::*;
+use std::collections::{BTreeMap, HashMap};
+
+/// A source of records accessible by ID.
+pub trait RecordLookup {
+    /// Returns the record with the ID, or None if it does not exist.
+    fn lookup(&mut self, id: &[u8]) -> Result<Option<Seq<'_>>, FastxErr>;
+}
+
+impl RecordLookup for RecordStore {
+    fn lookup(&mut self, id: &[u8]) -> Result<Option<Seq<'_>>, FastxErr> {
+        self.get(id)
+    }
+}
+
+impl RecordLookup for TwoBitReader {
+    fn lookup(&mut self, id: &[u8]) -> Result<Option<Seq<'_>>, FastxErr> {
+        self.get(id).transpose()
+    }
+}
+
+// approximate memory of an entry besides its data
+const ENTRY_OVERHEAD: usize = 128;
+
+// a cached record or region of a record
+struct Entry {
+    id: Vec<u8>,
+    desc: Vec<u8>,
+    seq: Vec<u8>,
+    qual: Option<Vec<u8>>,
+    tick: u64,
+}
+
+impl Entry {
+    fn new(seq: &Seq, start: usize, end: usize) -> Self {
+        Self {
+            id: seq.id.to_vec(),
+            desc: seq.desc.to_vec(),
+            seq: seq.seq[start..end].to_vec(),
+            qual: seq.qual.map(|q| q[start..end].to_vec()),
+            tick: 0,
+        }
+    }
+
+    fn size(&self) -> usize {
+        ENTRY_OVERHEAD
+            + 2 * self.id.len()
+            + self.desc.len()
+            + self.seq.len()
+            + self.qual.as_ref().map_or(0, |q| q.len())
+    }
+
+    fn as_seq(&self, start: usize, end: usize) -> Seq<'_> {
+        Seq {
+            id: &self.id,
+            desc: &self.desc,
+            seq: &self.seq[start..end],
+            qual: self.qual.as_ref().map(|q| &q[start..end]),
+        }
+    }
+}
+
+// a whole record, or a region (start, end) of it
+type Key = (Vec<u8>, Option<(usize, usize)>);
+
+// clamps a region to a sequence length
+fn clamp(len: usize, start: usize, end: usize) -> (usize, usize) {
+    let end = end.min(len);
+    (start.min(end), end)
+}
+
+/// Cache statistics.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+/// An LRU cache of records and regions over a record source, with a memory budget.
+///
+/// Whole records are cached by ID, and regions by (ID, start, end). A region is served from
+/// the cached whole record if there is one, otherwise only the region is cached, so extracting
+/// many short regions of a chromosome does not keep the chromosome in memory.
+/// The most recently fetched entry is always kept even if it alone exceeds the budget.
+pub struct RecordCache<S: RecordLookup> {
+    source: S,
+    max_bytes: usize,
+    bytes: usize,
+    reservation: Option<Reservation>,
+    entries: HashMap<Key, Entry>,
+    order: BTreeMap<u64, Key>, // tick -> key, the smallest tick is the least recently used
+    tick: u64,
+    stats: CacheStats,
+}
+
+impl<S: RecordLookup> RecordCache<S> {
+    /// Creates a cache over the source using at most about `max_bytes` of memory.
+    pub fn new(source: S, max_bytes: usize) -> Self {
+        Self {
+            source,
+            max_bytes,
+            bytes: 0,
+            reservation: None,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Also bounds the memory by a budget shared with other operations, entries are evicted
+    /// when the budget is exhausted.
+    pub fn set_memory_budget(&mut self, budget: &MemoryBudget) {
+        let mut reservation = budget.reservation();
+        reservation.try_resize(self.bytes);
+        self.reservation = Some(reservation);
+        self.evict();
+    }
+
+    /// Returns the approximate memory used by cached entries.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Returns the number of cached entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the statistics of hits, misses and evictions.
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// Removes all cached entries.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+        self.bytes = 0;
+        self.reserve();
+    }
+
+    /// Returns a mutable reference to the underlying source.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.source
+    }
+
+    /// Returns the underlying source.
+    pub fn into_inner(self) -> S {
+        self.source
+    }
+
+    /// Returns the record with the ID.
+    pub fn get(&mut self, id: &[u8]) -> Result<Option<Seq<'_>>, FastxErr> {
+        self.fetch(id, None)
+    }
+
+    /// Returns the region `start..end` (0-based, end-exclusive, clamped to the sequence length)
+    /// of the record with the ID.
+    pub fn get_region(
+        &mut self,
+        id: &[u8],
+        start: usize,
+        end: usize,
+    ) -> Result<Option<Seq<'_>>, FastxErr> {
+        self.fetch(id, Some((start, end)))
+    }
+
+    fn fetch(
+        &mut self,
+        id: &[u8],
+        region: Option<(usize, usize)>,
+    ) -> Result<Option<Seq<'_>>, FastxErr> {
+        let whole: Key = (id.to_vec(), None);
+        let key: Key = (id.to_vec(), region);
+        if self.entries.contains_key(&key) {
+            self.stats.hits += 1;
+            self.touch(&key);
+            let e = &self.entries[&key];
+            return Ok(Some(e.as_seq(0, e.seq.len())));
+        }
+        if let Some((start, end)) = region
+            && self.entries.contains_key(&whole)
+        {
+            self.stats.hits += 1;
+            self.touch(&whole);
+            let e = &self.entries[&whole];
+            let (start, end) = clamp(e.seq.len(), start, end);
+            return Ok(Some(e.as_seq(start, end)));
+        }
+
+        self.stats.misses += 1;
+        let entry = match self.source.lookup(id)? {
+            None => return Ok(None),
+            Some(seq) => {
+                let (start, end) = match region {
+                    Some((start, end)) => clamp(seq.seq.len(), start, end),
+                    None => (0, seq.seq.len()),
+                };
+                Entry::new(&seq, start, end)
+            }
+        };
+        self.bytes += entry.size();
+        self.entries.insert(key.clone(), entry);
+        self.touch(&key);
+        self.evict();
+
+        let e = &self.entries[&key];
+        Ok(Some(e.as_seq(0, e.seq.len())))
+    }
+
+    // marks the entry as the most recently used
+    fn touch(&mut self, key: &Key) {
+        self.tick += 1;
+        let e = self.entries.get_mut(key).unwrap();
+        self.order.remove(&e.tick);
+        e.tick = self.tick;
+        self.order.insert(self.tick, key.clone());
+    }
+
+    // evicts least recently used entries, except the latest one, until within the budget
+    fn evict(&mut self) {
+        while self.order.len() > 1 && (self.bytes > self.max_bytes || !self.reserve()) {
+            let (_, key) = self.order.pop_first().unwrap();
+            let e = self.entries.remove(&key).unwrap();
+            self.bytes -= e.size();
+            self.stats.evictions += 1;
+        }
+        self.reserve();
+    }
+
+    // resizes the reservation of the memory budget to the cached bytes
+    fn reserve(&mut self) -> bool {
+        self.reservation
+            .as_mut()
+            .is_none_or(|r| r.try_resize(self.bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // an in-memory source counting lookups
+    struct Source {
+        records: HashMap<Vec<u8>, Vec<u8>>,
+        lookups: usize,
+    }
+
+    impl RecordLookup for Source {
+        fn lookup(&mut self, id: &[u8]) -> Result<Option<Seq<'_>>, FastxErr> {
+            self.lookups += 1;
+            Ok(self.records.get_key_value(id).map(|(id, seq)| Seq {
+                id,
+                desc: b"",
+                seq,
+                qual: None,
+            }))
+        }
+    }
+
+    fn a_source() -> Source {
+        let mut records = HashMap::new();
+        records.insert(b"chr1".to_vec(), b"ACGTACGTAC".repeat(100));
+        records.insert(b"chr2".to_vec(), b"GGGGGCCCCC".repeat(100));
+        Source {
+            records,
+            lookups: 0,
+        }
+    }
+
+    #[test]
+    fn test_record_cache_regions() {
+        let mut cache = RecordCache::new(a_source(), 1 << 20);
+        assert_eq!(
+            cache.get_region(b"chr2", 3, 7).unwrap().unwrap().seq,
+            b"GGCC"
+        );
+        assert_eq!(
+            cache.get_region(b"chr2", 3, 7).unwrap().unwrap().seq,
+            b"GGCC"
+        );
+        assert_eq!(cache.get_mut().lookups, 1);
+
+        // regions are served from a cached whole record, and clamped
+        assert_eq!(cache.get(b"chr1").unwrap().unwrap().seq.len(), 1000);
+        assert_eq!(
+            cache.get_region(b"chr1", 0, 4).unwrap().unwrap().seq,
+            b"ACGT"
+        );
+        assert_eq!(
+            cache.get_region(b"chr1", 998, 2000).unwrap().unwrap().seq,
+            b"AC"
+        );
+        assert_eq!(
+            cache.get_region(b"chr1", 1200, 2000).unwrap().unwrap().seq,
+            b""
+        );
+        assert!(cache.get(b"chr3").unwrap().is_none());
+        assert_eq!(cache.get_mut().lookups, 3);
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 4,
+                misses: 3,
+                evictions: 0
+            }
+        );
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_record_cache_eviction() {
+        // room for only one whole record
+        let mut cache = RecordCache::new(a_source(), 1500);
+        cache.get(b"chr1").unwrap();
+        cache.get(b"chr2").unwrap();
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.stats().evictions, 1);
+        assert!(cache.bytes() <= 1500);
+
+        // small regions fit together, the least recently used one is evicted
+        let mut cache = RecordCache::new(a_source(), 2 * (ENTRY_OVERHEAD + 20));
+        cache.get_region(b"chr1", 0, 10).unwrap();
+        cache.get_region(b"chr1", 10, 20).unwrap();
+        cache.get_region(b"chr1", 0, 10).unwrap();
+        cache.get_region(b"chr2", 0, 10).unwrap();
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get_mut().lookups, 3);
+        cache.get_region(b"chr1", 0, 10).unwrap();
+        assert_eq!(cache.get_mut().lookups, 3);
+        cache.get_region(b"chr1", 10, 20).unwrap();
+        assert_eq!(cache.get_mut().lookups, 4);
+    }
+
+    #[test]
+    fn test_record_cache_memory_budget() {
+        // two caches sharing room for about three whole records
+        let budget = MemoryBudget::new(3 * (ENTRY_OVERHEAD + 1008));
+        let mut a = RecordCache::new(a_source(), 1 << 20);
+        let mut b = RecordCache::new(a_source(), 1 << 20);
+        a.set_memory_budget(&budget);
+        b.set_memory_budget(&budget);
+
+        a.get(b"chr1").unwrap();
+        a.get(b"chr2").unwrap();
+        assert_eq!(budget.used(), a.bytes());
+        b.get(b"chr1").unwrap();
+        assert_eq!((a.len(), b.len()), (2, 1));
+
+        // b evicts its own entries when the budget is exhausted
+        b.get(b"chr2").unwrap();
+        assert_eq!((a.len(), b.len()), (2, 1));
+        assert_eq!(b.stats().evictions, 1);
+        assert_eq!(budget.used(), a.bytes() + b.bytes());
+
+        a.clear();
+        drop(b);
+        assert_eq!(budget.used(), 0);
+    }
+}
@@ -0,0 +1,321 @@
+use crate::errors::*;
+use crate::reader::*;
+use std::fmt;
+use std::io::{BufRead, Write};
+
+// NCBI translation tables: amino acids and start codons ('M') of the 64 codons,
+// in the order of TTT, TTC, TTA, TTG, TCT, ..., GGG.
+const CODE_TABLES: [(u8, &[u8; 64], &[u8; 64]); 5] = [
+    (
+        1,
+        b"FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+        b"---M------**--*----M---------------M----------------------------",
+    ),
+    (
+        2,
+        b"FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIMMTTTTNNKKSS**VVVVAAAADDEEGGGG",
+        b"----------**--------------------MMMM----------**---M------------",
+    ),
+    (
+        4,
+        b"FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+        b"--MM------**-------M------------MMMM---------------M------------",
+    ),
+    (
+        5,
+        b"FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIMMTTTTNNKKSSSSVVVVAAAADDEEGGGG",
+        b"---M------**--------------------MMMM---------------M------------",
+    ),
+    (
+        11,
+        b"FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+        b"---M------**--*----M------------MMMM---------------M------------",
+    ),
+];
+
+/// A genetic code, i.e., an NCBI translation table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GeneticCode {
+    id: u8,
+    aas: &'static [u8; 64],
+    starts: &'static [u8; 64],
+}
+
+impl Default for GeneticCode {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
+
+impl GeneticCode {
+    /// Returns the standard code (table 1).
+    pub fn standard() -> Self {
+        Self::new(1).unwrap()
+    }
+
+    /// Returns the NCBI translation table of the ID, None if not supported.
+    /// Supported tables are 1 (standard), 2 (vertebrate mitochondrial), 4 (mold, protozoan and
+    /// coelenterate mitochondrial, mycoplasma), 5 (invertebrate mitochondrial) and 11 (bacterial,
+    /// archaeal and plant plastid).
+    pub fn new(id: u8) -> Option<Self> {
+        CODE_TABLES
+            .iter()
+            .find(|t| t.0 == id)
+            .map(|&(id, aas, starts)| Self { id, aas, starts })
+    }
+
+    /// Returns the ID of the table.
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Returns the amino acid of a codon (case-insensitive, U as T), `*` for stop codons,
+    /// or `X` for codons with other bases.
+    pub fn translate_codon(&self, codon: &[u8]) -> u8 {
+        match codon_index(codon) {
+            Some(i) => self.aas[i],
+            None => b'X',
+        }
+    }
+
+    /// Returns whether a codon is a start codon, including alternative ones of the code.
+    pub fn is_start(&self, codon: &[u8]) -> bool {
+        codon_index(codon).is_some_and(|i| self.starts[i] == b'M')
+    }
+
+    /// Returns whether a codon is a stop codon.
+    pub fn is_stop(&self, codon: &[u8]) -> bool {
+        codon_index(codon).is_some_and(|i| self.aas[i] == b'*')
+    }
+}
+
+// index of a codon in the TCAG order
+#[inline]
+fn codon_index(codon: &[u8]) -> Option<usize> {
+    if codon.len() != 3 {
+        return None;
+    }
+    let mut i = 0;
+    for &b in codon {
+        let v = match b {
+            b'T' | b't' | b'U' | b'u' => 0,
+            b'C' | b'c' => 1,
+            b'A' | b'a' => 2,
+            b'G' | b'g' => 3,
+            _ => return None,
+        };
+        i = i * 4 + v;
+    }
+    Some(i)
+}
+
+/// Options of CDS validation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CdsOptions {
+    /// The genetic code, the standard one by default.
+    pub code: GeneticCode,
+    /// Whether alternative start codons of the code are accepted, only ATG otherwise.
+    pub alternative_starts: bool,
+}
+
+impl Default for CdsOptions {
+    fn default() -> Self {
+        Self {
+            code: GeneticCode::standard(),
+            alternative_starts: true,
+        }
+    }
+}
+
+/// Problems of a coding sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CdsIssue {
+    /// The length is not a multiple of 3.
+    PartialCodon(usize),
+    /// The first codon is not a start codon.
+    NoStartCodon,
+    /// The last codon is not a stop codon.
+    NoStopCodon,
+    /// Stop codons before the last codon: the 1-based position of the first one, and the number.
+    InternalStop { pos: usize, count: usize },
+}
+
+impl fmt::Display for CdsIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CdsIssue::PartialCodon(len) => write!(f, "length {len} not a multiple of 3"),
+            CdsIssue::NoStartCodon => write!(f, "no start codon"),
+            CdsIssue::NoStopCodon => write!(f, "no stop codon"),
+            CdsIssue::InternalStop { pos, count } => {
+                write!(f, "{count} internal stop codon(s), the first at {pos}")
+            }
+        }
+    }
+}
+
+/// Checks a coding sequence: length divisible by 3, starting with a start codon, ending with a
+/// stop codon, and no internal stop codons in frame. An empty result means it passes.
+pub fn check_cds(seq: &[u8], opts: &CdsOptions) -> Vec<CdsIssue> {
+    let mut issues = Vec::new();
+    if !seq.len().is_multiple_of(3) {
+        issues.push(CdsIssue::PartialCodon(seq.len()));
+    }
+    let codons: Vec<&[u8]> = seq.chunks_exact(3).collect();
+
+    let start_ok = codons.first().is_some_and(|&c| {
+        if opts.alternative_starts {
+            opts.code.is_start(c)
+        } else {
+            c.eq_ignore_ascii_case(b"ATG") || c.eq_ignore_ascii_case(b"AUG")
+        }
+    });
+    if !start_ok {
+        issues.push(CdsIssue::NoStartCodon);
+    }
+
+    // a trailing partial codon leaves no final stop codon
+    let stop_ok =
+        seq.len().is_multiple_of(3) && codons.last().is_some_and(|&c| opts.code.is_stop(c));
+    if !stop_ok {
+        issues.push(CdsIssue::NoStopCodon);
+    }
+
+    let internal = if stop_ok {
+        &codons[..codons.len() - 1]
+    } else {
+        &codons[..]
+    };
+    let mut stops = internal
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| opts.code.is_stop(c));
+    if let Some((i, _)) = stops.next() {
+        issues.push(CdsIssue::InternalStop {
+            pos: i * 3 + 1,
+            count: stops.count() + 1,
+        });
+    }
+    issues
+}
+
+/// Statistics of validating coding sequences.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CdsStats {
+    pub records: u64,
+    pub passed: u64,
+    pub failed: u64,
+}
+
+/// Checks every record of the reader with `check_cds`, writing a tab-delimited report line of
+/// the ID, the sequence length, `pass` or `fail`, and the problems separated by "; " per record.
+pub fn validate_cds<R: BufRead, W: Write>(
+    reader: &mut Reader<R>,
+    opts: &CdsOptions,
+    report: &mut W,
+) -> Result<CdsStats, FastxErr> {
+    let mut stats = CdsStats::default();
+    while let Some(res) = reader.next() {
+        let record = res?;
+        stats.records += 1;
+        let issues = check_cds(record.seq, opts);
+        report.write_all(record.id)?;
+        if issues.is_empty() {
+            stats.passed += 1;
+            writeln!(report, "\t{}\tpass\t", record.seq.len())?;
+        } else {
+            stats.failed += 1;
+            let reasons: Vec<String> = issues.iter().map(|x| x.to_string()).collect();
+            writeln!(
+                report,
+                "\t{}\tfail\t{}",
+                record.seq.len(),
+                reasons.join("; ")
+            )?;
+        }
+    }
+    report.flush()?;
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_genetic_code() {
+        let code = GeneticCode::standard();
+        assert_eq!(code.translate_codon(b"ATG"), b'M');
+        assert_eq!(code.translate_codon(b"ugg"), b'W');
+        assert_eq!(code.translate_codon(b"TGA"), b'*');
+        assert_eq!(code.translate_codon(b"ANG"), b'X');
+        assert!(code.is_start(b"TTG"));
+        assert!(!code.is_start(b"ATA"));
+        assert!(code.is_stop(b"TAG"));
+
+        let mito = GeneticCode::new(2).unwrap();
+        assert_eq!(mito.id(), 2);
+        assert_eq!(mito.translate_codon(b"TGA"), b'W');
+        assert!(mito.is_stop(b"AGA"));
+        assert!(mito.is_start(b"ATA"));
+        assert!(GeneticCode::new(3).is_none());
+    }
+
+    #[test]
+    fn test_check_cds() {
+        let opts = CdsOptions::default();
+        assert!(check_cds(b"ATGAAATAA", &opts).is_empty());
+        assert!(check_cds(b"ttgaaauga", &opts).is_empty());
+        assert_eq!(
+            check_cds(
+                b"TTGAAATAA",
+                &CdsOptions {
+                    alternative_starts: false,
+                    ..opts
+                }
+            ),
+            vec![CdsIssue::NoStartCodon]
+        );
+        assert_eq!(
+            check_cds(b"ATGTAATAGAAAA", &opts),
+            vec![
+                CdsIssue::PartialCodon(13),
+                CdsIssue::NoStopCodon,
+                CdsIssue::InternalStop { pos: 4, count: 2 }
+            ]
+        );
+        assert_eq!(
+            check_cds(b"", &opts),
+            vec![CdsIssue::NoStartCodon, CdsIssue::NoStopCodon]
+        );
+
+        // TGA codes for tryptophan in vertebrate mitochondria
+        let mito = CdsOptions {
+            code: GeneticCode::new(2).unwrap(),
+            ..opts
+        };
+        assert_eq!(check_cds(b"ATGTGAAAA", &mito), vec![CdsIssue::NoStopCodon]);
+        assert!(check_cds(b"ATATGAAGA", &mito).is_empty());
+    }
+
+    #[test]
+    fn test_validate_cds() {
+        let input = ">c1\nATGAAA\nTAA\n>c2 partial\nATGAAATA\n>c3\nATGTAGCCCTGA\n";
+        let mut reader = Reader::from_bytes(input.as_bytes());
+        let mut report = Vec::new();
+        let stats = validate_cds(&mut reader, &CdsOptions::default(), &mut report).unwrap();
+        assert_eq!(
+            stats,
+            CdsStats {
+                records: 3,
+                passed: 1,
+                failed: 2,
+            }
+        );
+        assert_eq!(
+            String::from_utf8(report).unwrap(),
+            "c1\t9\tpass\t\n\
+             c2\t8\tfail\tlength 8 not a multiple of 3; no stop codon\n\
+             c3\t12\tfail\t1 internal stop codon(s), the first at 4\n"
+        );
+    }
+}
@@ -0,0 +1,478 @@
+use crate::errors::*;
+use crate::xopen::*;
+use std::fmt;
+use std::io::BufRead;
+use std::path::Path;
+
+/// Maximum number of issues kept in a report, further issues are only counted.
+pub const MAX_REPORTED_ISSUES: usize = 1000;
+
+/// Kinds of conformance issues.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IssueKind {
+    /// The first non-blank line starts with neither '>' nor '@'.
+    MissingHeader,
+    /// A header line without an ID, or with whitespace before the ID.
+    EmptyId,
+    /// A blank line inside the stream.
+    BlankLine,
+    /// Both LF and CRLF line endings are used.
+    MixedLineEndings,
+    /// The last line does not end with a line feed.
+    MissingFinalNewline,
+    /// A character not allowed in sequences.
+    InvalidSeqChar(u8),
+    /// A record without sequence.
+    EmptySequence,
+    /// A FASTA line width different from the first wrapped line of the stream,
+    /// or a line longer than previous lines of the same record.
+    InconsistentWrapping { expected: usize, found: usize },
+    /// A FASTQ record with sequence or quality over multiple lines.
+    WrappedFastq,
+    /// A FASTQ separator line not starting with '+'.
+    MissingSeparator,
+    /// A FASTQ separator line repeating a different header.
+    SeparatorMismatch,
+    /// A quality character outside '!'..='~'.
+    InvalidQualChar(u8),
+    /// Unequal lengths of sequence and quality.
+    QualLengthMismatch { seq: usize, qual: usize },
+    /// The stream ends in the middle of a FASTQ record.
+    TruncatedRecord,
+}
+
+impl fmt::Display for IssueKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IssueKind::MissingHeader => write!(f, "missing header line"),
+            IssueKind::EmptyId => write!(f, "empty sequence ID"),
+            IssueKind::BlankLine => write!(f, "blank line"),
+            IssueKind::MixedLineEndings => write!(f, "mixed LF and CRLF line endings"),
+            IssueKind::MissingFinalNewline => write!(f, "missing final newline"),
+            IssueKind::InvalidSeqChar(b) => {
+                write!(f, "invalid sequence character: {:?}", *b as char)
+            }
+            IssueKind::EmptySequence => write!(f, "empty sequence"),
+            IssueKind::InconsistentWrapping { expected, found } => write!(
+                f,
+                "inconsistent line width: expected {expected}, found {found}"
+            ),
+            IssueKind::WrappedFastq => write!(f, "multi-line FASTQ record"),
+            IssueKind::MissingSeparator => write!(f, "missing '+' separator line"),
+            IssueKind::SeparatorMismatch => write!(f, "separator line differs from the header"),
+            IssueKind::InvalidQualChar(b) => {
+                write!(f, "invalid quality character: {:?}", *b as char)
+            }
+            IssueKind::QualLengthMismatch { seq, qual } => {
+                write!(f, "unequal sequence and quality lengths: {seq} != {qual}")
+            }
+            IssueKind::TruncatedRecord => write!(f, "truncated record"),
+        }
+    }
+}
+
+/// A conformance issue at a 1-based line of the stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Issue {
+    pub line: u64,
+    /// The 1-based index of the record containing the line, 0 before the first record.
+    pub record: u64,
+    pub kind: IssueKind,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {} (record {}): {}",
+            self.line, self.record, self.kind
+        )
+    }
+}
+
+/// The result of validating a stream.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConformanceReport {
+    pub is_fastq: bool,
+    pub records: u64,
+    pub lines: u64,
+    /// The FASTA line width, i.e., the length of the first wrapped sequence line, 0 for unwrapped.
+    pub line_width: usize,
+    /// The first `MAX_REPORTED_ISSUES` issues.
+    pub issues: Vec<Issue>,
+    /// Number of all issues.
+    pub num_issues: u64,
+}
+
+impl ConformanceReport {
+    /// Returns whether no issue is found.
+    pub fn is_conformant(&self) -> bool {
+        self.num_issues == 0
+    }
+
+    fn add(&mut self, line: u64, kind: IssueKind) {
+        self.num_issues += 1;
+        if self.issues.len() < MAX_REPORTED_ISSUES {
+            self.issues.push(Issue {
+                line,
+                record: self.records,
+                kind,
+            });
+        }
+    }
+}
+
+// IUPAC nucleotide codes, amino acids (including U, O, J), gaps and stop
+#[inline]
+fn is_seq_char(b: u8) -> bool {
+    b.is_ascii_alphabetic() || matches!(b, b'-' | b'.' | b'*')
+}
+
+struct Lines<R> {
+    reader: R,
+    buf: Vec<u8>,
+    lineno: u64,
+    has_lf: bool,
+    has_crlf: bool,
+    mixed_reported: bool,
+    last_has_lf: bool,
+}
+
+impl<R: BufRead> Lines<R> {
+    // reads the next line without line ending into buf, returns false at EOF
+    fn next(&mut self, report: &mut ConformanceReport) -> Result<bool, FastxErr> {
+        self.buf.clear();
+        if self.reader.read_until(b'\n', &mut self.buf)? == 0 {
+            return Ok(false);
+        }
+        self.lineno += 1;
+        self.last_has_lf = self.buf.last() == Some(&b'\n');
+        if self.last_has_lf {
+            self.buf.pop();
+            if self.buf.last() == Some(&b'\r') {
+                self.buf.pop();
+                self.has_crlf = true;
+            } else {
+                self.has_lf = true;
+            }
+            if self.has_lf && self.has_crlf && !self.mixed_reported {
+                self.mixed_reported = true;
+                report.add(self.lineno, IssueKind::MixedLineEndings);
+            }
+        } else if self.buf.last() == Some(&b'\r') {
+            self.buf.pop();
+        }
+        Ok(true)
+    }
+}
+
+/// Checks a FASTA/FASTQ stream for conformance: header syntax, consistent line endings and
+/// wrapping, allowed sequence and quality characters, and FASTQ record structure.
+/// The format is determined by the first non-blank line.
+/// Only I/O failures are returned as errors, all format problems are collected in the report.
+pub fn validate_stream<R: BufRead>(reader: R) -> Result<ConformanceReport, FastxErr> {
+    let mut report = ConformanceReport::default();
+    let mut lines = Lines {
+        reader,
+        buf: Vec::with_capacity(1024),
+        lineno: 0,
+        has_lf: false,
+        has_crlf: false,
+        mixed_reported: false,
+        last_has_lf: true,
+    };
+
+    // the first non-blank line
+    loop {
+        if !lines.next(&mut report)? {
+            return Ok(report);
+        }
+        if !lines.buf.is_empty() {
+            break;
+        }
+    }
+    match lines.buf[0] {
+        b'>' => validate_fasta(&mut lines, &mut report)?,
+        b'@' => {
+            report.is_fastq = true;
+            validate_fastq(&mut lines, &mut report)?
+        }
+        _ => report.add(lines.lineno, IssueKind::MissingHeader),
+    }
+
+    if !lines.last_has_lf {
+        report.add(lines.lineno, IssueKind::MissingFinalNewline);
+    }
+    report.lines = lines.lineno;
+    Ok(report)
+}
+
+/// Checks a (possibly compressed) file for conformance, "-" for stdin. See `validate_stream`.
+pub fn validate_file<P: AsRef<Path>>(file: P) -> Result<ConformanceReport, FastxErr> {
+    validate_stream(xopen(file, 65536)?)
+}
+
+fn check_header(line: &[u8], lineno: u64, report: &mut ConformanceReport) {
+    if line.len() < 2 || line[1].is_ascii_whitespace() {
+        report.add(lineno, IssueKind::EmptyId);
+    }
+}
+
+fn check_seq(line: &[u8], lineno: u64, report: &mut ConformanceReport) {
+    if let Some(&b) = line.iter().find(|&&b| !is_seq_char(b)) {
+        report.add(lineno, IssueKind::InvalidSeqChar(b));
+    }
+}
+
+// lines.buf holds the first header line
+fn validate_fasta<R: BufRead>(
+    lines: &mut Lines<R>,
+    report: &mut ConformanceReport,
+) -> Result<(), FastxErr> {
+    let mut width = 0usize; // line width of the stream, from the first wrapped record
+    loop {
+        // a header
+        report.records += 1;
+        check_header(&lines.buf, lines.lineno, report);
+        let header_line = lines.lineno;
+
+        // sequence lines: (line number, length)
+        let mut seq_lines: Vec<(u64, usize)> = Vec::new();
+        let mut eof = true;
+        while lines.next(report)? {
+            if lines.buf.is_empty() {
+                report.add(lines.lineno, IssueKind::BlankLine);
+                continue;
+            }
+            if lines.buf[0] == b'>' {
+                eof = false;
+                break;
+            }
+            check_seq(&lines.buf, lines.lineno, report);
+            seq_lines.push((lines.lineno, lines.buf.len()));
+        }
+
+        if seq_lines.is_empty() {
+            report.add(header_line, IssueKind::EmptySequence);
+        } else if seq_lines.len() > 1 {
+            if width == 0 {
+                width = seq_lines[0].1;
+                report.line_width = width;
+            }
+            let (last, full) = seq_lines.split_last().unwrap();
+            for &(lineno, len) in full {
+                if len != width {
+                    report.add(
+                        lineno,
+                        IssueKind::InconsistentWrapping {
+                            expected: width,
+                            found: len,
+                        },
+                    );
+                }
+            }
+            if last.1 > width {
+                report.add(
+                    last.0,
+                    IssueKind::InconsistentWrapping {
+                        expected: width,
+                        found: last.1,
+                    },
+                );
+            }
+        } else if width > 0 && seq_lines[0].1 > width {
+            report.add(
+                seq_lines[0].0,
+                IssueKind::InconsistentWrapping {
+                    expected: width,
+                    found: seq_lines[0].1,
+                },
+            );
+        }
+
+        if eof {
+            return Ok(());
+        }
+    }
+}
+
+// lines.buf holds the first header line
+fn validate_fastq<R: BufRead>(
+    lines: &mut Lines<R>,
+    report: &mut ConformanceReport,
+) -> Result<(), FastxErr> {
+    let mut header: Vec<u8> = Vec::with_capacity(256);
+    loop {
+        // find a header, skipping blank lines
+        while lines.buf.is_empty() {
+            report.add(lines.lineno, IssueKind::BlankLine);
+            if !lines.next(report)? {
+                return Ok(());
+            }
+        }
+        report.records += 1;
+        if lines.buf[0] != b'@' {
+            report.add(lines.lineno, IssueKind::MissingHeader);
+        } else {
+            check_header(&lines.buf, lines.lineno, report);
+        }
+        header.clear();
+        header.extend_from_slice(&lines.buf[1..]);
+        let header_line = lines.lineno;
+
+        // sequence lines until the separator
+        let mut seq_len = 0usize;
+        let mut n_seq_lines = 0;
+        loop {
+            if !lines.next(report)? {
+                report.add(lines.lineno, IssueKind::TruncatedRecord);
+                return Ok(());
+            }
+            if lines.buf.first() == Some(&b'+') {
+                break;
+            }
+            if lines.buf.first() == Some(&b'@') && n_seq_lines > 0 {
+                // the next record, no separator
+                report.add(lines.lineno, IssueKind::MissingSeparator);
+                break;
+            }
+            check_seq(&lines.buf, lines.lineno, report);
+            seq_len += lines.buf.len();
+            n_seq_lines += 1;
+        }
+        if lines.buf.first() != Some(&b'+') {
+            continue;
+        }
+        if lines.buf.len() > 1 && lines.buf[1..] != header[..] {
+            report.add(lines.lineno, IssueKind::SeparatorMismatch);
+        }
+        if seq_len == 0 {
+            report.add(header_line, IssueKind::EmptySequence);
+        }
+
+        // quality lines until the sequence length is reached
+        let mut qual_len = 0usize;
+        let mut n_qual_lines = 0;
+        let mut eof = false;
+        loop {
+            if !lines.next(report)? {
+                eof = true;
+                break;
+            }
+            if let Some(&b) = lines.buf.iter().find(|&&b| !(b'!'..=b'~').contains(&b)) {
+                report.add(lines.lineno, IssueKind::InvalidQualChar(b));
+            }
+            qual_len += lines.buf.len();
+            n_qual_lines += 1;
+            if qual_len >= seq_len {
+                break;
+            }
+        }
+        if n_seq_lines > 1 || n_qual_lines > 1 {
+            report.add(header_line, IssueKind::WrappedFastq);
+        }
+        if qual_len != seq_len {
+            report.add(
+                lines.lineno,
+                if eof {
+                    IssueKind::TruncatedRecord
+                } else {
+                    IssueKind::QualLengthMismatch {
+                        seq: seq_len,
+                        qual: qual_len,
+                    }
+                },
+            );
+        }
+        if eof || !lines.next(report)? {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn kinds(input: &str) -> Vec<(u64, IssueKind)> {
+        validate_stream(Cursor::new(input.as_bytes()))
+            .unwrap()
+            .issues
+            .iter()
+            .map(|i| (i.line, i.kind))
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_conformant_streams() {
+        let report =
+            validate_stream(Cursor::new(&b">a x\nACGT\nAC\n>b\nACG\n>c\nAC\n"[..])).unwrap();
+        assert!(report.is_conformant());
+        assert_eq!((report.records, report.lines, report.line_width), (3, 7, 4));
+
+        let report =
+            validate_stream(Cursor::new(&b"@r1\nACGT\n+r1\nIIII\n@r2\nAC\n+\n@I\n"[..])).unwrap();
+        assert!(report.is_conformant(), "{:?}", report.issues);
+        assert!(report.is_fastq);
+        assert_eq!(report.records, 2);
+
+        assert!(
+            validate_stream(Cursor::new(&b""[..]))
+                .unwrap()
+                .is_conformant()
+        );
+    }
+
+    #[test]
+    fn test_validate_fasta_issues() {
+        assert_eq!(
+            kinds(">a\nACGT\nACGTA\nA\n>\nAC#T\n\n>b\r\nACGTAC\r\n>c\n>d\nAC"),
+            vec![
+                (
+                    3,
+                    IssueKind::InconsistentWrapping {
+                        expected: 4,
+                        found: 5
+                    }
+                ),
+                (5, IssueKind::EmptyId),
+                (6, IssueKind::InvalidSeqChar(b'#')),
+                (7, IssueKind::BlankLine),
+                (8, IssueKind::MixedLineEndings),
+                (
+                    9,
+                    IssueKind::InconsistentWrapping {
+                        expected: 4,
+                        found: 6
+                    }
+                ),
+                (10, IssueKind::EmptySequence),
+                (12, IssueKind::MissingFinalNewline),
+            ]
+        );
+        assert_eq!(kinds("ACGT\n"), vec![(1, IssueKind::MissingHeader)]);
+    }
+
+    #[test]
+    fn test_validate_fastq_issues() {
+        assert_eq!(
+            kinds("@r1\nACGT\n+r2\nII I\n@r2\nAC\nGT\n+\nIIII\n@r3\nACG\n+\nIIIII\n@r4\nA\n+\n"),
+            vec![
+                (3, IssueKind::SeparatorMismatch),
+                (4, IssueKind::InvalidQualChar(b' ')),
+                (5, IssueKind::WrappedFastq),
+                (13, IssueKind::QualLengthMismatch { seq: 3, qual: 5 }),
+                (16, IssueKind::TruncatedRecord),
+            ]
+        );
+
+        let report =
+            validate_stream(Cursor::new(&b"@r1\nAC\n+\nII\n\n@r2\nAC\n+\nII\n"[..])).unwrap();
+        assert_eq!(report.num_issues, 1);
+        assert_eq!(
+            report.issues[0].to_string(),
+            "line 5 (record 1): blank line"
+        );
+    }
+}
@@ -0,0 +1,249 @@
+use crate::errors::*;
+use crate::kmer::*;
+use crate::reader::*;
+use crate::seq::*;
+use crate::writer::*;
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+/// The screening result of a single read.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScreenHit {
+    /// Number of k-mers of the read.
+    pub kmers: usize,
+    /// Number of k-mers found in any contaminant.
+    pub hits: usize,
+    /// The contaminant sharing most k-mers with the read, if any.
+    pub contaminant: Option<usize>,
+}
+
+impl ScreenHit {
+    /// Returns the fraction of k-mers found in contaminants.
+    pub fn fraction(&self) -> f64 {
+        if self.kmers == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / self.kmers as f64
+    }
+}
+
+/// Statistics of screening a record stream.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScreenStats {
+    /// Number of reads screened.
+    pub reads: u64,
+    /// Number of reads flagged as contaminated.
+    pub flagged: u64,
+    /// Number of flagged reads per contaminant, in the order they were added.
+    pub per_contaminant: Vec<u64>,
+}
+
+/// A k-mer based contaminant screen.
+///
+/// Canonical k-mers of contaminant references (PhiX, adapters, host, ...) are stored in a hash set,
+/// and a read is flagged if the fraction of its k-mers found in the set reaches the threshold.
+pub struct ContaminantScreen {
+    k: usize,
+    threshold: f64,
+    kmers: HashMap<u64, u32>, // k-mer -> index of the first contaminant containing it
+    names: Vec<Vec<u8>>,
+    counts: Vec<usize>, // reused per read
+}
+
+impl ContaminantScreen {
+    /// Creates a screen with k-mer size k (1..=32) and a hit-fraction threshold.
+    pub fn new(k: usize, threshold: f64) -> Self {
+        assert!(
+            (1..=MAX_K).contains(&k),
+            "k should be in the range of 1..=32"
+        );
+        Self {
+            k,
+            threshold,
+            kmers: HashMap::new(),
+            names: Vec::new(),
+            counts: Vec::new(),
+        }
+    }
+
+    /// Returns the k-mer size.
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// Returns the names of contaminants.
+    pub fn names(&self) -> &[Vec<u8>] {
+        &self.names
+    }
+
+    /// Returns the number of distinct k-mers of all contaminants.
+    pub fn num_kmers(&self) -> usize {
+        self.kmers.len()
+    }
+
+    /// Adds a contaminant sequence, and returns its index.
+    pub fn add_reference(&mut self, name: &[u8], seq: &[u8]) -> usize {
+        let idx = self.names.len();
+        self.names.push(name.to_vec());
+        self.counts.push(0);
+        for (_, code) in KmerIter::new(seq, self.k, true) {
+            self.kmers.entry(code).or_insert(idx as u32);
+        }
+        idx
+    }
+
+    /// Adds every record of the reader as a contaminant, named by its ID.
+    pub fn add_references_from_reader<R: BufRead>(
+        &mut self,
+        reader: &mut Reader<R>,
+    ) -> Result<(), FastxErr> {
+        while let Some(res) = reader.next() {
+            let seq = res?;
+            self.add_reference(seq.id, seq.seq);
+        }
+        Ok(())
+    }
+
+    /// Screens a read.
+    pub fn check(&mut self, seq: &Seq) -> ScreenHit {
+        self.counts.fill(0);
+        let mut n = 0;
+        let mut hits = 0;
+        for (_, code) in KmerIter::new(seq.seq, self.k, true) {
+            n += 1;
+            if let Some(&idx) = self.kmers.get(&code) {
+                hits += 1;
+                self.counts[idx as usize] += 1;
+            }
+        }
+        let contaminant = if hits == 0 {
+            None
+        } else {
+            // the first contaminant with the most hits
+            let mut best = 0;
+            for (i, &c) in self.counts.iter().enumerate() {
+                if c > self.counts[best] {
+                    best = i;
+                }
+            }
+            Some(best)
+        };
+        ScreenHit {
+            kmers: n,
+            hits,
+            contaminant,
+        }
+    }
+
+    /// Returns whether a screening result passes the threshold.
+    pub fn is_contaminated(&self, hit: &ScreenHit) -> bool {
+        hit.hits > 0 && hit.fraction() >= self.threshold
+    }
+
+    /// Screens all reads of the reader, writes clean reads to `clean`
+    /// and flagged ones to `contaminated` if given.
+    pub fn screen<R: BufRead, W: Write, C: Write>(
+        &mut self,
+        reader: &mut Reader<R>,
+        clean: &mut Writer<W>,
+        mut contaminated: Option<&mut Writer<C>>,
+    ) -> Result<ScreenStats, FastxErr> {
+        let mut stats = ScreenStats {
+            per_contaminant: vec![0; self.names.len()],
+            ..Default::default()
+        };
+        while let Some(res) = reader.next() {
+            let seq = res?;
+            stats.reads += 1;
+            let hit = self.check(&seq);
+            if self.is_contaminated(&hit) {
+                stats.flagged += 1;
+                if let Some(idx) = hit.contaminant {
+                    stats.per_contaminant[idx] += 1;
+                }
+                if let Some(w) = contaminated.as_deref_mut() {
+                    w.write(&seq)?;
+                }
+            } else {
+                clean.write(&seq)?;
+            }
+        }
+        clean.flush()?;
+        if let Some(w) = contaminated {
+            w.flush()?;
+        }
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const PHIX_LIKE: &[u8] = b"GAGTTTTATCGCTTCCATGACGCAGAAGTTAACACTTTCGGATATTTCTGATGAGTCG";
+    const ADAPTER: &[u8] = b"AGATCGGAAGAGCACACGTCTGAACTCCAGTCA";
+
+    fn a_seq(seq: &'_ [u8]) -> Seq<'_> {
+        Seq {
+            id: b"r",
+            desc: b"",
+            seq,
+            qual: None,
+        }
+    }
+
+    #[test]
+    fn test_contaminant_check() {
+        let mut screen = ContaminantScreen::new(11, 0.5);
+        screen.add_reference(b"phix", PHIX_LIKE);
+        screen.add_reference(b"adapter", ADAPTER);
+
+        // reverse strand of a contaminant
+        let rc = revcomp(&PHIX_LIKE[5..40]);
+        let hit = screen.check(&a_seq(&rc));
+        assert_eq!(hit.kmers, 25);
+        assert_eq!(hit.hits, 25);
+        assert_eq!(hit.contaminant, Some(0));
+        assert!(screen.is_contaminated(&hit));
+
+        let hit = screen.check(&a_seq(b"CCCCCCCCCCCCCCCCCCCCAGATCGGAAGAGC"));
+        assert_eq!(hit.contaminant, Some(1));
+        assert!(!screen.is_contaminated(&hit));
+
+        let hit = screen.check(&a_seq(b"ACGT"));
+        assert_eq!(hit.fraction(), 0.0);
+        assert!(!screen.is_contaminated(&hit));
+    }
+
+    #[test]
+    fn test_contaminant_screen() {
+        let mut screen = ContaminantScreen::new(11, 0.5);
+        let refs = format!(">adapter\n{}\n", String::from_utf8_lossy(ADAPTER));
+        screen
+            .add_references_from_reader(&mut Reader::from_reader(Cursor::new(refs.as_bytes())))
+            .unwrap();
+
+        let input = format!(
+            ">r1\nCCCCCCCCCCCCCCCCCCCCCCCCCCCC\n>r2\n{}\n",
+            String::from_utf8_lossy(ADAPTER)
+        );
+        let mut reader = Reader::from_reader(Cursor::new(input.as_bytes()));
+        let mut clean = Writer::from_writer(Vec::new());
+        let mut contaminated = Writer::from_writer(Vec::new());
+        let stats = screen
+            .screen(&mut reader, &mut clean, Some(&mut contaminated))
+            .unwrap();
+
+        assert_eq!(
+            stats,
+            ScreenStats {
+                reads: 2,
+                flagged: 1,
+                per_contaminant: vec![1],
+            }
+        );
+        assert!(clean.into_inner().starts_with(b">r1\n"));
+        assert!(contaminated.into_inner().starts_with(b">r2\n"));
+    }
+}
@@ -0,0 +1,333 @@
+//! A corpus of tricky FASTA/FASTQ inputs with their expected records, and a harness to check
+//! any `RecordSource` implementation against it.
+//!
+//! ```
+//! use fastseq::Reader;
+//! use fastseq::corpus::*;
+//!
+//! let n = check_round_trip(|data| Reader::from_reader(std::io::Cursor::new(data))).unwrap();
+//! assert_eq!(n, fixtures().len());
+//! ```
+
+use crate::reader::*;
+use crate::seq::*;
+use crate::writer::*;
+
+/// An owned record expected from a fixture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpectedRecord {
+    pub id: Vec<u8>,
+    pub desc: Vec<u8>,
+    pub seq: Vec<u8>,
+    pub qual: Option<Vec<u8>>,
+}
+
+impl ExpectedRecord {
+    fn from_seq(seq: &Seq) -> Self {
+        Self {
+            id: seq.id.to_vec(),
+            desc: seq.desc.to_vec(),
+            seq: seq.seq.to_vec(),
+            qual: seq.qual.map(|q| q.to_vec()),
+        }
+    }
+
+    fn as_seq(&self) -> Seq<'_> {
+        Seq {
+            id: &self.id,
+            desc: &self.desc,
+            seq: &self.seq,
+            qual: self.qual.as_deref(),
+        }
+    }
+}
+
+/// An input file and the records it should be parsed into.
+#[derive(Debug, Clone)]
+pub struct Fixture {
+    pub name: &'static str,
+    /// Raw bytes of the file, gzip-compressed if `gzip` is true.
+    pub data: Vec<u8>,
+    pub gzip: bool,
+    pub expected: Vec<ExpectedRecord>,
+}
+
+fn rec(id: &str, desc: &str, seq: &str, qual: Option<&str>) -> ExpectedRecord {
+    ExpectedRecord {
+        id: id.as_bytes().to_vec(),
+        desc: desc.as_bytes().to_vec(),
+        seq: seq.as_bytes().to_vec(),
+        qual: qual.map(|q| q.as_bytes().to_vec()),
+    }
+}
+
+fn fixture(name: &'static str, data: &str, expected: Vec<ExpectedRecord>) -> Fixture {
+    Fixture {
+        name,
+        data: data.as_bytes().to_vec(),
+        gzip: false,
+        expected,
+    }
+}
+
+// two gzip members: ">r1 first member\nACGT\n" and ">r2\nTTGG\nCC\n"
+const MULTI_MEMBER_GZIP: [u8; 74] = [
+    0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xb3, 0x2b, 0x32, 0x54, 0x48, 0xcb,
+    0x2c, 0x2a, 0x2e, 0x51, 0xc8, 0x4d, 0xcd, 0x4d, 0x4a, 0x2d, 0xe2, 0x72, 0x74, 0x76, 0x0f, 0xe1,
+    0x02, 0x00, 0xc0, 0x7f, 0xed, 0xe7, 0x16, 0x00, 0x00, 0x00, 0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x02, 0x03, 0xb3, 0x2b, 0x32, 0xe2, 0x0a, 0x09, 0x71, 0x77, 0xe7, 0x72, 0x76, 0xe6,
+    0x02, 0x00, 0xec, 0xb8, 0x33, 0x01, 0x0c, 0x00, 0x00, 0x00,
+];
+
+/// Returns all fixtures of the corpus.
+pub fn fixtures() -> Vec<Fixture> {
+    let long_id = "x".repeat(100_000);
+    let long_desc = "d".repeat(5_000);
+    let long_seq = "ACGTN".repeat(40_000);
+    let wrapped: String = long_seq
+        .as_bytes()
+        .chunks(60)
+        .map(|line| format!("{}\n", String::from_utf8_lossy(line)))
+        .collect();
+    let long_qual = "I".repeat(long_seq.len());
+
+    vec![
+        fixture("empty_file", "", vec![]),
+        fixture("blank_lines_only", "\n\r\n\n", vec![]),
+        fixture(
+            "fasta_single_line",
+            ">a desc 1\nACGT\n>b\nGGCC\n",
+            vec![rec("a", "desc 1", "ACGT", None), rec("b", "", "GGCC", None)],
+        ),
+        fixture(
+            "fasta_multi_line",
+            ">a\nAC\nGT\nN\n>b\nA\n",
+            vec![rec("a", "", "ACGTN", None), rec("b", "", "A", None)],
+        ),
+        fixture(
+            "fasta_crlf",
+            ">a x\r\nAC\r\nGT\r\n>b\r\nTT\r\n",
+            vec![rec("a", "x", "ACGT", None), rec("b", "", "TT", None)],
+        ),
+        fixture(
+            "fasta_no_trailing_newline",
+            ">a\nAC\nGT",
+            vec![rec("a", "", "ACGT", None)],
+        ),
+        fixture(
+            "fasta_header_only_no_newline",
+            ">a",
+            vec![rec("a", "", "", None)],
+        ),
+        fixture(
+            "fasta_empty_seqs",
+            ">a\n>b\nACGT\n>c\n",
+            vec![
+                rec("a", "", "", None),
+                rec("b", "", "ACGT", None),
+                rec("c", "", "", None),
+            ],
+        ),
+        fixture(
+            "fasta_blank_lines",
+            "\n\n>a\nAC\n\nGT\n\n>b\nT\n\n",
+            vec![rec("a", "", "ACGT", None), rec("b", "", "T", None)],
+        ),
+        fixture(
+            "fasta_tabs_and_spaces_in_header",
+            ">a\t \tdesc with  spaces\nAC\n",
+            vec![rec("a", "desc with  spaces", "AC", None)],
+        ),
+        fixture(
+            "fasta_lowercase_and_iupac",
+            ">a\nacgtRYKMnn-*\n",
+            vec![rec("a", "", "acgtRYKMnn-*", None)],
+        ),
+        Fixture {
+            name: "fasta_long_header",
+            data: format!(">{long_id} {long_desc}\nACGT\n").into_bytes(),
+            gzip: false,
+            expected: vec![rec(&long_id, &long_desc, "ACGT", None)],
+        },
+        Fixture {
+            name: "fasta_long_wrapped_seq",
+            data: format!(">long\n{wrapped}>short\nA\n").into_bytes(),
+            gzip: false,
+            expected: vec![
+                rec("long", "", &long_seq, None),
+                rec("short", "", "A", None),
+            ],
+        },
+        fixture(
+            "fastq_standard",
+            "@r1 d\nACGT\n+\nIIII\n@r2\nAC\n+r2\n#!\n",
+            vec![
+                rec("r1", "d", "ACGT", Some("IIII")),
+                rec("r2", "", "AC", Some("#!")),
+            ],
+        ),
+        fixture(
+            "fastq_crlf",
+            "@r1 d\r\nACGT\r\n+\r\nIIII\r\n@r2\r\nAC\r\n+\r\nII\r\n",
+            vec![
+                rec("r1", "d", "ACGT", Some("IIII")),
+                rec("r2", "", "AC", Some("II")),
+            ],
+        ),
+        fixture(
+            "fastq_no_trailing_newline",
+            "@r\nAC\n+\nII",
+            vec![rec("r", "", "AC", Some("II"))],
+        ),
+        fixture(
+            "fastq_qual_starting_with_at",
+            "@r1\nAC\n+\n@I\n@r2\nGG\n+\nI@\n",
+            vec![
+                rec("r1", "", "AC", Some("@I")),
+                rec("r2", "", "GG", Some("I@")),
+            ],
+        ),
+        fixture(
+            "fastq_empty_seq",
+            "@r1\n\n+\n\n@r2\nA\n+\nI\n",
+            vec![rec("r1", "", "", Some("")), rec("r2", "", "A", Some("I"))],
+        ),
+        fixture(
+            "fastq_multi_line",
+            "@r1\nAC\nGT\n+ r1\nII\nII\n@r2\nA\n+\nI\n",
+            vec![
+                rec("r1", "", "ACGT", Some("IIII")),
+                rec("r2", "", "A", Some("I")),
+            ],
+        ),
+        Fixture {
+            name: "fastq_long_read",
+            data: format!("@long\n{long_seq}\n+\n{long_qual}\n").into_bytes(),
+            gzip: false,
+            expected: vec![rec("long", "", &long_seq, Some(&long_qual))],
+        },
+        Fixture {
+            name: "gzip_multi_member",
+            data: MULTI_MEMBER_GZIP.to_vec(),
+            gzip: true,
+            expected: vec![
+                rec("r1", "first member", "ACGT", None),
+                rec("r2", "", "TTGGCC", None),
+            ],
+        },
+    ]
+}
+
+/// Parses every fixture with a record source created by `open`,
+/// returns the number of fixtures checked or a message describing the first mismatch.
+/// `open` is responsible for decompressing gzip fixtures.
+pub fn check_record_source<S, F>(mut open: F) -> Result<usize, String>
+where
+    S: RecordSource,
+    F: FnMut(&Fixture) -> S,
+{
+    let fixtures = fixtures();
+    for f in &fixtures {
+        let mut source = open(f);
+        check_records(f.name, &mut source, &f.expected)?;
+    }
+    Ok(fixtures.len())
+}
+
+/// Writes the expected records of every fixture with `Writer`, parses the output with a record
+/// source created by `open` from the written bytes, and checks that records survive the round trip.
+pub fn check_round_trip<S, F>(mut open: F) -> Result<usize, String>
+where
+    S: RecordSource,
+    F: FnMut(Vec<u8>) -> S,
+{
+    let fixtures = fixtures();
+    for f in &fixtures {
+        for line_width in [0, 60] {
+            let mut writer = Writer::from_writer(Vec::new());
+            writer.set_line_width(line_width);
+            for r in &f.expected {
+                writer
+                    .write(&r.as_seq())
+                    .map_err(|e| format!("{}: failed to write: {e}", f.name))?;
+            }
+            let mut source = open(writer.into_inner());
+            check_records(f.name, &mut source, &f.expected)?;
+        }
+    }
+    Ok(fixtures.len())
+}
+
+fn check_records<S: RecordSource>(
+    name: &str,
+    source: &mut S,
+    expected: &[ExpectedRecord],
+) -> Result<(), String> {
+    let mut i = 0;
+    while let Some(res) = source.next_record() {
+        let seq = res.map_err(|e| format!("{name}: record {}: {e}", i + 1))?;
+        let Some(exp) = expected.get(i) else {
+            return Err(format!(
+                "{name}: {} records expected, got more",
+                expected.len()
+            ));
+        };
+        let got = ExpectedRecord::from_seq(&seq);
+        if &got != exp {
+            return Err(format!(
+                "{name}: record {}: expected {:?}, got {:?}",
+                i + 1,
+                exp.as_seq(),
+                got.as_seq()
+            ));
+        }
+        i += 1;
+    }
+    if i != expected.len() {
+        return Err(format!(
+            "{name}: {} records expected, got {i}",
+            expected.len()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xopen::*;
+    use std::io::{BufRead, Cursor};
+
+    #[test]
+    fn test_reader_passes_corpus() {
+        let n = check_record_source(|f: &Fixture| {
+            let r: Box<dyn BufRead> = match xopen_reader(Cursor::new(f.data.clone()), 4096) {
+                Ok(r) => r,
+                // gzip fixtures can't be opened without the gzip feature, read them as empty
+                Err(_) if f.gzip && !cfg!(feature = "gzip") => Box::new(std::io::empty()),
+                Err(e) => panic!("{}: {e}", f.name),
+            };
+            Reader::from_reader(r)
+        });
+        if cfg!(feature = "gzip") {
+            assert_eq!(n, Ok(fixtures().len()));
+        } else {
+            assert!(n.unwrap_err().starts_with("gzip_multi_member"));
+        }
+    }
+
+    #[test]
+    fn test_reader_round_trip() {
+        let n = check_round_trip(|data| Reader::from_reader(Cursor::new(data))).unwrap();
+        assert_eq!(n, fixtures().len());
+    }
+
+    #[test]
+    fn test_harness_reports_mismatches() {
+        let err = check_record_source(|_: &Fixture| {
+            Reader::from_reader(Cursor::new(b">a\nACGT\n".to_vec()))
+        })
+        .unwrap_err();
+        assert!(err.starts_with("empty_file: 0 records expected"), "{err}");
+    }
+}
@@ -0,0 +1,198 @@
+use crate::errors::*;
+use crate::kmer::*;
+use crate::reader::*;
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+
+/// A set of canonical k-mers of reads, for marking reference positions covered by any read.
+///
+/// This is an alignment-free coverage sanity check: a reference position is covered
+/// if any k-mer containing it occurs in the reads, on either strand.
+/// All distinct k-mers of the reads are kept in memory.
+pub struct KmerMask {
+    k: usize,
+    kmers: HashSet<u64>,
+}
+
+/// Statistics of scanning a reference against a k-mer mask.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MaskStats {
+    /// Number of reference records.
+    pub records: u64,
+    /// Number of reference bases.
+    pub bases: u64,
+    /// Number of bases not covered by any read k-mer.
+    pub uncovered_bases: u64,
+    /// Number of uncovered regions written.
+    pub regions: u64,
+}
+
+impl KmerMask {
+    /// Creates an empty mask with k-mer size k (1..=32).
+    pub fn new(k: usize) -> Self {
+        assert!(
+            (1..=MAX_K).contains(&k),
+            "k should be in the range of 1..=32"
+        );
+        Self {
+            k,
+            kmers: HashSet::new(),
+        }
+    }
+
+    /// Returns the k-mer size.
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// Returns the number of distinct k-mers.
+    pub fn len(&self) -> usize {
+        self.kmers.len()
+    }
+
+    /// Returns whether the mask is empty.
+    pub fn is_empty(&self) -> bool {
+        self.kmers.is_empty()
+    }
+
+    /// Adds k-mers of a read.
+    pub fn add_seq(&mut self, seq: &[u8]) {
+        self.kmers
+            .extend(KmerIter::new(seq, self.k, true).map(|(_, code)| code));
+    }
+
+    /// Adds k-mers of all reads of the reader, and returns the number of reads.
+    pub fn add_reader<R: BufRead>(&mut self, reader: &mut Reader<R>) -> Result<u64, FastxErr> {
+        let mut n = 0;
+        while let Some(res) = reader.next() {
+            self.add_seq(res?.seq);
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    /// Marks positions of a reference sequence covered by k-mers of the mask.
+    pub fn covered(&self, seq: &[u8], mask: &mut Vec<bool>) {
+        mask.clear();
+        mask.resize(seq.len(), false);
+        // end of the covered run so far, to avoid re-marking overlapping k-mers
+        let mut covered_to = 0;
+        for (pos, code) in KmerIter::new(seq, self.k, true) {
+            if self.kmers.contains(&code) {
+                let start = pos.max(covered_to);
+                let end = pos + self.k;
+                mask[start..end].fill(true);
+                covered_to = end;
+            }
+        }
+    }
+
+    /// Returns 0-based, end-exclusive regions of a reference sequence not covered by the mask.
+    pub fn uncovered_regions(&self, seq: &[u8]) -> Vec<(usize, usize)> {
+        let mut mask = Vec::new();
+        self.covered(seq, &mut mask);
+        uncovered_runs(&mask)
+    }
+}
+
+// runs of false in the mask
+fn uncovered_runs(mask: &[bool]) -> Vec<(usize, usize)> {
+    let mut regions = Vec::new();
+    let mut start = None;
+    for (i, &c) in mask.iter().enumerate() {
+        match (c, start) {
+            (false, None) => start = Some(i),
+            (true, Some(s)) => {
+                regions.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        regions.push((s, mask.len()));
+    }
+    regions
+}
+
+/// Scans all records of the reference, and writes regions not covered by the mask,
+/// of at least `min_len` bases, in BED format.
+pub fn write_uncovered_bed<R: BufRead, W: Write>(
+    mask: &KmerMask,
+    reference: &mut Reader<R>,
+    w: &mut W,
+    min_len: usize,
+) -> Result<MaskStats, FastxErr> {
+    let mut stats = MaskStats::default();
+    let mut covered = Vec::new();
+    while let Some(res) = reference.next() {
+        let record = res?;
+        stats.records += 1;
+        stats.bases += record.seq.len() as u64;
+        mask.covered(record.seq, &mut covered);
+        for (start, end) in uncovered_runs(&covered) {
+            stats.uncovered_bases += (end - start) as u64;
+            if end - start < min_len {
+                continue;
+            }
+            stats.regions += 1;
+            w.write_all(record.id)?;
+            writeln!(w, "\t{start}\t{end}")?;
+        }
+    }
+    w.flush()?;
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::seq::revcomp;
+    use std::io::Cursor;
+
+    const REF: &[u8] = b"ACGTTGCAAGGCTTACCGATNNNNGGATCCATGCAAGTC";
+
+    #[test]
+    fn test_uncovered_regions() {
+        let mut mask = KmerMask::new(7);
+        mask.add_seq(&REF[0..10]);
+        mask.add_seq(&revcomp(&REF[24..32])); // the other strand
+        assert_eq!(mask.uncovered_regions(REF), vec![(10, 24), (32, 39)]);
+        assert_eq!(KmerMask::new(7).uncovered_regions(b"ACG"), vec![(0, 3)]);
+    }
+
+    #[test]
+    fn test_write_uncovered_bed() {
+        let mut mask = KmerMask::new(7);
+        let reads = format!(
+            "@r1\n{0}\n+\n{1}\n",
+            String::from_utf8_lossy(&REF[..12]),
+            "I".repeat(12)
+        );
+        assert_eq!(
+            mask.add_reader(&mut Reader::from_reader(Cursor::new(reads)))
+                .unwrap(),
+            1
+        );
+
+        let reference = format!(">chr1\n{}\n>chr2\nACGTTGCA\n", String::from_utf8_lossy(REF));
+        let mut bed = Vec::new();
+        let stats = write_uncovered_bed(
+            &mask,
+            &mut Reader::from_reader(Cursor::new(reference)),
+            &mut bed,
+            10,
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(bed).unwrap(), "chr1\t12\t39\n");
+        assert_eq!(
+            stats,
+            MaskStats {
+                records: 2,
+                bases: 47,
+                uncovered_bases: 27,
+                regions: 1,
+            }
+        );
+    }
+}
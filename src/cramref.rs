@@ -0,0 +1,219 @@
+use crate::digest::*;
+use crate::errors::*;
+use crate::reader::*;
+use crate::seq::*;
+use crate::writer::*;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Options of exporting a reference for CRAM.
+#[derive(Debug, Clone)]
+pub struct CramRefOptions {
+    /// Line width of sequences, samtools and htslib default to 60. 0 for no wrapping.
+    pub line_width: usize,
+    /// Whether to keep descriptions in headers.
+    pub keep_desc: bool,
+    /// Directory of an htslib MD5 cache (`REF_CACHE`), sequences are stored as
+    /// `<dir>/<md5[0..2]>/<md5[2..4]>/<md5[4..]>`.
+    pub md5_cache: Option<PathBuf>,
+}
+
+impl Default for CramRefOptions {
+    fn default() -> Self {
+        Self {
+            line_width: 60,
+            keep_desc: true,
+            md5_cache: None,
+        }
+    }
+}
+
+/// An entry of a FASTA index (.fai).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaiEntry {
+    pub name: Vec<u8>,
+    pub length: u64,
+    /// Offset of the first base in the file.
+    pub offset: u64,
+    /// Number of bases per line.
+    pub line_bases: u64,
+    /// Number of bytes per line, including the newline.
+    pub line_width: u64,
+}
+
+/// Writes FASTA index entries in the .fai format.
+pub fn write_fai<W: Write>(entries: &[FaiEntry], w: &mut W) -> io::Result<()> {
+    for e in entries {
+        w.write_all(&e.name)?;
+        writeln!(
+            w,
+            "\t{}\t{}\t{}\t{}",
+            e.length, e.offset, e.line_bases, e.line_width
+        )?;
+    }
+    Ok(())
+}
+
+/// Result of exporting a reference.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CramRefReport {
+    /// Index entries of the written records.
+    pub fai: Vec<FaiEntry>,
+    /// Hexadecimal MD5 (the M5 tag of @SQ lines) of each record.
+    pub md5s: Vec<String>,
+}
+
+/// Writes a reference normalized the way htslib expects for CRAM: sequences are uppercased,
+/// bytes outside of `!`..`~` are removed, and lines are wrapped at a fixed width without blank lines,
+/// so the MD5s of the written sequences are the M5 tags computed by samtools.
+/// Returns the .fai entries and MD5s of all records; sequences are also stored
+/// in the MD5 cache if one is given.
+pub fn export_cram_reference<R: BufRead, W: Write>(
+    reader: &mut Reader<R>,
+    writer: &mut W,
+    opts: &CramRefOptions,
+) -> Result<CramRefReport, FastxErr> {
+    let mut report = CramRefReport::default();
+    let mut offset = 0u64;
+    let mut seq_buf = Vec::with_capacity(1 << 20);
+    let mut header = Vec::with_capacity(256);
+    while let Some(res) = reader.next() {
+        let record = res?;
+        seq_buf.clear();
+        seq_buf.extend(
+            record
+                .seq
+                .iter()
+                .filter(|b| b.is_ascii_graphic())
+                .map(|b| b.to_ascii_uppercase()),
+        );
+        let seq = Seq {
+            id: record.id,
+            desc: if opts.keep_desc { record.desc } else { &[] },
+            seq: &seq_buf,
+            qual: None,
+        };
+
+        header.clear();
+        write_fasta(&mut header, &Seq { seq: &[], ..seq }, 0)?;
+        writer.write_all(&header)?;
+        offset += header.len() as u64;
+
+        let len = seq_buf.len() as u64;
+        let line_bases = if opts.line_width == 0 {
+            len
+        } else {
+            len.min(opts.line_width as u64)
+        };
+        report.fai.push(FaiEntry {
+            name: seq.id.to_vec(),
+            length: len,
+            offset,
+            line_bases,
+            line_width: line_bases + 1,
+        });
+        if line_bases > 0 {
+            for line in seq_buf.chunks(line_bases as usize) {
+                writer.write_all(line)?;
+                writer.write_all(b"\n")?;
+                offset += line.len() as u64 + 1;
+            }
+        }
+
+        let md5 = to_hex(&md5(&seq_buf));
+        if let Some(dir) = &opts.md5_cache {
+            store_in_md5_cache(dir, &md5, &seq_buf)?;
+        }
+        report.md5s.push(md5);
+    }
+    writer.flush()?;
+    Ok(report)
+}
+
+/// Returns the path of a sequence in an htslib MD5 cache.
+pub fn md5_cache_path(dir: &Path, md5: &str) -> PathBuf {
+    dir.join(&md5[..2]).join(&md5[2..4]).join(&md5[4..])
+}
+
+// stores a sequence in the MD5 cache unless it already exists
+fn store_in_md5_cache(dir: &Path, md5: &str, seq: &[u8]) -> io::Result<()> {
+    let path = md5_cache_path(dir, md5);
+    if path.is_file() {
+        return Ok(());
+    }
+    fs::create_dir_all(path.parent().unwrap())?;
+    let part = path.with_extension("part");
+    fs::write(&part, seq)?;
+    fs::rename(&part, &path)
+}
+
+/// Exports a reference file for CRAM to `output`, and writes its index to `<output>.fai`.
+pub fn prepare_cram_reference(
+    input: &str,
+    output: &str,
+    opts: &CramRefOptions,
+) -> Result<CramRefReport, FastxErr> {
+    let mut reader = Reader::new(input)?;
+    let mut writer = BufWriter::new(File::create(output)?);
+    let report = export_cram_reference(&mut reader, &mut writer, opts)?;
+
+    let mut fai = BufWriter::new(File::create(format!("{output}.fai"))?);
+    write_fai(&report.fai, &mut fai)?;
+    fai.flush()?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_export_cram_reference() {
+        let input = ">chr1 desc\nacgtn\n\nACG T\n>chr2\nGGGGGGG\n>empty\n";
+        let opts = CramRefOptions {
+            line_width: 4,
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        let report = export_cram_reference(
+            &mut Reader::from_reader(Cursor::new(input.as_bytes())),
+            &mut out,
+            &opts,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            ">chr1 desc\nACGT\nNACG\nT\n>chr2\nGGGG\nGGG\n>empty\n"
+        );
+
+        let mut fai = Vec::new();
+        write_fai(&report.fai, &mut fai).unwrap();
+        assert_eq!(
+            String::from_utf8(fai).unwrap(),
+            "chr1\t9\t11\t4\t5\nchr2\t7\t29\t4\t5\nempty\t0\t45\t0\t1\n"
+        );
+        assert_eq!(report.md5s[1], to_hex(&md5(b"GGGGGGG")));
+    }
+
+    #[test]
+    fn test_md5_cache() {
+        let dir = std::env::temp_dir().join(format!("fastseq_md5_cache_{}", std::process::id()));
+        let opts = CramRefOptions {
+            md5_cache: Some(dir.clone()),
+            ..Default::default()
+        };
+        let report = export_cram_reference(
+            &mut Reader::from_reader(Cursor::new(b">s\nacgt\n".to_vec())),
+            &mut io::sink(),
+            &opts,
+        )
+        .unwrap();
+        assert_eq!(report.md5s[0], "f1f8f4bf413b16ad135722aa4591043e");
+        let path = md5_cache_path(&dir, &report.md5s[0]);
+        assert!(path.ends_with("f1/f8/f4bf413b16ad135722aa4591043e"));
+        assert_eq!(fs::read(&path).unwrap(), b"ACGT");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
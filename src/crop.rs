@@ -0,0 +1,144 @@
+use crate::errors::*;
+use crate::reader::*;
+use crate::seq::*;
+use crate::writer::*;
+use std::io::{BufRead, Write};
+
+/// Strategies of cropping sequences longer than a maximum length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CropStrategy {
+    /// Keeps the 5' end.
+    Head,
+    /// Keeps the 3' end.
+    Tail,
+    /// Keeps the center, with the extra base (if any) removed from the 3' end.
+    Center,
+    /// Splits into consecutive pieces of the maximum length, the last one may be shorter.
+    Split,
+}
+
+/// Returns `start..end` ranges of a sequence of length `len` kept by the strategy.
+/// Sequences not longer than `max_len` are kept as is, `max_len` of 0 means no limit.
+pub fn crop(len: usize, max_len: usize, strategy: CropStrategy) -> Vec<(usize, usize)> {
+    if max_len == 0 || len <= max_len {
+        return vec![(0, len)];
+    }
+    match strategy {
+        CropStrategy::Head => vec![(0, max_len)],
+        CropStrategy::Tail => vec![(len - max_len, len)],
+        CropStrategy::Center => {
+            let start = (len - max_len) / 2;
+            vec![(start, start + max_len)]
+        }
+        CropStrategy::Split => (0..len)
+            .step_by(max_len)
+            .map(|s| (s, (s + max_len).min(len)))
+            .collect(),
+    }
+}
+
+/// Statistics of cropping.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CropStats {
+    /// Number of input reads.
+    pub reads: u64,
+    /// Number of reads longer than the maximum length.
+    pub cropped: u64,
+    /// Number of output records.
+    pub records_out: u64,
+    /// Number of bases removed.
+    pub bases_removed: u64,
+}
+
+/// Enforces a maximum length on all records of the reader, writing results to the writer.
+/// With `CropStrategy::Split`, pieces of a split record are named `<id>_<n>` numbered from 1,
+/// records that need no splitting keep their IDs.
+pub fn crop_records<R: BufRead, W: Write>(
+    reader: &mut Reader<R>,
+    writer: &mut Writer<W>,
+    max_len: usize,
+    strategy: CropStrategy,
+) -> Result<CropStats, FastxErr> {
+    let mut stats = CropStats::default();
+    let mut id = Vec::with_capacity(256);
+    while let Some(res) = reader.next() {
+        let seq = res?;
+        stats.reads += 1;
+        if max_len > 0 && seq.len() > max_len {
+            stats.cropped += 1;
+        }
+        let ranges = crop(seq.len(), max_len, strategy);
+        let split = ranges.len() > 1;
+        for (n, &(start, end)) in ranges.iter().enumerate() {
+            let id = if split {
+                id.clear();
+                id.extend_from_slice(seq.id);
+                id.push(b'_');
+                id.extend_from_slice((n + 1).to_string().as_bytes());
+                &id[..]
+            } else {
+                seq.id
+            };
+            writer.write(&Seq {
+                id,
+                desc: seq.desc,
+                seq: &seq.seq[start..end],
+                qual: seq.qual.map(|q| &q[start..end]),
+            })?;
+            stats.records_out += 1;
+        }
+        let kept: usize = ranges.iter().map(|(s, e)| e - s).sum();
+        stats.bases_removed += (seq.len() - kept) as u64;
+    }
+    writer.flush()?;
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_crop_strategies() {
+        assert_eq!(crop(10, 4, CropStrategy::Head), vec![(0, 4)]);
+        assert_eq!(crop(10, 4, CropStrategy::Tail), vec![(6, 10)]);
+        assert_eq!(crop(10, 4, CropStrategy::Center), vec![(3, 7)]);
+        assert_eq!(crop(9, 4, CropStrategy::Center), vec![(2, 6)]);
+        assert_eq!(
+            crop(10, 4, CropStrategy::Split),
+            vec![(0, 4), (4, 8), (8, 10)]
+        );
+        assert_eq!(crop(4, 4, CropStrategy::Split), vec![(0, 4)]);
+        assert_eq!(crop(10, 0, CropStrategy::Head), vec![(0, 10)]);
+        assert_eq!(crop(0, 4, CropStrategy::Tail), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn test_crop_records() {
+        let input = "@r1 d\nACGTA\n+\nABCDE\n@r2\nAC\n+\nAB\n";
+
+        let mut reader = Reader::from_reader(Cursor::new(input.as_bytes()));
+        let mut writer = Writer::from_writer(Vec::new());
+        let stats = crop_records(&mut reader, &mut writer, 3, CropStrategy::Tail).unwrap();
+        assert_eq!(
+            stats,
+            CropStats {
+                reads: 2,
+                cropped: 1,
+                records_out: 2,
+                bases_removed: 2,
+            }
+        );
+        assert_eq!(writer.into_inner(), b"@r1 d\nGTA\n+\nCDE\n@r2\nAC\n+\nAB\n");
+
+        let mut reader = Reader::from_reader(Cursor::new(input.as_bytes()));
+        let mut writer = Writer::from_writer(Vec::new());
+        let stats = crop_records(&mut reader, &mut writer, 3, CropStrategy::Split).unwrap();
+        assert_eq!((stats.records_out, stats.bases_removed), (3, 0));
+        assert_eq!(
+            writer.into_inner(),
+            b"@r1_1 d\nACG\n+\nABC\n@r1_2 d\nTA\n+\nDE\n@r2\nAC\n+\nAB\n"
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use crate::errors::*;
+use crate::kmer::*;
+use crate::reader::*;
+use crate::seq::*;
+use crate::sketch::*;
+use crate::writer::*;
+use std::io::{BufRead, Write};
+
+/// Statistics of a digital normalization run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiginormStats {
+    /// Number of reads processed.
+    pub reads: u64,
+    /// Number of reads kept.
+    pub kept: u64,
+}
+
+/// Digital normalization (khmer-style): a read is kept only if the median abundance of its k-mers,
+/// counted in a count-min sketch over the reads kept so far, is below the target coverage.
+/// Memory is bounded by the sketch size.
+pub struct DigitalNormalizer {
+    k: usize,
+    cutoff: u32,
+    sketch: CountMinSketch,
+    kmers: Vec<u64>,
+    counts: Vec<u32>,
+}
+
+impl DigitalNormalizer {
+    /// Creates a normalizer with k-mer size k (1..=32), target coverage `cutoff`,
+    /// and a count-min sketch of `depth` rows of `width` counters.
+    pub fn new(k: usize, cutoff: u32, width: usize, depth: usize) -> Self {
+        assert!(
+            (1..=MAX_K).contains(&k),
+            "k should be in the range of 1..=32"
+        );
+        Self {
+            k,
+            cutoff,
+            sketch: CountMinSketch::new(width, depth),
+            kmers: Vec::with_capacity(1024),
+            counts: Vec::with_capacity(1024),
+        }
+    }
+
+    /// Returns the median k-mer abundance of a sequence, 0 for sequences without k-mers.
+    pub fn median_abundance(&mut self, seq: &[u8]) -> u32 {
+        self.kmers.clear();
+        self.kmers
+            .extend(KmerIter::new(seq, self.k, true).map(|(_, code)| code));
+        self.median_of_kmers()
+    }
+
+    fn median_of_kmers(&mut self) -> u32 {
+        if self.kmers.is_empty() {
+            return 0;
+        }
+        self.counts.clear();
+        self.counts
+            .extend(self.kmers.iter().map(|&code| self.sketch.estimate(code)));
+        let mid = self.counts.len() / 2;
+        *self.counts.select_nth_unstable(mid).1
+    }
+
+    /// Decides whether to keep a read, and counts its k-mers if kept.
+    /// Reads without any k-mer are always kept.
+    pub fn keep(&mut self, seq: &Seq) -> bool {
+        self.kmers.clear();
+        self.kmers
+            .extend(KmerIter::new(seq.seq, self.k, true).map(|(_, code)| code));
+        if self.median_of_kmers() >= self.cutoff {
+            return false;
+        }
+        for &code in &self.kmers {
+            self.sketch.add(code);
+        }
+        true
+    }
+
+    /// Normalizes all reads of the reader, writing kept reads to the writer.
+    pub fn normalize<R: BufRead, W: Write>(
+        &mut self,
+        reader: &mut Reader<R>,
+        writer: &mut Writer<W>,
+    ) -> Result<DiginormStats, FastxErr> {
+        let mut stats = DiginormStats::default();
+        while let Some(res) = reader.next() {
+            let seq = res?;
+            stats.reads += 1;
+            if self.keep(&seq) {
+                stats.kept += 1;
+                writer.write(&seq)?;
+            }
+        }
+        writer.flush()?;
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_diginorm_caps_coverage() {
+        let read = ">r\nGAGTTTTATCGCTTCCATGACGCAGAAGTTAACACTTTCGG\n";
+        let other = ">o\nAGATCGGAAGAGCACACGTCTGAACTCCAGTCA\n";
+        let input = format!("{}{}", read.repeat(20), other);
+
+        let mut dn = DigitalNormalizer::new(17, 5, 1 << 16, 4);
+        let mut reader = Reader::from_reader(Cursor::new(input.as_bytes()));
+        let mut writer = Writer::from_writer(Vec::new());
+        let stats = dn.normalize(&mut reader, &mut writer).unwrap();
+
+        assert_eq!(stats, DiginormStats { reads: 21, kept: 6 });
+        assert!(writer.into_inner().ends_with(other.as_bytes()));
+    }
+
+    #[test]
+    fn test_diginorm_keeps_short_reads() {
+        let mut dn = DigitalNormalizer::new(17, 1, 1024, 2);
+        let seq = Seq {
+            id: b"r",
+            desc: b"",
+            seq: b"ACGT",
+            qual: None,
+        };
+        assert!(dn.keep(&seq));
+        assert!(dn.keep(&seq));
+        assert_eq!(dn.median_abundance(b"ACGT"), 0);
+    }
+}
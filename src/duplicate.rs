@@ -0,0 +1,465 @@
+use crate::errors::*;
+use crate::hash::*;
+use crate::reader::*;
+use crate::seq::*;
+use crate::writer::*;
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+/// Options of duplicate rate estimation.
+#[derive(Debug, Clone, Copy)]
+pub struct DupOptions {
+    /// Length of the read prefix compared, reads shorter than it are compared entirely.
+    pub prefix_len: usize,
+    /// Maximum number of distinct prefixes kept, the sampling rate is halved when it is exceeded.
+    pub capacity: usize,
+    /// Maximum distance in pixels between clusters of optical duplicates on a tile.
+    /// 100 suits unpatterned flow cells, and 2500 patterned ones.
+    pub optical_distance: u32,
+    /// Hasher of read prefixes.
+    pub hasher: RecordHasher,
+}
+
+impl Default for DupOptions {
+    fn default() -> Self {
+        Self {
+            prefix_len: 50,
+            capacity: 1 << 20,
+            optical_distance: 100,
+            hasher: RecordHasher::default(),
+        }
+    }
+}
+
+/// Estimated duplication levels.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DupReport {
+    /// Number of reads seen.
+    pub reads: u64,
+    /// Fraction of prefixes sampled, all copies of a prefix are sampled or not together.
+    pub sampling_fraction: f64,
+    /// Number of sampled reads.
+    pub sampled_reads: u64,
+    /// Number of distinct prefixes among sampled reads.
+    pub sampled_distinct: u64,
+    /// Fraction of reads that are duplicates of an earlier read.
+    pub duplicate_rate: f64,
+    /// Fraction of reads that are optical duplicates, i.e., close on the same tile.
+    /// Only reads with Illumina coordinates in IDs are considered.
+    pub optical_duplicate_rate: f64,
+    /// Fraction of reads that are other (PCR) duplicates.
+    pub pcr_duplicate_rate: f64,
+    /// Estimated number of distinct prefixes in the whole input.
+    pub estimated_distinct: u64,
+    /// Number of sampled distinct prefixes by number of copies: 1, 2, ..., 9, and >=10.
+    pub levels: [u64; 10],
+}
+
+#[derive(Default)]
+struct Entry {
+    count: u32,
+    positions: Vec<[u32; 4]>, // lane, tile, x, y
+}
+
+/// A streaming duplicate rate estimator for huge inputs.
+///
+/// Read prefixes are hashed, and only those whose hashes fall below a threshold are counted.
+/// The threshold is lowered whenever more than `capacity` distinct prefixes are kept,
+/// so memory is bounded while every copy of a sampled prefix is still counted.
+pub struct DupEstimator {
+    opts: DupOptions,
+    shift: u32, // sample hashes with at least `shift` leading zeros
+    reads: u64,
+    entries: HashMap<u64, Entry>,
+}
+
+impl DupEstimator {
+    pub fn new(opts: DupOptions) -> Self {
+        Self {
+            opts,
+            shift: 0,
+            reads: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Adds a read.
+    pub fn add(&mut self, seq: &Seq) {
+        self.reads += 1;
+        let prefix = &seq.seq[..seq.len().min(self.opts.prefix_len)];
+        let hash = self.opts.hasher.hash(prefix);
+        if hash.leading_zeros() < self.shift {
+            return;
+        }
+        let entry = self.entries.entry(hash).or_default();
+        entry.count += 1;
+        if let Some(pos) = illumina_position(seq.id) {
+            entry.positions.push(pos);
+        }
+        if self.entries.len() > self.opts.capacity.max(1) {
+            self.shift += 1;
+            let shift = self.shift;
+            self.entries.retain(|h, _| h.leading_zeros() >= shift);
+        }
+    }
+
+    /// Adds all reads of the reader.
+    pub fn add_reader<R: BufRead>(&mut self, reader: &mut Reader<R>) -> Result<(), FastxErr> {
+        while let Some(res) = reader.next() {
+            self.add(&res?);
+        }
+        Ok(())
+    }
+
+    /// Returns the estimated duplication levels.
+    pub fn report(&self) -> DupReport {
+        let mut report = DupReport {
+            reads: self.reads,
+            sampling_fraction: 1.0 / (1u64 << self.shift.min(63)) as f64,
+            sampled_distinct: self.entries.len() as u64,
+            ..Default::default()
+        };
+        let mut optical = 0u64;
+        let mut duplicates = 0u64;
+        for e in self.entries.values() {
+            report.sampled_reads += e.count as u64;
+            duplicates += e.count as u64 - 1;
+            report.levels[(e.count as usize).min(10) - 1] += 1;
+            optical += self.count_optical(&e.positions);
+        }
+        if report.sampled_reads > 0 {
+            let n = report.sampled_reads as f64;
+            report.duplicate_rate = duplicates as f64 / n;
+            report.optical_duplicate_rate = optical as f64 / n;
+            report.pcr_duplicate_rate = (duplicates - optical) as f64 / n;
+        }
+        report.estimated_distinct = self.entries.len() as u64 * (1u64 << self.shift.min(63));
+        report
+    }
+
+    // number of positions close to an earlier position on the same tile
+    fn count_optical(&self, positions: &[[u32; 4]]) -> u64 {
+        let d = self.opts.optical_distance;
+        let mut n = 0;
+        for (i, p) in positions.iter().enumerate() {
+            if positions[..i].iter().any(|q| {
+                p[0] == q[0] && p[1] == q[1] && p[2].abs_diff(q[2]) <= d && p[3].abs_diff(q[3]) <= d
+            }) {
+                n += 1;
+            }
+        }
+        n
+    }
+}
+
+/// Policies of choosing the record kept among duplicates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DedupPolicy {
+    /// The first record, the default.
+    #[default]
+    First,
+    /// The record with the longest sequence, the first one of ties.
+    Longest,
+    /// The record with the highest mean quality (Phred+33), the first one of ties.
+    /// FASTA records all have a mean quality of 0.
+    HighestQuality,
+}
+
+/// Options of deduplication.
+#[derive(Debug, Clone, Copy)]
+pub struct DedupOptions {
+    /// Parts of records compared, the sequence by default.
+    pub key: HashKey,
+    /// The policy of choosing the record kept.
+    pub policy: DedupPolicy,
+    /// Hasher of keys, records with the same hash value are duplicates.
+    pub hasher: RecordHasher,
+}
+
+impl Default for DedupOptions {
+    fn default() -> Self {
+        Self {
+            key: HashKey::Seq,
+            policy: DedupPolicy::First,
+            hasher: RecordHasher::default(),
+        }
+    }
+}
+
+/// Statistics of deduplication.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DedupStats {
+    pub records: u64,
+    pub kept: u64,
+    pub discarded: u64,
+}
+
+// a group of duplicates: the record kept, its score, and (index, ID) of discarded records
+struct DupGroup {
+    kept: SeqOwned,
+    index: u64,
+    score: f64,
+    discarded: Vec<(u64, Vec<u8>)>,
+}
+
+/// Removes duplicate records, keeping one record of every group of duplicates by the policy.
+/// Kept records are written in the order of the first records of their groups, and a
+/// tab-delimited line of the discarded and the kept ID is written to `discarded` for every
+/// discarded record. With `DedupPolicy::First`, records are streamed and only IDs are kept in
+/// memory, other policies keep a record of every group in memory until the end.
+pub fn deduplicate<R: BufRead, W: Write, D: Write>(
+    reader: &mut Reader<R>,
+    opts: &DedupOptions,
+    writer: &mut Writer<W>,
+    discarded: &mut D,
+) -> Result<DedupStats, FastxErr> {
+    let mut stats = DedupStats::default();
+    if opts.policy == DedupPolicy::First {
+        let mut kept: HashMap<u64, Vec<u8>> = HashMap::new();
+        while let Some(res) = reader.next() {
+            let seq = res?;
+            stats.records += 1;
+            let hash = opts.hasher.hash_record(&seq, opts.key);
+            if let Some(id) = kept.get(&hash) {
+                stats.discarded += 1;
+                discarded.write_all(seq.id)?;
+                discarded.write_all(b"\t")?;
+                discarded.write_all(id)?;
+                discarded.write_all(b"\n")?;
+            } else {
+                stats.kept += 1;
+                kept.insert(hash, seq.id.to_vec());
+                writer.write(&seq)?;
+            }
+        }
+        writer.flush()?;
+        discarded.flush()?;
+        return Ok(stats);
+    }
+
+    let mut index: HashMap<u64, usize> = HashMap::new();
+    let mut groups: Vec<DupGroup> = Vec::new();
+    while let Some(res) = reader.next() {
+        let seq = res?;
+        let i = stats.records;
+        stats.records += 1;
+        let score = match opts.policy {
+            DedupPolicy::Longest => seq.len() as f64,
+            _ => mean_qual(&seq),
+        };
+        let hash = opts.hasher.hash_record(&seq, opts.key);
+        match index.get(&hash) {
+            Some(&g) => {
+                let group = &mut groups[g];
+                if score > group.score {
+                    let old = std::mem::replace(&mut group.kept, seq.to_owned());
+                    group.discarded.push((group.index, old.id));
+                    (group.index, group.score) = (i, score);
+                } else {
+                    group.discarded.push((i, seq.id.to_vec()));
+                }
+            }
+            None => {
+                index.insert(hash, groups.len());
+                groups.push(DupGroup {
+                    kept: seq.to_owned(),
+                    index: i,
+                    score,
+                    discarded: Vec::new(),
+                });
+            }
+        }
+    }
+
+    for group in &mut groups {
+        writer.write(&group.kept.as_seq())?;
+        stats.kept += 1;
+        group.discarded.sort_unstable_by_key(|d| d.0);
+        for (_, id) in &group.discarded {
+            stats.discarded += 1;
+            discarded.write_all(id)?;
+            discarded.write_all(b"\t")?;
+            discarded.write_all(&group.kept.id)?;
+            discarded.write_all(b"\n")?;
+        }
+    }
+    writer.flush()?;
+    discarded.flush()?;
+    Ok(stats)
+}
+
+// mean Phred+33 quality, 0 for FASTA records
+fn mean_qual(seq: &Seq) -> f64 {
+    match seq.qual {
+        Some(q) if !q.is_empty() => {
+            let sum: u64 = q.iter().map(|&b| b.saturating_sub(33) as u64).sum();
+            sum as f64 / q.len() as f64
+        }
+        _ => 0.0,
+    }
+}
+
+// parses lane, tile, x and y from Illumina read IDs:
+// "instrument:run:flowcell:lane:tile:x:y" (Casava 1.8+) or "instrument:lane:tile:x:y#index/1"
+fn illumina_position(id: &[u8]) -> Option<[u32; 4]> {
+    let fields: Vec<&[u8]> = id.split(|&b| b == b':').collect();
+    let coords = match fields.len() {
+        7 => &fields[3..7],
+        5 => &fields[1..5],
+        _ => return None,
+    };
+    let mut pos = [0u32; 4];
+    for (p, f) in pos.iter_mut().zip(coords) {
+        // y of the old format is followed by "#index/1"
+        let digits = f.iter().take_while(|b| b.is_ascii_digit()).count();
+        if digits == 0 {
+            return None;
+        }
+        *p = std::str::from_utf8(&f[..digits]).ok()?.parse().ok()?;
+    }
+    Some(pos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_illumina_position() {
+        assert_eq!(
+            illumina_position(b"A00123:8:HABCD:2:1101:1000:2000"),
+            Some([2, 1101, 1000, 2000])
+        );
+        assert_eq!(
+            illumina_position(b"HWUSI-EAS100R:6:73:941:1973#0/1"),
+            Some([6, 73, 941, 1973])
+        );
+        assert_eq!(illumina_position(b"read1"), None);
+    }
+
+    #[test]
+    fn test_dup_estimator_exact() {
+        let input = "\
+@A:1:F:1:1101:100:100
+ACGTACGTAA
++
+IIIIIIIIII
+@A:1:F:1:1101:150:120
+ACGTACGTAA
++
+IIIIIIIIII
+@A:1:F:1:1102:150:120
+ACGTACGTAA
++
+IIIIIIIIII
+@A:1:F:1:1101:5000:5000
+CCCCCCCCCC
++
+IIIIIIIIII
+";
+        let mut est = DupEstimator::new(DupOptions::default());
+        est.add_reader(&mut Reader::from_reader(Cursor::new(input.as_bytes())))
+            .unwrap();
+        let report = est.report();
+        assert_eq!(report.reads, 4);
+        assert_eq!(report.sampling_fraction, 1.0);
+        assert_eq!((report.sampled_reads, report.sampled_distinct), (4, 2));
+        assert_eq!(report.duplicate_rate, 0.5);
+        assert_eq!(report.optical_duplicate_rate, 0.25);
+        assert_eq!(report.pcr_duplicate_rate, 0.25);
+        assert_eq!(report.levels[0..3], [1, 0, 1]);
+    }
+
+    #[test]
+    fn test_deduplicate_policies() {
+        let input = "\
+@r1
+ACGT
++
+IIII
+@r2
+ACGT
++
+JJJJ
+@r3
+ACG
++
+!!!
+@r3 long
+ACGT
++
+JJJJ
+";
+        let dedup = |key, policy| {
+            let mut reader = Reader::from_bytes(input.as_bytes());
+            let mut writer = Writer::from_writer(Vec::new());
+            let mut discarded = Vec::new();
+            let opts = DedupOptions {
+                key,
+                policy,
+                ..Default::default()
+            };
+            let stats = deduplicate(&mut reader, &opts, &mut writer, &mut discarded).unwrap();
+            let mut ids = Vec::new();
+            let out = writer.into_inner();
+            let mut reader = Reader::from_bytes(&out);
+            reader.skip_id_parsing();
+            while let Some(res) = reader.next() {
+                ids.push(String::from_utf8_lossy(res.unwrap().id).to_string());
+            }
+            (ids, String::from_utf8(discarded).unwrap(), stats)
+        };
+
+        let (ids, discarded, stats) = dedup(HashKey::Seq, DedupPolicy::First);
+        assert_eq!(ids, ["r1", "r3"]);
+        assert_eq!(discarded, "r2\tr1\nr3\tr1\n");
+        assert_eq!(
+            stats,
+            DedupStats {
+                records: 4,
+                kept: 2,
+                discarded: 2,
+            }
+        );
+
+        // the first one of ties, reported in input order
+        let (ids, discarded, _) = dedup(HashKey::Seq, DedupPolicy::HighestQuality);
+        assert_eq!(ids, ["r2", "r3"]);
+        assert_eq!(discarded, "r1\tr2\nr3\tr2\n");
+
+        // a later and longer record replaces the kept one, staying in place
+        let (ids, discarded, _) = dedup(HashKey::Id, DedupPolicy::Longest);
+        assert_eq!(ids, ["r1", "r2", "r3 long"]);
+        assert_eq!(discarded, "r3\tr3\n");
+    }
+
+    #[test]
+    fn test_dup_estimator_sampling() {
+        let opts = DupOptions {
+            prefix_len: 12,
+            capacity: 500,
+            ..Default::default()
+        };
+        let mut est = DupEstimator::new(opts);
+        let mut rng = Rng::new(1);
+        // 4000 distinct sequences, each seen twice
+        let seqs: Vec<Vec<u8>> = (0..4000)
+            .map(|_| (0..20).map(|_| b"ACGT"[rng.below(4)]).collect())
+            .collect();
+        for s in seqs.iter().chain(seqs.iter()) {
+            est.add(&Seq {
+                id: b"r",
+                desc: b"",
+                seq: s,
+                qual: None,
+            });
+        }
+        let report = est.report();
+        assert!(report.sampling_fraction < 1.0);
+        assert!(report.sampled_distinct <= 500);
+        assert!((report.duplicate_rate - 0.5).abs() < 1e-9);
+        assert!(report.estimated_distinct > 3000 && report.estimated_distinct < 5000);
+    }
+}
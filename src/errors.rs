@@ -11,4 +11,71 @@ pub enum FastxErr {
 
     #[error("unequal lengths of sequence ({0}) and quality ({1})")]
     UnequalSeqAndQual(usize, usize),
+
+    #[error("invalid binary record stream: {0}")]
+    InvalidBinaryFormat(&'static str),
+
+    #[error("invalid 2bit file: {0}")]
+    InvalidTwoBitFormat(&'static str),
+
+    #[error("invalid sketch file: {0}")]
+    InvalidSketchFormat(&'static str),
+
+    #[error("invalid record store: {0}")]
+    InvalidRecordStore(&'static str),
+
+    #[error("unknown reference genome: {0}")]
+    UnknownReference(String),
+
+    #[error("SHA-256 checksum of {0} is {2}, expected {1}")]
+    ChecksumMismatch(String, String, String),
+
+    #[error("invalid sample sheet: {0}")]
+    InvalidSampleSheet(String),
+
+    #[error("too many reads ({0}) waiting for mates, are the files sorted differently?")]
+    TooManyPending(usize),
+
+    #[error("mates out of sync: {0} and {1}")]
+    MateMismatch(String, String),
+
+    #[error("invalid BED record: {0}")]
+    InvalidBed(String),
+
+    #[error("invalid name mapping: {0}")]
+    InvalidNameMapping(String),
+
+    #[error("invalid SAM record: {0}")]
+    InvalidSamRecord(String),
+
+    #[error("invalid sequence character {1:?} at position {2} of record {0}")]
+    InvalidSeqChar(String, char, usize),
+
+    #[error("invalid quality character {1:?} at position {2} of record {0}")]
+    InvalidQualChar(String, char, usize),
+
+    #[error("separator line '+{1}' differs from the header of record {0}")]
+    SeparatorMismatch(String, String),
+
+    #[error("{0} longer than the limit of {1} bytes")]
+    SizeLimitExceeded(&'static str, usize),
+
+    #[error("blank line at line {0}")]
+    BlankLine(u64),
+}
+
+impl FastxErr {
+    /// Returns whether the error is caused by writing to a closed pipe, e.g., `| head`.
+    pub fn is_broken_pipe(&self) -> bool {
+        matches!(self, FastxErr::IOError(e) if e.kind() == io::ErrorKind::BrokenPipe)
+    }
+}
+
+/// Turns broken-pipe errors into success, so command line tools piping into `head`
+/// stop quietly like well-behaved Unix programs.
+pub fn ok_on_broken_pipe(res: Result<(), FastxErr>) -> Result<(), FastxErr> {
+    match res {
+        Err(e) if e.is_broken_pipe() => Ok(()),
+        res => res,
+    }
 }
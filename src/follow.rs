@@ -0,0 +1,244 @@
+use crate::errors::*;
+use crate::reader::*;
+use crate::util::*;
+use crate::xopen::*;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, BufRead, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Options of following growing files.
+#[derive(Debug, Clone)]
+pub struct FollowOptions {
+    /// Time to wait before checking for new data again.
+    pub poll_interval: Duration,
+    /// Stop after no new data arrives for this long, None for waiting forever.
+    pub idle_timeout: Option<Duration>,
+    /// Stop when this flag is set, e.g., from a signal handler or another thread.
+    pub stop: Option<Arc<AtomicBool>>,
+}
+
+impl Default for FollowOptions {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_millis(500),
+            idle_timeout: None,
+            stop: None,
+        }
+    }
+}
+
+impl FollowOptions {
+    fn stopped(&self) -> bool {
+        self.stop
+            .as_ref()
+            .is_some_and(|s| s.load(Ordering::Relaxed))
+    }
+}
+
+/// A Read that waits for more data at EOF, like `tail -f`.
+///
+/// It returns EOF only when the idle timeout expires or the stop flag is set,
+/// so a parser on top of it blocks in the middle of a record until the writer completes it.
+pub struct FollowRead<R: Read> {
+    inner: R,
+    opts: FollowOptions,
+}
+
+impl<R: Read> FollowRead<R> {
+    pub fn new(inner: R, opts: FollowOptions) -> Self {
+        Self { inner, opts }
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for FollowRead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let start = Instant::now();
+        loop {
+            let n = self.inner.read(buf)?;
+            if n > 0 {
+                return Ok(n);
+            }
+            if self.opts.stopped()
+                || self
+                    .opts
+                    .idle_timeout
+                    .is_some_and(|timeout| start.elapsed() >= timeout)
+            {
+                return Ok(0);
+            }
+            thread::sleep(self.opts.poll_interval);
+        }
+    }
+}
+
+/// Creates a Reader following a growing file, e.g., one being written by a sequencer.
+/// Compressed files are supported as long as they are written in a streaming way.
+pub fn follow<P: AsRef<Path>>(
+    file: P,
+    opts: FollowOptions,
+) -> Result<Reader<Box<dyn BufRead>>, FastxErr> {
+    let r = FollowRead::new(File::open(long_path(file.as_ref()))?, opts);
+    Ok(Reader::from_reader(xopen_reader(r, 65536)?))
+}
+
+/// A watcher reporting new files in a directory whose names match a glob pattern.
+///
+/// Directories are polled, files are reported once, in the order of modification time
+/// and then name. Files already present when the watcher is created are reported too,
+/// unless they are skipped with `skip_existing`.
+pub struct DirWatcher {
+    dir: PathBuf,
+    pattern: Vec<u8>,
+    seen: HashSet<PathBuf>,
+    opts: FollowOptions,
+}
+
+impl DirWatcher {
+    /// Creates a watcher of the directory, for files matching the pattern (e.g. `*.fastq.gz`).
+    pub fn new<P: Into<PathBuf>>(dir: P, pattern: &str, opts: FollowOptions) -> Self {
+        Self {
+            dir: dir.into(),
+            pattern: pattern.as_bytes().to_vec(),
+            seen: HashSet::new(),
+            opts,
+        }
+    }
+
+    /// Marks all existing files as seen.
+    pub fn skip_existing(&mut self) -> io::Result<()> {
+        self.poll()?;
+        Ok(())
+    }
+
+    /// Returns new matching files since the last call, without waiting.
+    pub fn poll(&mut self) -> io::Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if self.seen.contains(&path)
+                || !entry.file_type()?.is_file()
+                || !glob_match(&self.pattern, entry.file_name().as_encoded_bytes())
+            {
+                continue;
+            }
+            let mtime = entry.metadata()?.modified()?;
+            files.push((mtime, path));
+        }
+        files.sort();
+        Ok(files
+            .into_iter()
+            .map(|(_, path)| {
+                self.seen.insert(path.clone());
+                path
+            })
+            .collect())
+    }
+
+    /// Waits for new matching files, returns an empty list if the watcher is stopped
+    /// or no new files appear within the idle timeout.
+    pub fn wait(&mut self) -> io::Result<Vec<PathBuf>> {
+        let start = Instant::now();
+        loop {
+            let files = self.poll()?;
+            if !files.is_empty()
+                || self.opts.stopped()
+                || self
+                    .opts
+                    .idle_timeout
+                    .is_some_and(|timeout| start.elapsed() >= timeout)
+            {
+                return Ok(files);
+            }
+            thread::sleep(self.opts.poll_interval);
+        }
+    }
+
+    /// Returns the watched directory.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn tmp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("fastseq_follow_{}_{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_follow_growing_file() {
+        let dir = tmp_dir("file");
+        let path = dir.join("reads.fq");
+        let mut f = File::create(&path).unwrap();
+        f.write_all(b"@r1\nACGT\n+\nII").unwrap();
+        f.flush().unwrap();
+
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            f.write_all(b"II\n@r2\nAC").unwrap();
+            f.flush().unwrap();
+            thread::sleep(Duration::from_millis(50));
+            f.write_all(b"\n+\nII\n").unwrap();
+        });
+
+        let opts = FollowOptions {
+            poll_interval: Duration::from_millis(5),
+            idle_timeout: Some(Duration::from_millis(300)),
+            stop: None,
+        };
+        let mut reader = follow(&path, opts).unwrap();
+        let mut ids = Vec::new();
+        while let Some(res) = reader.next() {
+            ids.push(res.unwrap().id.to_vec());
+        }
+        writer.join().unwrap();
+        assert_eq!(ids, vec![b"r1".to_vec(), b"r2".to_vec()]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_dir_watcher() {
+        let dir = tmp_dir("dir");
+        fs::write(dir.join("old.fastq"), b"").unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut watcher = DirWatcher::new(
+            &dir,
+            "*.fastq",
+            FollowOptions {
+                poll_interval: Duration::from_millis(5),
+                idle_timeout: None,
+                stop: Some(stop.clone()),
+            },
+        );
+        watcher.skip_existing().unwrap();
+
+        fs::write(dir.join("new.fastq"), b"").unwrap();
+        fs::write(dir.join("new.txt"), b"").unwrap();
+        assert_eq!(watcher.wait().unwrap(), vec![dir.join("new.fastq")]);
+        assert!(watcher.poll().unwrap().is_empty());
+
+        stop.store(true, Ordering::Relaxed);
+        assert!(watcher.wait().unwrap().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
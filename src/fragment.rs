@@ -0,0 +1,193 @@
+use crate::errors::*;
+use crate::reader::*;
+use crate::seq::*;
+use crate::writer::*;
+use std::io::{BufRead, Write};
+
+/// Options of splitting reads into fragments.
+#[derive(Debug, Clone)]
+pub struct FragmentOptions {
+    /// Minimum length of runs of N (case-insensitive) to split at, 0 to keep all N bases.
+    pub min_n_run: usize,
+    /// Bases with quality below this are split at, 0 to disable. Ignored for FASTA records.
+    pub min_qual: u8,
+    /// Phred offset of quality scores.
+    pub offset: u8,
+    /// Minimum length of emitted fragments.
+    pub min_len: usize,
+}
+
+impl Default for FragmentOptions {
+    fn default() -> Self {
+        Self {
+            min_n_run: 1,
+            min_qual: 0,
+            offset: 33,
+            min_len: 1,
+        }
+    }
+}
+
+/// Returns `start..end` ranges of fragments of a read that are at least `min_len` long,
+/// after removing runs of N and low-quality bases.
+pub fn fragments(seq: &Seq, opts: &FragmentOptions) -> Vec<(usize, usize)> {
+    let s = seq.seq;
+    let min_qual = match seq.qual {
+        Some(_) if opts.min_qual > 0 => opts.offset.saturating_add(opts.min_qual),
+        _ => 0,
+    };
+
+    let mut ranges = Vec::new();
+    let mut push = |start: usize, end: usize| {
+        if end > start && end - start >= opts.min_len.max(1) {
+            ranges.push((start, end));
+        }
+    };
+
+    let mut start = 0;
+    let mut i = 0;
+    while i < s.len() {
+        if min_qual > 0 && seq.qual.unwrap()[i] < min_qual {
+            push(start, i);
+            i += 1;
+            start = i;
+            continue;
+        }
+        if opts.min_n_run > 0 && (s[i] | 0x20) == b'n' {
+            let mut j = i + 1;
+            while j < s.len() && (s[j] | 0x20) == b'n' {
+                j += 1;
+            }
+            if j - i >= opts.min_n_run {
+                push(start, i);
+                start = j;
+            }
+            i = j;
+            continue;
+        }
+        i += 1;
+    }
+    push(start, s.len());
+    ranges
+}
+
+/// Statistics of fragmenting.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FragmentStats {
+    /// Number of input reads.
+    pub reads: u64,
+    /// Number of emitted fragments.
+    pub fragments: u64,
+    /// Number of input bases.
+    pub bases_in: u64,
+    /// Number of output bases.
+    pub bases_out: u64,
+}
+
+/// Splits all reads of the reader and writes fragments to the writer.
+/// Fragments are named `<id>_<n>`, numbered from 1 for every read, and keep the description.
+pub fn fragment_records<R: BufRead, W: Write>(
+    reader: &mut Reader<R>,
+    writer: &mut Writer<W>,
+    opts: &FragmentOptions,
+) -> Result<FragmentStats, FastxErr> {
+    let mut stats = FragmentStats::default();
+    let mut id = Vec::with_capacity(256);
+    while let Some(res) = reader.next() {
+        let seq = res?;
+        stats.reads += 1;
+        stats.bases_in += seq.len() as u64;
+        for (n, (start, end)) in fragments(&seq, opts).into_iter().enumerate() {
+            id.clear();
+            id.extend_from_slice(seq.id);
+            id.push(b'_');
+            id.extend_from_slice((n + 1).to_string().as_bytes());
+            writer.write(&Seq {
+                id: &id,
+                desc: seq.desc,
+                seq: &seq.seq[start..end],
+                qual: seq.qual.map(|q| &q[start..end]),
+            })?;
+            stats.fragments += 1;
+            stats.bases_out += (end - start) as u64;
+        }
+    }
+    writer.flush()?;
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn a_read<'a>(seq: &'a [u8], qual: Option<&'a [u8]>) -> Seq<'a> {
+        Seq {
+            id: b"r",
+            desc: b"",
+            seq,
+            qual,
+        }
+    }
+
+    #[test]
+    fn test_fragments_at_n() {
+        let opts = FragmentOptions::default();
+        assert_eq!(
+            fragments(&a_read(b"NACGTnnAANTTN", None), &opts),
+            vec![(1, 5), (7, 9), (10, 12)]
+        );
+        assert!(fragments(&a_read(b"NNN", None), &opts).is_empty());
+
+        let opts = FragmentOptions {
+            min_n_run: 2,
+            min_len: 3,
+            ..Default::default()
+        };
+        assert_eq!(
+            fragments(&a_read(b"ACGTNNAANTTN", None), &opts),
+            vec![(0, 4), (6, 12)]
+        );
+    }
+
+    #[test]
+    fn test_fragments_at_low_quality() {
+        let opts = FragmentOptions {
+            min_qual: 20,
+            min_len: 2,
+            ..Default::default()
+        };
+        // '5' = Q20, '4' = Q19
+        assert_eq!(
+            fragments(&a_read(b"ACGTACGT", Some(b"554555I4")), &opts),
+            vec![(0, 2), (3, 7)]
+        );
+        // quality cutoff is ignored for FASTA
+        assert_eq!(fragments(&a_read(b"ACGTACGT", None), &opts), vec![(0, 8)]);
+    }
+
+    #[test]
+    fn test_fragment_records() {
+        let input = "@r1 d\nACNNGTT\n+\nIIIIII#\n@r2\nNNNN\n+\nIIII\n";
+        let mut reader = Reader::from_reader(Cursor::new(input.as_bytes()));
+        let mut writer = Writer::from_writer(Vec::new());
+        let opts = FragmentOptions {
+            min_qual: 10,
+            ..Default::default()
+        };
+        let stats = fragment_records(&mut reader, &mut writer, &opts).unwrap();
+        assert_eq!(
+            stats,
+            FragmentStats {
+                reads: 2,
+                fragments: 2,
+                bases_in: 11,
+                bases_out: 4,
+            }
+        );
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            "@r1_1 d\nAC\n+\nII\n@r1_2 d\nGT\n+\nII\n"
+        );
+    }
+}
@@ -0,0 +1,264 @@
+use crate::seq::*;
+use crate::util::*;
+use twox_hash::XxHash3_64;
+
+/// Hash functions of records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HashAlgorithm {
+    /// 64-bit XXH3, the default.
+    #[default]
+    Xxh3,
+    /// 64-bit FNV-1a, with the seed xored into the offset basis.
+    Fnv1a,
+}
+
+/// Parts of a record to hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HashKey {
+    /// The sequence ID, the default.
+    #[default]
+    Id,
+    /// The sequence.
+    Seq,
+    /// The ID and the sequence.
+    IdSeq,
+}
+
+/// A seeded hasher of records, used to assign records to shards and to compare records
+/// in deduplication.
+///
+/// Hash values are part of the API: for the same algorithm, seed and input, they are identical
+/// across runs, platforms and versions of this crate, so sharding is reproducible.
+/// Changing them is a breaking change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RecordHasher {
+    algorithm: HashAlgorithm,
+    seed: u64,
+}
+
+impl RecordHasher {
+    /// Creates a hasher.
+    pub fn new(algorithm: HashAlgorithm, seed: u64) -> Self {
+        Self { algorithm, seed }
+    }
+
+    /// Creates an XXH3 hasher with the seed.
+    pub fn with_seed(seed: u64) -> Self {
+        Self::new(HashAlgorithm::Xxh3, seed)
+    }
+
+    /// Returns the hash algorithm.
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    /// Returns the seed.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Returns the hash value of the data.
+    #[inline]
+    pub fn hash(&self, data: &[u8]) -> u64 {
+        match self.algorithm {
+            HashAlgorithm::Xxh3 => XxHash3_64::oneshot_with_seed(self.seed, data),
+            HashAlgorithm::Fnv1a => fnv1a64_with_basis(FNV1A64_BASIS ^ self.seed, data),
+        }
+    }
+
+    /// Returns the hash value of a part of a record.
+    /// For `HashKey::IdSeq`, the ID and the sequence are joined with a newline.
+    pub fn hash_record(&self, seq: &Seq, key: HashKey) -> u64 {
+        match key {
+            HashKey::Id => self.hash(seq.id),
+            HashKey::Seq => self.hash(seq.seq),
+            HashKey::IdSeq => {
+                let mut data = Vec::with_capacity(seq.id.len() + seq.seq.len() + 1);
+                data.extend_from_slice(seq.id);
+                data.push(b'\n');
+                data.extend_from_slice(seq.seq);
+                self.hash(&data)
+            }
+        }
+    }
+
+    /// Returns the shard (0..n) of the data, n must be positive.
+    ///
+    /// The hash value is mapped to shards by multiplication instead of modulo,
+    /// i.e., `(hash * n) >> 64`, so high bits decide the shard.
+    #[inline]
+    pub fn shard(&self, data: &[u8], n: usize) -> usize {
+        assert!(n > 0, "the number of shards should be positive");
+        ((self.hash(data) as u128 * n as u128) >> 64) as usize
+    }
+
+    /// Returns the shard (0..n) of a record.
+    pub fn shard_record(&self, seq: &Seq, key: HashKey, n: usize) -> usize {
+        assert!(n > 0, "the number of shards should be positive");
+        ((self.hash_record(seq, key) as u128 * n as u128) >> 64) as usize
+    }
+}
+
+/// A Bloom filter of byte strings, with a bounded memory and a configurable false-positive rate.
+///
+/// Positions are derived from two XXH3 hashes by double hashing (Kirsch and Mitzenmacher).
+/// There are no false negatives.
+#[derive(Debug, Clone)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    n_bits: u64,
+    n_hashes: u32,
+    items: u64,
+}
+
+const BLOOM_SEED2: u64 = 0x9e37_79b9_7f4a_7c15;
+
+impl BloomFilter {
+    /// Creates a filter sized for `expected_items` items at the false-positive rate,
+    /// which is clamped to [1e-9, 0.5], with NaN taken as 0.5.
+    pub fn with_rate(expected_items: usize, fp_rate: f64) -> Self {
+        let fp_rate = if fp_rate.is_nan() {
+            0.5
+        } else {
+            fp_rate.clamp(1e-9, 0.5)
+        };
+        let n = expected_items.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let n_bits = (-n * fp_rate.ln() / (ln2 * ln2)).ceil().max(64.0) as u64;
+        let n_hashes = ((n_bits as f64 / n) * ln2).round().clamp(1.0, 30.0) as u32;
+        Self::new(n_bits, n_hashes)
+    }
+
+    /// Creates a filter of `n_bits` bits (rounded up to a multiple of 64) and `n_hashes` hashes.
+    pub fn new(n_bits: u64, n_hashes: u32) -> Self {
+        let words = n_bits.max(1).div_ceil(64);
+        Self {
+            bits: vec![0; words as usize],
+            n_bits: words * 64,
+            n_hashes: n_hashes.max(1),
+            items: 0,
+        }
+    }
+
+    /// Returns the number of bits.
+    pub fn num_bits(&self) -> u64 {
+        self.n_bits
+    }
+
+    /// Returns the number of hash functions.
+    pub fn num_hashes(&self) -> u32 {
+        self.n_hashes
+    }
+
+    /// Returns the number of inserted items, including duplicates.
+    pub fn len(&self) -> u64 {
+        self.items
+    }
+
+    /// Returns whether no item was inserted.
+    pub fn is_empty(&self) -> bool {
+        self.items == 0
+    }
+
+    /// Returns the memory used by the bits in bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        self.bits.len() * 8
+    }
+
+    /// Returns the expected false-positive rate for the inserted items.
+    pub fn estimated_fp_rate(&self) -> f64 {
+        let k = self.n_hashes as f64;
+        (1.0 - (-k * self.items as f64 / self.n_bits as f64).exp()).powf(k)
+    }
+
+    #[inline]
+    fn positions(&self, data: &[u8]) -> impl Iterator<Item = u64> + use<> {
+        let h1 = XxHash3_64::oneshot_with_seed(0, data);
+        let h2 = XxHash3_64::oneshot_with_seed(BLOOM_SEED2, data) | 1;
+        let n_bits = self.n_bits;
+        (0..self.n_hashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % n_bits)
+    }
+
+    /// Inserts an item.
+    pub fn insert(&mut self, data: &[u8]) {
+        for p in self.positions(data) {
+            self.bits[(p >> 6) as usize] |= 1 << (p & 63);
+        }
+        self.items += 1;
+    }
+
+    /// Returns whether the item may have been inserted. False positives are possible.
+    #[inline]
+    pub fn contains(&self, data: &[u8]) -> bool {
+        self.positions(data)
+            .all(|p| self.bits[(p >> 6) as usize] & (1 << (p & 63)) != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_hasher_stable() {
+        // reference values of XXH3-64 and FNV-1a 64
+        let h = RecordHasher::default();
+        assert_eq!(h.hash(b""), 0x2d06800538d394c2);
+        assert_eq!(h.hash(b"a"), 0xe6c632b61e964e1f);
+        let fnv = RecordHasher::new(HashAlgorithm::Fnv1a, 0);
+        assert_eq!(fnv.hash(b"a"), 0xaf63dc4c8601ec8c);
+
+        let seeded = RecordHasher::with_seed(42);
+        assert_ne!(seeded.hash(b"a"), h.hash(b"a"));
+        assert_eq!(seeded.hash(b"a"), RecordHasher::with_seed(42).hash(b"a"));
+        assert_ne!(
+            RecordHasher::new(HashAlgorithm::Fnv1a, 1).hash(b"a"),
+            fnv.hash(b"a")
+        );
+    }
+
+    #[test]
+    fn test_record_hasher_shard() {
+        let seq = Seq {
+            id: b"read1",
+            desc: b"",
+            seq: b"ACGT",
+            qual: None,
+        };
+        let h = RecordHasher::with_seed(7);
+        assert_eq!(h.hash_record(&seq, HashKey::Id), h.hash(b"read1"));
+        assert_eq!(h.hash_record(&seq, HashKey::IdSeq), h.hash(b"read1\nACGT"));
+        assert_eq!(h.shard_record(&seq, HashKey::Seq, 1), 0);
+
+        let mut counts = [0usize; 4];
+        for i in 0..4000 {
+            counts[h.shard(format!("read{i}").as_bytes(), 4)] += 1;
+        }
+        assert!(counts.iter().all(|&c| c > 800 && c < 1200));
+    }
+
+    #[test]
+    fn test_bloom_filter() {
+        let mut bloom = BloomFilter::with_rate(1000, 0.01);
+        assert!(bloom.is_empty());
+        assert_eq!((bloom.num_bits(), bloom.num_hashes()), (9600, 7));
+        for i in 0..1000 {
+            bloom.insert(format!("read{i}").as_bytes());
+        }
+        assert_eq!(bloom.len(), 1000);
+        for i in 0..1000 {
+            assert!(bloom.contains(format!("read{i}").as_bytes()));
+        }
+        let fp = (0..10000)
+            .filter(|i| bloom.contains(format!("other{i}").as_bytes()))
+            .count();
+        assert!(fp < 300, "too many false positives: {fp}");
+        assert!(bloom.estimated_fp_rate() < 0.02);
+        assert_eq!(bloom.size_in_bytes(), 1200);
+
+        // out-of-range rates are clamped
+        for (fp_rate, n_bits) in [(0.0, 4352), (-1.0, 4352), (1.5, 192), (f64::NAN, 192)] {
+            assert_eq!(BloomFilter::with_rate(100, fp_rate).num_bits(), n_bits);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use std::borrow::Cow;
+
+// NCBI database tags of FASTA identifiers like "ref|NM_000546.6|" and "sp|P04637|P53_HUMAN",
+// followed by the accession
+const DB_TAGS: [&[u8]; 12] = [
+    b"gb", b"emb", b"dbj", b"ref", b"sp", b"tr", b"pir", b"prf", b"pdb", b"tpg", b"tpe", b"tpd",
+];
+
+/// Rules of normalizing sequence IDs before matching them, so records from different databases
+/// are matched despite cosmetic differences like "gi|123|ref|NM_000546.6|" and "nm_000546".
+///
+/// Rules are applied in the order of the fields. The default applies no rule.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IdNormalizer {
+    /// Strips "lcl|" and "gi|<number>|" prefixes, and reduces NCBI database identifiers like
+    /// "ref|NM_000546.6|" and "sp|P04637|P53_HUMAN" to the accessions.
+    pub strip_db_prefix: bool,
+    /// Strips version suffixes like ".1" of accession-shaped IDs, i.e., letters, an optional
+    /// underscore, and digits, like "NM_000546.6" and "P04637.2". SRA run accessions
+    /// ("SRR"/"ERR"/"DRR") are left intact, their ".N" suffixes number reads.
+    pub strip_version: bool,
+    /// Folds IDs to lowercase.
+    pub ignore_case: bool,
+}
+
+impl IdNormalizer {
+    /// Returns a normalizer applying all rules.
+    pub fn all() -> Self {
+        Self {
+            strip_db_prefix: true,
+            strip_version: true,
+            ignore_case: true,
+        }
+    }
+
+    /// Returns the normalizer without version stripping, for matching mates, whose IDs may
+    /// differ only in ".N" suffixes of different reads.
+    pub(crate) fn for_mates(&self) -> Self {
+        Self {
+            strip_version: false,
+            ..*self
+        }
+    }
+
+    /// Returns whether no rule is applied.
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Returns the normalized ID, which is only copied when case folding changes it.
+    pub fn normalize<'a>(&self, id: &'a [u8]) -> Cow<'a, [u8]> {
+        let id = self.strip(id);
+        if self.ignore_case && id.iter().any(|b| b.is_ascii_uppercase()) {
+            Cow::Owned(id.to_ascii_lowercase())
+        } else {
+            Cow::Borrowed(id)
+        }
+    }
+
+    /// Returns whether two IDs are the same after normalization, without copying them.
+    pub fn same_id(&self, a: &[u8], b: &[u8]) -> bool {
+        let (a, b) = (self.strip(a), self.strip(b));
+        if self.ignore_case {
+            a.eq_ignore_ascii_case(b)
+        } else {
+            a == b
+        }
+    }
+
+    #[inline]
+    fn strip<'a>(&self, mut id: &'a [u8]) -> &'a [u8] {
+        if self.strip_db_prefix {
+            id = strip_db_prefix(id);
+        }
+        if self.strip_version {
+            id = strip_version(id);
+        }
+        id
+    }
+}
+
+fn strip_db_prefix(mut id: &[u8]) -> &[u8] {
+    if let Some(rest) = id.strip_prefix(b"lcl|") {
+        id = rest;
+    }
+    if let Some(rest) = id.strip_prefix(b"gi|") {
+        let n = rest.iter().take_while(|b| b.is_ascii_digit()).count();
+        match rest.get(n) {
+            None if n > 0 => return rest, // a bare GI number
+            Some(b'|') if n > 0 && n + 1 < rest.len() => id = &rest[n + 1..],
+            _ => {}
+        }
+    }
+    if let Some(i) = id.iter().position(|&b| b == b'|')
+        && DB_TAGS.contains(&&id[..i])
+    {
+        let acc = &id[i + 1..];
+        let end = acc.iter().position(|&b| b == b'|').unwrap_or(acc.len());
+        if end > 0 {
+            return &acc[..end];
+        }
+    }
+    id
+}
+
+// SRA, ENA and DDBJ run accessions, whose read IDs are "<run>.<spot>"
+const RUN_PREFIXES: [&[u8]; 3] = [b"SRR", b"ERR", b"DRR"];
+
+fn strip_version(id: &[u8]) -> &[u8] {
+    let Some(i) = id.iter().rposition(|&b| b == b'.') else {
+        return id;
+    };
+    let (acc, ver) = (&id[..i], &id[i + 1..]);
+    if ver.is_empty() || !ver.iter().all(|b| b.is_ascii_digit()) || !is_accession(acc) {
+        return id;
+    }
+    acc
+}
+
+// letters, an optional underscore, and digits
+fn is_accession(acc: &[u8]) -> bool {
+    let letters = acc.iter().take_while(|b| b.is_ascii_alphabetic()).count();
+    if letters == 0
+        || RUN_PREFIXES
+            .iter()
+            .any(|p| acc[..letters].eq_ignore_ascii_case(p))
+    {
+        return false;
+    }
+    let digits = match acc.get(letters) {
+        Some(b'_') => &acc[letters + 1..],
+        _ => &acc[letters..],
+    };
+    !digits.is_empty() && digits.iter().all(|b| b.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_id_normalizer_rules() {
+        let strip_db_prefix = IdNormalizer {
+            strip_db_prefix: true,
+            ..Default::default()
+        };
+        for (id, expected) in [
+            (&b"lcl|contig_1"[..], &b"contig_1"[..]),
+            (b"gi|4507341|ref|NM_000546.6|", b"NM_000546.6"),
+            (b"gi|4507341", b"4507341"),
+            (b"sp|P04637|P53_HUMAN", b"P04637"),
+            (b"ref|NM_000546.6|", b"NM_000546.6"),
+            (b"gi|x|ref|a", b"gi|x|ref|a"),
+            (b"chr1|a", b"chr1|a"),
+            (b"ref|", b"ref|"),
+        ] {
+            assert_eq!(strip_db_prefix.normalize(id), expected);
+        }
+
+        let strip_version = IdNormalizer {
+            strip_version: true,
+            ..Default::default()
+        };
+        for (id, expected) in [
+            (&b"NM_000546.6"[..], &b"NM_000546"[..]),
+            (b"NC_000001.11", b"NC_000001"),
+            (b"P04637.2", b"P04637"),
+            (b"contig.a", b"contig.a"),
+            (b"read.", b"read."),
+            (b".1", b".1"),
+            (b"r.1", b"r.1"),
+            (b"SRR1.1", b"SRR1.1"),
+            (b"NM_.1", b"NM_.1"),
+            (b"chr1_random.1", b"chr1_random.1"),
+        ] {
+            assert_eq!(strip_version.normalize(id), expected);
+        }
+
+        let all = IdNormalizer::all();
+        assert_eq!(
+            all.normalize(b"gi|4507341|ref|NM_000546.6|"),
+            &b"nm_000546"[..]
+        );
+        assert!(matches!(all.normalize(b"nm_000546.6"), Cow::Borrowed(_)));
+        assert!(IdNormalizer::default().is_identity());
+        assert_eq!(IdNormalizer::default().normalize(b"A.1"), &b"A.1"[..]);
+    }
+
+    #[test]
+    fn test_id_normalizer_same_id() {
+        let all = IdNormalizer::all();
+        assert!(all.same_id(b"lcl|NM_000546.6", b"nm_000546"));
+        assert!(all.same_id(b"sp|P04637|P53_HUMAN", b"P04637.2"));
+        assert!(!all.same_id(b"NM_000546", b"NM_000547"));
+        assert!(!IdNormalizer::default().same_id(b"a", b"A"));
+    }
+}
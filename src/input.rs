@@ -0,0 +1,528 @@
+use crate::errors::*;
+use crate::reader::*;
+use crate::seq::*;
+use crate::util::*;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::{Component, Path, PathBuf};
+
+/// File extensions of FASTA/Q files, matched case-insensitively after removing
+/// a compression extension.
+pub const DEFAULT_EXTENSIONS: &[&str] = &[
+    "fa", "fasta", "fna", "ffn", "faa", "fas", "fsa", "fq", "fastq",
+];
+
+pub(crate) const COMPRESSION_EXTENSIONS: &[&str] = &["gz", "xz", "bz2", "zst", "zstd", "lz4"];
+
+/// Options of expanding input arguments.
+#[derive(Debug, Clone)]
+pub struct ExpandOptions {
+    /// Extensions of files to collect from directories, see `DEFAULT_EXTENSIONS`.
+    pub extensions: Vec<String>,
+    /// Whether to search directories recursively.
+    pub recursive: bool,
+}
+
+impl Default for ExpandOptions {
+    fn default() -> Self {
+        Self {
+            extensions: DEFAULT_EXTENSIONS.iter().map(|s| s.to_string()).collect(),
+            recursive: true,
+        }
+    }
+}
+
+/// Returns whether the file name has one of the extensions, optionally followed by
+/// a compression extension, e.g., `reads.fq.gz` for `fq`.
+pub fn has_extension<S: AsRef<str>>(name: &str, extensions: &[S]) -> bool {
+    let name = name.to_ascii_lowercase();
+    let mut stem = name.as_str();
+    if let Some((s, ext)) = stem.rsplit_once('.')
+        && COMPRESSION_EXTENSIONS.contains(&ext)
+    {
+        stem = s;
+    }
+    match stem.rsplit_once('.') {
+        Some((_, ext)) => extensions
+            .iter()
+            .any(|e| e.as_ref().eq_ignore_ascii_case(ext)),
+        None => false,
+    }
+}
+
+fn has_glob_chars(s: &str) -> bool {
+    s.contains(['*', '?', '['])
+}
+
+fn sort_paths(paths: &mut [PathBuf]) {
+    paths.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+}
+
+/// Expands input arguments into a list of files, in a deterministic order:
+///
+/// - `-` (STDIN) and plain file paths are kept as they are;
+/// - directories are searched (recursively by default) for files with the given extensions,
+///   skipping hidden files and directories, and the files are sorted in natural order;
+/// - glob patterns (`*`, `?`, `[...]`, in any path component) are expanded and sorted in natural order.
+///
+/// Arguments are expanded in order, and a file is only listed once.
+/// It fails if a glob pattern or a directory matches no files.
+pub fn expand_inputs<S: AsRef<str>>(
+    inputs: &[S],
+    opts: &ExpandOptions,
+) -> io::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    for input in inputs {
+        let input = input.as_ref();
+        let path = Path::new(input);
+        let mut matched = if input != "-" && path.is_dir() {
+            let mut found = Vec::new();
+            walk_dir(path, opts, &mut found)?;
+            found
+        } else if input != "-" && has_glob_chars(input) && !path.exists() {
+            expand_glob(path)?
+        } else {
+            files.push(path.to_path_buf());
+            continue;
+        };
+        if matched.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no input files found in {input}"),
+            ));
+        }
+        sort_paths(&mut matched);
+        for p in matched {
+            if !files.contains(&p) {
+                files.push(p);
+            }
+        }
+    }
+    Ok(files)
+}
+
+fn walk_dir(dir: &Path, opts: &ExpandOptions, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        // follows symbolic links
+        let meta = fs::metadata(&path)?;
+        if meta.is_dir() {
+            if opts.recursive {
+                walk_dir(&path, opts, files)?;
+            }
+        } else if has_extension(&name, &opts.extensions) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+// expands glob patterns component by component
+fn expand_glob(pattern: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut candidates = vec![PathBuf::new()];
+    let components: Vec<Component> = pattern.components().collect();
+    for (i, c) in components.iter().enumerate() {
+        let last = i == components.len() - 1;
+        let part = c.as_os_str().to_string_lossy();
+        if !matches!(c, Component::Normal(_)) || !has_glob_chars(&part) {
+            for p in candidates.iter_mut() {
+                p.push(c.as_os_str());
+            }
+            continue;
+        }
+        let mut next = Vec::new();
+        for dir in &candidates {
+            let read_dir = if dir.as_os_str().is_empty() {
+                fs::read_dir(".")
+            } else {
+                fs::read_dir(dir)
+            };
+            let Ok(entries) = read_dir else {
+                continue;
+            };
+            for entry in entries {
+                let entry = entry?;
+                let name = entry.file_name();
+                // hidden files only match patterns starting with a dot
+                if name.as_encoded_bytes().first() == Some(&b'.') && !part.starts_with('.') {
+                    continue;
+                }
+                if glob_match(part.as_bytes(), name.as_encoded_bytes()) {
+                    let path = dir.join(&name);
+                    if last || path.is_dir() {
+                        next.push(path);
+                    }
+                }
+            }
+        }
+        candidates = next;
+    }
+    candidates.retain(|p| p.is_file());
+    Ok(candidates)
+}
+
+/// How a MultiReader handles a file that cannot be opened or parsed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Returns the error and stops reading.
+    #[default]
+    Strict,
+    /// Skips the rest of the file, records the error, and continues with the next file.
+    Permissive,
+}
+
+/// A file skipped by a permissive MultiReader.
+#[derive(Debug)]
+pub struct FileFailure {
+    pub file: PathBuf,
+    /// Number of records read from the file before the error.
+    pub records: u64,
+    pub error: FastxErr,
+}
+
+impl fmt::Display for FileFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} (after {} records)",
+            self.file.display(),
+            self.error,
+            self.records
+        )
+    }
+}
+
+/// Provenance of a record of a MultiReader.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RecordOrigin {
+    /// Global 0-based ordinal of the record across all files.
+    pub ordinal: u64,
+    /// Index of the source file in `files()`.
+    pub file_index: usize,
+    /// 0-based ordinal of the record in its source file.
+    pub file_ordinal: u64,
+}
+
+/// A reader of records of multiple files in turn.
+///
+/// Every record has a stable origin, its global ordinal and the index of its source file,
+/// which only depends on the list of files and the error policy,
+/// so results of sharded processing can be merged back into the input order.
+pub struct MultiReader {
+    files: Vec<PathBuf>,
+    next_file: usize,
+    reader: Option<Reader<Box<dyn BufRead>>>,
+    policy: ErrorPolicy,
+    records_in_file: u64,
+    records: u64,
+    failures: Vec<FileFailure>,
+    stopped: bool,
+}
+
+impl MultiReader {
+    /// Creates a reader of the files, `-` for STDIN.
+    pub fn new(files: Vec<PathBuf>) -> Self {
+        Self {
+            files,
+            next_file: 0,
+            reader: None,
+            policy: ErrorPolicy::default(),
+            records_in_file: 0,
+            records: 0,
+            failures: Vec::new(),
+            stopped: false,
+        }
+    }
+
+    /// Creates a reader of files expanded from input arguments with `expand_inputs`.
+    pub fn from_inputs<S: AsRef<str>>(
+        inputs: &[S],
+        opts: &ExpandOptions,
+    ) -> Result<Self, FastxErr> {
+        Ok(Self::new(expand_inputs(inputs, opts)?))
+    }
+
+    /// Sets how unreadable or corrupt files are handled, `ErrorPolicy::Strict` by default.
+    pub fn set_error_policy(&mut self, policy: ErrorPolicy) {
+        self.policy = policy;
+    }
+
+    /// Returns all files.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// Returns the file being read.
+    pub fn current_file(&self) -> Option<&Path> {
+        if self.reader.is_some() {
+            Some(&self.files[self.next_file - 1])
+        } else {
+            None
+        }
+    }
+
+    /// Returns the origin of the last record returned by `next()`.
+    pub fn origin(&self) -> Option<RecordOrigin> {
+        if self.records == 0 || self.records_in_file == 0 {
+            return None;
+        }
+        Some(RecordOrigin {
+            ordinal: self.records - 1,
+            file_index: self.next_file - 1,
+            file_ordinal: self.records_in_file - 1,
+        })
+    }
+
+    /// Returns the next record with its origin.
+    pub fn next_with_origin(&mut self) -> Option<Result<(RecordOrigin, Seq<'_>), FastxErr>> {
+        match self.advance()? {
+            Ok((header_end, seq_end)) => {
+                let origin = self.origin()?;
+                let seq = self.reader.as_ref()?.record(header_end, seq_end);
+                Some(Ok((origin, seq)))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+
+    /// Returns the files skipped in the permissive mode, with their errors.
+    pub fn failures(&self) -> &[FileFailure] {
+        &self.failures
+    }
+
+    // returns None if all records of all files are read, otherwise returns Some(Ok(Seq)) or Some(Err(e))
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Result<Seq<'_>, FastxErr>> {
+        match self.advance()? {
+            Ok((header_end, seq_end)) => {
+                Some(Ok(self.reader.as_ref()?.record(header_end, seq_end)))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+
+    // reads the next record, see Reader::read_record
+    fn advance(&mut self) -> Option<Result<(usize, usize), FastxErr>> {
+        loop {
+            if self.stopped {
+                return None;
+            }
+            let reader = match self.reader.as_mut() {
+                Some(reader) => reader,
+                None => {
+                    let file = self.files.get(self.next_file)?;
+                    self.next_file += 1;
+                    self.records_in_file = 0;
+                    match Reader::new(file) {
+                        Ok(reader) => self.reader.insert(reader),
+                        Err(e) => match self.fail(e) {
+                            Some(e) => return Some(Err(e)),
+                            None => continue,
+                        },
+                    }
+                }
+            };
+            match reader.read_record() {
+                Some(Ok((header_end, seq_end))) => {
+                    self.records_in_file += 1;
+                    self.records += 1;
+                    return Some(Ok((header_end, seq_end)));
+                }
+                Some(Err(e)) => {
+                    self.reader = None;
+                    if let Some(e) = self.fail(e) {
+                        return Some(Err(e));
+                    }
+                }
+                None => self.reader = None,
+            }
+        }
+    }
+
+    // handles an error of the current file, returns the error if it should be returned
+    fn fail(&mut self, error: FastxErr) -> Option<FastxErr> {
+        match self.policy {
+            ErrorPolicy::Strict => {
+                self.stopped = true;
+                Some(error)
+            }
+            ErrorPolicy::Permissive => {
+                let failure = FileFailure {
+                    file: self.files[self.next_file - 1].clone(),
+                    records: self.records_in_file,
+                    error,
+                };
+                warn!("skipped {failure}");
+                self.failures.push(failure);
+                None
+            }
+        }
+    }
+}
+
+impl RecordSource for MultiReader {
+    fn next_record(&mut self) -> Option<Result<Seq<'_>, FastxErr>> {
+        self.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tmp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("fastseq_input_{}_{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_has_extension() {
+        let exts = DEFAULT_EXTENSIONS;
+        assert!(has_extension("a.fq.gz", exts));
+        assert!(has_extension("A.FASTA", exts));
+        assert!(has_extension("a.b.fa.zst", exts));
+        assert!(!has_extension("a.txt.gz", exts));
+        assert!(!has_extension("fq", exts));
+        assert!(!has_extension("a.gz", exts));
+    }
+
+    #[test]
+    fn test_expand_inputs() {
+        let dir = tmp_dir("expand");
+        fs::create_dir_all(dir.join("sub/deep")).unwrap();
+        fs::create_dir_all(dir.join(".hidden")).unwrap();
+        for f in [
+            "r10.fq.gz",
+            "r2.fq.gz",
+            "notes.txt",
+            "sub/r1.fa",
+            "sub/deep/x.fastq",
+            ".hidden/h.fq",
+            ".h.fq",
+        ] {
+            fs::write(dir.join(f), b"").unwrap();
+        }
+
+        let d = dir.to_string_lossy().to_string();
+        let files = expand_inputs(&[d.as_str()], &ExpandOptions::default()).unwrap();
+        assert_eq!(
+            files,
+            vec![
+                dir.join("r2.fq.gz"),
+                dir.join("r10.fq.gz"),
+                dir.join("sub/deep/x.fastq"),
+                dir.join("sub/r1.fa"),
+            ]
+        );
+
+        let opts = ExpandOptions {
+            recursive: false,
+            ..Default::default()
+        };
+        assert_eq!(expand_inputs(&[d.as_str()], &opts).unwrap().len(), 2);
+
+        // globs, plain paths and duplicates
+        let glob = format!("{d}/*/r?.f[aq]");
+        let plain = format!("{d}/notes.txt");
+        let star = format!("{d}/r*.fq.gz");
+        let files =
+            expand_inputs(&[glob.as_str(), plain.as_str(), star.as_str(), "-"], &opts).unwrap();
+        assert_eq!(
+            files,
+            vec![
+                dir.join("sub/r1.fa"),
+                dir.join("notes.txt"),
+                dir.join("r2.fq.gz"),
+                dir.join("r10.fq.gz"),
+                PathBuf::from("-"),
+            ]
+        );
+
+        let none = format!("{d}/*.bam");
+        assert!(expand_inputs(&[none.as_str()], &opts).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_multi_reader() {
+        let dir = tmp_dir("multi");
+        fs::write(dir.join("a.fa"), b">a1\nACGT\n>a2\nAC\n").unwrap();
+        fs::write(dir.join("b.fa"), b"\n\n").unwrap();
+        fs::write(dir.join("c.fq"), b"@c1\nA\n+\nI\n\n").unwrap();
+
+        let mut reader =
+            MultiReader::from_inputs(&[dir.to_string_lossy()], &ExpandOptions::default()).unwrap();
+        let mut ids = Vec::new();
+        while let Some(res) = reader.next() {
+            ids.push(String::from_utf8(res.unwrap().id.to_vec()).unwrap());
+            assert!(reader.current_file().is_some());
+        }
+        assert_eq!(ids, vec!["a1", "a2", "c1"]);
+
+        let mut reader = MultiReader::new(reader.files().to_vec());
+        let mut origins = Vec::new();
+        while let Some(res) = reader.next_with_origin() {
+            let (origin, _) = res.unwrap();
+            origins.push((origin.ordinal, origin.file_index, origin.file_ordinal));
+        }
+        assert_eq!(origins, vec![(0, 0, 0), (1, 0, 1), (2, 2, 0)]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_multi_reader_error_policy() {
+        let dir = tmp_dir("policy");
+        fs::write(
+            dir.join("bad.fq"),
+            b"@b1\nAC\n+\nII\n@b2\nACGT\n+\nIIIIII\n@b3\nA\n+\nI\n",
+        )
+        .unwrap();
+        fs::write(dir.join("good.fq"), b"@g1\nA\n+\nI\n").unwrap();
+        let files = vec![
+            dir.join("missing.fa"),
+            dir.join("bad.fq"),
+            dir.join("good.fq"),
+        ];
+
+        let mut reader = MultiReader::new(files.clone());
+        assert!(matches!(reader.next(), Some(Err(FastxErr::IOError(_)))));
+        assert!(reader.next().is_none());
+
+        let mut reader = MultiReader::new(files);
+        reader.set_error_policy(ErrorPolicy::Permissive);
+        let mut ids = Vec::new();
+        while let Some(res) = reader.next() {
+            ids.push(String::from_utf8(res.unwrap().id.to_vec()).unwrap());
+        }
+        assert_eq!(ids, vec!["b1", "g1"]);
+        assert_eq!(
+            reader.origin(),
+            Some(RecordOrigin {
+                ordinal: 1,
+                file_index: 2,
+                file_ordinal: 0,
+            })
+        );
+        let failures = reader.failures();
+        assert_eq!(failures.len(), 2);
+        assert_eq!(failures[0].file, dir.join("missing.fa"));
+        assert_eq!(failures[1].records, 1);
+        assert!(matches!(
+            failures[1].error,
+            FastxErr::UnequalSeqAndQual(4, 6)
+        ));
+        assert!(failures[1].to_string().contains("bad.fq"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
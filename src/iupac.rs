@@ -0,0 +1,180 @@
+/// 4-bit masks of IUPAC nucleotide codes (case-insensitive): A=1, C=2, G=4, T/U=8,
+/// and degenerate codes are unions of them, e.g., R=A|G and N=15. Other bytes are 0.
+pub const IUPAC_MASK: [u8; 256] = make_iupac_mask_table();
+
+const fn make_iupac_mask_table() -> [u8; 256] {
+    const CODES: [(u8, u8); 16] = [
+        (b'A', 1),
+        (b'C', 2),
+        (b'G', 4),
+        (b'T', 8),
+        (b'U', 8),
+        (b'R', 1 | 4),
+        (b'Y', 2 | 8),
+        (b'S', 2 | 4),
+        (b'W', 1 | 8),
+        (b'K', 4 | 8),
+        (b'M', 1 | 2),
+        (b'B', 2 | 4 | 8),
+        (b'D', 1 | 4 | 8),
+        (b'H', 1 | 2 | 8),
+        (b'V', 1 | 2 | 4),
+        (b'N', 15),
+    ];
+    let mut table = [0u8; 256];
+    let mut i = 0;
+    while i < CODES.len() {
+        let (b, mask) = CODES[i];
+        table[b as usize] = mask;
+        table[b.to_ascii_lowercase() as usize] = mask;
+        i += 1;
+    }
+    table
+}
+
+const BASES: [u8; 4] = [b'A', b'C', b'G', b'T'];
+
+/// Returns the concrete bases (uppercase ACGT) of an IUPAC code, empty for other bytes.
+pub fn bases_of(code: u8) -> Vec<u8> {
+    let mask = IUPAC_MASK[code as usize];
+    (0..4)
+        .filter(|i| mask & (1 << i) != 0)
+        .map(|i| BASES[i])
+        .collect()
+}
+
+/// Returns the number of concrete sequences of a degenerate sequence, saturating at u64::MAX.
+/// It is 0 if the sequence contains bytes other than IUPAC nucleotide codes.
+pub fn num_expansions(seq: &[u8]) -> u64 {
+    seq.iter().fold(1u64, |n, &b| {
+        n.saturating_mul(IUPAC_MASK[b as usize].count_ones() as u64)
+    })
+}
+
+/// Expands a degenerate sequence into all concrete sequences of uppercase ACGT,
+/// or returns None if there are more than `cap` of them. See `Expansions` for the order.
+pub fn expand(seq: &[u8], cap: usize) -> Option<Vec<Vec<u8>>> {
+    if num_expansions(seq) > cap as u64 {
+        return None;
+    }
+    Some(Expansions::new(seq).collect())
+}
+
+/// Returns whether a sequence is compatible with a degenerate one: they have the same length, and
+/// every base is one of the bases of the degenerate code at its position, e.g., `ACGT` matches
+/// `ACRN`. Degenerate bases of the sequence must be covered entirely, e.g., `R` matches `N`.
+pub fn is_compatible(degenerate: &[u8], seq: &[u8]) -> bool {
+    degenerate.len() == seq.len()
+        && degenerate.iter().zip(seq).all(|(&d, &b)| {
+            let m = IUPAC_MASK[b as usize];
+            m != 0 && m & IUPAC_MASK[d as usize] == m
+        })
+}
+
+/// An iterator of concrete sequences of a degenerate sequence, from `expand_iter`.
+///
+/// Sequences are yielded in lexicographic order, with the last position changing fastest.
+/// Nothing is yielded if the sequence contains bytes other than IUPAC nucleotide codes.
+pub struct Expansions {
+    choices: Vec<Vec<u8>>, // concrete bases of every position
+    idx: Vec<usize>,
+    done: bool,
+}
+
+impl Expansions {
+    /// Creates an iterator of concrete sequences of a degenerate sequence.
+    pub fn new(seq: &[u8]) -> Self {
+        let choices: Vec<Vec<u8>> = seq.iter().map(|&b| bases_of(b)).collect();
+        let done = choices.iter().any(|c| c.is_empty());
+        Self {
+            idx: vec![0; choices.len()],
+            choices,
+            done,
+        }
+    }
+}
+
+impl Iterator for Expansions {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let seq = self
+            .idx
+            .iter()
+            .zip(&self.choices)
+            .map(|(&i, c)| c[i])
+            .collect();
+
+        // advance the odometer
+        self.done = true;
+        for (i, c) in self.idx.iter_mut().zip(&self.choices).rev() {
+            *i += 1;
+            if *i < c.len() {
+                self.done = false;
+                break;
+            }
+            *i = 0;
+        }
+        Some(seq)
+    }
+}
+
+/// Returns an iterator of all concrete sequences of a degenerate sequence.
+pub fn expand_iter(seq: &[u8]) -> Expansions {
+    Expansions::new(seq)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_iupac_expand() {
+        assert_eq!(bases_of(b'r'), b"AG");
+        assert_eq!(bases_of(b'N'), b"ACGT");
+        assert_eq!(bases_of(b'U'), b"T");
+        assert!(bases_of(b'-').is_empty());
+
+        assert_eq!(num_expansions(b"ACRYN"), 16);
+        assert_eq!(num_expansions(b""), 1);
+        assert_eq!(num_expansions(b"AC-"), 0);
+        assert_eq!(num_expansions(&[b'N'; 40]), u64::MAX);
+
+        assert_eq!(
+            expand(b"aRy", 4).unwrap(),
+            vec![
+                b"AAC".to_vec(),
+                b"AAT".to_vec(),
+                b"AGC".to_vec(),
+                b"AGT".to_vec()
+            ]
+        );
+        assert_eq!(expand(b"ARY", 3), None);
+        assert_eq!(expand(b"", 1).unwrap(), vec![Vec::<u8>::new()]);
+
+        // lazily, even if there are too many to collect
+        let mut it = expand_iter(&[b'N'; 40]);
+        assert_eq!(it.next().unwrap(), vec![b'A'; 40]);
+        assert!(it.next().unwrap().ends_with(b"AC"));
+        assert_eq!(expand_iter(b"AXC").count(), 0);
+        assert_eq!(expand_iter(b"GTGYCAGCMGCCGCGGTAA").count(), 4);
+    }
+
+    #[test]
+    fn test_iupac_is_compatible() {
+        assert!(is_compatible(b"ACRN", b"ACGT"));
+        assert!(is_compatible(b"ACRN", b"acat"));
+        assert!(is_compatible(b"ACRN", b"ACRY"));
+        assert!(!is_compatible(b"ACRN", b"ACCT"));
+        assert!(!is_compatible(b"ACGT", b"ACGN"));
+        assert!(!is_compatible(b"ACN", b"ACGT"));
+        assert!(!is_compatible(b"ACN", b"AC-"));
+
+        // every expansion is compatible
+        let primer = b"GTGYCAGCMGCCGCGGTAA";
+        assert!(expand_iter(primer).all(|s| is_compatible(primer, &s)));
+    }
+}
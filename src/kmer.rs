@@ -0,0 +1,163 @@
+/// Maximum k-mer size supported by 2-bit encoding in a u64.
+pub const MAX_K: usize = 32;
+
+/// 2-bit codes of bases: A=0, C=1, G=2, T=3 (case-insensitive), and 4 for all other bytes.
+pub const BASE2BIT: [u8; 256] = make_base2bit_table();
+
+const fn make_base2bit_table() -> [u8; 256] {
+    let mut table = [4u8; 256];
+    table[b'A' as usize] = 0;
+    table[b'C' as usize] = 1;
+    table[b'G' as usize] = 2;
+    table[b'T' as usize] = 3;
+    table[b'a' as usize] = 0;
+    table[b'c' as usize] = 1;
+    table[b'g' as usize] = 2;
+    table[b't' as usize] = 3;
+    table
+}
+
+/// Encodes a k-mer (k <= 32) into a u64, returns None if it contains bases other than ACGT.
+pub fn encode_kmer(kmer: &[u8]) -> Option<u64> {
+    if kmer.len() > MAX_K {
+        return None;
+    }
+    let mut code = 0u64;
+    for &b in kmer {
+        let c = BASE2BIT[b as usize];
+        if c > 3 {
+            return None;
+        }
+        code = (code << 2) | c as u64;
+    }
+    Some(code)
+}
+
+/// Decodes a k-mer code into uppercase bases.
+pub fn decode_kmer(code: u64, k: usize) -> Vec<u8> {
+    const BASES: [u8; 4] = [b'A', b'C', b'G', b'T'];
+    (0..k)
+        .map(|i| BASES[((code >> (2 * (k - 1 - i))) & 3) as usize])
+        .collect()
+}
+
+/// Returns the code of the reverse complement of a k-mer code.
+pub fn revcomp_kmer(code: u64, k: usize) -> u64 {
+    let mut code = !code;
+    let mut rc = 0u64;
+    for _ in 0..k {
+        rc = (rc << 2) | (code & 3);
+        code >>= 2;
+    }
+    rc
+}
+
+/// An iterator of 2-bit encoded k-mers of a sequence, yielding (start position, code).
+///
+/// K-mers containing bases other than ACGT are skipped. For canonical k-mers,
+/// the smaller one of the k-mer and its reverse complement is returned.
+pub struct KmerIter<'a> {
+    seq: &'a [u8],
+    k: usize,
+    canonical: bool,
+    mask: u64,
+    shift: u64,
+    fwd: u64,
+    rev: u64,
+    pos: usize,
+    valid: usize, // number of consecutive valid bases ending at pos
+}
+
+impl<'a> KmerIter<'a> {
+    /// Creates a k-mer iterator, k must be in 1..=32.
+    pub fn new(seq: &'a [u8], k: usize, canonical: bool) -> Self {
+        assert!(
+            (1..=MAX_K).contains(&k),
+            "k should be in the range of 1..=32"
+        );
+        Self {
+            seq,
+            k,
+            canonical,
+            mask: if k == 32 {
+                u64::MAX
+            } else {
+                (1u64 << (2 * k)) - 1
+            },
+            shift: 2 * (k as u64 - 1),
+            fwd: 0,
+            rev: 0,
+            pos: 0,
+            valid: 0,
+        }
+    }
+}
+
+impl Iterator for KmerIter<'_> {
+    type Item = (usize, u64);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pos < self.seq.len() {
+            let c = BASE2BIT[self.seq[self.pos] as usize] as u64;
+            self.pos += 1;
+            if c > 3 {
+                self.valid = 0;
+                continue;
+            }
+            self.fwd = ((self.fwd << 2) | c) & self.mask;
+            self.rev = (self.rev >> 2) | ((3 - c) << self.shift);
+            self.valid += 1;
+            if self.valid >= self.k {
+                let code = if self.canonical {
+                    self.fwd.min(self.rev)
+                } else {
+                    self.fwd
+                };
+                return Some((self.pos - self.k, code));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() {
+        assert_eq!(encode_kmer(b"ACGT"), Some(0b00_01_10_11));
+        assert_eq!(encode_kmer(b"acgn"), None);
+        assert_eq!(decode_kmer(0b00_01_10_11, 4), b"ACGT");
+        assert_eq!(
+            revcomp_kmer(encode_kmer(b"AACG").unwrap(), 4),
+            encode_kmer(b"CGTT").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_kmer_iter() {
+        let kmers: Vec<(usize, Vec<u8>)> = KmerIter::new(b"ACGTNAcgtt", 3, false)
+            .map(|(i, c)| (i, decode_kmer(c, 3)))
+            .collect();
+        assert_eq!(
+            kmers,
+            vec![
+                (0, b"ACG".to_vec()),
+                (1, b"CGT".to_vec()),
+                (5, b"ACG".to_vec()),
+                (6, b"CGT".to_vec()),
+                (7, b"GTT".to_vec()),
+            ]
+        );
+
+        // canonical k-mers are the same on both strands
+        let fwd: Vec<u64> = KmerIter::new(b"AAACGTTG", 5, true).map(|x| x.1).collect();
+        let mut rev: Vec<u64> = KmerIter::new(b"CAACGTTT", 5, true).map(|x| x.1).collect();
+        rev.reverse();
+        assert_eq!(fwd, rev);
+
+        assert_eq!(KmerIter::new(&[b'A'; 40], 32, false).count(), 9);
+    }
+}
@@ -0,0 +1,365 @@
+use crate::budget::*;
+use crate::errors::*;
+use crate::kmer::*;
+use crate::reader::*;
+use crate::sketch::mix64;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// K-mers up to this size are counted in a dense array of 4^k u32 counters
+/// (64 MiB for k=12, 4 GiB for k=15).
+pub const DENSE_MAX_K: usize = 15;
+
+/// Minimizer size used to partition k-mers larger than `DENSE_MAX_K`.
+const MINIMIZER_SIZE: usize = 11;
+
+/// Bytes reserved from a `MemoryBudget` at a time for buffered k-mers.
+const BUDGET_CHUNK: usize = 64 << 10;
+
+static SPILL_DIR_ID: AtomicUsize = AtomicUsize::new(0);
+
+enum Counts {
+    Dense(Vec<u32>),
+    Partitioned {
+        dir: PathBuf,
+        buffers: Vec<Vec<u64>>,
+        buffered: usize,
+        max_buffered: usize,
+        spilled: bool,
+        reservation: Option<Reservation>,
+    },
+}
+
+/// An exact k-mer counter.
+///
+/// For k <= 15, k-mers are counted in a dense array. For larger k, k-mers are partitioned by
+/// their minimizers and buffered in memory, buffers are spilled to files in a temporary directory
+/// when they exceed the limit, and each partition is counted with a hash map at the end.
+pub struct ExactKmerCounter {
+    k: usize,
+    canonical: bool,
+    counts: Counts,
+}
+
+impl ExactKmerCounter {
+    /// Creates a counter for k-mers of size k (1..=32). For k > 15, at most `max_buffered` k-mers
+    /// are kept in memory in `num_partitions` partitions before spilling to a new directory in `tmp_dir`.
+    pub fn new(
+        k: usize,
+        canonical: bool,
+        tmp_dir: &Path,
+        num_partitions: usize,
+        max_buffered: usize,
+    ) -> Result<Self, FastxErr> {
+        assert!(
+            (1..=MAX_K).contains(&k),
+            "k should be in the range of 1..=32"
+        );
+        let counts = if k <= DENSE_MAX_K {
+            Counts::Dense(vec![0; 1 << (2 * k)])
+        } else {
+            let dir = tmp_dir.join(format!(
+                "fastseq-kmercount-{}-{}",
+                std::process::id(),
+                SPILL_DIR_ID.fetch_add(1, Ordering::Relaxed)
+            ));
+            fs::create_dir_all(&dir)?;
+            Counts::Partitioned {
+                dir,
+                buffers: vec![Vec::new(); num_partitions.max(1)],
+                buffered: 0,
+                max_buffered: max_buffered.max(1),
+                spilled: false,
+                reservation: None,
+            }
+        };
+        Ok(Self {
+            k,
+            canonical,
+            counts,
+        })
+    }
+
+    /// Returns the k-mer size.
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// Bounds the memory of buffered k-mers by a budget shared with other operations, in addition
+    /// to `max_buffered`. Buffers are spilled when the budget is exhausted. No effect for k <= 15.
+    pub fn set_memory_budget(&mut self, budget: &MemoryBudget) {
+        if let Counts::Partitioned { reservation, .. } = &mut self.counts {
+            *reservation = Some(budget.reservation());
+        }
+    }
+
+    /// Counts the k-mers of a sequence.
+    pub fn add_seq(&mut self, seq: &[u8]) -> Result<(), FastxErr> {
+        let k = self.k;
+        match &mut self.counts {
+            Counts::Dense(counts) => {
+                for (_, code) in KmerIter::new(seq, k, self.canonical) {
+                    counts[code as usize] = counts[code as usize].saturating_add(1);
+                }
+            }
+            Counts::Partitioned {
+                dir,
+                buffers,
+                buffered,
+                max_buffered,
+                spilled,
+                reservation,
+            } => {
+                let n = buffers.len() as u64;
+                for (_, code) in KmerIter::new(seq, k, self.canonical) {
+                    let p = (mix64(minimizer(code, k)) % n) as usize;
+                    buffers[p].push(code);
+                    *buffered += 1;
+                    let over_budget = reservation
+                        .as_mut()
+                        .is_some_and(|r| *buffered * 8 > r.bytes() && !r.try_grow(BUDGET_CHUNK));
+                    if *buffered >= *max_buffered || over_budget {
+                        spill(dir, buffers)?;
+                        *buffered = 0;
+                        *spilled = true;
+                        if let Some(r) = reservation {
+                            r.release();
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Counts the k-mers of all records of the reader, and returns the number of records.
+    pub fn add_reader<R: BufRead>(&mut self, reader: &mut Reader<R>) -> Result<u64, FastxErr> {
+        let mut n = 0u64;
+        while let Some(res) = reader.next() {
+            self.add_seq(res?.seq)?;
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    /// Calls `f` with every k-mer code and its count (>= `min_count`), in ascending order of codes,
+    /// i.e. lexicographic order of k-mers.
+    pub fn for_each<F: FnMut(u64, u32) -> Result<(), FastxErr>>(
+        &mut self,
+        min_count: u32,
+        mut f: F,
+    ) -> Result<(), FastxErr> {
+        let min_count = min_count.max(1);
+        match &mut self.counts {
+            Counts::Dense(counts) => {
+                for (code, &c) in counts.iter().enumerate() {
+                    if c >= min_count {
+                        f(code as u64, c)?;
+                    }
+                }
+            }
+            Counts::Partitioned {
+                dir,
+                buffers,
+                spilled,
+                ..
+            } => {
+                // partitions are disjoint, so sorting the merged counts of all partitions
+                // would need all of them in memory; instead sort each partition and k-way merge.
+                let mut sorted: Vec<Vec<(u64, u32)>> = Vec::with_capacity(buffers.len());
+                for (p, buf) in buffers.iter_mut().enumerate() {
+                    let mut counts: HashMap<u64, u32> = HashMap::new();
+                    if *spilled {
+                        read_partition(&partition_file(dir, p), &mut counts)?;
+                    }
+                    for &code in buf.iter() {
+                        *counts.entry(code).or_insert(0) += 1;
+                    }
+                    let mut v: Vec<(u64, u32)> = counts
+                        .into_iter()
+                        .filter(|&(_, c)| c >= min_count)
+                        .collect();
+                    v.sort_unstable();
+                    sorted.push(v);
+                }
+                let mut idx = vec![0usize; sorted.len()];
+                loop {
+                    let mut best: Option<usize> = None;
+                    for (p, v) in sorted.iter().enumerate() {
+                        if let Some(&(code, _)) = v.get(idx[p])
+                            && best.is_none_or(|b| code < sorted[b][idx[b]].0)
+                        {
+                            best = Some(p);
+                        }
+                    }
+                    let Some(p) = best else { break };
+                    let (code, c) = sorted[p][idx[p]];
+                    idx[p] += 1;
+                    f(code, c)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes k-mers with counts >= `min_count` as "KMER COUNT" lines sorted by k-mer,
+    /// the same as the column format of `jellyfish dump -c`.
+    pub fn dump<W: Write>(&mut self, w: &mut W, min_count: u32) -> Result<(), FastxErr> {
+        let k = self.k;
+        self.for_each(min_count, |code, c| {
+            w.write_all(&decode_kmer(code, k))?;
+            writeln!(w, " {c}")?;
+            Ok(())
+        })?;
+        w.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for ExactKmerCounter {
+    fn drop(&mut self) {
+        if let Counts::Partitioned { dir, .. } = &self.counts {
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+}
+
+// the smallest m-mer (by hash) of a k-mer code
+#[inline]
+fn minimizer(code: u64, k: usize) -> u64 {
+    let m = MINIMIZER_SIZE.min(k);
+    let mask = (1u64 << (2 * m)) - 1;
+    let mut best = u64::MAX;
+    let mut best_mmer = 0;
+    for i in 0..=(k - m) {
+        let mmer = (code >> (2 * i)) & mask;
+        let h = mix64(mmer);
+        if h < best {
+            best = h;
+            best_mmer = mmer;
+        }
+    }
+    best_mmer
+}
+
+fn partition_file(dir: &Path, p: usize) -> PathBuf {
+    dir.join(format!("part-{p}.bin"))
+}
+
+fn spill(dir: &Path, buffers: &mut [Vec<u64>]) -> io::Result<()> {
+    for (p, buf) in buffers.iter_mut().enumerate() {
+        if buf.is_empty() {
+            continue;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(partition_file(dir, p))?;
+        let mut w = BufWriter::new(file);
+        for code in buf.iter() {
+            w.write_all(&code.to_le_bytes())?;
+        }
+        w.flush()?;
+        buf.clear();
+    }
+    Ok(())
+}
+
+fn read_partition(path: &Path, counts: &mut HashMap<u64, u32>) -> io::Result<()> {
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let mut r = BufReader::new(file);
+    let mut b = [0u8; 8];
+    loop {
+        match r.read_exact(&mut b) {
+            Ok(()) => *counts.entry(u64::from_le_bytes(b)).or_insert(0) += 1,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const INPUT: &str =
+        ">a\nACGTACGTACGTACGTACGTAAAAAAAAAAAAAAAAAAAAA\n>b\nACGTACGTACGTACGTACGTCCCC\n";
+
+    fn dump(k: usize, max_buffered: usize, min_count: u32) -> String {
+        let mut counter =
+            ExactKmerCounter::new(k, true, &std::env::temp_dir(), 4, max_buffered).unwrap();
+        counter
+            .add_reader(&mut Reader::from_reader(Cursor::new(INPUT.as_bytes())))
+            .unwrap();
+        let mut out = Vec::new();
+        counter.dump(&mut out, min_count).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    // count with a naive HashMap of canonical k-mer strings
+    fn naive(k: usize, min_count: u32) -> String {
+        let mut counts: HashMap<Vec<u8>, u32> = HashMap::new();
+        for line in INPUT.lines().filter(|l| !l.starts_with('>')) {
+            for w in line.as_bytes().windows(k) {
+                let rc = crate::seq::revcomp(w);
+                *counts.entry(w.to_vec().min(rc)).or_insert(0) += 1;
+            }
+        }
+        let mut v: Vec<_> = counts.into_iter().filter(|x| x.1 >= min_count).collect();
+        v.sort();
+        v.iter()
+            .map(|(k, c)| format!("{} {}\n", String::from_utf8_lossy(k), c))
+            .collect()
+    }
+
+    #[test]
+    fn test_exact_kmer_count_dense() {
+        assert_eq!(dump(5, 0, 1), naive(5, 1));
+        assert_eq!(dump(5, 0, 3), naive(5, 3));
+        assert!(dump(4, 0, 1).starts_with("AAAA 18\nACGT 10\n"));
+    }
+
+    #[test]
+    fn test_exact_kmer_count_partitioned_and_spilled() {
+        let expected = naive(17, 1);
+        // no spilling
+        assert_eq!(dump(17, 1_000_000, 1), expected);
+        // spill every 3 k-mers
+        assert_eq!(dump(17, 3, 1), expected);
+        assert_eq!(dump(17, 3, 2), naive(17, 2));
+    }
+
+    #[test]
+    fn test_exact_kmer_count_memory_budget() {
+        let count = |budget: &MemoryBudget| {
+            let mut counter =
+                ExactKmerCounter::new(17, true, &std::env::temp_dir(), 4, 1_000_000).unwrap();
+            counter.set_memory_budget(budget);
+            counter
+                .add_reader(&mut Reader::from_reader(Cursor::new(INPUT.as_bytes())))
+                .unwrap();
+            let spilled = matches!(counter.counts, Counts::Partitioned { spilled: true, .. });
+            let mut out = Vec::new();
+            counter.dump(&mut out, 1).unwrap();
+            (String::from_utf8(out).unwrap(), spilled)
+        };
+
+        let budget = MemoryBudget::new(BUDGET_CHUNK);
+        assert_eq!(count(&budget), (naive(17, 1), false));
+        assert_eq!(budget.used(), 0);
+
+        // an exhausted budget spills every k-mer
+        let budget = MemoryBudget::new(BUDGET_CHUNK);
+        let mut other = budget.reservation();
+        assert!(other.try_grow(BUDGET_CHUNK));
+        assert_eq!(count(&budget), (naive(17, 1), true));
+    }
+}
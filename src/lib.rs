@@ -1,8 +1,78 @@
+#[macro_use]
+mod logging;
+
+pub mod adapter;
+pub mod aggregate;
+pub mod annotate;
+pub mod batch;
+pub mod binary;
+pub mod blast;
+pub mod budget;
+pub mod cache;
+pub mod cds;
+pub mod conformance;
+pub mod contam;
+pub mod corpus;
+pub mod coverage;
+pub mod cramref;
+pub mod crop;
+pub mod digest;
+pub mod diginorm;
+pub mod duplicate;
 pub mod errors;
+pub mod follow;
+pub mod fragment;
+pub mod hash;
+pub mod idnorm;
+pub mod input;
+pub mod iupac;
+pub mod kmer;
+pub mod kmercount;
+pub mod merge;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(all(feature = "mmap", unix))]
+pub mod mmap;
+#[cfg(feature = "presets")]
+pub mod oligos;
+pub mod orient;
+pub mod pair;
+pub mod parallel;
+pub mod partition;
+#[cfg(feature = "presets")]
+pub mod presets;
+pub mod profile;
+pub mod qbin;
+pub mod qc;
+pub mod quantile;
 pub mod reader;
+#[cfg(feature = "registry")]
+pub mod registry;
+pub mod rename;
+#[cfg(feature = "gzip")]
+pub mod salvage;
+pub mod sample;
+pub mod samplesheet;
+pub mod sanitize;
+pub mod scratch;
+pub mod search;
 pub mod seq;
+pub mod sketch;
+pub mod spectrum;
+pub mod store;
+#[cfg(feature = "async")]
+pub mod stream;
+pub mod summary;
+pub mod swap;
+pub mod trim;
+pub mod twobit;
+pub mod usam;
 pub mod util;
+pub mod writer;
 pub mod xopen;
+#[cfg(feature = "zstd")]
+pub mod zdict;
 
-pub use reader::Reader;
-pub use seq::Seq;
+pub use reader::{Reader, ReaderBuilder};
+pub use seq::{RecordBuf, Seq, SeqOwned};
+pub use writer::Writer;
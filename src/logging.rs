@@ -0,0 +1,35 @@
+// Logging macros forwarding to the `log` crate with the target "fastseq" when the "log" feature
+// is enabled, and compiling to nothing otherwise. Applications choose the logger
+// (env_logger, a tracing subscriber via tracing-log, ...) and the level.
+
+#[cfg(feature = "log")]
+macro_rules! log_event {
+    ($level:ident, $($arg:tt)+) => {
+        log::$level!(target: "fastseq", $($arg)+)
+    };
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! log_event {
+    ($level:ident, $($arg:tt)+) => {
+        if false {
+            let _ = format_args!($($arg)+);
+        }
+    };
+}
+
+macro_rules! warn {
+    ($($arg:tt)+) => { log_event!(warn, $($arg)+) };
+}
+
+macro_rules! info {
+    ($($arg:tt)+) => { log_event!(info, $($arg)+) };
+}
+
+macro_rules! debug {
+    ($($arg:tt)+) => { log_event!(debug, $($arg)+) };
+}
+
+macro_rules! trace {
+    ($($arg:tt)+) => { log_event!(trace, $($arg)+) };
+}
@@ -0,0 +1,323 @@
+use crate::errors::*;
+use crate::reader::*;
+use crate::seq::*;
+use std::io::BufRead;
+
+/// Options of overlapping read pairs.
+#[derive(Debug, Clone, Copy)]
+pub struct MergeOptions {
+    /// Minimum length of the overlap.
+    pub min_overlap: usize,
+    /// Maximum fraction of mismatches in the overlap, bases other than ACGT are not counted.
+    pub max_mismatch_rate: f64,
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        Self {
+            min_overlap: 10,
+            max_mismatch_rate: 0.1,
+        }
+    }
+}
+
+/// An overlap of read 1 and the reverse complement of read 2,
+/// which starts at `offset` of read 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overlap {
+    pub offset: usize,
+    pub len: usize,
+    pub mismatches: usize,
+    /// Length of the fragment, i.e., of the merged read.
+    pub insert_size: usize,
+}
+
+/// Finds the overlap of read 1 and the reverse complement of read 2 (`rc2`) with the lowest
+/// mismatch rate, the longest one for ties. Read-through pairs, whose fragments are shorter than
+/// the reads, are only found after adapter trimming.
+pub fn find_overlap(seq1: &[u8], rc2: &[u8], opts: &MergeOptions) -> Option<Overlap> {
+    let min_overlap = opts.min_overlap.max(1);
+    if seq1.len() < min_overlap || rc2.len() < min_overlap {
+        return None;
+    }
+    let mut best: Option<Overlap> = None;
+    for offset in 0..=seq1.len() - min_overlap {
+        let len = (seq1.len() - offset).min(rc2.len());
+        let max_mismatches = (len as f64 * opts.max_mismatch_rate) as usize;
+        let mut mismatches = 0;
+        for (&a, &b) in seq1[offset..offset + len].iter().zip(rc2) {
+            let (a, b) = (a.to_ascii_uppercase(), b.to_ascii_uppercase());
+            if a != b && is_acgt(a) && is_acgt(b) {
+                mismatches += 1;
+                if mismatches > max_mismatches {
+                    break;
+                }
+            }
+        }
+        if mismatches > max_mismatches {
+            continue;
+        }
+        let better = match best {
+            None => true,
+            // mismatches / len < best.mismatches / best.len
+            Some(b) => mismatches * b.len < b.mismatches * len,
+        };
+        if better {
+            best = Some(Overlap {
+                offset,
+                len,
+                mismatches,
+                insert_size: (offset + rc2.len()).max(seq1.len()),
+            });
+            if mismatches == 0 {
+                break;
+            }
+        }
+    }
+    best
+}
+
+#[inline]
+fn is_acgt(b: u8) -> bool {
+    matches!(b, b'A' | b'C' | b'G' | b'T')
+}
+
+/// Merges a read pair into one read, written into `seq_buf` and `qual_buf` (left empty for FASTA).
+/// In the overlap, the base with the higher quality is kept, the one of read 1 for ties or FASTA.
+/// Returns None if the mates do not overlap.
+pub fn merge_pair(
+    r1: &Seq,
+    r2: &Seq,
+    opts: &MergeOptions,
+    seq_buf: &mut Vec<u8>,
+    qual_buf: &mut Vec<u8>,
+) -> Option<Overlap> {
+    let rc2 = revcomp(r2.seq);
+    let ov = find_overlap(r1.seq, &rc2, opts)?;
+    let rq2: Option<Vec<u8>> = r2.qual.map(|q| q.iter().rev().copied().collect());
+
+    seq_buf.clear();
+    qual_buf.clear();
+    seq_buf.extend_from_slice(&r1.seq[..ov.offset]);
+    if let Some(q1) = r1.qual {
+        qual_buf.extend_from_slice(&q1[..ov.offset]);
+    }
+    for i in 0..ov.len {
+        let (b1, b2) = (r1.seq[ov.offset + i], rc2[i]);
+        match (r1.qual, &rq2) {
+            (Some(q1), Some(q2)) => {
+                let (q1, q2) = (q1[ov.offset + i], q2[i]);
+                if q2 > q1 {
+                    seq_buf.push(b2);
+                    qual_buf.push(q2);
+                } else {
+                    seq_buf.push(b1);
+                    qual_buf.push(q1);
+                }
+            }
+            _ => seq_buf.push(b1),
+        }
+    }
+    if ov.offset + ov.len < r1.seq.len() {
+        // read 2 is contained in read 1
+        seq_buf.extend_from_slice(&r1.seq[ov.offset + ov.len..]);
+        if let Some(q1) = r1.qual {
+            qual_buf.extend_from_slice(&q1[ov.offset + ov.len..]);
+        }
+    } else {
+        seq_buf.extend_from_slice(&rc2[ov.len..]);
+        if let (Some(_), Some(q2)) = (r1.qual, &rq2) {
+            qual_buf.extend_from_slice(&q2[ov.len..]);
+        }
+    }
+    Some(ov)
+}
+
+/// An insert size distribution estimated from overlapping read pairs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InsertSizeStats {
+    /// Number of pairs examined.
+    pub pairs: u64,
+    /// Number of pairs with an overlap.
+    pub merged: u64,
+    /// Number of merged pairs by insert size.
+    pub histogram: Vec<u64>,
+}
+
+impl InsertSizeStats {
+    /// Adds an insert size.
+    pub fn add(&mut self, insert_size: usize) {
+        if self.histogram.len() <= insert_size {
+            self.histogram.resize(insert_size + 1, 0);
+        }
+        self.histogram[insert_size] += 1;
+        self.merged += 1;
+    }
+
+    /// Returns the fraction of pairs with an overlap. Pairs from fragments longer than the sum of
+    /// read lengths can not be merged, so a low fraction means the distribution is biased to short
+    /// inserts.
+    pub fn merged_fraction(&self) -> f64 {
+        if self.pairs == 0 {
+            return 0.0;
+        }
+        self.merged as f64 / self.pairs as f64
+    }
+
+    /// Returns the mean insert size.
+    pub fn mean(&self) -> f64 {
+        if self.merged == 0 {
+            return 0.0;
+        }
+        let sum: f64 = self
+            .histogram
+            .iter()
+            .enumerate()
+            .map(|(size, &n)| size as f64 * n as f64)
+            .sum();
+        sum / self.merged as f64
+    }
+
+    /// Returns the standard deviation of insert sizes.
+    pub fn sd(&self) -> f64 {
+        if self.merged < 2 {
+            return 0.0;
+        }
+        let mean = self.mean();
+        let ss: f64 = self
+            .histogram
+            .iter()
+            .enumerate()
+            .map(|(size, &n)| (size as f64 - mean).powi(2) * n as f64)
+            .sum();
+        (ss / (self.merged - 1) as f64).sqrt()
+    }
+
+    /// Returns the median insert size.
+    pub fn median(&self) -> usize {
+        let half = self.merged.div_ceil(2);
+        let mut acc = 0;
+        for (size, &n) in self.histogram.iter().enumerate() {
+            acc += n;
+            if acc >= half && n > 0 {
+                return size;
+            }
+        }
+        0
+    }
+}
+
+/// Estimates the insert size distribution from the first `max_pairs` pairs (0 for all)
+/// of two in-sync mate files, without alignment.
+pub fn estimate_insert_size<R1: BufRead, R2: BufRead>(
+    r1: &mut Reader<R1>,
+    r2: &mut Reader<R2>,
+    max_pairs: u64,
+    opts: &MergeOptions,
+) -> Result<InsertSizeStats, FastxErr> {
+    let mut stats = InsertSizeStats::default();
+    let mut rc2 = Vec::with_capacity(1024);
+    while max_pairs == 0 || stats.pairs < max_pairs {
+        let (a, b) = match (r1.next(), r2.next()) {
+            (Some(a), Some(b)) => (a?, b?),
+            _ => break,
+        };
+        stats.pairs += 1;
+        rc2.clear();
+        rc2.extend(revcomp(b.seq));
+        if let Some(ov) = find_overlap(a.seq, &rc2, opts) {
+            stats.add(ov.insert_size);
+        }
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const FRAGMENT: &[u8] = b"ACGTTGCAAGGCTTACCGATGCATGCCAGTAGCTAGGATCCA";
+
+    fn pair(len: usize, insert: usize) -> (Vec<u8>, Vec<u8>) {
+        let frag = &FRAGMENT[..insert];
+        (frag[..len].to_vec(), revcomp(&frag[insert - len..]))
+    }
+
+    #[test]
+    fn test_find_overlap() {
+        let (r1, r2) = pair(25, 40);
+        let ov = find_overlap(&r1, &revcomp(&r2), &MergeOptions::default()).unwrap();
+        assert_eq!(ov.offset, 15);
+        assert_eq!(ov.len, 10);
+        assert_eq!(ov.insert_size, 40);
+
+        // a mismatch is tolerated
+        let (mut r1, r2) = pair(30, 35);
+        r1[20] = b'N';
+        r1[22] = if r1[22] == b'A' { b'C' } else { b'A' };
+        let ov = find_overlap(&r1, &revcomp(&r2), &MergeOptions::default()).unwrap();
+        assert_eq!((ov.insert_size, ov.mismatches), (35, 1));
+
+        let (r1, r2) = pair(10, 40);
+        assert_eq!(
+            find_overlap(&r1, &revcomp(&r2), &MergeOptions::default()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_merge_pair() {
+        let (s1, s2) = pair(25, 40);
+        let q1 = vec![b'I'; 25];
+        let mut q2 = vec![b'I'; 25];
+        let mut s1 = s1;
+        s1[20] = b'T'; // low quality error in read 1
+        let mut q1 = q1;
+        q1[20] = b'#';
+        q2[0] = b'5';
+        let r1 = Seq {
+            id: b"r",
+            desc: b"",
+            seq: &s1,
+            qual: Some(&q1),
+        };
+        let r2 = Seq {
+            id: b"r",
+            desc: b"",
+            seq: &s2,
+            qual: Some(&q2),
+        };
+        let (mut seq, mut qual) = (Vec::new(), Vec::new());
+        let ov = merge_pair(&r1, &r2, &MergeOptions::default(), &mut seq, &mut qual).unwrap();
+        assert_eq!(ov.insert_size, 40);
+        assert_eq!(seq, &FRAGMENT[..40]);
+        assert_eq!(qual.len(), 40);
+        assert_eq!(qual[39], b'5');
+    }
+
+    #[test]
+    fn test_estimate_insert_size() {
+        let (mut in1, mut in2) = (String::new(), String::new());
+        for (i, insert) in [30, 35, 40, 40].iter().enumerate() {
+            let (a, b) = pair(25, *insert);
+            in1.push_str(&format!(">p{i}\n{}\n", String::from_utf8_lossy(&a)));
+            in2.push_str(&format!(">p{i}\n{}\n", String::from_utf8_lossy(&b)));
+        }
+        in1.push_str(">p4\nAAAAAAAAAAAAAAA\n");
+        in2.push_str(">p4\nCCCCCCCCCCCCCCC\n");
+
+        let stats = estimate_insert_size(
+            &mut Reader::from_reader(Cursor::new(in1.as_bytes())),
+            &mut Reader::from_reader(Cursor::new(in2.as_bytes())),
+            0,
+            &MergeOptions::default(),
+        )
+        .unwrap();
+        assert_eq!((stats.pairs, stats.merged), (5, 4));
+        assert_eq!(stats.mean(), 36.25);
+        assert_eq!(stats.median(), 35);
+        assert!((stats.sd() - 4.787).abs() < 0.001);
+        assert_eq!(stats.merged_fraction(), 0.8);
+    }
+}
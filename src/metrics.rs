@@ -0,0 +1,331 @@
+use crate::errors::*;
+use crate::reader::*;
+use crate::seq::*;
+use crate::writer::*;
+use crate::xopen::*;
+use std::io::{self, BufRead, Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+
+/// Counters and gauges of record streams, shared by metered readers and writers.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub records_read: AtomicU64,
+    pub bases_read: AtomicU64,
+    /// Uncompressed bytes read.
+    pub bytes_read: AtomicU64,
+    pub records_written: AtomicU64,
+    /// Uncompressed bytes written.
+    pub bytes_written: AtomicU64,
+    /// Number of parsing and I/O errors.
+    pub errors: AtomicU64,
+    pub readers_opened: AtomicU64,
+    /// Number of metered readers not dropped yet.
+    pub readers_active: AtomicI64,
+}
+
+/// Returns the process-wide metrics used by metered readers and writers by default.
+pub fn global() -> Arc<Metrics> {
+    static GLOBAL: OnceLock<Arc<Metrics>> = OnceLock::new();
+    GLOBAL.get_or_init(|| Arc::new(Metrics::default())).clone()
+}
+
+impl Metrics {
+    /// Writes all metrics in the Prometheus text exposition format.
+    pub fn write_prometheus<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let counters = [
+            (
+                "fastseq_records_read_total",
+                "Records parsed.",
+                &self.records_read,
+            ),
+            (
+                "fastseq_bases_read_total",
+                "Bases parsed.",
+                &self.bases_read,
+            ),
+            (
+                "fastseq_bytes_read_total",
+                "Uncompressed bytes read.",
+                &self.bytes_read,
+            ),
+            (
+                "fastseq_records_written_total",
+                "Records written.",
+                &self.records_written,
+            ),
+            (
+                "fastseq_bytes_written_total",
+                "Uncompressed bytes written.",
+                &self.bytes_written,
+            ),
+            (
+                "fastseq_errors_total",
+                "Parsing and I/O errors.",
+                &self.errors,
+            ),
+            (
+                "fastseq_readers_opened_total",
+                "Readers opened.",
+                &self.readers_opened,
+            ),
+        ];
+        for (name, help, value) in counters {
+            writeln!(w, "# HELP {name} {help}")?;
+            writeln!(w, "# TYPE {name} counter")?;
+            writeln!(w, "{name} {}", value.load(Ordering::Relaxed))?;
+        }
+        writeln!(w, "# HELP fastseq_readers_active Readers currently open.")?;
+        writeln!(w, "# TYPE fastseq_readers_active gauge")?;
+        writeln!(
+            w,
+            "fastseq_readers_active {}",
+            self.readers_active.load(Ordering::Relaxed)
+        )
+    }
+
+    /// Returns all metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut buf = Vec::new();
+        self.write_prometheus(&mut buf)
+            .expect("writing to a Vec never fails");
+        String::from_utf8(buf).unwrap()
+    }
+}
+
+/// A BufRead counting consumed bytes into `Metrics::bytes_read`.
+pub struct CountingReader<R: BufRead> {
+    inner: R,
+    metrics: Arc<Metrics>,
+}
+
+impl<R: BufRead> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.metrics
+            .bytes_read
+            .fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for CountingReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.metrics
+            .bytes_read
+            .fetch_add(amt as u64, Ordering::Relaxed);
+        self.inner.consume(amt)
+    }
+}
+
+/// A Reader updating `Metrics` for every record.
+pub struct MeteredReader<R: BufRead> {
+    reader: Reader<CountingReader<R>>,
+    metrics: Arc<Metrics>,
+}
+
+impl MeteredReader<Box<dyn BufRead>> {
+    /// Opens a file like `Reader::new`, with the global metrics.
+    pub fn new<P: AsRef<Path>>(file: P) -> Result<Self, FastxErr> {
+        let metrics = global();
+        match xopen(file, 65536) {
+            Ok(r) => Ok(Self::with_metrics(r, metrics)),
+            Err(e) => {
+                metrics.errors.fetch_add(1, Ordering::Relaxed);
+                Err(FastxErr::IOError(e))
+            }
+        }
+    }
+}
+
+impl<R: BufRead> MeteredReader<R> {
+    /// Creates a metered Reader from any BufRead, with the global metrics.
+    pub fn from_reader(reader: R) -> Self {
+        Self::with_metrics(reader, global())
+    }
+
+    /// Creates a metered Reader from any BufRead, updating the given metrics.
+    pub fn with_metrics(reader: R, metrics: Arc<Metrics>) -> Self {
+        metrics.readers_opened.fetch_add(1, Ordering::Relaxed);
+        metrics.readers_active.fetch_add(1, Ordering::Relaxed);
+        Self {
+            reader: Reader::from_reader(CountingReader {
+                inner: reader,
+                metrics: metrics.clone(),
+            }),
+            metrics,
+        }
+    }
+
+    /// Returns the metrics.
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.metrics
+    }
+
+    // returns None if all records are read, otherwise returns Some(Ok(Seq)) or Some(Err(e))
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Result<Seq<'_>, FastxErr>> {
+        let res = self.reader.next();
+        match &res {
+            Some(Ok(seq)) => {
+                self.metrics.records_read.fetch_add(1, Ordering::Relaxed);
+                self.metrics
+                    .bases_read
+                    .fetch_add(seq.seq.len() as u64, Ordering::Relaxed);
+            }
+            Some(Err(_)) => {
+                self.metrics.errors.fetch_add(1, Ordering::Relaxed);
+            }
+            None => {}
+        }
+        res
+    }
+}
+
+impl<R: BufRead> RecordSource for MeteredReader<R> {
+    fn next_record(&mut self) -> Option<Result<Seq<'_>, FastxErr>> {
+        self.next()
+    }
+}
+
+impl<R: BufRead> Drop for MeteredReader<R> {
+    fn drop(&mut self) {
+        self.metrics.readers_active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A Write counting written bytes into `Metrics::bytes_written`.
+pub struct CountingWriter<W: Write> {
+    inner: W,
+    metrics: Arc<Metrics>,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.metrics
+            .bytes_written
+            .fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: FinishWrite> FinishWrite for CountingWriter<W> {
+    fn finish(&mut self) -> io::Result<()> {
+        self.inner.finish()
+    }
+}
+
+/// A Writer updating `Metrics` for every record.
+pub struct MeteredWriter<W: Write> {
+    writer: Writer<CountingWriter<W>>,
+    metrics: Arc<Metrics>,
+}
+
+impl MeteredWriter<Box<dyn FinishWrite>> {
+    /// Creates a file like `Writer::new`, with the global metrics.
+    pub fn new<P: AsRef<Path>>(file: P) -> Result<Self, FastxErr> {
+        let w = xwrite(file, 65536)?;
+        Ok(Self::with_metrics(w, global()))
+    }
+}
+
+impl<W: Write> MeteredWriter<W> {
+    /// Creates a metered Writer from any Write, with the global metrics.
+    pub fn from_writer(writer: W) -> Self {
+        Self::with_metrics(writer, global())
+    }
+
+    /// Creates a metered Writer from any Write, updating the given metrics.
+    pub fn with_metrics(writer: W, metrics: Arc<Metrics>) -> Self {
+        Self {
+            writer: Writer::from_writer(CountingWriter {
+                inner: writer,
+                metrics: metrics.clone(),
+            }),
+            metrics,
+        }
+    }
+
+    /// Returns the underlying Writer, e.g., to set the line width.
+    pub fn get_mut(&mut self) -> &mut Writer<CountingWriter<W>> {
+        &mut self.writer
+    }
+
+    /// Writes a record.
+    pub fn write(&mut self, seq: &Seq) -> Result<(), FastxErr> {
+        match self.writer.write(seq) {
+            Ok(()) => {
+                self.metrics.records_written.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            }
+            Err(e) => {
+                self.metrics.errors.fetch_add(1, Ordering::Relaxed);
+                Err(e)
+            }
+        }
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&mut self) -> Result<(), FastxErr> {
+        self.writer.flush()
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer.into_inner().inner
+    }
+}
+
+impl<W: FinishWrite> MeteredWriter<W> {
+    /// Flushes and finalizes the output, see `Writer::finish`.
+    pub fn finish(&mut self) -> Result<(), FastxErr> {
+        self.writer.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_metered_reader_and_writer() {
+        let metrics = Arc::new(Metrics::default());
+        let input = "@r1\nACGT\n+\nIIII\n@r2\nAC\n+\nI\n";
+        let mut writer = MeteredWriter::with_metrics(Vec::new(), metrics.clone());
+        {
+            let mut reader =
+                MeteredReader::with_metrics(Cursor::new(input.as_bytes()), metrics.clone());
+            assert_eq!(metrics.readers_active.load(Ordering::Relaxed), 1);
+            while let Some(Ok(seq)) = reader.next() {
+                writer.write(&seq).unwrap();
+            }
+        }
+        writer.flush().unwrap();
+        assert_eq!(writer.into_inner().len(), 16);
+
+        let text = metrics.render();
+        for line in [
+            "fastseq_records_read_total 1\n",
+            "fastseq_bases_read_total 4\n",
+            "fastseq_records_written_total 1\n",
+            "fastseq_bytes_written_total 16\n",
+            "fastseq_errors_total 1\n",
+            "fastseq_readers_opened_total 1\n",
+            "# TYPE fastseq_readers_active gauge\nfastseq_readers_active 0\n",
+        ] {
+            assert!(text.contains(line), "{line} not in\n{text}");
+        }
+        assert!(metrics.bytes_read.load(Ordering::Relaxed) >= 16);
+    }
+}
@@ -0,0 +1,387 @@
+use crate::errors::*;
+use crate::reader::*;
+use crate::seq::*;
+use crate::util::*;
+use crate::xopen::*;
+use memchr::memchr;
+use std::fs::File;
+use std::io;
+use std::ops::Deref;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::ptr;
+use std::slice;
+
+/// A read-only memory map of a whole file.
+pub struct Mmap {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+// the mapping is read-only and owned, so sharing it across threads is safe
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+impl Mmap {
+    /// Maps a file into memory.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated, by this or another process, while it is
+    /// mapped: changes show up in the borrowed bytes, and reading truncated pages raises SIGBUS.
+    pub unsafe fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(long_path(path.as_ref()))?;
+        let len = usize::try_from(file.metadata()?.len())
+            .map_err(|_| io::Error::other("file is too large to map"))?;
+        if len == 0 {
+            // mmap rejects empty mappings
+            return Ok(Self {
+                ptr: ptr::null_mut(),
+                len,
+            });
+        }
+
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        // only a hint for read-ahead, failures are harmless
+        unsafe { libc::madvise(ptr, len, libc::MADV_SEQUENTIAL) };
+
+        Ok(Self { ptr, len })
+    }
+}
+
+impl Deref for Mmap {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        unsafe { slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            unsafe { libc::munmap(self.ptr, self.len) };
+        }
+    }
+}
+
+// a field of a record, either borrowed from the map or copied into the buffer
+enum Part<'a> {
+    Map(&'a [u8]),
+    Buf(usize, usize),
+}
+
+impl<'a> Part<'a> {
+    #[inline]
+    fn resolve(self, buf: &'a [u8]) -> &'a [u8] {
+        match self {
+            Part::Map(s) => s,
+            Part::Buf(s, e) => &buf[s..e],
+        }
+    }
+}
+
+/// A FASTA/Q reader over a memory-mapped uncompressed file.
+///
+/// Headers, single-line sequences and single-line quality strings are borrowed directly from
+/// the mapped file, so reading FASTQ and unwrapped FASTA involves no copying at all.
+/// Lines of wrapped sequences are joined into an internal buffer, which is still much cheaper
+/// than buffered reading. Compressed files are rejected, use `Reader` for them.
+pub struct MmapReader {
+    map: Mmap,
+    pos: usize,
+    format: Option<Format>,
+    buf: Vec<u8>,
+    parse_id: bool,
+}
+
+impl MmapReader {
+    /// Maps a file and creates a reader of it.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while the reader exists, see `Mmap::open`.
+    pub unsafe fn new<P: AsRef<Path>>(path: P) -> Result<Self, FastxErr> {
+        let map = unsafe { Mmap::open(path)? };
+        if detect_compression(&map) != CompressionFormat::None {
+            return Err(FastxErr::IOError(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "memory-mapped reading does not support compressed files",
+            )));
+        }
+        Ok(Self {
+            map,
+            pos: 0,
+            format: None,
+            buf: Vec::new(),
+            parse_id: true,
+        })
+    }
+
+    /// Skips parsing the ID and description, the whole header line is returned as the ID.
+    pub fn skip_id_parsing(&mut self) {
+        self.parse_id = false
+    }
+
+    /// Returns the mapped file content.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.map
+    }
+
+    /// Returns the offset of the next record in the file.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Rewinds to the beginning of the file for another scan.
+    pub fn rewind(&mut self) {
+        self.pos = 0;
+    }
+
+    // returns the next line without the line ending, and advances the position
+    #[inline]
+    fn line<'a>(data: &'a [u8], pos: &mut usize) -> &'a [u8] {
+        let start = *pos;
+        let end = match memchr(b'\n', &data[start..]) {
+            Some(i) => {
+                *pos = start + i + 1;
+                start + i
+            }
+            None => {
+                *pos = data.len();
+                data.len()
+            }
+        };
+        trim_crlf(&data[start..end])
+    }
+
+    // returns None if EOF is reached, otherwise returns Some(Ok(Seq)) or Some(Err(e))
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Result<Seq<'_>, FastxErr>> {
+        let data: &[u8] = &self.map;
+        let mut pos = self.pos;
+
+        // header
+        let header = loop {
+            if pos >= data.len() {
+                self.pos = pos;
+                return None;
+            }
+            let line = Self::line(data, &mut pos);
+            if !line.is_empty() {
+                break line;
+            }
+        };
+        let format = match (self.format, header[0]) {
+            (None, b'>') => Format::Fasta,
+            (None, b'@') => Format::Fastq,
+            (Some(Format::Fasta), b'>') => Format::Fasta,
+            (Some(Format::Fastq), b'@') => Format::Fastq,
+            _ => {
+                // skip the line, so reading can go on
+                self.pos = pos;
+                return Some(Err(FastxErr::InvalidFormat));
+            }
+        };
+        self.format = Some(format);
+        let header = &header[1..];
+
+        // sequence
+        self.buf.clear();
+        let mut seq = Part::Map(&[]);
+        let mut n = 0;
+        while pos < data.len() {
+            let start = pos;
+            let line = Self::line(data, &mut pos);
+            if line.is_empty() {
+                continue;
+            }
+            if format == Format::Fasta && line[0] == b'>' {
+                pos = start;
+                break;
+            }
+            if format == Format::Fastq && line[0] == b'+' {
+                break;
+            }
+            seq = Self::append(&mut self.buf, seq, line, n);
+            n += 1;
+        }
+
+        // quality
+        let mut qual = None;
+        if format == Format::Fastq {
+            let seq_len = match seq {
+                Part::Map(s) => s.len(),
+                Part::Buf(s, e) => e - s,
+            };
+            let mut q = Part::Map(&[]);
+            let mut qual_len = 0;
+            let mut n = 0;
+            while qual_len < seq_len && pos < data.len() {
+                let line = Self::line(data, &mut pos);
+                if line.is_empty() {
+                    continue;
+                }
+                qual_len += line.len();
+                if qual_len > seq_len {
+                    self.pos = pos;
+                    return Some(Err(FastxErr::UnequalSeqAndQual(seq_len, qual_len)));
+                }
+                q = Self::append(&mut self.buf, q, line, n);
+                n += 1;
+            }
+            if qual_len != seq_len {
+                self.pos = pos;
+                return Some(Err(FastxErr::UnequalSeqAndQual(seq_len, qual_len)));
+            }
+            qual = Some(q);
+        }
+        self.pos = pos;
+
+        let (id, desc) = if self.parse_id {
+            parse_header(header)
+        } else {
+            (header, &[][..])
+        };
+        Some(Ok(Seq {
+            id,
+            desc,
+            seq: seq.resolve(&self.buf),
+            qual: qual.map(|q| q.resolve(&self.buf)),
+        }))
+    }
+
+    // adds the n-th line of a field, copying lines into the buffer from the second one on
+    #[inline]
+    fn append<'a>(buf: &mut Vec<u8>, part: Part<'a>, line: &'a [u8], n: usize) -> Part<'a> {
+        match (n, part) {
+            (0, _) => Part::Map(line),
+            (_, Part::Map(first)) => {
+                let start = buf.len();
+                buf.extend_from_slice(first);
+                buf.extend_from_slice(line);
+                Part::Buf(start, buf.len())
+            }
+            (_, Part::Buf(start, _)) => {
+                buf.extend_from_slice(line);
+                Part::Buf(start, buf.len())
+            }
+        }
+    }
+}
+
+impl RecordSource for MmapReader {
+    fn next_record(&mut self) -> Option<Result<Seq<'_>, FastxErr>> {
+        self.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    type OwnedRecord = (Vec<u8>, Vec<u8>, Vec<u8>, Option<Vec<u8>>);
+
+    fn temp_file(content: &[u8]) -> PathBuf {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let path =
+            std::env::temp_dir().join(format!("fastseq-mmap-{}-{nanos}.fx", std::process::id()));
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn read_all(content: &[u8]) -> Result<Vec<OwnedRecord>, FastxErr> {
+        let path = temp_file(content);
+        let mut reader = unsafe { MmapReader::new(&path)? };
+        let mut records = Vec::new();
+        while let Some(res) = reader.next() {
+            let seq = res?;
+            records.push((
+                seq.id.to_vec(),
+                seq.desc.to_vec(),
+                seq.seq.to_vec(),
+                seq.qual.map(|q| q.to_vec()),
+            ));
+        }
+        fs::remove_file(&path).unwrap();
+        Ok(records)
+    }
+
+    #[test]
+    fn test_mmap_reader_fasta() {
+        let records = read_all(b"\n>s1 desc\r\nACGT\r\nTT\n\n>s2\nGG\n>s3\n").unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].0, b"s1");
+        assert_eq!(records[0].1, b"desc");
+        assert_eq!(records[0].2, b"ACGTTT");
+        assert_eq!(records[1].2, b"GG");
+        assert_eq!(records[2].2, b"");
+        assert_eq!(records[2].3, None);
+
+        assert!(read_all(b"").unwrap().is_empty());
+        assert!(matches!(read_all(b"ACGT\n"), Err(FastxErr::InvalidFormat)));
+
+        // a stray line is skipped after the error
+        let path = temp_file(b"ACGT\n>s1\nAC\n");
+        let mut reader = unsafe { MmapReader::new(&path).unwrap() };
+        assert!(matches!(reader.next(), Some(Err(FastxErr::InvalidFormat))));
+        assert_eq!(reader.next().unwrap().unwrap().seq, b"AC");
+        assert!(reader.next().is_none());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_mmap_reader_fastq() {
+        let records = read_all(b"@r1\nACGT\n+\nI@II\n@r2\nAC\nGT\n+r2\nII\n@I\n").unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].3.as_deref(), Some(&b"I@II"[..]));
+        assert_eq!(records[1].2, b"ACGT");
+        assert_eq!(records[1].3.as_deref(), Some(&b"II@I"[..]));
+
+        assert!(matches!(
+            read_all(b"@r1\nACGT\n+\nII\n"),
+            Err(FastxErr::UnequalSeqAndQual(4, 2))
+        ));
+    }
+
+    #[test]
+    fn test_mmap_reader_zero_copy_and_rewind() {
+        let path = temp_file(b"@r1 d\nACGT\n+\nIIII\n");
+        let mut reader = unsafe { MmapReader::new(&path).unwrap() };
+        let range = reader.as_bytes().as_ptr_range();
+        {
+            let seq = reader.next().unwrap().unwrap();
+            assert!(range.contains(&seq.seq.as_ptr()));
+            assert!(range.contains(&seq.qual.unwrap().as_ptr()));
+        }
+        assert!(reader.next().is_none());
+        assert_eq!(reader.position(), 18);
+
+        reader.rewind();
+        reader.skip_id_parsing();
+        assert_eq!(reader.next().unwrap().unwrap().id, b"r1 d");
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,215 @@
+use crate::contam::*;
+
+/// An adapter or primer sequence of the embedded catalog.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Oligo {
+    pub name: &'static str,
+    /// 5'->3' sequence, primers may contain IUPAC degenerate bases.
+    pub seq: &'static [u8],
+}
+
+impl Oligo {
+    /// Returns whether the sequence contains bases other than ACGT,
+    /// which are not matched by exact searches like `SeqGrep`.
+    pub fn is_degenerate(&self) -> bool {
+        self.seq
+            .iter()
+            .any(|b| !matches!(b.to_ascii_uppercase(), b'A' | b'C' | b'G' | b'T'))
+    }
+}
+
+// so that catalogs can be passed to `SeqGrep::new` and `AhoCorasick::new` directly
+impl AsRef<[u8]> for Oligo {
+    fn as_ref(&self) -> &[u8] {
+        self.seq
+    }
+}
+
+/// Illumina TruSeq read 1 3' adapter.
+pub const TRUSEQ_READ1: Oligo = Oligo {
+    name: "TruSeq_Read1",
+    seq: b"AGATCGGAAGAGCACACGTCTGAACTCCAGTCA",
+};
+
+/// Illumina TruSeq read 2 3' adapter.
+pub const TRUSEQ_READ2: Oligo = Oligo {
+    name: "TruSeq_Read2",
+    seq: b"AGATCGGAAGAGCGTCGTGTAGGGAAAGAGTGT",
+};
+
+/// The first 13 bases shared by both TruSeq adapters, searched by Trim Galore and fastp.
+pub const TRUSEQ_UNIVERSAL: Oligo = Oligo {
+    name: "TruSeq_Universal",
+    seq: b"AGATCGGAAGAGC",
+};
+
+/// Nextera (Tn5) transposase mosaic end, the 3' adapter of Nextera and Nextera XT libraries.
+pub const NEXTERA: Oligo = Oligo {
+    name: "Nextera_Transposase",
+    seq: b"CTGTCTCTTATACACATCT",
+};
+
+/// Illumina small RNA 3' adapter.
+pub const ILLUMINA_SMALL_RNA_3P: Oligo = Oligo {
+    name: "Illumina_SmallRNA_3p",
+    seq: b"TGGAATTCTCGGGTGCCAAGG",
+};
+
+/// Oxford Nanopore ligation adapter (Y adapter top strand), found at the start of reads.
+pub const ONT_LIGATION_ADAPTER: Oligo = Oligo {
+    name: "ONT_Ligation_Y_Top",
+    seq: b"AATGTACTTCGTTCAGTTACGTATTGCT",
+};
+
+/// Oxford Nanopore rapid adapter, found at the start of reads of rapid kits.
+pub const ONT_RAPID_ADAPTER: Oligo = Oligo {
+    name: "ONT_Rapid",
+    seq: b"GTTTTCGCATTTATCGTGAAACGCTTTCGCGTTTTTCGTGCGCCGCTTCA",
+};
+
+/// PacBio SMRTbell hairpin adapter.
+pub const PACBIO_SMRTBELL: Oligo = Oligo {
+    name: "PacBio_SMRTbell",
+    seq: b"ATCTCTCTCAACAACAACAACGGAGGAGGAGGAAAAGAGAGAGAT",
+};
+
+/// All embedded adapters.
+pub const ADAPTERS: &[Oligo] = &[
+    TRUSEQ_READ1,
+    TRUSEQ_READ2,
+    TRUSEQ_UNIVERSAL,
+    NEXTERA,
+    ILLUMINA_SMALL_RNA_3P,
+    ONT_LIGATION_ADAPTER,
+    ONT_RAPID_ADAPTER,
+    PACBIO_SMRTBELL,
+];
+
+/// 16S rRNA V4 forward primer 515F (Parada et al.), Earth Microbiome Project.
+pub const PRIMER_16S_515F: Oligo = Oligo {
+    name: "16S_515F",
+    seq: b"GTGYCAGCMGCCGCGGTAA",
+};
+
+/// 16S rRNA V4 reverse primer 806R (Apprill et al.), Earth Microbiome Project.
+pub const PRIMER_16S_806R: Oligo = Oligo {
+    name: "16S_806R",
+    seq: b"GGACTACNVGGGTWTCTAAT",
+};
+
+/// 16S rRNA V3-V4 forward primer 341F (Klindworth et al.), Illumina 16S protocol.
+pub const PRIMER_16S_341F: Oligo = Oligo {
+    name: "16S_341F",
+    seq: b"CCTACGGGNGGCWGCAG",
+};
+
+/// 16S rRNA V3-V4 reverse primer 785R (Klindworth et al.), Illumina 16S protocol.
+pub const PRIMER_16S_785R: Oligo = Oligo {
+    name: "16S_785R",
+    seq: b"GACTACHVGGGTATCTAATCC",
+};
+
+/// 16S rRNA full-length forward primer 27F.
+pub const PRIMER_16S_27F: Oligo = Oligo {
+    name: "16S_27F",
+    seq: b"AGAGTTTGATCMTGGCTCAG",
+};
+
+/// 16S rRNA full-length reverse primer 1492R.
+pub const PRIMER_16S_1492R: Oligo = Oligo {
+    name: "16S_1492R",
+    seq: b"TACGGYTACCTTGTTACGACTT",
+};
+
+/// Fungal ITS1 forward primer ITS1F.
+pub const PRIMER_ITS1F: Oligo = Oligo {
+    name: "ITS1F",
+    seq: b"CTTGGTCATTTAGAGGAAGTAA",
+};
+
+/// Fungal ITS1 reverse primer ITS2.
+pub const PRIMER_ITS2: Oligo = Oligo {
+    name: "ITS2",
+    seq: b"GCTGCGTTCTTCATCGATGC",
+};
+
+/// All embedded primers.
+pub const PRIMERS: &[Oligo] = &[
+    PRIMER_16S_515F,
+    PRIMER_16S_806R,
+    PRIMER_16S_341F,
+    PRIMER_16S_785R,
+    PRIMER_16S_27F,
+    PRIMER_16S_1492R,
+    PRIMER_ITS1F,
+    PRIMER_ITS2,
+];
+
+/// Returns an adapter or primer by name, case-insensitively.
+pub fn find_oligo(name: &str) -> Option<&'static Oligo> {
+    ADAPTERS
+        .iter()
+        .chain(PRIMERS)
+        .find(|o| o.name.eq_ignore_ascii_case(name))
+}
+
+/// Returns oligos in FASTA format, e.g., as the adapter file of other tools.
+pub fn oligos_fasta(oligos: &[Oligo]) -> String {
+    let mut fasta = String::new();
+    for o in oligos {
+        fasta.push('>');
+        fasta.push_str(o.name);
+        fasta.push('\n');
+        fasta.push_str(&String::from_utf8_lossy(o.seq));
+        fasta.push('\n');
+    }
+    fasta
+}
+
+/// Builds a contaminant screen of oligos. K-mers with degenerate bases are skipped.
+pub fn oligo_screen(oligos: &[Oligo], k: usize, threshold: f64) -> ContaminantScreen {
+    let mut screen = ContaminantScreen::new(k, threshold);
+    for o in oligos {
+        screen.add_reference(o.name.as_bytes(), o.seq);
+    }
+    screen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::*;
+    use crate::seq::*;
+
+    #[test]
+    fn test_catalog() {
+        assert_eq!(find_oligo("truseq_read1"), Some(&TRUSEQ_READ1));
+        assert_eq!(find_oligo("16S_806R").unwrap().seq, b"GGACTACNVGGGTWTCTAAT");
+        assert!(find_oligo("x").is_none());
+        assert!(ADAPTERS.iter().all(|o| !o.is_degenerate()));
+        assert!(PRIMER_16S_515F.is_degenerate());
+        assert!(!PRIMER_ITS2.is_degenerate());
+        assert_eq!(
+            oligos_fasta(&[NEXTERA, PRIMER_ITS2]),
+            ">Nextera_Transposase\nCTGTCTCTTATACACATCT\n>ITS2\nGCTGCGTTCTTCATCGATGC\n"
+        );
+    }
+
+    #[test]
+    fn test_catalog_search() {
+        let seq = Seq {
+            id: b"r",
+            desc: b"",
+            seq: b"ACGTACGTACGTAGATCGGAAGAGCACACGTCTGAACTCCAGTCAC",
+            qual: None,
+        };
+        let grep = SeqGrep::new(&[TRUSEQ_READ1, NEXTERA], false, true);
+        let hits = grep.find(&seq);
+        assert_eq!(hits.len(), 1);
+        assert_eq!((hits[0].query, hits[0].start), (0, 12));
+
+        let mut screen = oligo_screen(ADAPTERS, 13, 0.5);
+        let hit = screen.check(&seq);
+        assert_eq!(screen.names()[hit.contaminant.unwrap()], b"TruSeq_Read1");
+    }
+}
@@ -0,0 +1,194 @@
+use crate::errors::*;
+use crate::kmer::*;
+use crate::reader::*;
+use crate::seq::*;
+use crate::writer::*;
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+
+/// Orientation of a read relative to the references.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    Forward,
+    Reverse,
+    Unknown,
+}
+
+impl Orientation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Orientation::Forward => "+",
+            Orientation::Reverse => "-",
+            Orientation::Unknown => "?",
+        }
+    }
+}
+
+/// Statistics of orienting a record stream.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OrientStats {
+    pub forward: u64,
+    pub reversed: u64,
+    pub unknown: u64,
+}
+
+/// Detects the orientation of reads by counting k-mers shared with the forward strand of
+/// references (e.g. a reference amplicon or primers) on both strands of reads.
+pub struct Orienter {
+    k: usize,
+    kmers: HashSet<u64>,
+    min_hits: usize,
+}
+
+impl Orienter {
+    /// Creates an orienter with k-mer size k (1..=32). Reads need at least `min_hits` more
+    /// hits on one strand than the other to be oriented.
+    pub fn new(k: usize, min_hits: usize) -> Self {
+        assert!(
+            (1..=MAX_K).contains(&k),
+            "k should be in the range of 1..=32"
+        );
+        Self {
+            k,
+            kmers: HashSet::new(),
+            min_hits: min_hits.max(1),
+        }
+    }
+
+    /// Adds a reference sequence in the desired orientation.
+    pub fn add_reference(&mut self, seq: &[u8]) {
+        self.kmers
+            .extend(KmerIter::new(seq, self.k, false).map(|(_, code)| code));
+    }
+
+    /// Adds every record of the reader as a reference.
+    pub fn add_references_from_reader<R: BufRead>(
+        &mut self,
+        reader: &mut Reader<R>,
+    ) -> Result<(), FastxErr> {
+        while let Some(res) = reader.next() {
+            self.add_reference(res?.seq);
+        }
+        Ok(())
+    }
+
+    /// Detects the orientation of a read, returns it with the numbers of forward and reverse hits.
+    pub fn detect(&self, seq: &Seq) -> (Orientation, usize, usize) {
+        let mut fwd = 0;
+        let mut rev = 0;
+        for (_, code) in KmerIter::new(seq.seq, self.k, false) {
+            fwd += self.kmers.contains(&code) as usize;
+            rev += self.kmers.contains(&revcomp_kmer(code, self.k)) as usize;
+        }
+        let orientation = if fwd >= rev + self.min_hits {
+            Orientation::Forward
+        } else if rev >= fwd + self.min_hits {
+            Orientation::Reverse
+        } else {
+            Orientation::Unknown
+        };
+        (orientation, fwd, rev)
+    }
+
+    /// Writes all reads in the forward orientation: reverse reads are reverse complemented
+    /// (with quality scores reversed), forward and unknown ones are written unchanged.
+    /// A tab-delimited line of ID, orientation (+/-/?), forward hits and reverse hits is written
+    /// to `report` for every read if given.
+    pub fn orient<R: BufRead, W: Write, T: Write>(
+        &self,
+        reader: &mut Reader<R>,
+        writer: &mut Writer<W>,
+        mut report: Option<&mut T>,
+    ) -> Result<OrientStats, FastxErr> {
+        let mut stats = OrientStats::default();
+        let mut qual_buf = Vec::new();
+        while let Some(res) = reader.next() {
+            let seq = res?;
+            let (orientation, fwd, rev) = self.detect(&seq);
+            if let Some(w) = report.as_deref_mut() {
+                w.write_all(seq.id)?;
+                writeln!(w, "\t{}\t{fwd}\t{rev}", orientation.as_str())?;
+            }
+            match orientation {
+                Orientation::Reverse => {
+                    stats.reversed += 1;
+                    let rc = seq.rc();
+                    let qual = seq.qual.map(|q| {
+                        qual_buf.clear();
+                        qual_buf.extend(q.iter().rev());
+                        qual_buf.as_slice()
+                    });
+                    writer.write(&Seq {
+                        seq: &rc,
+                        qual,
+                        ..seq
+                    })?;
+                }
+                Orientation::Forward => {
+                    stats.forward += 1;
+                    writer.write(&seq)?;
+                }
+                Orientation::Unknown => {
+                    stats.unknown += 1;
+                    writer.write(&seq)?;
+                }
+            }
+        }
+        writer.flush()?;
+        if let Some(w) = report {
+            w.flush()?;
+        }
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const AMPLICON: &[u8] = b"GTGCCAGCMGCCGCGGTAATACGTAGGGTGCAAGCGTTAATCGGAATTACTGGGCGTAAAG";
+
+    #[test]
+    fn test_orient() {
+        let mut orienter = Orienter::new(11, 2);
+        orienter.add_reference(AMPLICON);
+
+        let fwd = &AMPLICON[15..50];
+        let rev = revcomp(fwd);
+        let input = format!(
+            "@f\n{}\n+\n{}\n@r\n{}\n+\n#{}\n@u\nCCCCCCCCCCCCCCC\n+\nIIIIIIIIIIIIIII\n",
+            String::from_utf8_lossy(fwd),
+            "I".repeat(fwd.len()),
+            String::from_utf8_lossy(&rev),
+            "I".repeat(rev.len() - 1),
+        );
+
+        let mut reader = Reader::from_reader(Cursor::new(input.as_bytes()));
+        let mut writer = Writer::from_writer(Vec::new());
+        let mut report = Vec::new();
+        let stats = orienter
+            .orient(&mut reader, &mut writer, Some(&mut report))
+            .unwrap();
+
+        assert_eq!(
+            stats,
+            OrientStats {
+                forward: 1,
+                reversed: 1,
+                unknown: 1,
+            }
+        );
+        let report = String::from_utf8(report).unwrap();
+        assert!(report.starts_with("f\t+\t25\t0\nr\t-\t0\t25\nu\t?\t0\t0\n"));
+
+        // the reversed read is now identical to the forward one, with reversed qualities
+        let out = String::from_utf8(writer.into_inner()).unwrap();
+        let expected = format!(
+            "@r\n{}\n+\n{}#\n",
+            String::from_utf8_lossy(fwd),
+            "I".repeat(fwd.len() - 1)
+        );
+        assert!(out.contains(&expected));
+    }
+}
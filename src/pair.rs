@@ -0,0 +1,1048 @@
+use crate::errors::*;
+use crate::idnorm::*;
+use crate::reader::*;
+use crate::seq::*;
+use crate::util::*;
+use crate::writer::*;
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+// an owned record buffered while waiting for its mate
+struct Pending {
+    id: Vec<u8>,
+    desc: Vec<u8>,
+    seq: Vec<u8>,
+    qual: Option<Vec<u8>>,
+    order: u64, // arrival order, to write singletons in input order
+}
+
+impl Pending {
+    fn new(seq: &Seq, order: u64) -> Self {
+        Self {
+            id: seq.id.to_vec(),
+            desc: seq.desc.to_vec(),
+            seq: seq.seq.to_vec(),
+            qual: seq.qual.map(|q| q.to_vec()),
+            order,
+        }
+    }
+
+    fn as_seq(&self) -> Seq<'_> {
+        Seq {
+            id: &self.id,
+            desc: &self.desc,
+            seq: &self.seq,
+            qual: self.qual.as_deref(),
+        }
+    }
+}
+
+/// A reader of paired-end reads in two in-sync files, yielding both mates of every pair.
+///
+/// Mates must have the same ID, apart from "/1" and "/2" suffixes or descriptions starting with
+/// "1:" and "2:" (Casava 1.8+). Reading fails with `MateMismatch` at the first pair of different
+/// IDs, or when one file ends earlier (shown as "EOF"), e.g., after filtering one file alone;
+/// such files can be fixed with `repair`.
+pub struct PairedReader<R1: BufRead, R2: BufRead> {
+    r1: Reader<R1>,
+    r2: Reader<R2>,
+    pairs: u64,
+    norm: IdNormalizer,
+}
+
+impl PairedReader<Box<dyn BufRead>, Box<dyn BufRead>> {
+    /// Creates a PairedReader from the file paths of read 1 and read 2.
+    pub fn new<P1: AsRef<Path>, P2: AsRef<Path>>(r1: P1, r2: P2) -> Result<Self, FastxErr> {
+        Ok(Self::from_readers(Reader::new(r1)?, Reader::new(r2)?))
+    }
+}
+
+impl<R1: BufRead, R2: BufRead> PairedReader<R1, R2> {
+    /// Creates a PairedReader from the readers of read 1 and read 2.
+    pub fn from_readers(r1: Reader<R1>, r2: Reader<R2>) -> Self {
+        Self {
+            r1,
+            r2,
+            pairs: 0,
+            norm: IdNormalizer::default(),
+        }
+    }
+
+    /// Sets the normalizer of mate IDs before comparing them, none by default.
+    /// Version stripping is not applied, see `check_pairs_with`.
+    pub fn set_id_normalizer(&mut self, norm: IdNormalizer) {
+        self.norm = norm.for_mates();
+    }
+
+    /// Returns the number of pairs read.
+    pub fn pairs(&self) -> u64 {
+        self.pairs
+    }
+
+    /// Returns the readers of read 1 and read 2.
+    pub fn into_inner(self) -> (Reader<R1>, Reader<R2>) {
+        (self.r1, self.r2)
+    }
+
+    /// Returns the next pair of mates.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Result<(Seq<'_>, Seq<'_>), FastxErr>> {
+        let res = next_pair(&mut self.r1, &mut self.r2, &self.norm);
+        if let Some(Ok(_)) = res {
+            self.pairs += 1;
+        }
+        res
+    }
+}
+
+// reads the next records of in-sync files, failing with `MateMismatch` if they are not mates
+fn next_pair<'a, 'b, R1: BufRead, R2: BufRead>(
+    r1: &'a mut Reader<R1>,
+    r2: &'b mut Reader<R2>,
+    norm: &IdNormalizer,
+) -> Option<Result<(Seq<'a>, Seq<'b>), FastxErr>> {
+    let (a, b) = match (r1.next(), r2.next()) {
+        (None, None) => return None,
+        (Some(Ok(a)), Some(Ok(b))) => (a, b),
+        (Some(Err(e)), _) | (_, Some(Err(e))) => return Some(Err(e)),
+        (Some(Ok(a)), None) => return Some(Err(mate_mismatch(a.id, b"EOF"))),
+        (None, Some(Ok(b))) => return Some(Err(mate_mismatch(b"EOF", b.id))),
+    };
+    if !norm.same_id(a.mate_id().0, b.mate_id().0) {
+        return Some(Err(mate_mismatch(a.id, b.id)));
+    }
+    Some(Ok((a, b)))
+}
+
+fn mate_mismatch(id1: &[u8], id2: &[u8]) -> FastxErr {
+    FastxErr::MateMismatch(
+        String::from_utf8_lossy(id1).to_string(),
+        String::from_utf8_lossy(id2).to_string(),
+    )
+}
+
+/// A reader of paired-end reads interleaved in one file, i.e., read 1 and read 2 of every pair
+/// in turn, yielding both mates of every pair.
+///
+/// Mate IDs are checked as by `PairedReader`. Reading fails with `MateMismatch` at the first pair
+/// of different IDs, or on an odd trailing record (its mate shown as "EOF").
+pub struct InterleavedReader<R: BufRead> {
+    reader: Reader<R>,
+    mate1: RecordBuf,
+    pairs: u64,
+    norm: IdNormalizer,
+}
+
+impl InterleavedReader<Box<dyn BufRead>> {
+    /// Creates an InterleavedReader from a file path, "-" for STDIN.
+    pub fn new<P: AsRef<Path>>(file: P) -> Result<Self, FastxErr> {
+        Ok(Self::from_reader(Reader::new(file)?))
+    }
+}
+
+impl<R: BufRead> InterleavedReader<R> {
+    /// Creates an InterleavedReader from a reader.
+    pub fn from_reader(reader: Reader<R>) -> Self {
+        Self {
+            reader,
+            mate1: RecordBuf::new(),
+            pairs: 0,
+            norm: IdNormalizer::default(),
+        }
+    }
+
+    /// Sets the normalizer of mate IDs before comparing them, none by default.
+    /// Version stripping is not applied, see `check_pairs_with`.
+    pub fn set_id_normalizer(&mut self, norm: IdNormalizer) {
+        self.norm = norm.for_mates();
+    }
+
+    /// Returns the number of pairs read.
+    pub fn pairs(&self) -> u64 {
+        self.pairs
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> Reader<R> {
+        self.reader
+    }
+
+    /// Returns the next pair of mates.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Result<(Seq<'_>, Seq<'_>), FastxErr>> {
+        // read 1 is copied, so both mates can be borrowed at once
+        match self.reader.read_into(&mut self.mate1) {
+            Ok(true) => {}
+            Ok(false) => return None,
+            Err(e) => return Some(Err(e)),
+        }
+        let a = self.mate1.as_seq();
+        let b = match self.reader.next() {
+            None => return Some(Err(mate_mismatch(a.id, b"EOF"))),
+            Some(Err(e)) => return Some(Err(e)),
+            Some(Ok(b)) => b,
+        };
+        if !self.norm.same_id(a.mate_id().0, b.mate_id().0) {
+            return Some(Err(mate_mismatch(a.id, b.id)));
+        }
+        self.pairs += 1;
+        Some(Ok((a, b)))
+    }
+}
+
+/// A reader pulling one record from each of multiple readers in turn, e.g., to re-interleave
+/// split lanes or to merge technical replicates in a streaming fashion.
+///
+/// Readers reaching EOF drop out of the rotation, and the remaining ones keep taking turns
+/// until all are exhausted.
+pub struct MergedReader<R: BufRead> {
+    readers: Vec<Reader<R>>,
+    done: Vec<bool>,
+    next: usize,
+    source: usize,
+    records: u64,
+}
+
+impl MergedReader<Box<dyn BufRead>> {
+    /// Creates a MergedReader from file paths, "-" for STDIN.
+    pub fn new<P: AsRef<Path>>(files: &[P]) -> Result<Self, FastxErr> {
+        let readers = files
+            .iter()
+            .map(Reader::new)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::from_readers(readers))
+    }
+}
+
+impl<R: BufRead> MergedReader<R> {
+    /// Creates a MergedReader from readers, taking turns in the given order.
+    pub fn from_readers(readers: Vec<Reader<R>>) -> Self {
+        Self {
+            done: vec![false; readers.len()],
+            readers,
+            next: 0,
+            source: 0,
+            records: 0,
+        }
+    }
+
+    /// Returns the index of the reader of the last record.
+    pub fn source(&self) -> usize {
+        self.source
+    }
+
+    /// Returns the number of records read.
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    /// Returns the underlying readers.
+    pub fn into_inner(self) -> Vec<Reader<R>> {
+        self.readers
+    }
+
+    /// Returns the next record of the reader in turn.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Result<Seq<'_>, FastxErr>> {
+        let n = self.readers.len();
+        for _ in 0..n {
+            let i = self.next;
+            self.next = (i + 1) % n;
+            if self.done[i] {
+                continue;
+            }
+            match self.readers[i].read_record() {
+                None => self.done[i] = true,
+                Some(Err(e)) => {
+                    // a broken reader is not read again
+                    self.done[i] = true;
+                    return Some(Err(e));
+                }
+                Some(Ok((header_end, seq_end))) => {
+                    self.source = i;
+                    self.records += 1;
+                    return Some(Ok(self.readers[i].record(header_end, seq_end)));
+                }
+            }
+        }
+        None
+    }
+}
+
+impl<R: BufRead> RecordSource for MergedReader<R> {
+    fn next_record(&mut self) -> Option<Result<Seq<'_>, FastxErr>> {
+        self.next()
+    }
+}
+
+/// The result of checking whether two files are in sync.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PairCheck {
+    /// Number of record pairs with matching mate IDs before the first mismatch.
+    pub pairs: u64,
+    /// The 1-based index and IDs of the first pair with mismatched IDs.
+    pub first_mismatch: Option<(u64, Vec<u8>, Vec<u8>)>,
+    /// Whether one file has more records than the other.
+    pub unequal_counts: bool,
+}
+
+impl PairCheck {
+    /// Returns whether all records are properly paired.
+    pub fn is_ok(&self) -> bool {
+        self.first_mismatch.is_none() && !self.unequal_counts
+    }
+}
+
+/// Checks that the i-th records of the two readers are mates, stopping at the first mismatch.
+pub fn check_pairs<R1: BufRead, R2: BufRead>(
+    r1: &mut Reader<R1>,
+    r2: &mut Reader<R2>,
+) -> Result<PairCheck, FastxErr> {
+    check_pairs_with(r1, r2, &IdNormalizer::default())
+}
+
+/// Checks pairs like `check_pairs`, comparing mate IDs after normalization.
+///
+/// Version stripping is not applied to mate IDs, since ".N" suffixes of read IDs like
+/// "SRR1.1" and "SRR1.2" tell different reads apart.
+pub fn check_pairs_with<R1: BufRead, R2: BufRead>(
+    r1: &mut Reader<R1>,
+    r2: &mut Reader<R2>,
+    norm: &IdNormalizer,
+) -> Result<PairCheck, FastxErr> {
+    let norm = norm.for_mates();
+    let mut check = PairCheck::default();
+    loop {
+        match (r1.next(), r2.next()) {
+            (None, None) => return Ok(check),
+            (Some(a), Some(b)) => {
+                let (a, b) = (a?, b?);
+                if !norm.same_id(a.mate_id().0, b.mate_id().0) {
+                    check.first_mismatch = Some((check.pairs + 1, a.id.to_vec(), b.id.to_vec()));
+                    return Ok(check);
+                }
+                check.pairs += 1;
+            }
+            (Some(res), None) | (None, Some(res)) => {
+                res?;
+                check.unequal_counts = true;
+                return Ok(check);
+            }
+        }
+    }
+}
+
+/// Statistics of repairing paired files.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairStats {
+    pub pairs: u64,
+    /// Reads of the first file without mates.
+    pub singletons1: u64,
+    /// Reads of the second file without mates.
+    pub singletons2: u64,
+    /// The largest number of reads buffered while waiting for mates.
+    pub max_pending: usize,
+}
+
+/// Re-pairs reads of two desynchronized mate files (e.g., filtered separately) by mate IDs,
+/// writes matched pairs to `out1` and `out2` in sync, and reads without mates to `singletons`
+/// if given.
+///
+/// Both files are read in turn and reads are buffered until their mates show up, so memory
+/// usage is bounded by how far the files are out of sync rather than their sizes.
+/// It fails with `TooManyPending` if more than `max_pending` reads (0 for no limit) are buffered,
+/// e.g., when the files are sorted differently.
+pub fn repair<R1: BufRead, R2: BufRead, W1: Write, W2: Write, S: Write>(
+    r1: &mut Reader<R1>,
+    r2: &mut Reader<R2>,
+    out1: &mut Writer<W1>,
+    out2: &mut Writer<W2>,
+    singletons: Option<&mut Writer<S>>,
+    max_pending: usize,
+) -> Result<RepairStats, FastxErr> {
+    repair_with(
+        r1,
+        r2,
+        out1,
+        out2,
+        singletons,
+        max_pending,
+        &IdNormalizer::default(),
+    )
+}
+
+/// Re-pairs reads like `repair`, matching mates by normalized IDs.
+/// Version stripping is not applied, see `check_pairs_with`.
+pub fn repair_with<R1: BufRead, R2: BufRead, W1: Write, W2: Write, S: Write>(
+    r1: &mut Reader<R1>,
+    r2: &mut Reader<R2>,
+    out1: &mut Writer<W1>,
+    out2: &mut Writer<W2>,
+    mut singletons: Option<&mut Writer<S>>,
+    max_pending: usize,
+    norm: &IdNormalizer,
+) -> Result<RepairStats, FastxErr> {
+    let norm = norm.for_mates();
+    let mut stats = RepairStats::default();
+    // mate ID -> buffered read, of each file
+    let mut pending: [HashMap<Vec<u8>, Pending>; 2] = [HashMap::new(), HashMap::new()];
+    let mut order = 0u64;
+    let (mut eof1, mut eof2) = (false, false);
+
+    while !(eof1 && eof2) {
+        for side in 0..2 {
+            let res = if side == 0 {
+                if eof1 {
+                    continue;
+                }
+                r1.next()
+            } else {
+                if eof2 {
+                    continue;
+                }
+                r2.next()
+            };
+            let Some(res) = res else {
+                if side == 0 {
+                    eof1 = true;
+                } else {
+                    eof2 = true;
+                }
+                continue;
+            };
+            let seq = res?;
+            let key = norm.normalize(seq.mate_id().0);
+            match pending[1 - side].remove(key.as_ref()) {
+                Some(mate) => {
+                    let (a, b) = if side == 0 {
+                        (seq, mate.as_seq())
+                    } else {
+                        (mate.as_seq(), seq)
+                    };
+                    out1.write(&a)?;
+                    out2.write(&b)?;
+                    stats.pairs += 1;
+                }
+                None => {
+                    order += 1;
+                    if let Some(dup) = pending[side].insert(key.to_vec(), Pending::new(&seq, order))
+                    {
+                        // a duplicated ID, the earlier read can no longer be paired
+                        warn!(
+                            "duplicated read ID {} in file {}, the earlier read is written as a singleton",
+                            String::from_utf8_lossy(&key),
+                            side + 1
+                        );
+                        write_singletons(&mut singletons, vec![dup])?;
+                        if side == 0 {
+                            stats.singletons1 += 1;
+                        } else {
+                            stats.singletons2 += 1;
+                        }
+                    }
+                    let n = pending[0].len() + pending[1].len();
+                    stats.max_pending = stats.max_pending.max(n);
+                    if max_pending > 0 && n > max_pending {
+                        return Err(FastxErr::TooManyPending(n));
+                    }
+                }
+            }
+        }
+    }
+
+    let [p1, p2] = pending;
+    if !p1.is_empty() || !p2.is_empty() {
+        info!(
+            "{} reads of file 1 and {} reads of file 2 have no mates",
+            p1.len(),
+            p2.len()
+        );
+    }
+    stats.singletons1 += p1.len() as u64;
+    stats.singletons2 += p2.len() as u64;
+    let mut rest: Vec<Pending> = p1.into_values().chain(p2.into_values()).collect();
+    rest.sort_unstable_by_key(|p| p.order);
+    write_singletons(&mut singletons, rest)?;
+
+    out1.flush()?;
+    out2.flush()?;
+    if let Some(w) = singletons {
+        w.flush()?;
+    }
+    Ok(stats)
+}
+
+/// Statistics of processing paired reads.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PairedStats {
+    /// Number of input pairs.
+    pub pairs: u64,
+    /// Number of pairs with both mates kept.
+    pub kept_pairs: u64,
+    /// Number of kept reads of the first file whose mates were removed.
+    pub singletons1: u64,
+    /// Number of kept reads of the second file whose mates were removed.
+    pub singletons2: u64,
+    /// Number of pairs with only the first mate failing.
+    pub failed1: u64,
+    /// Number of pairs with only the second mate failing.
+    pub failed2: u64,
+    /// Number of pairs with both mates failing.
+    pub failed_both: u64,
+}
+
+/// How pairs with one failing mate are handled by filters and transforms,
+/// like `--pair-filter` of cutadapt and unpaired outputs of Trimmomatic and fastp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PairFilterMode {
+    /// Drops the pair if either mate fails, the default of cutadapt and fastp.
+    Any,
+    /// Drops the pair only if both mates fail, otherwise both mates are written,
+    /// the failing one untransformed.
+    Both,
+    /// Writes the passing mate as a singleton, like Trimmomatic.
+    #[default]
+    Singletons,
+}
+
+/// Applies a per-read transform (filtering, trimming, ...) to both mates of in-sync paired files.
+///
+/// `f` is called with the mate number (1 or 2) and the read, and returns the transformed read,
+/// e.g., a trimmed slice of it, or None to remove it. Pairs with both mates kept are written to
+/// `out1` and `out2`. A kept read whose mate is removed is an orphan, it is written to `singletons`
+/// if given, instead of being dropped silently, and counted either way.
+/// It fails with `MateMismatch` if the files are out of sync.
+pub fn process_pairs<R1, R2, W1, W2, S, F>(
+    r1: &mut Reader<R1>,
+    r2: &mut Reader<R2>,
+    out1: &mut Writer<W1>,
+    out2: &mut Writer<W2>,
+    singletons: Option<&mut Writer<S>>,
+    f: F,
+) -> Result<PairedStats, FastxErr>
+where
+    R1: BufRead,
+    R2: BufRead,
+    W1: Write,
+    W2: Write,
+    S: Write,
+    F: for<'a> FnMut(u8, Seq<'a>) -> Option<Seq<'a>>,
+{
+    process_pairs_with_mode(
+        r1,
+        r2,
+        out1,
+        out2,
+        singletons,
+        PairFilterMode::Singletons,
+        f,
+    )
+}
+
+/// Like `process_pairs`, with pairs of one failing mate handled by the mode.
+/// Singletons are only produced with `PairFilterMode::Singletons`.
+pub fn process_pairs_with_mode<R1, R2, W1, W2, S, F>(
+    r1: &mut Reader<R1>,
+    r2: &mut Reader<R2>,
+    out1: &mut Writer<W1>,
+    out2: &mut Writer<W2>,
+    mut singletons: Option<&mut Writer<S>>,
+    mode: PairFilterMode,
+    mut f: F,
+) -> Result<PairedStats, FastxErr>
+where
+    R1: BufRead,
+    R2: BufRead,
+    W1: Write,
+    W2: Write,
+    S: Write,
+    F: for<'a> FnMut(u8, Seq<'a>) -> Option<Seq<'a>>,
+{
+    let mut stats = PairedStats::default();
+    while let Some(res) = next_pair(r1, r2, &IdNormalizer::default()) {
+        let (a, b) = res?;
+        stats.pairs += 1;
+
+        let (ta, tb) = (f(1, a), f(2, b));
+        match (&ta, &tb) {
+            (Some(_), None) => stats.failed2 += 1,
+            (None, Some(_)) => stats.failed1 += 1,
+            (None, None) => stats.failed_both += 1,
+            _ => {}
+        }
+        match (ta, tb, mode) {
+            (Some(a), Some(b), _) => {
+                out1.write(&a)?;
+                out2.write(&b)?;
+                stats.kept_pairs += 1;
+            }
+            (None, None, _) | (_, _, PairFilterMode::Any) => {}
+            (ta, tb, PairFilterMode::Both) => {
+                // the failing mate is written as it was read
+                out1.write(&ta.unwrap_or(a))?;
+                out2.write(&tb.unwrap_or(b))?;
+                stats.kept_pairs += 1;
+            }
+            (Some(orphan), None, PairFilterMode::Singletons) => {
+                stats.singletons1 += 1;
+                if let Some(w) = singletons.as_deref_mut() {
+                    w.write(&orphan)?;
+                }
+            }
+            (None, Some(orphan), PairFilterMode::Singletons) => {
+                stats.singletons2 += 1;
+                if let Some(w) = singletons.as_deref_mut() {
+                    w.write(&orphan)?;
+                }
+            }
+        }
+    }
+
+    out1.flush()?;
+    out2.flush()?;
+    if let Some(w) = singletons {
+        w.flush()?;
+    }
+    Ok(stats)
+}
+
+/// Filters in-sync paired files with a per-read predicate, see `process_pairs` for orphan handling.
+pub fn filter_pairs<R1, R2, W1, W2, S, F>(
+    r1: &mut Reader<R1>,
+    r2: &mut Reader<R2>,
+    out1: &mut Writer<W1>,
+    out2: &mut Writer<W2>,
+    singletons: Option<&mut Writer<S>>,
+    mut keep: F,
+) -> Result<PairedStats, FastxErr>
+where
+    R1: BufRead,
+    R2: BufRead,
+    W1: Write,
+    W2: Write,
+    S: Write,
+    F: FnMut(&Seq) -> bool,
+{
+    process_pairs(r1, r2, out1, out2, singletons, |_, seq| {
+        keep(&seq).then_some(seq)
+    })
+}
+
+/// Like `filter_pairs`, with pairs of one failing mate handled by the mode.
+pub fn filter_pairs_with_mode<R1, R2, W1, W2, S, F>(
+    r1: &mut Reader<R1>,
+    r2: &mut Reader<R2>,
+    out1: &mut Writer<W1>,
+    out2: &mut Writer<W2>,
+    singletons: Option<&mut Writer<S>>,
+    mode: PairFilterMode,
+    mut keep: F,
+) -> Result<PairedStats, FastxErr>
+where
+    R1: BufRead,
+    R2: BufRead,
+    W1: Write,
+    W2: Write,
+    S: Write,
+    F: FnMut(&Seq) -> bool,
+{
+    process_pairs_with_mode(r1, r2, out1, out2, singletons, mode, |_, seq| {
+        keep(&seq).then_some(seq)
+    })
+}
+
+/// Subsamples pairs of in-sync paired files with a probability, keeping or removing both mates
+/// together, so no orphans are produced.
+pub fn sample_pairs<R1, R2, W1, W2>(
+    r1: &mut Reader<R1>,
+    r2: &mut Reader<R2>,
+    out1: &mut Writer<W1>,
+    out2: &mut Writer<W2>,
+    fraction: f64,
+    seed: u64,
+) -> Result<PairedStats, FastxErr>
+where
+    R1: BufRead,
+    R2: BufRead,
+    W1: Write,
+    W2: Write,
+{
+    let mut rng = Rng::new(seed);
+    let mut keep = false;
+    process_pairs::<_, _, _, _, Vec<u8>, _>(r1, r2, out1, out2, None, |mate, seq| {
+        if mate == 1 {
+            keep = rng.next_f64() < fraction;
+        }
+        keep.then_some(seq)
+    })
+}
+
+fn write_singletons<S: Write>(
+    singletons: &mut Option<&mut Writer<S>>,
+    reads: Vec<Pending>,
+) -> Result<(), FastxErr> {
+    if let Some(w) = singletons.as_deref_mut() {
+        for p in &reads {
+            w.write(&p.as_seq())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn reader(s: &str) -> Reader<Cursor<&[u8]>> {
+        Reader::from_reader(Cursor::new(s.as_bytes()))
+    }
+
+    fn ids(data: Vec<u8>) -> Vec<String> {
+        let mut r = Reader::from_reader(Cursor::new(data));
+        let mut ids = Vec::new();
+        while let Some(res) = r.next() {
+            ids.push(String::from_utf8_lossy(res.unwrap().id).to_string());
+        }
+        ids
+    }
+
+    #[test]
+    fn test_paired_reader() {
+        let mut pr = PairedReader::from_readers(
+            reader(">a/1\nAC\n>b 1:N:0\nG\n>c/1\nT\n"),
+            reader(">a/2\nGT\n>b 2:N:0\nC\n>d/2\nT\n"),
+        );
+        let (a, b) = pr.next().unwrap().unwrap();
+        assert_eq!(
+            (a.id, a.seq, b.id, b.seq),
+            (&b"a/1"[..], &b"AC"[..], &b"a/2"[..], &b"GT"[..])
+        );
+        let (a, b) = pr.next().unwrap().unwrap();
+        assert_eq!((a.id, b.id), (&b"b"[..], &b"b"[..]));
+        assert_eq!(pr.pairs(), 2);
+        match pr.next().unwrap() {
+            Err(FastxErr::MateMismatch(a, b)) => {
+                assert_eq!((a.as_str(), b.as_str()), ("c/1", "d/2"))
+            }
+            res => panic!("unexpected {:?}", res.map(|_| ())),
+        }
+        assert_eq!(pr.pairs(), 2);
+
+        let mut pr = PairedReader::from_readers(reader(">a/1\nA\n"), reader(">a/2\nA\n>b/2\nC\n"));
+        assert!(pr.next().unwrap().is_ok());
+        assert_eq!(
+            pr.next().unwrap().unwrap_err().to_string(),
+            "mates out of sync: EOF and b/2"
+        );
+        assert!(pr.next().is_none());
+
+        assert!(PairedReader::new("/nonexistent/r1.fq", "/nonexistent/r2.fq").is_err());
+    }
+
+    #[test]
+    fn test_interleaved_reader() {
+        let input =
+            "@a/1\nAC\n+\nII\n@a/2\nGT\n+\nII\n@b 1:N\nA\n+\nI\n@b 2:N\nC\n+\nI\n@c/1\nA\n+\nI\n";
+        let mut ir = InterleavedReader::from_reader(reader(input));
+        let (a, b) = ir.next().unwrap().unwrap();
+        assert_eq!(
+            (a.id, a.qual, b.seq),
+            (&b"a/1"[..], Some(&b"II"[..]), &b"GT"[..])
+        );
+        let (a, b) = ir.next().unwrap().unwrap();
+        assert_eq!((a.desc, b.desc), (&b"1:N"[..], &b"2:N"[..]));
+        assert_eq!(
+            ir.next().unwrap().unwrap_err().to_string(),
+            "mates out of sync: c/1 and EOF"
+        );
+        assert!(ir.next().is_none());
+        assert_eq!(ir.pairs(), 2);
+
+        let mut ir = InterleavedReader::from_reader(reader(">a/1\nA\n>b/2\nA\n"));
+        assert!(matches!(ir.next(), Some(Err(FastxErr::MateMismatch(..)))));
+    }
+
+    #[test]
+    fn test_merged_reader() {
+        let mut mr = MergedReader::from_readers(vec![
+            reader(">a1\nA\n>a2\nA\n>a3\nA\n"),
+            reader(""),
+            reader(">b1\nC\n"),
+            reader(">c1\nG\n>c2\nG\n"),
+        ]);
+        let mut merged = Vec::new();
+        while let Some(res) = mr.next() {
+            let seq = res.unwrap();
+            merged.push(String::from_utf8_lossy(seq.id).to_string());
+            let source = mr.source();
+            merged.push(source.to_string());
+        }
+        assert_eq!(
+            merged,
+            [
+                "a1", "0", "b1", "2", "c1", "3", "a2", "0", "c2", "3", "a3", "0"
+            ]
+        );
+        assert_eq!(mr.records(), 6);
+        assert!(mr.next().is_none());
+
+        let ids: Vec<Vec<u8>> = MergedReader::from_readers(vec![reader(">x\nA\n")])
+            .map_records(|seq| seq.id.to_vec())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(ids, vec![b"x".to_vec()]);
+        assert!(
+            MergedReader::from_readers(Vec::<Reader<&[u8]>>::new())
+                .next()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_check_pairs() {
+        let check = check_pairs(
+            &mut reader(">a/1\nA\n>b/1\nA\n"),
+            &mut reader(">a/2\nA\n>c/2\nA\n"),
+        )
+        .unwrap();
+        assert_eq!(check.pairs, 1);
+        assert_eq!(
+            check.first_mismatch,
+            Some((2, b"b/1".to_vec(), b"c/2".to_vec()))
+        );
+
+        let check = check_pairs(&mut reader(">a/1\nA\n"), &mut reader(">a/2\nA\n>b\nC\n")).unwrap();
+        assert!(check.unequal_counts);
+        assert!(!check.is_ok());
+    }
+
+    #[test]
+    fn test_repair() {
+        let mut r1 = reader("@a/1\nA\n+\nI\n@b/1\nC\n+\nI\n@c/1\nG\n+\nI\n@e/1\nT\n+\nI\n");
+        let mut r2 = reader("@b/2\nC\n+\nI\n@c/2\nG\n+\nI\n@d/2\nT\n+\nI\n@a/2\nA\n+\nI\n");
+        let mut out1 = Writer::from_writer(Vec::new());
+        let mut out2 = Writer::from_writer(Vec::new());
+        let mut single = Writer::from_writer(Vec::new());
+        let stats = repair(&mut r1, &mut r2, &mut out1, &mut out2, Some(&mut single), 0).unwrap();
+        assert_eq!(stats.pairs, 3);
+        assert_eq!((stats.singletons1, stats.singletons2), (1, 1));
+
+        assert_eq!(ids(out1.into_inner()), vec!["b/1", "c/1", "a/1"]);
+        assert_eq!(ids(out2.into_inner()), vec!["b/2", "c/2", "a/2"]);
+        assert_eq!(ids(single.into_inner()), vec!["d/2", "e/1"]);
+    }
+
+    #[test]
+    fn test_pairs_normalized_ids() {
+        let norm = IdNormalizer {
+            ignore_case: true,
+            ..Default::default()
+        };
+        let mut pr =
+            PairedReader::from_readers(reader(">A/1\nA\n>b/1\nC\n"), reader(">a/2\nA\n>B/2\nC\n"));
+        assert!(pr.next().unwrap().is_err());
+        let mut pr =
+            PairedReader::from_readers(reader(">A/1\nA\n>b/1\nC\n"), reader(">a/2\nA\n>B/2\nC\n"));
+        pr.set_id_normalizer(norm);
+        while let Some(res) = pr.next() {
+            res.unwrap();
+        }
+        assert_eq!(pr.pairs(), 2);
+
+        // ".N" suffixes of reads are not stripped as versions
+        let mut ir = InterleavedReader::from_reader(reader(">r.1/1\nA\n>r.2/2\nA\n"));
+        ir.set_id_normalizer(IdNormalizer::all());
+        assert!(matches!(
+            ir.next(),
+            Some(Err(FastxErr::MateMismatch(a, b))) if a == "r.1/1" && b == "r.2/2"
+        ));
+        let check = check_pairs_with(
+            &mut reader(">SRR1.1/1\nA\n"),
+            &mut reader(">SRR1.2/2\nA\n"),
+            &IdNormalizer::all(),
+        )
+        .unwrap();
+        assert_eq!(check.pairs, 0);
+
+        let check = check_pairs_with(
+            &mut reader(">lcl|a\nA\n>b\nC\n"),
+            &mut reader(">a\nA\n>c\nC\n"),
+            &IdNormalizer::all(),
+        )
+        .unwrap();
+        assert_eq!(check.pairs, 1);
+        assert_eq!(
+            check.first_mismatch,
+            Some((2, b"b".to_vec(), b"c".to_vec()))
+        );
+
+        let mut r1 = reader("@A/1\nA\n+\nI\n@b/1\nC\n+\nI\n");
+        let mut r2 = reader("@B/2\nC\n+\nI\n@a/2\nA\n+\nI\n");
+        let mut out1 = Writer::from_writer(Vec::new());
+        let mut out2 = Writer::from_writer(Vec::new());
+        let stats = repair_with(
+            &mut r1,
+            &mut r2,
+            &mut out1,
+            &mut out2,
+            None::<&mut Writer<Vec<u8>>>,
+            0,
+            &norm,
+        )
+        .unwrap();
+        assert_eq!(stats.pairs, 2);
+        assert_eq!(ids(out1.into_inner()), vec!["b/1", "A/1"]);
+        assert_eq!(ids(out2.into_inner()), vec!["B/2", "a/2"]);
+    }
+
+    #[test]
+    fn test_process_pairs_orphans() {
+        let mut r1 = reader("@a/1\nACGT\n+\nIIII\n@b/1\nA\n+\nI\n@c/1\nAC\n+\nII\n");
+        let mut r2 = reader("@a/2\nACGT\n+\nIIII\n@b/2\nACGT\n+\nIIII\n@c/2\nA\n+\nI\n");
+        let mut out1 = Writer::from_writer(Vec::new());
+        let mut out2 = Writer::from_writer(Vec::new());
+        let mut single = Writer::from_writer(Vec::new());
+        // trim the last base, and remove reads shorter than 2 bp
+        let stats = process_pairs(
+            &mut r1,
+            &mut r2,
+            &mut out1,
+            &mut out2,
+            Some(&mut single),
+            |_, seq| {
+                (seq.len() >= 2).then(|| Seq {
+                    seq: &seq.seq[..seq.len() - 1],
+                    qual: seq.qual.map(|q| &q[..q.len() - 1]),
+                    ..seq
+                })
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            stats,
+            PairedStats {
+                pairs: 3,
+                kept_pairs: 1,
+                singletons1: 1,
+                singletons2: 1,
+                failed1: 1,
+                failed2: 1,
+                failed_both: 0,
+            }
+        );
+        assert_eq!(out1.into_inner(), b"@a/1\nACG\n+\nIII\n");
+        assert_eq!(out2.into_inner(), b"@a/2\nACG\n+\nIII\n");
+        assert_eq!(single.into_inner(), b"@b/2\nACG\n+\nIII\n@c/1\nA\n+\nI\n");
+    }
+
+    #[test]
+    fn test_filter_and_sample_pairs() {
+        let mut out1 = Writer::from_writer(Vec::new());
+        let mut out2 = Writer::from_writer(Vec::new());
+        let stats = filter_pairs::<_, _, _, _, Vec<u8>, _>(
+            &mut reader(">a/1\nNN\n>b/1\nAC\n"),
+            &mut reader(">a/2\nAC\n>b/2\nAC\n"),
+            &mut out1,
+            &mut out2,
+            None,
+            |seq| seq.count_base(b'N') == 0,
+        )
+        .unwrap();
+        assert_eq!((stats.kept_pairs, stats.singletons2), (1, 1));
+
+        let input1: String = (0..100).map(|i| format!(">r{i}/1\nA\n")).collect();
+        let input2: String = (0..100).map(|i| format!(">r{i}/2\nA\n")).collect();
+        let mut out1 = Writer::from_writer(Vec::new());
+        let mut out2 = Writer::from_writer(Vec::new());
+        let stats = sample_pairs(
+            &mut reader(&input1),
+            &mut reader(&input2),
+            &mut out1,
+            &mut out2,
+            0.5,
+            11,
+        )
+        .unwrap();
+        assert!(stats.kept_pairs > 25 && stats.kept_pairs < 75);
+        assert_eq!(stats.singletons1 + stats.singletons2, 0);
+        assert_eq!(
+            out1.into_inner().len(),
+            out2.into_inner().len(),
+            "mates should be kept together"
+        );
+
+        let res = filter_pairs::<_, _, _, _, Vec<u8>, _>(
+            &mut reader(">a/1\nA\n"),
+            &mut reader(">b/2\nA\n"),
+            &mut Writer::from_writer(Vec::new()),
+            &mut Writer::from_writer(Vec::new()),
+            None,
+            |_| true,
+        );
+        assert!(matches!(res, Err(FastxErr::MateMismatch(_, _))));
+    }
+
+    #[test]
+    fn test_filter_pairs_with_mode() {
+        let input1 = ">a/1\nNN\n>b/1\nAC\n>c/1\nNA\n>d/1\nAC\n";
+        let input2 = ">a/2\nAC\n>b/2\nNC\n>c/2\nNN\n>d/2\nAC\n";
+        let run = |mode| {
+            let mut out1 = Writer::from_writer(Vec::new());
+            let mut out2 = Writer::from_writer(Vec::new());
+            let mut single = Writer::from_writer(Vec::new());
+            let stats = filter_pairs_with_mode(
+                &mut reader(input1),
+                &mut reader(input2),
+                &mut out1,
+                &mut out2,
+                Some(&mut single),
+                mode,
+                |seq| seq.count_base(b'N') == 0,
+            )
+            .unwrap();
+            (
+                stats,
+                ids(out1.into_inner()),
+                ids(out2.into_inner()),
+                ids(single.into_inner()),
+            )
+        };
+
+        let (stats, out1, out2, single) = run(PairFilterMode::Any);
+        assert_eq!((stats.failed1, stats.failed2, stats.failed_both), (1, 1, 1));
+        assert_eq!((stats.kept_pairs, stats.singletons1), (1, 0));
+        assert_eq!(out1, vec!["d/1"]);
+        assert_eq!(out2, vec!["d/2"]);
+        assert!(single.is_empty());
+
+        let (stats, out1, out2, single) = run(PairFilterMode::Both);
+        assert_eq!(stats.kept_pairs, 3);
+        assert_eq!(out1, vec!["a/1", "b/1", "d/1"]);
+        assert_eq!(out2, vec!["a/2", "b/2", "d/2"]);
+        assert!(single.is_empty());
+
+        let (stats, _, _, single) = run(PairFilterMode::Singletons);
+        assert_eq!(
+            (stats.kept_pairs, stats.singletons1, stats.singletons2),
+            (1, 1, 1)
+        );
+        assert_eq!(single, vec!["a/2", "b/1"]);
+    }
+
+    #[test]
+    fn test_repair_max_pending() {
+        let mut out1 = Writer::from_writer(Vec::new());
+        let mut out2 = Writer::from_writer(Vec::new());
+        let res = repair::<_, _, _, _, Vec<u8>>(
+            &mut reader(">a/1\nA\n>b/1\nA\n>c/1\nA\n"),
+            &mut reader(">c/2\nA\n>b/2\nA\n>a/2\nA\n"),
+            &mut out1,
+            &mut out2,
+            None,
+            2,
+        );
+        assert!(matches!(res, Err(FastxErr::TooManyPending(3))));
+    }
+}
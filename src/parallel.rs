@@ -0,0 +1,376 @@
+use crate::batch::*;
+use crate::errors::*;
+use crate::reader::*;
+use crate::scratch::*;
+use crate::seq::*;
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// Number of records of a batch sent by `spawn_reader`.
+pub const SPAWN_BATCH_RECORDS: usize = 1024;
+
+/// Maximum number of batches queued in the channel of `spawn_reader`.
+pub const SPAWN_QUEUE_SIZE: usize = 4;
+
+/// Options of `process_parallel`.
+#[derive(Debug, Clone)]
+pub struct ParallelOptions {
+    /// Number of worker threads, 0 for the number of available CPUs.
+    pub threads: usize,
+    /// Maximum number of records of a batch sent to a worker.
+    pub batch_records: usize,
+    /// Maximum number of bytes of a batch, 0 for no limit.
+    pub batch_bytes: usize,
+    /// Whether results are passed to the output closure in the input order.
+    pub ordered: bool,
+}
+
+impl Default for ParallelOptions {
+    fn default() -> Self {
+        Self {
+            threads: 0,
+            batch_records: 1024,
+            batch_bytes: 4 << 20,
+            ordered: true,
+        }
+    }
+}
+
+// a batch returned by a worker, None if the worker panicked
+type Done<T> = Option<(usize, RecordBatch, Vec<T>)>;
+
+/// Processes records of a source with a pool of worker threads, and returns the number of records.
+///
+/// Records are parsed on the calling thread and sent to workers in batches. `work` is called on
+/// worker threads with every record and a `Scratch` owned by the worker, and results are passed
+/// to `output` on the calling thread, in the input order if `opts.ordered` is set. So neither the
+/// source nor the output (e.g., a `Writer`) has to be `Send`.
+/// At most two batches per worker are in flight, which bounds the memory.
+/// Processing stops at the first error of reading or of `output`. Panics of workers are
+/// propagated to the calling thread.
+pub fn process_parallel<S, T, F, O>(
+    source: &mut S,
+    opts: &ParallelOptions,
+    work: F,
+    mut output: O,
+) -> Result<u64, FastxErr>
+where
+    S: RecordSource,
+    T: Send,
+    F: Fn(Seq, &mut Scratch) -> T + Sync,
+    O: FnMut(T) -> Result<(), FastxErr>,
+{
+    let threads = match opts.threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+    let max_in_flight = threads * 2;
+    let batch_records = opts.batch_records.max(1);
+
+    thread::scope(|scope| {
+        let (job_tx, job_rx) = mpsc::sync_channel::<(usize, RecordBatch)>(max_in_flight);
+        let (done_tx, done_rx) = mpsc::channel::<Done<T>>();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+
+        for _ in 0..threads {
+            let job_rx = Arc::clone(&job_rx);
+            let done_tx = done_tx.clone();
+            let work = &work;
+            scope.spawn(move || {
+                let mut scratch = Scratch::new();
+                let res = panic::catch_unwind(AssertUnwindSafe(|| {
+                    worker(&job_rx, &done_tx, work, &mut scratch)
+                }));
+                if let Err(e) = res {
+                    let _ = done_tx.send(None);
+                    panic::resume_unwind(e);
+                }
+            });
+        }
+        drop(done_tx);
+
+        let mut job_tx = Some(job_tx);
+        let mut free: Vec<RecordBatch> = Vec::new();
+        let mut pending: BTreeMap<usize, Vec<T>> = BTreeMap::new();
+        let (mut next_id, mut next_out, mut in_flight) = (0usize, 0usize, 0usize);
+        let mut records = 0u64;
+        let mut result: Result<(), FastxErr> = Ok(());
+
+        loop {
+            // keep workers busy while there is input
+            if result.is_ok()
+                && in_flight < max_in_flight
+                && let Some(tx) = &job_tx
+            {
+                let mut batch = free.pop().unwrap_or_default();
+                batch.clear();
+                match batch.fill(source, batch_records, opts.batch_bytes) {
+                    Ok(0) => job_tx = None,
+                    Ok(n) => {
+                        // never blocks, the channel has room for all batches in flight
+                        if tx.send((next_id, batch)).is_err() {
+                            // all workers panicked
+                            break;
+                        }
+                        records += n as u64;
+                        next_id += 1;
+                        in_flight += 1;
+                    }
+                    Err(e) => result = Err(e),
+                }
+                continue;
+            }
+            if in_flight == 0 {
+                break;
+            }
+
+            let Ok(Some((id, batch, results))) = done_rx.recv() else {
+                // a worker panicked, its panic is propagated when the scope ends
+                break;
+            };
+            in_flight -= 1;
+            free.push(batch);
+            if result.is_err() {
+                continue;
+            }
+            if opts.ordered {
+                pending.insert(id, results);
+                while let Some(results) = pending.remove(&next_out) {
+                    next_out += 1;
+                    if let Err(e) = results.into_iter().try_for_each(&mut output) {
+                        result = Err(e);
+                        break;
+                    }
+                }
+            } else if let Err(e) = results.into_iter().try_for_each(&mut output) {
+                result = Err(e);
+            }
+            if result.is_err() {
+                job_tx = None;
+            }
+        }
+        drop(job_tx);
+
+        result.map(|_| records)
+    })
+}
+
+/// Reads and decompresses a file ("-" for stdin) on a dedicated thread, and sends records in
+/// batches of `SPAWN_BATCH_RECORDS` over a bounded channel, so reading overlaps with the
+/// computation and writing of the receiving side.
+///
+/// The thread returns the number of records sent, or the first error of opening or reading the
+/// file, after which the channel is closed. It also stops when the receiver is dropped.
+pub fn spawn_reader<P: AsRef<Path>>(
+    path: P,
+) -> (JoinHandle<Result<u64, FastxErr>>, Receiver<Vec<SeqOwned>>) {
+    spawn_reader_with(path, SPAWN_BATCH_RECORDS, SPAWN_QUEUE_SIZE)
+}
+
+/// Same as `spawn_reader`, with the number of records of a batch and the maximum number of
+/// queued batches, which bound the memory together.
+pub fn spawn_reader_with<P: AsRef<Path>>(
+    path: P,
+    batch_records: usize,
+    queue_size: usize,
+) -> (JoinHandle<Result<u64, FastxErr>>, Receiver<Vec<SeqOwned>>) {
+    let path = path.as_ref().to_path_buf();
+    let batch_records = batch_records.max(1);
+    let (tx, rx) = mpsc::sync_channel(queue_size);
+    let handle = thread::spawn(move || {
+        let mut reader = Reader::new(&path)?;
+        let mut records = 0u64;
+        loop {
+            let mut batch = Vec::with_capacity(batch_records);
+            let n = reader.read_batch(batch_records, &mut batch)?;
+            if n == 0 || tx.send(batch).is_err() {
+                break;
+            }
+            records += n as u64;
+        }
+        Ok(records)
+    });
+    (handle, rx)
+}
+
+fn worker<T, F>(
+    jobs: &Mutex<Receiver<(usize, RecordBatch)>>,
+    done: &mpsc::Sender<Done<T>>,
+    work: &F,
+    scratch: &mut Scratch,
+) where
+    F: Fn(Seq, &mut Scratch) -> T,
+{
+    loop {
+        let job = jobs.lock().unwrap_or_else(|e| e.into_inner()).recv();
+        let Ok((id, batch)) = job else {
+            return;
+        };
+        let results: Vec<T> = batch.iter().map(|seq| work(seq, scratch)).collect();
+        if done.send(Some((id, batch, results))).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::*;
+
+    fn input(n: usize) -> String {
+        (0..n)
+            .map(|i| format!(">r{i}\nACG{}\n", "T".repeat(i % 7)))
+            .collect()
+    }
+
+    #[test]
+    fn test_spawn_reader() {
+        let path =
+            std::env::temp_dir().join(format!("fastseq-spawn-reader-{}.fa", std::process::id()));
+        let mut writer = Writer::new(&path).unwrap();
+        let data = input(2500);
+        let mut reader = Reader::from_bytes(data.as_bytes());
+        while let Some(res) = reader.next() {
+            writer.write(&res.unwrap()).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let (handle, rx) = spawn_reader_with(&path, 1000, 1);
+        let batches: Vec<Vec<SeqOwned>> = rx.iter().collect();
+        assert_eq!(handle.join().unwrap().unwrap(), 2500);
+        assert_eq!(
+            batches.iter().map(|b| b.len()).collect::<Vec<_>>(),
+            vec![1000, 1000, 500]
+        );
+        assert_eq!(batches[2][499].id, b"r2499");
+        assert_eq!(batches[0][3].seq, b"ACGTTT");
+
+        // stops when the receiver is dropped
+        let (handle, rx) = spawn_reader_with(&path, 10, 1);
+        assert_eq!(rx.recv().unwrap().len(), 10);
+        drop(rx);
+        assert!(handle.join().unwrap().unwrap() < 2500);
+        std::fs::remove_file(&path).unwrap();
+
+        let (handle, rx) = spawn_reader(&path);
+        assert!(rx.recv().is_err());
+        assert!(handle.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_process_parallel_ordered() {
+        let data = input(5000);
+        let mut reader = Reader::from_bytes(data.as_bytes());
+        let mut writer = Writer::from_writer(Vec::new());
+        let opts = ParallelOptions {
+            threads: 4,
+            batch_records: 64,
+            ..Default::default()
+        };
+        let n = process_parallel(
+            &mut reader,
+            &opts,
+            |seq, scratch| {
+                let rc = scratch.revcomp(seq.seq).to_vec();
+                (seq.id.to_vec(), rc)
+            },
+            |(id, rc)| {
+                writer.write(&Seq {
+                    id: &id,
+                    desc: b"",
+                    seq: &rc,
+                    qual: None,
+                })
+            },
+        )
+        .unwrap();
+        assert_eq!(n, 5000);
+
+        let out = writer.into_inner();
+        let mut expected = Vec::new();
+        let mut reader = Reader::from_bytes(data.as_bytes());
+        while let Some(seq) = reader.next() {
+            let seq = seq.unwrap();
+            expected
+                .extend_from_slice(format!(">{}\n", String::from_utf8_lossy(seq.id)).as_bytes());
+            expected.extend_from_slice(&seq.rc());
+            expected.push(b'\n');
+        }
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_process_parallel_unordered_and_errors() {
+        let data = input(1000);
+        let opts = ParallelOptions {
+            threads: 3,
+            batch_records: 10,
+            ordered: false,
+            ..Default::default()
+        };
+        let mut total = 0;
+        let n = process_parallel(
+            &mut Reader::from_bytes(data.as_bytes()),
+            &opts,
+            |seq, _| seq.len(),
+            |len| {
+                total += len;
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(n, 1000);
+        assert_eq!(total, (0..1000).map(|i| 3 + i % 7).sum::<usize>());
+
+        // reading errors
+        let res = process_parallel(
+            &mut Reader::from_bytes(b"@a\nA\n+\nI\n@b\nACGT\n+\nII\n"),
+            &opts,
+            |seq, _| seq.len(),
+            |_| Ok(()),
+        );
+        assert!(res.is_err());
+
+        // output errors stop processing
+        let mut seen = 0;
+        let res = process_parallel(
+            &mut Reader::from_bytes(data.as_bytes()),
+            &opts,
+            |seq, _| seq.len(),
+            |_| {
+                seen += 1;
+                if seen == 5 {
+                    Err(FastxErr::InvalidFormat)
+                } else {
+                    Ok(())
+                }
+            },
+        );
+        assert!(matches!(res, Err(FastxErr::InvalidFormat)));
+        assert_eq!(seen, 5);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_process_parallel_worker_panic() {
+        let data = input(100);
+        let opts = ParallelOptions {
+            threads: 2,
+            batch_records: 10,
+            ..Default::default()
+        };
+        let _ = process_parallel(
+            &mut Reader::from_bytes(data.as_bytes()),
+            &opts,
+            |seq, _| {
+                assert!(seq.id != b"r42", "boom");
+            },
+            |_| Ok(()),
+        );
+    }
+}
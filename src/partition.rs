@@ -0,0 +1,191 @@
+use crate::errors::*;
+use crate::reader::*;
+use crate::seq::*;
+use crate::writer::*;
+use crate::xopen::*;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// The property records are binned by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinBy {
+    /// GC content in [0, 1].
+    Gc,
+    /// Sequence length.
+    Length,
+}
+
+/// Bins of a property with user-defined edges.
+///
+/// Bin i holds values in [edges[i-1], edges[i]), so n edges make n+1 bins, the first one for
+/// values below the first edge, and the last one for values at or above the last edge.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bins {
+    by: BinBy,
+    edges: Vec<f64>,
+}
+
+impl Bins {
+    /// Creates bins with edges, which are sorted and deduplicated.
+    pub fn new(by: BinBy, mut edges: Vec<f64>) -> Self {
+        edges.retain(|e| !e.is_nan());
+        edges.sort_by(f64::total_cmp);
+        edges.dedup();
+        Self { by, edges }
+    }
+
+    /// Returns the number of bins.
+    pub fn num_bins(&self) -> usize {
+        self.edges.len() + 1
+    }
+
+    /// Returns the bin of a record.
+    #[inline]
+    pub fn bin(&self, seq: &Seq) -> usize {
+        let value = match self.by {
+            BinBy::Gc => seq.gc_content() as f64,
+            BinBy::Length => seq.seq.len() as f64,
+        };
+        self.edges.partition_point(|&e| e <= value)
+    }
+
+    /// Returns the label of a bin, e.g., `gc_0.4-0.6` or `len_1000-inf`.
+    pub fn label(&self, i: usize) -> String {
+        let (name, min, max) = match self.by {
+            BinBy::Gc => ("gc", "0", "1"),
+            BinBy::Length => ("len", "0", "inf"),
+        };
+        let lo = if i == 0 {
+            min.to_string()
+        } else {
+            self.edges[i - 1].to_string()
+        };
+        let hi = match self.edges.get(i) {
+            Some(e) => e.to_string(),
+            None => max.to_string(),
+        };
+        format!("{name}_{lo}-{hi}")
+    }
+}
+
+/// Records and bases of a bin written by `partition_records`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BinStats {
+    pub label: String,
+    /// The output file, created only if the bin has records.
+    pub path: PathBuf,
+    pub records: u64,
+    pub bases: u64,
+}
+
+/// Routes records of the reader into files of bins in `out_dir`, named by bin labels and the
+/// extension, e.g., `gc_0.4-0.6.fq.gz`, for building stratified benchmark subsets.
+/// Output files are compressed by the extension, and created only for non-empty bins.
+/// Returns the statistics of all bins.
+pub fn partition_records<R: BufRead>(
+    reader: &mut Reader<R>,
+    bins: &Bins,
+    out_dir: &Path,
+    ext: &str,
+) -> Result<Vec<BinStats>, FastxErr> {
+    let mut stats: Vec<BinStats> = (0..bins.num_bins())
+        .map(|i| {
+            let label = bins.label(i);
+            BinStats {
+                path: out_dir.join(format!("{label}{ext}")),
+                label,
+                records: 0,
+                bases: 0,
+            }
+        })
+        .collect();
+    let mut writers: Vec<Option<Writer<Box<dyn FinishWrite>>>> =
+        (0..bins.num_bins()).map(|_| None).collect();
+
+    while let Some(res) = reader.next() {
+        let seq = res?;
+        let i = bins.bin(&seq);
+        let writer = match &mut writers[i] {
+            Some(w) => w,
+            w => w.insert(Writer::new(&stats[i].path)?),
+        };
+        writer.write(&seq)?;
+        stats[i].records += 1;
+        stats[i].bases += seq.seq.len() as u64;
+    }
+    for w in writers.iter_mut().flatten() {
+        w.finish()?;
+    }
+    Ok(stats)
+}
+
+/// Writes the statistics of bins as a tab-delimited table with a header line.
+pub fn write_bin_summary<W: Write>(stats: &[BinStats], w: &mut W) -> io::Result<()> {
+    writeln!(w, "bin\tfile\trecords\tbases")?;
+    for s in stats {
+        let file = if s.records > 0 {
+            s.path.to_string_lossy()
+        } else {
+            "-".into()
+        };
+        writeln!(w, "{}\t{}\t{}\t{}", s.label, file, s.records, s.bases)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn a_seq(seq: &[u8]) -> Seq<'_> {
+        Seq {
+            id: b"r",
+            desc: b"",
+            seq,
+            qual: None,
+        }
+    }
+
+    #[test]
+    fn test_bins() {
+        let bins = Bins::new(BinBy::Gc, vec![0.6, 0.4, 0.4]);
+        assert_eq!(bins.num_bins(), 3);
+        assert_eq!(bins.bin(&a_seq(b"AAAT")), 0);
+        assert_eq!(bins.bin(&a_seq(b"GCAAT")), 1);
+        assert_eq!(bins.bin(&a_seq(b"GCGAT")), 2);
+        assert_eq!(
+            (0..3).map(|i| bins.label(i)).collect::<Vec<_>>(),
+            vec!["gc_0-0.4", "gc_0.4-0.6", "gc_0.6-1"]
+        );
+
+        let bins = Bins::new(BinBy::Length, vec![1000.0]);
+        assert_eq!(bins.bin(&a_seq(&[b'A'; 999])), 0);
+        assert_eq!(bins.bin(&a_seq(&[b'A'; 1000])), 1);
+        assert_eq!(bins.label(1), "len_1000-inf");
+    }
+
+    #[test]
+    fn test_partition_records() {
+        let dir = std::env::temp_dir().join(format!("fastseq-partition-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let input = b">a\nACGT\n>b\nAAAAAAAA\n>c\nAC\n>d\nAAAAA\n";
+        let bins = Bins::new(BinBy::Length, vec![3.0, 5.0, 100.0]);
+        let stats = partition_records(&mut Reader::from_bytes(input), &bins, &dir, ".fa").unwrap();
+        let counts: Vec<(u64, u64)> = stats.iter().map(|s| (s.records, s.bases)).collect();
+        assert_eq!(counts, vec![(1, 2), (1, 4), (2, 13), (0, 0)]);
+        assert_eq!(
+            fs::read_to_string(dir.join("len_5-100.fa")).unwrap(),
+            ">b\nAAAAAAAA\n>d\nAAAAA\n"
+        );
+        assert!(!dir.join("len_100-inf.fa").exists());
+
+        let mut out = Vec::new();
+        write_bin_summary(&stats, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("bin\tfile\trecords\tbases\nlen_0-3\t"));
+        assert!(out.ends_with("len_100-inf\t-\t0\t0\n"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,76 @@
+use crate::contam::*;
+use crate::errors::*;
+use crate::oligos::*;
+use crate::reader::*;
+use crate::writer::*;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+/// Common Illumina adapters of the adapter screen, from the embedded `oligos` catalog.
+pub const PRESET_ADAPTERS: &[Oligo] = &[TRUSEQ_READ1, TRUSEQ_READ2, NEXTERA, ILLUMINA_SMALL_RNA_3P];
+
+/// Default k-mer size of preset screens.
+pub const PRESET_K: usize = 21;
+
+/// Default hit-fraction threshold of preset screens.
+pub const PRESET_THRESHOLD: f64 = 0.5;
+
+/// Builds a contaminant screen from the preset adapters.
+/// Adapters are short, so a smaller k (e.g. 13) gives a better sensitivity.
+pub fn adapter_screen(k: usize, threshold: f64) -> ContaminantScreen {
+    oligo_screen(PRESET_ADAPTERS, k, threshold)
+}
+
+/// Builds a PhiX174 contaminant screen with default parameters from a reference file
+/// (e.g. NCBI NC_001422.1). The genome is not bundled with the crate.
+pub fn phix_screen<P: AsRef<Path>>(phix_file: P) -> Result<ContaminantScreen, FastxErr> {
+    let mut screen = ContaminantScreen::new(PRESET_K, PRESET_THRESHOLD);
+    screen.add_references_from_reader(&mut Reader::new(phix_file)?)?;
+    Ok(screen)
+}
+
+/// Removes reads made up mostly of adapter sequences (adapter dimers), with k=13.
+pub fn remove_adapters<R: BufRead, W: Write>(
+    reader: &mut Reader<R>,
+    clean: &mut Writer<W>,
+) -> Result<ScreenStats, FastxErr> {
+    adapter_screen(13, PRESET_THRESHOLD).screen(reader, clean, None::<&mut Writer<Vec<u8>>>)
+}
+
+/// Removes PhiX174 reads, using the PhiX reference in `phix_file`.
+pub fn remove_phix<R: BufRead, W: Write, P: AsRef<Path>>(
+    reader: &mut Reader<R>,
+    clean: &mut Writer<W>,
+    phix_file: P,
+) -> Result<ScreenStats, FastxErr> {
+    phix_screen(phix_file)?.screen(reader, clean, None::<&mut Writer<Vec<u8>>>)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_adapter_screen() {
+        let screen = adapter_screen(13, PRESET_THRESHOLD);
+        assert_eq!(screen.names().len(), 4);
+        assert_eq!(screen.names()[0], b"TruSeq_Read1");
+    }
+
+    #[test]
+    fn test_remove_adapters() {
+        let input = "\
+>dimer
+AGATCGGAAGAGCACACGTCTGAACTCCAGTCA
+>insert
+GAGTTTTATCGCTTCCATGACGCAGAAGTTAACACTTTCGGATATTTCTGATGAGTCG
+";
+        let mut reader = Reader::from_reader(Cursor::new(input.as_bytes()));
+        let mut clean = Writer::from_writer(Vec::new());
+        let stats = remove_adapters(&mut reader, &mut clean).unwrap();
+        assert_eq!(stats.flagged, 1);
+        assert_eq!(stats.per_contaminant, vec![1, 0, 0, 0]);
+        assert!(clean.into_inner().starts_with(b">insert\n"));
+    }
+}
@@ -0,0 +1,282 @@
+use crate::errors::*;
+use crate::reader::*;
+use crate::seq::*;
+use crate::xopen::*;
+use std::cell::Cell;
+use std::fmt;
+use std::io::{self, BufRead, Read, Write};
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Time spent on a batch of records.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BatchProfile {
+    pub records: u64,
+    pub bases: u64,
+    /// Time spent on reading and decompressing input.
+    pub read: Duration,
+    /// Time spent on parsing records, excluding reading.
+    pub parse: Duration,
+    /// Time spent by the caller between two records, i.e., the user transform.
+    pub transform: Duration,
+}
+
+impl BatchProfile {
+    fn merge(&mut self, other: &BatchProfile) {
+        self.records += other.records;
+        self.bases += other.bases;
+        self.read += other.read;
+        self.parse += other.parse;
+        self.transform += other.transform;
+    }
+
+    /// Returns the total time.
+    pub fn elapsed(&self) -> Duration {
+        self.read + self.parse + self.transform
+    }
+}
+
+/// A profile report of a record stream, with one entry per batch.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProfileReport {
+    pub batches: Vec<BatchProfile>,
+}
+
+impl ProfileReport {
+    /// Returns the sum of all batches.
+    pub fn total(&self) -> BatchProfile {
+        let mut total = BatchProfile::default();
+        for b in &self.batches {
+            total.merge(b);
+        }
+        total
+    }
+
+    /// Writes per-batch profiles as a tab-delimited table, times in milliseconds.
+    pub fn write_tsv<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "batch\trecords\tbases\tread_ms\tparse_ms\ttransform_ms")?;
+        for (i, b) in self.batches.iter().enumerate() {
+            writeln!(
+                w,
+                "{}\t{}\t{}\t{:.3}\t{:.3}\t{:.3}",
+                i + 1,
+                b.records,
+                b.bases,
+                b.read.as_secs_f64() * 1000.0,
+                b.parse.as_secs_f64() * 1000.0,
+                b.transform.as_secs_f64() * 1000.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ProfileReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.total();
+        let elapsed = total.elapsed().as_secs_f64();
+        let pct = |d: Duration| {
+            if elapsed > 0.0 {
+                d.as_secs_f64() / elapsed * 100.0
+            } else {
+                0.0
+            }
+        };
+        writeln!(
+            f,
+            "records: {}, bases: {}, batches: {}",
+            total.records,
+            total.bases,
+            self.batches.len()
+        )?;
+        for (name, d) in [
+            ("read/decompress", total.read),
+            ("parse", total.parse),
+            ("transform", total.transform),
+        ] {
+            writeln!(f, "{name:>15}: {:.3}s ({:.1}%)", d.as_secs_f64(), pct(d))?;
+        }
+        Ok(())
+    }
+}
+
+// A BufRead wrapper accumulating the time spent in refilling the inner buffer.
+// Only calls that may hit the underlying source are timed,
+// so that the many cheap fill_buf calls of the parser stay cheap.
+struct TimedRead<R> {
+    inner: R,
+    avail: usize, // bytes of the last returned buffer not consumed yet
+    elapsed: Rc<Cell<Duration>>,
+}
+
+impl<R: BufRead> Read for TimedRead<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let start = Instant::now();
+        let n = self.inner.read(out);
+        self.elapsed.set(self.elapsed.get() + start.elapsed());
+        self.avail = 0;
+        n
+    }
+}
+
+impl<R: BufRead> BufRead for TimedRead<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.avail > 0 {
+            return self.inner.fill_buf();
+        }
+        let start = Instant::now();
+        let buf = self.inner.fill_buf()?;
+        self.elapsed.set(self.elapsed.get() + start.elapsed());
+        self.avail = buf.len();
+        Ok(buf)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.avail = self.avail.saturating_sub(amt);
+        self.inner.consume(amt);
+    }
+}
+
+/// A Reader recording time spent on reading/decompression, parsing, and the caller's own processing
+/// of records, per batch of records.
+///
+/// Timers are coarse: one pair per record and one per buffer refill,
+/// which costs far less than parsing itself.
+pub struct ProfiledReader<R: BufRead> {
+    reader: Reader<TimedRead<R>>,
+    read_time: Rc<Cell<Duration>>,
+    batch_size: u64,
+    current: BatchProfile,
+    batches: Vec<BatchProfile>,
+    last_return: Option<Instant>,
+}
+
+impl ProfiledReader<Box<dyn BufRead>> {
+    /// Creates a profiled Reader from a file path, "-" for stdin.
+    pub fn new<P: AsRef<Path>>(file: P, batch_size: u64) -> Result<Self, FastxErr> {
+        Ok(Self::from_reader(xopen(file, 65536)?, batch_size))
+    }
+}
+
+impl<R: BufRead> ProfiledReader<R> {
+    /// Creates a profiled Reader from any BufRead, with `batch_size` records per batch.
+    pub fn from_reader(reader: R, batch_size: u64) -> Self {
+        let read_time = Rc::new(Cell::new(Duration::ZERO));
+        Self {
+            reader: Reader::from_reader(TimedRead {
+                inner: reader,
+                avail: 0,
+                elapsed: Rc::clone(&read_time),
+            }),
+            read_time,
+            batch_size: batch_size.max(1),
+            current: BatchProfile::default(),
+            batches: Vec::new(),
+            last_return: None,
+        }
+    }
+
+    /// Skips parsing IDs, see `Reader::skip_id_parsing`.
+    pub fn skip_id_parsing(&mut self) {
+        self.reader.skip_id_parsing();
+    }
+
+    /// Returns the next record, like `Reader::next`.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Result<Seq<'_>, FastxErr>> {
+        let start = Instant::now();
+        if let Some(t) = self.last_return.take() {
+            self.current.transform += start - t;
+        }
+        let read_before = self.read_time.get();
+
+        let res = self.reader.next();
+
+        let end = Instant::now();
+        let read = self.read_time.get() - read_before;
+        self.current.read += read;
+        self.current.parse += (end - start).saturating_sub(read);
+        if let Some(Ok(seq)) = &res {
+            self.current.records += 1;
+            self.current.bases += seq.seq.len() as u64;
+            if self.current.records == self.batch_size {
+                trace!(
+                    "batch {}: {} records, {} bases, read {:?}, parse {:?}",
+                    self.batches.len(),
+                    self.current.records,
+                    self.current.bases,
+                    self.current.read,
+                    self.current.parse
+                );
+                self.batches.push(std::mem::take(&mut self.current));
+            }
+            self.last_return = Some(end);
+        }
+        res
+    }
+
+    /// Returns the profile report, including the unfinished batch.
+    pub fn report(&self) -> ProfileReport {
+        let mut batches = self.batches.clone();
+        if self.current != BatchProfile::default() {
+            batches.push(self.current);
+        }
+        ProfileReport { batches }
+    }
+}
+
+impl<R: BufRead> RecordSource for ProfiledReader<R> {
+    fn next_record(&mut self) -> Option<Result<Seq<'_>, FastxErr>> {
+        self.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_profiled_reader() {
+        let input = ">a\nACGT\n>b\nAC\n>c\nA\n";
+        let mut reader = ProfiledReader::from_reader(Cursor::new(input.as_bytes()), 2);
+        let mut ids = Vec::new();
+        while let Some(res) = reader.next() {
+            ids.push(res.unwrap().id.to_vec());
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        assert_eq!(ids, vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]);
+
+        let report = reader.report();
+        assert_eq!(report.batches.len(), 2);
+        assert_eq!(report.batches[0].records, 2);
+        assert_eq!(report.batches[1].bases, 1);
+        let total = report.total();
+        assert_eq!((total.records, total.bases), (3, 7));
+        assert!(total.transform >= Duration::from_millis(6));
+        assert!(total.transform > total.parse);
+
+        let mut tsv = Vec::new();
+        report.write_tsv(&mut tsv).unwrap();
+        assert_eq!(String::from_utf8(tsv).unwrap().lines().count(), 3);
+        assert!(report.to_string().contains("transform"));
+    }
+
+    #[test]
+    fn test_timed_read_times_refills_only() {
+        let elapsed = Rc::new(Cell::new(Duration::ZERO));
+        let mut r = TimedRead {
+            inner: Cursor::new(b"abcdef".to_vec()),
+            avail: 0,
+            elapsed: Rc::clone(&elapsed),
+        };
+        assert_eq!(r.fill_buf().unwrap(), b"abcdef");
+        assert_eq!(r.avail, 6);
+        r.consume(4);
+        assert_eq!(r.fill_buf().unwrap(), b"ef");
+        assert_eq!(r.avail, 2);
+        r.consume(2);
+        assert_eq!(r.fill_buf().unwrap(), b"");
+    }
+}
@@ -0,0 +1,213 @@
+use crate::errors::*;
+use crate::reader::*;
+use crate::seq::*;
+use crate::writer::*;
+use std::io::{BufRead, Write};
+
+/// Lossy binning schemes of quality scores, which make FASTQ files compress much better.
+///
+/// Scores below 2 (e.g., `#` of no-calls in phred+33) are kept as is by all schemes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QualityBinning {
+    /// The 8-level binning of Illumina (HiSeq X/4000, NextSeq): 2-9 to 6, 10-19 to 15,
+    /// 20-24 to 22, 25-29 to 27, 30-34 to 33, 35-39 to 37, and 40 and above to 40.
+    Illumina8,
+    /// Four levels like NovaSeq: 2 to 2, 3-14 to 12, 15-30 to 23, and 31 and above to 37.
+    Illumina4,
+    /// All scores set to one value.
+    Constant(u8),
+}
+
+impl QualityBinning {
+    /// Returns the binned quality score.
+    pub fn bin(&self, q: u8) -> u8 {
+        if q < 2 {
+            return q;
+        }
+        match self {
+            QualityBinning::Illumina8 => match q {
+                2..=9 => 6,
+                10..=19 => 15,
+                20..=24 => 22,
+                25..=29 => 27,
+                30..=34 => 33,
+                35..=39 => 37,
+                _ => 40,
+            },
+            QualityBinning::Illumina4 => match q {
+                2 => 2,
+                3..=14 => 12,
+                15..=30 => 23,
+                _ => 37,
+            },
+            QualityBinning::Constant(c) => *c,
+        }
+    }
+}
+
+/// The impact of binning on quality scores, for judging the effect on downstream analyses,
+/// e.g., variant calling relying on base qualities.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BinningReport {
+    /// Number of reads with quality scores.
+    pub reads: u64,
+    /// Number of quality scores.
+    pub bases: u64,
+    /// Number of altered scores.
+    pub changed: u64,
+    /// Sum of absolute differences of scores.
+    pub sum_abs_delta: u64,
+    /// Largest absolute difference of a score.
+    pub max_abs_delta: u8,
+}
+
+impl BinningReport {
+    /// Returns the mean absolute difference of scores, |ΔQ|.
+    pub fn mean_abs_delta(&self) -> f64 {
+        if self.bases == 0 {
+            return 0.0;
+        }
+        self.sum_abs_delta as f64 / self.bases as f64
+    }
+
+    /// Returns the fraction of altered scores.
+    pub fn changed_fraction(&self) -> f64 {
+        if self.bases == 0 {
+            return 0.0;
+        }
+        self.changed as f64 / self.bases as f64
+    }
+}
+
+/// Bins quality scores of reads with a lookup table, and accumulates a `BinningReport`.
+pub struct QualityBinner {
+    table: [u8; 256],
+    report: BinningReport,
+}
+
+impl QualityBinner {
+    /// Creates a binner for quality scores encoded with the offset (33 or 64).
+    /// Bytes below the offset are kept as is.
+    pub fn new(binning: QualityBinning, offset: u8) -> Self {
+        let mut table = [0u8; 256];
+        for (b, v) in table.iter_mut().enumerate() {
+            let b = b as u8;
+            *v = match b.checked_sub(offset) {
+                Some(q) => binning.bin(q).saturating_add(offset),
+                None => b,
+            };
+        }
+        Self {
+            table,
+            report: BinningReport::default(),
+        }
+    }
+
+    /// Bins quality scores into the buffer, which is cleared first, and returns the binned scores.
+    pub fn bin<'a>(&mut self, qual: &[u8], out: &'a mut Vec<u8>) -> &'a [u8] {
+        out.clear();
+        out.reserve(qual.len());
+        self.report.reads += 1;
+        self.report.bases += qual.len() as u64;
+        for &b in qual {
+            let v = self.table[b as usize];
+            let delta = v.abs_diff(b);
+            if delta > 0 {
+                self.report.changed += 1;
+                self.report.sum_abs_delta += delta as u64;
+                self.report.max_abs_delta = self.report.max_abs_delta.max(delta);
+            }
+            out.push(v);
+        }
+        out
+    }
+
+    /// Returns the report of all binned scores.
+    pub fn report(&self) -> &BinningReport {
+        &self.report
+    }
+}
+
+/// Bins quality scores of all records of the reader before writing them to the writer.
+/// FASTA records are written as is.
+pub fn bin_records<R: BufRead, W: Write>(
+    reader: &mut Reader<R>,
+    writer: &mut Writer<W>,
+    binning: QualityBinning,
+    offset: u8,
+) -> Result<BinningReport, FastxErr> {
+    let mut binner = QualityBinner::new(binning, offset);
+    let mut buf = Vec::new();
+    while let Some(res) = reader.next() {
+        let seq = res?;
+        match seq.qual {
+            Some(qual) => {
+                let qual = binner.bin(qual, &mut buf);
+                writer.write(&Seq {
+                    qual: Some(qual),
+                    ..seq
+                })?;
+            }
+            None => writer.write(&seq)?,
+        }
+    }
+    writer.flush()?;
+    Ok(binner.report().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quality_binning() {
+        let bins = |b: QualityBinning, qs: &[u8]| qs.iter().map(|&q| b.bin(q)).collect::<Vec<_>>();
+        let qs = [0, 1, 2, 9, 10, 19, 20, 24, 25, 29, 30, 34, 35, 39, 40, 41];
+        assert_eq!(
+            bins(QualityBinning::Illumina8, &qs),
+            [0, 1, 6, 6, 15, 15, 22, 22, 27, 27, 33, 33, 37, 37, 40, 40]
+        );
+        assert_eq!(
+            bins(QualityBinning::Illumina4, &[1, 2, 3, 14, 15, 30, 31, 41]),
+            [1, 2, 12, 12, 23, 23, 37, 37]
+        );
+        assert_eq!(
+            bins(QualityBinning::Constant(30), &[0, 2, 15, 41]),
+            [0, 30, 30, 30]
+        );
+    }
+
+    #[test]
+    fn test_bin_records() {
+        let input = b"@r1\nACGT\n+\n#+?I\n@r2\nA\n+\n5\n";
+        let mut reader = Reader::from_bytes(input);
+        let mut writer = Writer::from_writer(Vec::new());
+        let report = bin_records(&mut reader, &mut writer, QualityBinning::Illumina8, 33).unwrap();
+        assert_eq!(writer.into_inner(), b"@r1\nACGT\n+\n'0BI\n@r2\nA\n+\n7\n");
+        // Q2->6, Q10->15, Q30->33, Q40 kept, Q20->22
+        assert_eq!(
+            report,
+            BinningReport {
+                reads: 2,
+                bases: 5,
+                changed: 4,
+                sum_abs_delta: 4 + 5 + 3 + 2,
+                max_abs_delta: 5,
+            }
+        );
+        assert!((report.mean_abs_delta() - 2.8).abs() < 1e-9);
+        assert!((report.changed_fraction() - 0.8).abs() < 1e-9);
+
+        // FASTA records are written as is
+        let mut writer = Writer::from_writer(Vec::new());
+        let report = bin_records(
+            &mut Reader::from_bytes(b">r\nACGT\n"),
+            &mut writer,
+            QualityBinning::Illumina4,
+            33,
+        )
+        .unwrap();
+        assert_eq!(writer.into_inner(), b">r\nACGT\n");
+        assert_eq!(report.mean_abs_delta(), 0.0);
+    }
+}
@@ -0,0 +1,275 @@
+use crate::seq::*;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+/// A streaming accumulator of read and base quality statistics.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QcStats {
+    offset: u8,
+    /// Number of reads.
+    pub reads: u64,
+    /// Number of bases.
+    pub bases: u64,
+    /// Number of bases with quality >= 20.
+    pub q20_bases: u64,
+    /// Number of bases with quality >= 30.
+    pub q30_bases: u64,
+    /// Number of G and C bases.
+    pub gc_bases: u64,
+    /// Number of reads by length.
+    pub lengths: BTreeMap<usize, u64>,
+}
+
+impl Default for QcStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl QcStats {
+    /// Creates an accumulator, quality scores are Phred+33 encoded.
+    pub fn new() -> Self {
+        Self {
+            offset: 33,
+            reads: 0,
+            bases: 0,
+            q20_bases: 0,
+            q30_bases: 0,
+            gc_bases: 0,
+            lengths: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the offset of quality scores.
+    pub fn set_phred_offset(&mut self, offset: u8) {
+        self.offset = offset;
+    }
+
+    /// Adds a read.
+    pub fn add(&mut self, seq: &Seq) {
+        self.reads += 1;
+        self.bases += seq.len() as u64;
+        *self.lengths.entry(seq.len()).or_default() += 1;
+        self.gc_bases += seq.count_bases(b"GCgc") as u64;
+        if let Some(qual) = seq.qual {
+            for &b in qual {
+                let q = b.saturating_sub(self.offset);
+                if q >= 20 {
+                    self.q20_bases += 1;
+                    if q >= 30 {
+                        self.q30_bases += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Adds the statistics of another accumulator, e.g., of another file of the sample.
+    pub fn merge(&mut self, other: &QcStats) {
+        self.reads += other.reads;
+        self.bases += other.bases;
+        self.q20_bases += other.q20_bases;
+        self.q30_bases += other.q30_bases;
+        self.gc_bases += other.gc_bases;
+        for (&len, &n) in &other.lengths {
+            *self.lengths.entry(len).or_default() += n;
+        }
+    }
+
+    /// Returns the fraction of bases with quality >= 20.
+    pub fn q20_rate(&self) -> f64 {
+        ratio(self.q20_bases, self.bases)
+    }
+
+    /// Returns the fraction of bases with quality >= 30.
+    pub fn q30_rate(&self) -> f64 {
+        ratio(self.q30_bases, self.bases)
+    }
+
+    /// Returns the GC content.
+    pub fn gc_content(&self) -> f64 {
+        ratio(self.gc_bases, self.bases)
+    }
+
+    /// Returns the mean read length.
+    pub fn mean_len(&self) -> f64 {
+        ratio(self.bases, self.reads)
+    }
+
+    /// Returns the minimum read length, 0 for no reads.
+    pub fn min_len(&self) -> usize {
+        self.lengths.keys().next().copied().unwrap_or(0)
+    }
+
+    /// Returns the maximum read length, 0 for no reads.
+    pub fn max_len(&self) -> usize {
+        self.lengths.keys().next_back().copied().unwrap_or(0)
+    }
+
+    /// Writes the statistics as a JSON object.
+    pub fn write_json<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write!(
+            w,
+            "{{\"reads\":{},\"bases\":{},\"q20_rate\":{},\"q30_rate\":{},\"gc_content\":{},\
+             \"mean_length\":{},\"min_length\":{},\"max_length\":{},\"length_distribution\":{{",
+            self.reads,
+            self.bases,
+            json_f64(self.q20_rate()),
+            json_f64(self.q30_rate()),
+            json_f64(self.gc_content()),
+            json_f64(self.mean_len()),
+            self.min_len(),
+            self.max_len(),
+        )?;
+        for (i, (len, n)) in self.lengths.iter().enumerate() {
+            if i > 0 {
+                w.write_all(b",")?;
+            }
+            write!(w, "\"{len}\":{n}")?;
+        }
+        w.write_all(b"}}")
+    }
+}
+
+/// A before/after comparison of a pipeline, like the summary of fastp.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QcComparison {
+    pub before: QcStats,
+    pub after: QcStats,
+}
+
+impl QcComparison {
+    /// Creates an empty comparison.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an input read.
+    pub fn add_before(&mut self, seq: &Seq) {
+        self.before.add(seq);
+    }
+
+    /// Adds an output read.
+    pub fn add_after(&mut self, seq: &Seq) {
+        self.after.add(seq);
+    }
+
+    /// Returns the fraction of reads kept.
+    pub fn reads_kept_rate(&self) -> f64 {
+        ratio(self.after.reads, self.before.reads)
+    }
+
+    /// Returns the fraction of bases kept.
+    pub fn bases_kept_rate(&self) -> f64 {
+        ratio(self.after.bases, self.before.bases)
+    }
+
+    /// Returns the change of the Q30 rate, after minus before.
+    pub fn q30_rate_change(&self) -> f64 {
+        self.after.q30_rate() - self.before.q30_rate()
+    }
+
+    /// Returns the change of the mean read length, after minus before.
+    pub fn mean_len_change(&self) -> f64 {
+        self.after.mean_len() - self.before.mean_len()
+    }
+
+    /// Writes the comparison as a JSON object with `summary`, `before` and `after`.
+    pub fn write_json<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write!(
+            w,
+            "{{\"summary\":{{\"reads_kept\":{},\"reads_kept_rate\":{},\"bases_kept\":{},\
+             \"bases_kept_rate\":{},\"q30_rate_change\":{},\"mean_length_change\":{}}},\"before\":",
+            self.after.reads,
+            json_f64(self.reads_kept_rate()),
+            self.after.bases,
+            json_f64(self.bases_kept_rate()),
+            json_f64(self.q30_rate_change()),
+            json_f64(self.mean_len_change()),
+        )?;
+        self.before.write_json(w)?;
+        w.write_all(b",\"after\":")?;
+        self.after.write_json(w)?;
+        w.write_all(b"}\n")
+    }
+}
+
+#[inline]
+fn ratio(a: u64, b: u64) -> f64 {
+    if b == 0 { 0.0 } else { a as f64 / b as f64 }
+}
+
+// rounds to 6 decimal places, JSON has no NaN or infinity
+fn json_f64(v: f64) -> String {
+    if v.is_finite() {
+        let v = (v * 1e6).round() / 1e6;
+        format!("{v}")
+    } else {
+        "null".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trim::*;
+
+    fn a_read<'a>(seq: &'a [u8], qual: &'a [u8]) -> Seq<'a> {
+        Seq {
+            id: b"r",
+            desc: b"",
+            seq,
+            qual: Some(qual),
+        }
+    }
+
+    #[test]
+    fn test_qc_stats() {
+        let mut stats = QcStats::new();
+        stats.add(&a_read(b"ACGG", b"I5+#"));
+        stats.add(&a_read(b"AT", b"II"));
+        assert_eq!((stats.reads, stats.bases), (2, 6));
+        assert_eq!(
+            (stats.q20_bases, stats.q30_bases, stats.gc_bases),
+            (4, 3, 3)
+        );
+        assert_eq!((stats.min_len(), stats.max_len()), (2, 4));
+        assert_eq!(stats.mean_len(), 3.0);
+
+        let mut out = Vec::new();
+        stats.write_json(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"{"reads":2,"bases":6,"q20_rate":0.666667,"q30_rate":0.5,"gc_content":0.5,"mean_length":3,"min_length":2,"max_length":4,"length_distribution":{"2":1,"4":1}}"#
+        );
+    }
+
+    #[test]
+    fn test_qc_comparison() {
+        let trimmer = Trimmer::new(vec![TrimStep::Trailing(20), TrimStep::MinLen(2)]);
+        let reads = [
+            a_read(b"ACGTAC", b"IIII##"),
+            a_read(b"ACGT", b"IIII"),
+            a_read(b"AC", b"I#"),
+        ];
+        let mut cmp = QcComparison::new();
+        for read in &reads {
+            cmp.add_before(read);
+            let t = trimmer.trim(read);
+            if t.discarded.is_none() {
+                cmp.add_after(&t.apply(read));
+            }
+        }
+        assert_eq!(cmp.after.reads, 2);
+        assert_eq!(cmp.bases_kept_rate(), 8.0 / 12.0);
+        assert_eq!(cmp.q30_rate_change(), 1.0 - 9.0 / 12.0);
+
+        let mut out = Vec::new();
+        cmp.write_json(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with(
+            r#"{"summary":{"reads_kept":2,"reads_kept_rate":0.666667,"bases_kept":8,"bases_kept_rate":0.666667,"q30_rate_change":0.25,"mean_length_change":0},"before":{"reads":3,"#
+        ));
+        assert!(out.ends_with("\"length_distribution\":{\"4\":2}}}\n"));
+    }
+}
@@ -0,0 +1,380 @@
+use crate::seq::*;
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+
+/// Default compression of t-digests, the maximum number of centroids.
+pub const DEFAULT_COMPRESSION: f64 = 100.0;
+
+/// A merging t-digest (Dunning) estimating quantiles of an unbounded stream of weighted values
+/// in bounded memory, with accurate tails: centroids near the extremes hold few values.
+#[derive(Debug, Clone)]
+pub struct TDigest {
+    compression: f64,
+    centroids: Vec<(f64, f64)>, // (mean, weight), sorted by mean
+    buffer: Vec<(f64, f64)>,
+    total: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Default for TDigest {
+    fn default() -> Self {
+        Self::new(DEFAULT_COMPRESSION)
+    }
+}
+
+impl TDigest {
+    /// Creates a digest, a larger compression gives more accurate estimates.
+    pub fn new(compression: f64) -> Self {
+        Self {
+            compression: compression.max(10.0),
+            centroids: Vec::new(),
+            buffer: Vec::new(),
+            total: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    /// Returns the total weight of values added.
+    pub fn count(&self) -> f64 {
+        self.total
+    }
+
+    /// Adds a value.
+    pub fn add(&mut self, x: f64) {
+        self.add_weighted(x, 1.0);
+    }
+
+    /// Adds a value with a weight. NaN values and non-positive weights are ignored.
+    pub fn add_weighted(&mut self, x: f64, w: f64) {
+        if x.is_nan() || w <= 0.0 {
+            return;
+        }
+        self.buffer.push((x, w));
+        self.total += w;
+        self.min = self.min.min(x);
+        self.max = self.max.max(x);
+        if self.buffer.len() >= 5 * self.compression as usize {
+            self.compress();
+        }
+    }
+
+    /// Merges another digest into this one, e.g., of another thread or file.
+    pub fn merge(&mut self, other: &TDigest) {
+        self.buffer.extend_from_slice(&other.centroids);
+        self.buffer.extend_from_slice(&other.buffer);
+        self.total += other.total;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.compress();
+    }
+
+    // merges buffered values into centroids, each centroid spans at most 1 on the k1 scale
+    // k(q) = compression / (2 * PI) * asin(2q - 1), which keeps at most `compression` centroids,
+    // and small ones at the tails
+    fn compress(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let mut all = std::mem::take(&mut self.centroids);
+        all.append(&mut self.buffer);
+        all.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut merged: Vec<(f64, f64)> = Vec::with_capacity(2 * self.compression as usize);
+        let k = |q: f64| self.compression / (2.0 * PI) * (2.0 * q - 1.0).clamp(-1.0, 1.0).asin();
+        let mut done = 0.0; // weight of merged centroids before the current one
+        let mut k_start = k(0.0);
+        let mut cur = all[0];
+        for &c in &all[1..] {
+            let w = cur.1 + c.1;
+            if k((done + w) / self.total) - k_start <= 1.0 {
+                cur = (cur.0 + (c.0 - cur.0) * c.1 / w, w);
+            } else {
+                done += cur.1;
+                k_start = k(done / self.total);
+                merged.push(cur);
+                cur = c;
+            }
+        }
+        merged.push(cur);
+        self.centroids = merged;
+        self.buffer = all; // reuse the allocation
+        self.buffer.clear();
+    }
+
+    /// Returns the estimated q-quantile (0 <= q <= 1), NaN for an empty digest.
+    pub fn quantile(&mut self, q: f64) -> f64 {
+        self.compress();
+        if self.centroids.is_empty() {
+            return f64::NAN;
+        }
+        let q = q.clamp(0.0, 1.0);
+        let cs = &self.centroids;
+        if cs.len() == 1 {
+            return cs[0].0;
+        }
+        let target = q * self.total;
+
+        // values are interpolated between centers of centroids, and the extremes
+        let mut left = (0.0, self.min);
+        let mut acc = 0.0;
+        for &(mean, w) in cs {
+            let center = acc + w / 2.0;
+            if target < center {
+                return interpolate(left, (center, mean), target);
+            }
+            left = (center, mean);
+            acc += w;
+        }
+        interpolate(left, (self.total, self.max), target)
+    }
+}
+
+fn interpolate((x0, y0): (f64, f64), (x1, y1): (f64, f64), x: f64) -> f64 {
+    if x1 <= x0 {
+        return y1;
+    }
+    y0 + (y1 - y0) * (x - x0) / (x1 - x0)
+}
+
+/// How quantiles are computed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QuantileMode {
+    /// Exact quantiles, storing a histogram of (rounded) values, i.e., one entry per distinct
+    /// length, which is small for short reads, but grows with long reads.
+    Exact,
+    /// Approximate quantiles of t-digests with the compression, in constant memory.
+    Approximate(f64),
+}
+
+impl Default for QuantileMode {
+    fn default() -> Self {
+        QuantileMode::Approximate(DEFAULT_COMPRESSION)
+    }
+}
+
+/// A quantile estimator of weighted integer values, exact or approximate.
+#[derive(Debug, Clone)]
+pub enum QuantileEstimator {
+    Exact(BTreeMap<u64, f64>),
+    Approximate(TDigest),
+}
+
+impl QuantileEstimator {
+    /// Creates an estimator.
+    pub fn new(mode: QuantileMode) -> Self {
+        match mode {
+            QuantileMode::Exact => QuantileEstimator::Exact(BTreeMap::new()),
+            QuantileMode::Approximate(c) => QuantileEstimator::Approximate(TDigest::new(c)),
+        }
+    }
+
+    /// Adds a value with a weight.
+    pub fn add_weighted(&mut self, x: u64, w: f64) {
+        match self {
+            QuantileEstimator::Exact(h) => *h.entry(x).or_default() += w,
+            QuantileEstimator::Approximate(d) => d.add_weighted(x as f64, w),
+        }
+    }
+
+    /// Returns the q-quantile, the smallest value with a cumulative weight of at least q of
+    /// the total for exact estimators (the lower median for q = 0.5). NaN for no values.
+    pub fn quantile(&mut self, q: f64) -> f64 {
+        match self {
+            QuantileEstimator::Exact(h) => {
+                let total: f64 = h.values().sum();
+                let target = (q.clamp(0.0, 1.0) * total).max(f64::MIN_POSITIVE);
+                let mut acc = 0.0;
+                for (&x, &w) in h.iter() {
+                    acc += w;
+                    if acc >= target * (1.0 - 1e-12) {
+                        return x as f64;
+                    }
+                }
+                h.keys().next_back().map_or(f64::NAN, |&x| x as f64)
+            }
+            QuantileEstimator::Approximate(d) => d.quantile(q),
+        }
+    }
+}
+
+/// Streaming statistics of read lengths (quantiles and Nx) and of mean read qualities.
+#[derive(Debug, Clone)]
+pub struct StreamingStats {
+    offset: u8,
+    reads: u64,
+    bases: u64,
+    lengths: QuantileEstimator,
+    bases_by_length: QuantileEstimator, // lengths weighted by lengths, for Nx
+    quals: QuantileEstimator,           // mean qualities, in 0.01 units
+}
+
+impl StreamingStats {
+    /// Creates an accumulator, quality scores are Phred+33 encoded.
+    pub fn new(mode: QuantileMode) -> Self {
+        Self {
+            offset: 33,
+            reads: 0,
+            bases: 0,
+            lengths: QuantileEstimator::new(mode),
+            bases_by_length: QuantileEstimator::new(mode),
+            quals: QuantileEstimator::new(mode),
+        }
+    }
+
+    /// Sets the offset of quality scores.
+    pub fn set_phred_offset(&mut self, offset: u8) {
+        self.offset = offset;
+    }
+
+    /// Adds a read.
+    pub fn add(&mut self, seq: &Seq) {
+        let len = seq.len() as u64;
+        self.reads += 1;
+        self.bases += len;
+        self.lengths.add_weighted(len, 1.0);
+        if len > 0 {
+            self.bases_by_length.add_weighted(len, len as f64);
+        }
+        if let Some(qual) = seq.qual
+            && !qual.is_empty()
+        {
+            let sum: u64 = qual
+                .iter()
+                .map(|&q| q.saturating_sub(self.offset) as u64)
+                .sum();
+            self.quals
+                .add_weighted((sum * 100) / qual.len() as u64, 1.0);
+        }
+    }
+
+    /// Returns the number of reads.
+    pub fn reads(&self) -> u64 {
+        self.reads
+    }
+
+    /// Returns the number of bases.
+    pub fn bases(&self) -> u64 {
+        self.bases
+    }
+
+    /// Returns the q-quantile of read lengths.
+    pub fn len_quantile(&mut self, q: f64) -> f64 {
+        self.lengths.quantile(q)
+    }
+
+    /// Returns the median read length.
+    pub fn median_len(&mut self) -> f64 {
+        self.len_quantile(0.5)
+    }
+
+    /// Returns the Nx (0 < x < 100) length: reads at least this long hold x% of all bases.
+    pub fn nx(&mut self, x: f64) -> f64 {
+        match &mut self.bases_by_length {
+            QuantileEstimator::Exact(h) => {
+                // from the longest reads down
+                let target = self.bases as f64 * x / 100.0;
+                let mut acc = 0.0;
+                for (&len, &w) in h.iter().rev() {
+                    acc += w;
+                    if acc >= target {
+                        return len as f64;
+                    }
+                }
+                f64::NAN
+            }
+            QuantileEstimator::Approximate(d) => d.quantile(1.0 - x / 100.0),
+        }
+    }
+
+    /// Returns the N50 length.
+    pub fn n50(&mut self) -> f64 {
+        self.nx(50.0)
+    }
+
+    /// Returns the q-quantile of mean read qualities, NaN for FASTA records.
+    pub fn qual_quantile(&mut self, q: f64) -> f64 {
+        self.quals.quantile(q) / 100.0
+    }
+
+    /// Returns the median of mean read qualities, NaN for FASTA records.
+    pub fn median_qual(&mut self) -> f64 {
+        self.qual_quantile(0.5)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::*;
+
+    #[test]
+    fn test_tdigest() {
+        let mut d = TDigest::default();
+        assert!(d.quantile(0.5).is_nan());
+        let mut rng = Rng::new(7);
+        let mut values: Vec<f64> = (0..100_000).map(|_| rng.next_f64() * 1000.0).collect();
+        for &v in &values {
+            d.add(v);
+        }
+        assert!(d.centroids.len() <= 100);
+        values.sort_by(f64::total_cmp);
+        for q in [0.001, 0.1, 0.5, 0.9, 0.999] {
+            let exact = values[(q * values.len() as f64) as usize];
+            assert!((d.quantile(q) - exact).abs() < 5.0, "{q}");
+        }
+        assert_eq!(d.quantile(0.0), values[0]);
+        assert_eq!(d.quantile(1.0), values[values.len() - 1]);
+
+        // merging halves
+        let mut a = TDigest::default();
+        let mut b = TDigest::default();
+        for (i, &v) in values.iter().enumerate() {
+            if i % 2 == 0 { a.add(v) } else { b.add(v) }
+        }
+        a.merge(&b);
+        assert_eq!(a.count(), 100_000.0);
+        assert!((a.quantile(0.5) - 500.0).abs() < 10.0);
+    }
+
+    fn read(len: usize, q: u8) -> SeqOwned {
+        SeqOwned {
+            id: b"r".to_vec(),
+            desc: Vec::new(),
+            seq: vec![b'A'; len],
+            qual: Some(vec![q + 33; len]),
+        }
+    }
+
+    #[test]
+    fn test_streaming_stats() {
+        // N50 of 2, 3, 4, 5, 6, 7, 8, 9, 10 (total 54) is 8: 10 + 9 + 8 = 27
+        let reads: Vec<SeqOwned> = (2..=10).map(|len| read(len, len as u8 * 3)).collect();
+        let mut exact = StreamingStats::new(QuantileMode::Exact);
+        let mut approx = StreamingStats::new(QuantileMode::default());
+        for r in &reads {
+            exact.add(&r.as_seq());
+            approx.add(&r.as_seq());
+        }
+        assert_eq!((exact.reads(), exact.bases()), (9, 54));
+        assert_eq!(exact.median_len(), 6.0);
+        assert_eq!(exact.len_quantile(0.0), 2.0);
+        assert_eq!(exact.len_quantile(1.0), 10.0);
+        assert_eq!(exact.n50(), 8.0);
+        assert_eq!(exact.nx(90.0), 4.0);
+        assert_eq!(exact.median_qual(), 18.0);
+        assert!((approx.median_len() - 6.0).abs() <= 0.5);
+        assert!((approx.n50() - 8.0).abs() <= 1.0);
+
+        let mut fasta = StreamingStats::new(QuantileMode::Exact);
+        fasta.add(&Seq {
+            id: b"r",
+            desc: b"",
+            seq: b"ACGT",
+            qual: None,
+        });
+        assert!(fasta.median_qual().is_nan());
+        assert_eq!(fasta.n50(), 4.0);
+    }
+}
@@ -1,17 +1,25 @@
 use crate::errors::*;
+use crate::parallel::*;
+use crate::scratch::*;
 use crate::seq::*;
 use crate::util::*;
 use crate::xopen::*;
 use memchr::{memchr, memchr2};
-use std::io::BufRead;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
 
 /// A FASTA/Q reader that can read from any BufRead.
 /// It supports both FASTA and FASTQ formats,
 /// and can automatically detect the format based on the first non-empty line.
+///
+/// Multi-line (wrapped) FASTQ records are parsed as well: sequence lines are joined until the
+/// `+` separator line, and quality lines until the quality is as long as the sequence, so quality
+/// lines starting with `@` or `+` are not mistaken for headers or separators.
 pub struct Reader<R: BufRead> {
     reader: R,
 
     is_fastq: bool,
+    format: Format, // Unknown until the first record is read
 
     record_buf: Vec<u8>,
     line_buf: Vec<u8>,
@@ -19,19 +27,291 @@ pub struct Reader<R: BufRead> {
     has_lookahead: bool,
 
     parse_id: bool,
+
+    consumed: u64, // bytes consumed from the reader
+    lines: u64,    // line feeds consumed from the reader
+    lookahead_pos: RecordPosition,
+    record_pos: RecordPosition,
+
+    on_malformed: Option<MalformedCallback>,
+    malformed: u64,
+
+    on_comment: Option<CommentCallback>, // FASTA comment lines are skipped if set
+
+    strict: Option<StrictOptions>,
+    sep_line: Vec<u8>, // content of the FASTQ separator line after '+', only kept in strict mode
+
+    limits: SizeLimits,
+    buf_limit: usize, // record_buf stops growing past it within a long line
+
+    blank_lines: BlankLinePolicy,
+    blank_line: u64, // the first blank line of the current record, only kept by BlankLinePolicy::Error
+    blank_line_count: u64,
+}
+
+/// Size limits of records of a reader, see `Reader::set_limits`. There are no limits by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeLimits {
+    /// Maximum length of header lines, without the leading '>' or '@'.
+    pub max_header_len: usize,
+    /// Maximum length of sequences.
+    pub max_seq_len: usize,
+}
+
+impl Default for SizeLimits {
+    fn default() -> Self {
+        Self {
+            max_header_len: usize::MAX,
+            max_seq_len: usize::MAX,
+        }
+    }
+}
+
+/// Policies of treating blank lines, see `Reader::set_blank_lines`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BlankLinePolicy {
+    /// Skip blank lines silently, the default.
+    #[default]
+    Skip,
+    /// Skip blank lines, logging a warning for every one. Warnings need the `log` feature,
+    /// without it this is the same as `Skip`; the count is available either way from
+    /// `Reader::skipped_blank_lines`.
+    SkipWithWarning,
+    /// Return a `BlankLine` error for the record during whose reading a blank line is met.
+    Error,
+}
+
+/// Checks of a strict reader, see `Reader::set_strict`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StrictOptions {
+    /// The alphabet of sequences.
+    pub alphabet: Alphabet,
+    /// Whether a non-empty separator line must repeat the header line.
+    pub check_separator: bool,
+    /// The range of quality characters, '!'..='~' (Phred+33 0-93) by default.
+    pub qual_range: (u8, u8),
+}
+
+impl Default for StrictOptions {
+    fn default() -> Self {
+        Self {
+            alphabet: Alphabet::Iupac,
+            check_separator: true,
+            qual_range: (b'!', b'~'),
+        }
+    }
+}
+
+/// The position of a record in the (decompressed) input: the byte offset and the 1-based line
+/// number of its header line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RecordPosition {
+    pub offset: u64,
+    pub line: u64,
+}
+
+type MalformedCallback = Box<dyn FnMut(&MalformedRecord) + Send>;
+type CommentCallback = Box<dyn FnMut(&[u8]) + Send>;
+
+/// A record skipped by a lenient reader, see `Reader::set_lenient`.
+#[derive(Debug)]
+pub struct MalformedRecord<'a> {
+    pub position: RecordPosition,
+    pub error: &'a FastxErr,
+    /// Bytes of the record parsed before the error, without line endings: the stray line,
+    /// or the header, sequence and quality lines concatenated.
+    pub bytes: &'a [u8],
+}
+
+/// A builder of readers, collecting options of reading in one place, e.g.,
+///
+/// ```no_run
+/// use fastseq::ReaderBuilder;
+/// use fastseq::reader::{SizeLimits, StrictOptions};
+///
+/// let mut reader = ReaderBuilder::new()
+///     .buffer_size(1 << 20)
+///     .strict(StrictOptions::default())
+///     .limits(SizeLimits {
+///         max_header_len: 1 << 10,
+///         max_seq_len: 1 << 30,
+///     })
+///     .lenient(|m| eprintln!("skipped a record at line {}", m.position.line))
+///     .open("reads.fq.gz")
+///     .unwrap();
+/// ```
+pub struct ReaderBuilder {
+    buf_size: usize,
+    buf_align: usize,
+    parse_id: bool,
+    cr_line_endings: bool,
+    on_malformed: Option<MalformedCallback>,
+    on_comment: Option<CommentCallback>,
+    strict: Option<StrictOptions>,
+    limits: SizeLimits,
+    blank_lines: BlankLinePolicy,
+}
+
+impl Default for ReaderBuilder {
+    fn default() -> Self {
+        Self {
+            buf_size: 65536,
+            buf_align: DEFAULT_IO_BUFFER_ALIGNMENT,
+            parse_id: true,
+            cr_line_endings: false,
+            on_malformed: None,
+            on_comment: None,
+            strict: None,
+            limits: SizeLimits::default(),
+            blank_lines: BlankLinePolicy::Skip,
+        }
+    }
+}
+
+impl ReaderBuilder {
+    /// Creates a builder with the default options, the same as `Reader::new`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the size of the input buffer, 64 KiB by default.
+    /// It doesn't apply to readers built by `from_reader`.
+    pub fn buffer_size(mut self, buf_size: usize) -> Self {
+        self.buf_size = buf_size;
+        self
+    }
+
+    /// Sets the alignment of the input buffer of files opened by `open`, a power of two.
+    pub fn buffer_alignment(mut self, buf_align: usize) -> Self {
+        self.buf_align = buf_align;
+        self
+    }
+
+    /// Sets whether to split headers into IDs and descriptions, see `Reader::skip_id_parsing`.
+    pub fn parse_id(mut self, parse_id: bool) -> Self {
+        self.parse_id = parse_id;
+        self
+    }
+
+    /// Sets whether a bare '\r' also ends a line, for old Mac line endings, see `CrLineEndings`.
+    /// It applies to readers built by `open`, `open_paths` and `from_read_decompressed`,
+    /// wrap the input in `CrLineEndings` for the others.
+    pub fn cr_line_endings(mut self, cr_line_endings: bool) -> Self {
+        self.cr_line_endings = cr_line_endings;
+        self
+    }
+
+    /// Skips malformed records, see `Reader::set_lenient`.
+    pub fn lenient<F: FnMut(&MalformedRecord) + Send + 'static>(mut self, on_malformed: F) -> Self {
+        self.on_malformed = Some(Box::new(on_malformed));
+        self
+    }
+
+    /// Skips FASTA comment lines, see `Reader::skip_comments`.
+    pub fn skip_comments(self) -> Self {
+        self.on_comment(|_| {})
+    }
+
+    /// Skips FASTA comment lines, calling the callback with every one, see `Reader::set_on_comment`.
+    pub fn on_comment<F: FnMut(&[u8]) + Send + 'static>(mut self, on_comment: F) -> Self {
+        self.on_comment = Some(Box::new(on_comment));
+        self
+    }
+
+    /// Validates every record, see `Reader::set_strict`.
+    pub fn strict(mut self, opts: StrictOptions) -> Self {
+        self.strict = Some(opts);
+        self
+    }
+
+    /// Limits the sizes of records, see `Reader::set_limits`.
+    pub fn limits(mut self, limits: SizeLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets the policy of blank lines, see `Reader::set_blank_lines`.
+    pub fn blank_lines(mut self, policy: BlankLinePolicy) -> Self {
+        self.blank_lines = policy;
+        self
+    }
+
+    /// Builds a reader of a file, see `Reader::new`.
+    pub fn open<P: AsRef<Path>>(self, file: P) -> Result<Reader<Box<dyn BufRead>>, FastxErr> {
+        let r = xopen_with_alignment(file, self.buf_size, self.buf_align)?;
+        Ok(self.build_boxed(r))
+    }
+
+    /// Builds a reader of multiple files in turn, see `Reader::from_paths`.
+    pub fn open_paths<P: AsRef<Path>>(
+        self,
+        files: &[P],
+    ) -> Result<Reader<Box<dyn BufRead>>, FastxErr> {
+        for file in files {
+            if let IoPath::File(path) = IoPath::new(file) {
+                std::fs::metadata(long_path(&path))?;
+            }
+        }
+        let r = Box::new(ChainReader::new(files, self.buf_size));
+        Ok(self.build_boxed(r))
+    }
+
+    /// Builds a reader of any Read, decompressing it if needed, see `Reader::from_read_decompressed`.
+    pub fn from_read_decompressed<T: Read + 'static>(
+        self,
+        inner: T,
+    ) -> Result<Reader<Box<dyn BufRead>>, FastxErr> {
+        let r = xopen_reader_with_alignment(inner, self.buf_size, self.buf_align)?;
+        Ok(self.build_boxed(r))
+    }
+
+    /// Builds a reader of any Read without decompression, see `Reader::from_read`.
+    pub fn from_read<T: Read>(self, inner: T) -> Reader<BufReader<T>> {
+        let r = BufReader::with_capacity(self.buf_size, inner);
+        self.build(r)
+    }
+
+    /// Builds a reader of any BufRead, see `Reader::from_reader`.
+    pub fn from_reader<R: BufRead>(self, reader: R) -> Reader<R> {
+        self.build(reader)
+    }
+
+    fn build_boxed(self, r: Box<dyn BufRead>) -> Reader<Box<dyn BufRead>> {
+        if self.cr_line_endings {
+            let r = Box::new(CrLineEndings::new(r, self.buf_size));
+            return self.build(r);
+        }
+        self.build(r)
+    }
+
+    fn build<R: BufRead>(self, reader: R) -> Reader<R> {
+        let mut reader = Reader::from_reader(reader);
+        reader.parse_id = self.parse_id;
+        reader.on_malformed = self.on_malformed;
+        reader.on_comment = self.on_comment;
+        reader.strict = self.strict;
+        reader.limits = self.limits;
+        reader.blank_lines = self.blank_lines;
+        reader
+    }
 }
 
 impl Reader<Box<dyn BufRead>> {
+    /// Returns a builder of readers with the default options.
+    pub fn builder() -> ReaderBuilder {
+        ReaderBuilder::new()
+    }
+
     /// Creates a new Reader from a file path.
     /// Stdin is supported by passing "-" as the file path.
     /// The reader will take ownership of the file reader.
-    pub fn new(file: &str) -> Result<Self, FastxErr> {
+    pub fn new<P: AsRef<Path>>(file: P) -> Result<Self, FastxErr> {
         Self::new_with_buffer_alignment(file, 65536, DEFAULT_IO_BUFFER_ALIGNMENT)
     }
 
     /// Creates a new Reader from a file path with specified buffer size and alignment.
-    pub fn new_with_buffer_alignment(
-        file: &str,
+    pub fn new_with_buffer_alignment<P: AsRef<Path>>(
+        file: P,
         buf_size: usize,
         buf_align: usize,
     ) -> Result<Self, FastxErr> {
@@ -39,6 +319,46 @@ impl Reader<Box<dyn BufRead>> {
             xopen_with_alignment(file, buf_size, buf_align).map_err(FastxErr::IOError)?;
         Ok(Self::from_reader(r))
     }
+
+    /// Creates a new Reader of records of multiple files in turn, as if they were concatenated.
+    /// Files are opened one by one and may be compressed differently, but should be all FASTA or
+    /// all FASTQ. Use `MultiReader` to know which file a record comes from.
+    /// It fails if a file does not exist, other errors show up when the file is read.
+    pub fn from_paths<P: AsRef<Path>>(files: &[P]) -> Result<Self, FastxErr> {
+        ReaderBuilder::new().open_paths(files)
+    }
+
+    /// Creates a new Reader from a file compressed with zstd using a dictionary,
+    /// e.g., written by `Writer::with_zstd_dictionary`.
+    #[cfg(feature = "zstd")]
+    pub fn with_zstd_dictionary<P: AsRef<Path>>(file: P, dict: &[u8]) -> Result<Self, FastxErr> {
+        Ok(Self::from_reader(xopen_zstd_with_dictionary(
+            file, dict, 65536,
+        )?))
+    }
+
+    /// Creates a new Reader from any Read, e.g., a socket or a decompressor from another crate.
+    /// Compressed input is detected and decompressed like files opened by path.
+    pub fn from_read_decompressed<T: Read + 'static>(inner: T) -> Result<Self, FastxErr> {
+        let r = xopen_reader(inner, 65536).map_err(FastxErr::IOError)?;
+        Ok(Self::from_reader(r))
+    }
+}
+
+impl<T: Read> Reader<BufReader<T>> {
+    /// Creates a new Reader from any Read, wrapping it in a BufReader of 64 KiB.
+    /// No decompression is performed.
+    pub fn from_read(inner: T) -> Self {
+        Self::from_reader(BufReader::with_capacity(65536, inner))
+    }
+}
+
+impl<'a> Reader<&'a [u8]> {
+    /// Creates a new Reader parsing records held in memory, without copying the input.
+    /// For owned data, use `Reader::from_reader(Cursor::new(data))`.
+    pub fn from_bytes(data: &'a [u8]) -> Self {
+        Self::from_reader(data)
+    }
 }
 
 impl<R: BufRead> Reader<R> {
@@ -47,32 +367,275 @@ impl<R: BufRead> Reader<R> {
         Self {
             reader,
             is_fastq: false,
+            format: Format::Unknown,
             record_buf: Vec::with_capacity(1 << 20),
             line_buf: Vec::with_capacity(1024),
             lookahead_line: Vec::with_capacity(1024),
             has_lookahead: false,
             parse_id: true,
+            consumed: 0,
+            lines: 0,
+            lookahead_pos: RecordPosition::default(),
+            record_pos: RecordPosition::default(),
+            on_malformed: None,
+            malformed: 0,
+            on_comment: None,
+            strict: None,
+            sep_line: Vec::new(),
+            limits: SizeLimits::default(),
+            buf_limit: usize::MAX,
+            blank_lines: BlankLinePolicy::Skip,
+            blank_line: 0,
+            blank_line_count: 0,
         }
     }
 
+    // gives access to the input, e.g., for appending data of streams fed asynchronously
+    #[cfg(feature = "async")]
+    pub(crate) fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
     // skip parsing the ID and description fields in the header, and just return the raw header line as the ID
     pub fn skip_id_parsing(&mut self) {
         self.parse_id = false
     }
 
+    /// Skips records failing to parse instead of returning errors, e.g., stray lines or
+    /// truncated quality, calling the callback with every skipped record.
+    /// In lenient mode, headers of the other format than the first record are also skipped.
+    /// I/O errors are still returned.
+    pub fn set_lenient<F: FnMut(&MalformedRecord) + Send + 'static>(&mut self, on_malformed: F) {
+        self.on_malformed = Some(Box::new(on_malformed));
+    }
+
+    /// Skips comment lines of classic FASTA, i.e., lines starting with ';' before or inside
+    /// records, which are otherwise errors before the first record or parts of sequences.
+    /// FASTQ records are not affected, as ';' is a valid quality character.
+    pub fn skip_comments(&mut self) {
+        self.set_on_comment(|_| {});
+    }
+
+    /// Skips FASTA comment lines like `skip_comments`, calling the callback with every comment,
+    /// without the leading ';'. Comments longer than the header size limit are cut short.
+    pub fn set_on_comment<F: FnMut(&[u8]) + Send + 'static>(&mut self, on_comment: F) {
+        self.on_comment = Some(Box::new(on_comment));
+    }
+
+    /// Validates every record, returning `InvalidSeqChar`, `SeparatorMismatch` or `InvalidQualChar`
+    /// errors on violations. Combined with `set_lenient`, invalid records are skipped instead.
+    pub fn set_strict(&mut self, opts: StrictOptions) {
+        self.strict = Some(opts);
+    }
+
+    /// Limits the sizes of records, so malformed or hostile input can't grow buffers unboundedly:
+    /// buffers are bounded by the limits plus the size of the input buffer.
+    /// Records exceeding a limit are skipped and a `SizeLimitExceeded` error is returned,
+    /// or they are reported as malformed records in lenient mode.
+    pub fn set_limits(&mut self, limits: SizeLimits) {
+        self.limits = limits;
+    }
+
+    /// Sets how blank lines are treated, they are skipped silently by default.
+    /// With `BlankLinePolicy::Error`, a record is read entirely before the error is returned,
+    /// so reading can go on. Blank lines between records belong to the record reading them,
+    /// i.e., the previous one in FASTA and the next one in FASTQ.
+    pub fn set_blank_lines(&mut self, policy: BlankLinePolicy) {
+        self.blank_lines = policy;
+    }
+
+    /// Returns the number of blank lines skipped, under any policy.
+    pub fn skipped_blank_lines(&self) -> u64 {
+        self.blank_line_count
+    }
+
+    /// Returns the number of malformed records skipped in lenient mode.
+    pub fn malformed_records(&self) -> u64 {
+        self.malformed
+    }
+
+    /// Returns the position of the last record read, e.g., for building indexes,
+    /// or for pointing at the record in error messages.
+    pub fn last_record_position(&self) -> RecordPosition {
+        self.record_pos
+    }
+
+    /// Returns the current position in the input: the number of bytes consumed,
+    /// and the number of the line to be read next.
+    pub fn position(&self) -> RecordPosition {
+        RecordPosition {
+            offset: self.consumed,
+            line: self.lines + 1,
+        }
+    }
+
+    #[inline(always)]
+    fn consume(&mut self, n: usize, has_lf: bool) {
+        self.reader.consume(n);
+        self.consumed += n as u64;
+        self.lines += has_lf as u64;
+    }
+
+    /// Returns the format of the input: the format of the records once one has been read,
+    /// otherwise detected from the buffered input without consuming it, see `detect_format`.
+    pub fn format(&mut self) -> Format {
+        if self.format != Format::Unknown {
+            return self.format;
+        }
+        detect_format(&mut self.reader).unwrap_or(Format::Unknown)
+    }
+
+    /// Counts the remaining records as fast as possible, without copying or validating them:
+    /// FASTA records are counted by headers, FASTQ records are checked only for the lengths of
+    /// sequences and qualities. Lenient and strict modes don't apply, and the input is consumed,
+    /// with `position()` only tracking the byte offset afterwards.
+    pub fn count(&mut self) -> Result<u64, FastxErr> {
+        // the first record decides the format
+        let mut n = 0;
+        if self.format == Format::Unknown {
+            match self.read_record() {
+                None => return Ok(0),
+                Some(Err(e)) => return Err(e),
+                Some(Ok(_)) => n += 1,
+            }
+        }
+        if self.has_lookahead {
+            self.has_lookahead = false;
+            n += 1;
+        }
+        if self.is_fastq {
+            Ok(n + self.count_fastq()?)
+        } else {
+            Ok(n + self.count_fasta()?)
+        }
+    }
+
+    // counts '>' at the start of lines, the reader is at the start of a line
+    fn count_fasta(&mut self) -> Result<u64, FastxErr> {
+        let mut n = 0;
+        let mut prev = b'\n';
+        loop {
+            let buf = self.reader.fill_buf()?;
+            if buf.is_empty() {
+                return Ok(n);
+            }
+            for i in memchr::memchr_iter(b'>', buf) {
+                let before = if i == 0 { prev } else { buf[i - 1] };
+                if before == b'\n' {
+                    n += 1;
+                }
+            }
+            prev = buf[buf.len() - 1];
+            let len = buf.len();
+            self.reader.consume(len);
+            self.consumed += len as u64;
+        }
+    }
+
+    // counts FASTQ records by line lengths, the reader is at the start of a line
+    fn count_fastq(&mut self) -> Result<u64, FastxErr> {
+        enum State {
+            Header,
+            Seq,
+            Qual,
+        }
+        let mut state = State::Header;
+        let (mut n, mut seq_len, mut qual_len) = (0, 0, 0);
+        // the current line, which may span buffers
+        let (mut first, mut line_len, mut cr) = (0u8, 0usize, false);
+
+        let mut on_line = |first: u8, len: usize| -> Result<(), FastxErr> {
+            if len == 0 {
+                return Ok(()); // skip blank lines
+            }
+            match state {
+                State::Header if first == b'@' => {
+                    n += 1;
+                    seq_len = 0;
+                    state = State::Seq;
+                }
+                State::Header => return Err(FastxErr::InvalidFormat),
+                State::Seq if first == b'+' => {
+                    qual_len = 0;
+                    state = if seq_len == 0 {
+                        State::Header
+                    } else {
+                        State::Qual
+                    };
+                }
+                State::Seq => seq_len += len,
+                State::Qual => {
+                    qual_len += len;
+                    if qual_len > seq_len {
+                        return Err(FastxErr::UnequalSeqAndQual(seq_len, qual_len));
+                    }
+                    if qual_len == seq_len {
+                        state = State::Header;
+                    }
+                }
+            }
+            Ok(())
+        };
+
+        loop {
+            let buf = self.reader.fill_buf()?;
+            if buf.is_empty() {
+                break;
+            }
+            let mut pos = 0;
+            while pos < buf.len() {
+                if line_len == 0 {
+                    first = buf[pos];
+                }
+                match memchr(b'\n', &buf[pos..]) {
+                    Some(i) => {
+                        line_len += i;
+                        if (i > 0 && buf[pos + i - 1] == b'\r') || (i == 0 && cr) {
+                            line_len -= 1;
+                        }
+                        on_line(first, line_len)?;
+                        (line_len, cr) = (0, false);
+                        pos += i + 1;
+                    }
+                    None => {
+                        line_len += buf.len() - pos;
+                        cr = buf[buf.len() - 1] == b'\r';
+                        pos = buf.len();
+                    }
+                }
+            }
+            let len = buf.len();
+            self.reader.consume(len);
+            self.consumed += len as u64;
+        }
+        if cr {
+            line_len -= 1;
+        }
+        on_line(first, line_len)?; // the last line without a line feed
+
+        match state {
+            State::Header => Ok(n),
+            State::Seq if seq_len == 0 => Ok(n),
+            State::Seq => Err(FastxErr::UnequalSeqAndQual(seq_len, 0)),
+            State::Qual => Err(FastxErr::UnequalSeqAndQual(seq_len, qual_len)),
+        }
+    }
+
     // Read a line into line_buf, stripping any trailing "\r\n" or "\n".
     // Returns the number of raw bytes consumed from the reader (line + line ending).
     // 0 means EOF was reached without consuming anything.
+    // Lines longer than `limit` are cut short but still consumed, and are left longer than it.
     #[inline(always)]
-    fn read_line_fill_buf(&mut self) -> std::io::Result<usize> {
+    fn read_line_fill_buf(&mut self, limit: usize) -> std::io::Result<usize> {
         self.line_buf.clear();
 
         let mut total = 0;
+        let mut over = false;
         loop {
             let buf = self.reader.fill_buf()?;
             if buf.is_empty() {
                 // EOF — strip any trailing \r that might have been buffered without a following \n
-                if self.line_buf.last() == Some(&b'\r') {
+                if !over && self.line_buf.last() == Some(&b'\r') {
                     self.line_buf.pop();
                 }
                 return Ok(total);
@@ -83,28 +646,37 @@ impl<R: BufRead> Reader<R> {
                 Some(pos) => {
                     // found a line ending; copy the line (including '\n') into line_buf
                     let end = pos + 1;
-                    self.line_buf.extend_from_slice(&buf[..end]);
+                    if !over {
+                        self.line_buf.extend_from_slice(&buf[..end]);
+                    }
                     (end, true)
                 }
                 None => {
                     // no line ending found, consume the entire buffer and continue reading
-                    self.line_buf.extend_from_slice(buf);
+                    if !over {
+                        self.line_buf.extend_from_slice(buf);
+                    }
                     (buf.len(), false)
                 }
             };
 
-            self.reader.consume(consumed);
+            self.consume(consumed, done);
             total += consumed;
 
             //
             if done {
                 // strip trailing '\n' and any preceding '\r' so callers see a clean line
-                self.line_buf.pop();
-                if self.line_buf.last() == Some(&b'\r') {
+                if !over {
                     self.line_buf.pop();
+                    if self.line_buf.last() == Some(&b'\r') {
+                        self.line_buf.pop();
+                    }
                 }
                 return Ok(total);
             }
+
+            // a trailing '\r' may be pending
+            over = self.line_buf.len() > limit.saturating_add(1);
         }
     }
 
@@ -112,12 +684,52 @@ impl<R: BufRead> Reader<R> {
     #[inline(always)]
     fn read_next_nonempty_line(&mut self) -> Result<bool, FastxErr> {
         loop {
-            match self.read_line_fill_buf() {
-                Ok(0) => return Ok(false),                     // EOF
-                Ok(_) if self.line_buf.is_empty() => continue, // skip blank lines
-                Ok(_) => return Ok(true),                      // non-empty line read successfully
-                Err(e) => return Err(FastxErr::IOError(e)),    // I/O error occurred
+            self.record_pos = self.position();
+            match self.read_line_fill_buf(self.limits.max_header_len.saturating_add(1)) {
+                Ok(0) => return Ok(false),                   // EOF
+                Ok(_) if self.skip_blank_line() => continue, // skip blank lines
+                Ok(_) if self.skip_comment() => continue,    // skip FASTA comment lines
+                Ok(_) => return Ok(true),                    // non-empty line read successfully
+                Err(e) => return Err(FastxErr::IOError(e)),  // I/O error occurred
+            }
+        }
+    }
+
+    // returns true if line_buf is a blank line, read from the position of record_pos
+    fn skip_blank_line(&mut self) -> bool {
+        if !self.line_buf.is_empty() {
+            return false;
+        }
+        self.on_blank_line(self.record_pos.line);
+        true
+    }
+
+    // handles a blank line at the 1-based line number by the policy
+    #[cold]
+    fn on_blank_line(&mut self, line: u64) {
+        self.blank_line_count += 1;
+        match self.blank_lines {
+            BlankLinePolicy::Skip => {}
+            BlankLinePolicy::SkipWithWarning => warn!("skipped blank line {line}"),
+            BlankLinePolicy::Error => {
+                if self.blank_line == 0 {
+                    self.blank_line = line;
+                }
+            }
+        }
+    }
+
+    // calls the comment callback and returns true if line_buf is a FASTA comment line to skip
+    fn skip_comment(&mut self) -> bool {
+        if self.line_buf[0] != b';' || self.format == Format::Fastq {
+            return false;
+        }
+        match self.on_comment.as_mut() {
+            Some(f) => {
+                f(&self.line_buf[1..]);
+                true
             }
+            None => false,
         }
     }
 
@@ -153,30 +765,46 @@ impl<R: BufRead> Reader<R> {
 
                 if line_len == 0 {
                     // blank line — drop the buf borrow and consume
-                    self.reader.consume(consumed);
+                    self.on_blank_line(self.lines + 1);
+                    self.consume(consumed, true);
                     continue;
                 }
 
                 let first_char = buf[0];
 
+                if !STOP_ON_FASTQ_SEP
+                    && first_char == b';'
+                    && let Some(f) = self.on_comment.as_mut()
+                {
+                    // a FASTA comment line
+                    f(&buf[1..line_len]);
+                    self.consume(consumed, true);
+                    continue;
+                }
+
                 if STOP_ON_FASTA_HEADER && first_char == b'>' {
                     // stash the header (already trimmed) into lookahead, last use of `buf`
                     self.lookahead_line.clear();
                     self.lookahead_line.extend_from_slice(&buf[..line_len]);
-                    self.reader.consume(consumed);
+                    self.lookahead_pos = self.position();
+                    self.consume(consumed, true);
                     self.has_lookahead = true;
                     return Ok(ReadLineOutcome::NextHeader);
                 }
 
                 if STOP_ON_FASTQ_SEP && first_char == b'+' {
-                    // separator — no copy needed
-                    self.reader.consume(consumed);
+                    // separator — only copied to be checked in strict mode
+                    if self.strict.is_some() {
+                        self.sep_line.clear();
+                        self.sep_line.extend_from_slice(&buf[1..line_len]);
+                    }
+                    self.consume(consumed, true);
                     return Ok(ReadLineOutcome::FastqSep);
                 }
 
                 // normal line: append the trimmed content directly from the reader's buffer
                 self.record_buf.extend_from_slice(&buf[..line_len]);
-                self.reader.consume(consumed);
+                self.consume(consumed, true);
                 return Ok(ReadLineOutcome::Appended(line_len));
             }
 
@@ -187,27 +815,38 @@ impl<R: BufRead> Reader<R> {
             // If the first byte cannot be a header / separator, hand off to the long-line
             // appender — it streams directly into record_buf without lookahead concerns.
             if !((STOP_ON_FASTA_HEADER && first_char == b'>')
-                || (STOP_ON_FASTQ_SEP && first_char == b'+'))
+                || (STOP_ON_FASTQ_SEP && first_char == b'+')
+                || (!STOP_ON_FASTQ_SEP && first_char == b';' && self.on_comment.is_some()))
             {
                 return self.read_long_line_into_record_buf();
             }
 
             // Slow path: assemble the full line into self.line_buf so we can preserve it as
             // a lookahead header if needed.
-            match self.read_line_fill_buf() {
+            let start = self.position();
+            match self.read_line_fill_buf(self.limits.max_header_len.saturating_add(1)) {
                 Ok(0) => return Ok(ReadLineOutcome::Eof),
                 Ok(_) => {
                     if self.line_buf.is_empty() {
+                        self.on_blank_line(start.line);
                         continue;
                     }
                     let first_char = self.line_buf[0];
+                    if !STOP_ON_FASTQ_SEP && self.skip_comment() {
+                        continue;
+                    }
                     if STOP_ON_FASTA_HEADER && first_char == b'>' {
                         // line_buf is already trimmed; swap it into lookahead_line
                         std::mem::swap(&mut self.line_buf, &mut self.lookahead_line);
+                        self.lookahead_pos = start;
                         self.has_lookahead = true;
                         return Ok(ReadLineOutcome::NextHeader);
                     }
                     if STOP_ON_FASTQ_SEP && first_char == b'+' {
+                        if self.strict.is_some() {
+                            self.sep_line.clear();
+                            self.sep_line.extend_from_slice(&self.line_buf[1..]);
+                        }
                         return Ok(ReadLineOutcome::FastqSep);
                     }
                     let len = self.line_buf.len();
@@ -233,11 +872,12 @@ impl<R: BufRead> Reader<R> {
                 let consumed = pos + 1;
                 let line_len = trim_crlf(&buf[..consumed]).len();
                 if line_len == 0 {
-                    self.reader.consume(consumed);
+                    self.on_blank_line(self.lines + 1);
+                    self.consume(consumed, true);
                     continue;
                 }
                 self.record_buf.extend_from_slice(&buf[..line_len]);
-                self.reader.consume(consumed);
+                self.consume(consumed, true);
                 return Ok(ReadLineOutcome::Appended(line_len));
             }
 
@@ -295,15 +935,18 @@ impl<R: BufRead> Reader<R> {
             }
 
             if !data.is_empty() {
-                self.record_buf.extend_from_slice(data);
+                if self.record_buf.len() <= self.buf_limit {
+                    self.record_buf.extend_from_slice(data);
+                }
                 line_len += data.len();
             }
 
             pending_cr = ends_with_cr && !has_lf;
-            self.reader.consume(consumed);
+            self.consume(consumed, has_lf);
 
             if has_lf {
                 if line_len == 0 {
+                    self.on_blank_line(self.lines);
                     continue;
                 }
                 return Ok(ReadLineOutcome::Appended(line_len));
@@ -313,8 +956,110 @@ impl<R: BufRead> Reader<R> {
 
     // returns None if EOF is reached, otherwise returns Some(Ok(Seq)) or Some(Err(e))
     #[allow(clippy::should_implement_trait)]
+    #[inline]
     pub fn next(&mut self) -> Option<Result<Seq<'_>, FastxErr>> {
+        match self.read_record()? {
+            Ok((header_end, seq_end)) => Some(Ok(self.record(header_end, seq_end))),
+            Err(e) => Some(Err(e)),
+        }
+    }
+
+    /// Reads the next record into the caller's buffers, returning false if EOF is reached.
+    /// Buffers are reused across records, so the loop does not allocate once they are large enough.
+    pub fn read_into(&mut self, buf: &mut RecordBuf) -> Result<bool, FastxErr> {
+        match self.read_record() {
+            None => Ok(false),
+            Some(Ok((header_end, seq_end))) => {
+                buf.set(&self.record(header_end, seq_end));
+                Ok(true)
+            }
+            Some(Err(e)) => Err(e),
+        }
+    }
+
+    /// Reads up to `n` records into the vector, and returns the number of records read, 0 on EOF.
+    /// The vector is truncated to the records read, and records already in it are overwritten
+    /// reusing their buffers, so recycled vectors make the loop allocation-free.
+    /// See also `RecordBatch`, which stores a batch in one buffer.
+    pub fn read_batch(&mut self, n: usize, batch: &mut Vec<SeqOwned>) -> Result<usize, FastxErr> {
+        let mut i = 0;
+        while i < n {
+            match self.read_record() {
+                None => break,
+                Some(Ok((header_end, seq_end))) => {
+                    let seq = self.record(header_end, seq_end);
+                    if i < batch.len() {
+                        batch[i].set(&seq);
+                    } else {
+                        batch.push(seq.to_owned());
+                    }
+                    i += 1;
+                }
+                Some(Err(e)) => {
+                    batch.truncate(i);
+                    return Err(e);
+                }
+            }
+        }
+        batch.truncate(i);
+        Ok(i)
+    }
+
+    /// Processes records with `threads` worker threads (0 for all CPUs), passing results to
+    /// `output` in the input order, and returns the number of records.
+    /// See `process_parallel` for details and `ParallelOptions` for batching and unordered output.
+    pub fn process_parallel<T, F, O>(
+        &mut self,
+        threads: usize,
+        work: F,
+        output: O,
+    ) -> Result<u64, FastxErr>
+    where
+        T: Send,
+        F: Fn(Seq, &mut Scratch) -> T + Sync,
+        O: FnMut(T) -> Result<(), FastxErr>,
+    {
+        let opts = ParallelOptions {
+            threads,
+            ..Default::default()
+        };
+        process_parallel(self, &opts, work, output)
+    }
+
+    // reads the next record into record_buf, and returns the ends of the header and the sequence.
+    // Splitting it from next() lets wrappers decide what to do on errors and EOF
+    // before borrowing the record.
+    #[inline(always)]
+    pub(crate) fn read_record(&mut self) -> Option<Result<(usize, usize), FastxErr>> {
+        loop {
+            match self.parse_record() {
+                Some(Err(e))
+                    if self.on_malformed.is_some() && !matches!(e, FastxErr::IOError(_)) =>
+                {
+                    let bytes = if self.record_buf.is_empty() {
+                        &self.line_buf
+                    } else {
+                        &self.record_buf
+                    };
+                    let bad = MalformedRecord {
+                        position: self.record_pos,
+                        error: &e,
+                        bytes,
+                    };
+                    if let Some(f) = self.on_malformed.as_mut() {
+                        f(&bad);
+                    }
+                    self.malformed += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn parse_record(&mut self) -> Option<Result<(usize, usize), FastxErr>> {
         self.record_buf.clear();
+        self.blank_line = 0;
 
         // --- Step 1: load or read Header into self.line_buf ---
         // line_buf and lookahead_line are kept already-trimmed (no trailing \r\n).
@@ -331,22 +1076,46 @@ impl<R: BufRead> Reader<R> {
                 b'@' => true,
                 _ => return Some(Err(FastxErr::InvalidFormat)), // not a valid fasta/q record
             };
+            // lenient readers stick to the format of the first record
+            if self.on_malformed.is_some()
+                && self.format != Format::Unknown
+                && self.is_fastq != (self.format == Format::Fastq)
+            {
+                self.is_fastq = !self.is_fastq;
+                return Some(Err(FastxErr::InvalidFormat));
+            }
+            self.format = if self.is_fastq {
+                Format::Fastq
+            } else {
+                Format::Fasta
+            };
         } else {
             // not the first record
             std::mem::swap(&mut self.line_buf, &mut self.lookahead_line);
+            self.record_pos = self.lookahead_pos;
             self.has_lookahead = false;
         }
 
         // extract header from the header line and store it into record_buf
         // (line_buf is already trimmed; just skip the leading '>' or '@')
-        let header: &[u8] = &self.line_buf[1..];
+        // Headers over the size limit are cut short, and the record is skipped after reading it.
+        let max_header_len = self.limits.max_header_len;
+        let header_too_long = self.line_buf.len() - 1 > max_header_len;
+        let end = self.line_buf.len().min(max_header_len.saturating_add(1));
+        let header: &[u8] = &self.line_buf[1..end];
         self.record_buf.extend_from_slice(header);
         let header_end = self.record_buf.len();
 
         // --- Step 2: read Sequence ---
         // The const generics specialize each call site so the dead branches are folded away.
+        // Sequences over the size limit are dropped, but still read to skip the record.
+
+        let max_seq_len = self.limits.max_seq_len;
+        let mut seq_len = 0;
+        self.buf_limit = header_end.saturating_add(max_seq_len);
 
         if self.is_fastq {
+            self.sep_line.clear();
             loop {
                 match self.read_next_nonempty_line_into_record_buf::<true, true>() {
                     Ok(
@@ -354,7 +1123,12 @@ impl<R: BufRead> Reader<R> {
                         | ReadLineOutcome::NextHeader
                         | ReadLineOutcome::FastqSep,
                     ) => break,
-                    Ok(ReadLineOutcome::Appended(_)) => {}
+                    Ok(ReadLineOutcome::Appended(len)) => {
+                        seq_len += len;
+                        if seq_len > max_seq_len {
+                            self.record_buf.truncate(header_end);
+                        }
+                    }
                     Err(e) => return Some(Err(e)),
                 }
             }
@@ -366,7 +1140,12 @@ impl<R: BufRead> Reader<R> {
                         | ReadLineOutcome::NextHeader
                         | ReadLineOutcome::FastqSep,
                     ) => break,
-                    Ok(ReadLineOutcome::Appended(_)) => {}
+                    Ok(ReadLineOutcome::Appended(len)) => {
+                        seq_len += len;
+                        if seq_len > max_seq_len {
+                            self.record_buf.truncate(header_end);
+                        }
+                    }
                     Err(e) => return Some(Err(e)),
                 }
             }
@@ -379,8 +1158,8 @@ impl<R: BufRead> Reader<R> {
         // header/separator decision logic is eliminated for the entire quality block.
 
         if self.is_fastq {
-            let seq_len = seq_end - header_end;
             let mut qual_read_len = 0;
+            self.buf_limit = seq_end.saturating_add(seq_len.min(max_seq_len));
 
             while qual_read_len < seq_len {
                 match self.read_qual_line_into_record_buf() {
@@ -391,6 +1170,9 @@ impl<R: BufRead> Reader<R> {
                         if qual_read_len > seq_len {
                             return Some(Err(FastxErr::UnequalSeqAndQual(seq_len, qual_read_len)));
                         }
+                        if seq_len > max_seq_len {
+                            self.record_buf.truncate(seq_end);
+                        }
                     }
                     // read_qual_line_into_record_buf never produces these variants
                     Ok(ReadLineOutcome::NextHeader | ReadLineOutcome::FastqSep) => unreachable!(),
@@ -403,6 +1185,66 @@ impl<R: BufRead> Reader<R> {
             }
         }
 
+        if header_too_long {
+            return Some(Err(FastxErr::SizeLimitExceeded("header", max_header_len)));
+        }
+        if seq_len > max_seq_len {
+            return Some(Err(FastxErr::SizeLimitExceeded("sequence", max_seq_len)));
+        }
+        if self.blank_line > 0 {
+            return Some(Err(FastxErr::BlankLine(self.blank_line)));
+        }
+
+        if let Some(opts) = self.strict
+            && let Err(e) = self.validate(&opts, header_end, seq_end)
+        {
+            return Some(Err(e));
+        }
+
+        Some(Ok((header_end, seq_end)))
+    }
+
+    // checks a record read by parse_record() in strict mode
+    fn validate(
+        &self,
+        opts: &StrictOptions,
+        header_end: usize,
+        seq_end: usize,
+    ) -> Result<(), FastxErr> {
+        let header = &self.record_buf[..header_end];
+        let id = || String::from_utf8_lossy(parse_header(header).0).to_string();
+        if let Some((i, b)) = opts
+            .alphabet
+            .find_invalid(&self.record_buf[header_end..seq_end])
+        {
+            return Err(FastxErr::InvalidSeqChar(id(), b as char, i));
+        }
+        if !self.is_fastq {
+            return Ok(());
+        }
+        if opts.check_separator && !self.sep_line.is_empty() && self.sep_line != header {
+            return Err(FastxErr::SeparatorMismatch(
+                id(),
+                String::from_utf8_lossy(&self.sep_line).to_string(),
+            ));
+        }
+        let (lo, hi) = opts.qual_range;
+        if let Some(i) = self.record_buf[seq_end..]
+            .iter()
+            .position(|&q| q < lo || q > hi)
+        {
+            return Err(FastxErr::InvalidQualChar(
+                id(),
+                self.record_buf[seq_end + i] as char,
+                i,
+            ));
+        }
+        Ok(())
+    }
+
+    // returns the record read by read_record()
+    #[inline(always)]
+    pub(crate) fn record(&self, header_end: usize, seq_end: usize) -> Seq<'_> {
         let buf_slice: &Vec<u8> = &self.record_buf;
         let id_slice: &[u8] = &buf_slice[0..header_end];
         let seq_slice: &[u8] = &buf_slice[header_end..seq_end];
@@ -415,19 +1257,200 @@ impl<R: BufRead> Reader<R> {
 
         if self.parse_id {
             let (id, desc) = parse_header(id_slice);
-            return Some(Ok(Seq {
+            return Seq {
                 id,
                 desc,
                 seq: seq_slice,
                 qual: qual_slice,
-            }));
+            };
         }
-        Some(Ok(Seq {
+        Seq {
             id: id_slice,
             desc: &[],
             seq: seq_slice,
             qual: qual_slice,
-        }))
+        }
+    }
+}
+
+impl<R: BufRead + Seek> Reader<R> {
+    /// Moves to the first record starting at or after the byte offset of the (decompressed)
+    /// input, e.g., an offset from `last_record_position` or the start of a chunk, for
+    /// index-based and chunk-parallel reading. It works on uncompressed input, e.g., a Reader
+    /// from `from_read(File)`, or BGZF input from `BgzfReader`.
+    ///
+    /// FASTA records start with lines starting with '>'. For FASTQ, a line starting with '@' is
+    /// only taken as a header if followed by a sequence line, a '+' line, and a quality line of
+    /// the same length, so records with wrapped lines are skipped until an unwrapped one.
+    /// Line numbers of positions restart at 1 from the record found.
+    pub fn seek_to(&mut self, offset: u64) -> Result<(), FastxErr> {
+        if self.format == Format::Unknown {
+            self.reader.seek(SeekFrom::Start(0))?;
+            self.format = detect_format(&mut self.reader)?;
+        }
+
+        // start from the byte before the offset and skip to the end of its line,
+        // so the offset itself can be the start of a record
+        let mut pos = offset.saturating_sub(1);
+        self.reader.seek(SeekFrom::Start(pos))?;
+        if offset > 0 {
+            pos += self.read_line_fill_buf(usize::MAX)? as u64;
+        }
+
+        if self.format == Format::Fastq {
+            loop {
+                let n = self.read_line_fill_buf(usize::MAX)? as u64;
+                if n == 0 {
+                    break;
+                }
+                if self.line_buf.first() == Some(&b'@') {
+                    // go back to the header, or to the line after it
+                    let found = self.fastq_record_follows()?;
+                    let next = if found { pos } else { pos + n };
+                    self.reader.seek(SeekFrom::Start(next))?;
+                    if found {
+                        break;
+                    }
+                }
+                pos += n;
+            }
+        } else {
+            loop {
+                let buf = self.reader.fill_buf()?;
+                if buf.is_empty() || buf[0] == b'>' {
+                    break;
+                }
+                pos += self.read_line_fill_buf(usize::MAX)? as u64;
+            }
+        }
+
+        self.has_lookahead = false;
+        self.record_buf.clear();
+        self.consumed = pos;
+        self.lines = 0;
+        Ok(())
+    }
+
+    // reads the three lines after a FASTQ header candidate, and checks they are the sequence,
+    // the separator and the quality of a record
+    fn fastq_record_follows(&mut self) -> Result<bool, FastxErr> {
+        if self.read_line_fill_buf(usize::MAX)? == 0 {
+            return Ok(false);
+        }
+        let seq_len = self.line_buf.len();
+        if self.read_line_fill_buf(usize::MAX)? == 0 || self.line_buf.first() != Some(&b'+') {
+            return Ok(false);
+        }
+        Ok(self.read_line_fill_buf(usize::MAX)? > 0 && self.line_buf.len() == seq_len)
+    }
+}
+
+/// Sequence file formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Fasta,
+    Fastq,
+    /// Empty input, or not starting with `>` or `@`.
+    Unknown,
+}
+
+/// Detects the format of (decompressed) input from the first non-whitespace byte
+/// of the buffered data, without consuming it, so the reader can still be read from the start.
+/// Returns `Unknown` for empty input, or if the buffered data is all whitespace.
+pub fn detect_format<R: BufRead>(reader: &mut R) -> std::io::Result<Format> {
+    let buf = reader.fill_buf()?;
+    Ok(match buf.iter().find(|b| !b.is_ascii_whitespace()) {
+        Some(b'>') => Format::Fasta,
+        Some(b'@') => Format::Fastq,
+        _ => Format::Unknown,
+    })
+}
+
+/// Counts records of a file as fast as possible, see `Reader::count`.
+pub fn count_records<P: AsRef<Path>>(file: P) -> Result<u64, FastxErr> {
+    Reader::new(file)?.count()
+}
+
+/// A source of FASTA/Q records, implemented by Reader and the readers built on top of it.
+///
+/// Records borrow the buffer of the source, so a source is a lending iterator:
+/// use `for_each_record` for a borrowed loop, or `map_records` to turn each record into an owned
+/// value and get a standard `Iterator` composing with `filter`, `map`, `collect`, etc.
+/// Pass `&mut source` to keep using the source afterwards.
+pub trait RecordSource {
+    /// Returns the next record, None if EOF is reached.
+    fn next_record(&mut self) -> Option<Result<Seq<'_>, FastxErr>>;
+
+    /// Calls a closure on every record, stopping at the first error of reading or of the closure.
+    fn for_each_record<E, F>(&mut self, mut f: F) -> Result<(), E>
+    where
+        Self: Sized,
+        E: From<FastxErr>,
+        F: FnMut(Seq) -> Result<(), E>,
+    {
+        while let Some(res) = self.next_record() {
+            f(res?)?;
+        }
+        Ok(())
+    }
+
+    /// Returns an iterator of values mapped from borrowed records by the closure.
+    fn map_records<T, F>(self, f: F) -> MapRecords<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(Seq) -> T,
+    {
+        MapRecords { source: self, f }
+    }
+
+    /// Returns an iterator of owned records.
+    fn into_records(self) -> IntoRecords<Self>
+    where
+        Self: Sized,
+    {
+        self.map_records(|seq| seq.to_owned())
+    }
+}
+
+/// An iterator of owned records of a source, see `RecordSource::into_records`.
+pub type IntoRecords<S> = MapRecords<S, fn(Seq) -> SeqOwned>;
+
+impl<R: BufRead> RecordSource for Reader<R> {
+    fn next_record(&mut self) -> Option<Result<Seq<'_>, FastxErr>> {
+        self.next()
+    }
+}
+
+impl<S: RecordSource + ?Sized> RecordSource for &mut S {
+    fn next_record(&mut self) -> Option<Result<Seq<'_>, FastxErr>> {
+        (**self).next_record()
+    }
+}
+
+impl<S: RecordSource + ?Sized> RecordSource for Box<S> {
+    fn next_record(&mut self) -> Option<Result<Seq<'_>, FastxErr>> {
+        (**self).next_record()
+    }
+}
+
+/// An iterator of values mapped from records of a source, see `RecordSource::map_records`.
+pub struct MapRecords<S, F> {
+    source: S,
+    f: F,
+}
+
+impl<S, F> MapRecords<S, F> {
+    /// Returns the underlying source.
+    pub fn into_inner(self) -> S {
+        self.source
+    }
+}
+
+impl<S: RecordSource, T, F: FnMut(Seq) -> T> Iterator for MapRecords<S, F> {
+    type Item = Result<T, FastxErr>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.source.next_record()?.map(&mut self.f))
     }
 }
 
@@ -439,7 +1462,7 @@ enum ReadLineOutcome {
 }
 
 #[inline]
-fn parse_header(line: &[u8]) -> (&[u8], &[u8]) {
+pub(crate) fn parse_header(line: &[u8]) -> (&[u8], &[u8]) {
     let Some(id_end) = memchr2(b' ', b'\t', line) else {
         return (line, &[]);
     };
@@ -455,11 +1478,13 @@ fn parse_header(line: &[u8]) -> (&[u8], &[u8]) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(feature = "gzip")]
     use flate2::Compression;
+    #[cfg(feature = "gzip")]
     use flate2::write::GzEncoder;
-    use std::fs::{self, File};
+    use std::fs;
+    use std::io::Cursor;
     use std::io::Write;
-    use std::io::{BufReader, Cursor};
     use std::path::PathBuf;
     use std::process::{Command, Stdio};
     use std::time::{SystemTime, UNIX_EPOCH};
@@ -494,7 +1519,10 @@ mod tests {
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_nanos();
-        std::env::temp_dir().join(format!("fastseq-test-{}-{nanos}{suffix}", std::process::id()))
+        std::env::temp_dir().join(format!(
+            "fastseq-test-{}-{nanos}{suffix}",
+            std::process::id()
+        ))
     }
 
     fn read_file_to_owned(path: &str) -> Result<Vec<OwnedRecord>, FastxErr> {
@@ -512,6 +1540,694 @@ mod tests {
         Ok(results)
     }
 
+    #[test]
+    fn test_reader_from_paths() {
+        let gz = if cfg!(feature = "gzip") {
+            ".fa.gz"
+        } else {
+            ".fa"
+        };
+        let (p1, p2, p3) = (temp_path(".fa"), temp_path(gz), temp_path(".fa"));
+        fs::write(&p1, ">a\nAC\nGT").unwrap(); // no trailing newline
+        let mut w = crate::Writer::new(&p2).unwrap();
+        w.write(&Seq {
+            id: b"b",
+            desc: b"",
+            seq: b"CC",
+            qual: None,
+        })
+        .unwrap();
+        w.finish().unwrap();
+        fs::write(&p3, "").unwrap();
+
+        let mut reader = Reader::from_paths(&[&p1, &p3, &p2, &p1]).unwrap();
+        let mut records = Vec::new();
+        while let Some(res) = reader.next() {
+            let seq = res.unwrap();
+            records.push(format!(
+                "{}:{}",
+                String::from_utf8_lossy(seq.id),
+                String::from_utf8_lossy(seq.seq)
+            ));
+        }
+        assert_eq!(records, ["a:ACGT", "b:CC", "a:ACGT"]);
+
+        assert!(Reader::from_paths::<&str>(&[]).unwrap().next().is_none());
+        for p in [&p1, &p2, &p3] {
+            fs::remove_file(p).unwrap();
+        }
+        assert!(Reader::from_paths(&[&p1]).is_err());
+    }
+
+    #[test]
+    fn test_reader_from_read() {
+        let input: &[u8] = b">s1 d\nACGT\n>s2\nTT\n";
+        let mut reader = Reader::from_read(input);
+        assert_eq!(reader.next().unwrap().unwrap().id, b"s1");
+        assert_eq!(reader.next().unwrap().unwrap().seq, b"TT");
+        assert!(reader.next().is_none());
+
+        let mut reader = Reader::from_read_decompressed(Cursor::new(input.to_vec())).unwrap();
+        assert_eq!(reader.next().unwrap().unwrap().desc, b"d");
+    }
+
+    #[test]
+    fn test_reader_builder() {
+        use std::sync::{Arc, Mutex};
+
+        let input: &[u8] = b">s1 d\nACGT\n>s2 x\nACGTACGT\nAC\n>s3\nAXGT\n>s4\nTT\n";
+        let skipped = Arc::new(Mutex::new(Vec::new()));
+        let sink = skipped.clone();
+        let mut reader = Reader::builder()
+            .buffer_size(16)
+            .parse_id(false)
+            .strict(StrictOptions::default())
+            .limits(SizeLimits {
+                max_header_len: 8,
+                max_seq_len: 8,
+            })
+            .lenient(move |m| sink.lock().unwrap().push(m.error.to_string()))
+            .from_read(input);
+        assert_eq!(reader.reader.capacity(), 16);
+        let mut ids = Vec::new();
+        while let Some(res) = reader.next() {
+            ids.push(String::from_utf8_lossy(res.unwrap().id).to_string());
+        }
+        assert_eq!(ids, ["s1 d", "s4"]);
+        assert_eq!(reader.malformed_records(), 2);
+        assert!(skipped.lock().unwrap()[0].contains("sequence longer than"));
+
+        let path = temp_path(".fa");
+        fs::write(&path, input).unwrap();
+        let mut reader = ReaderBuilder::new()
+            .buffer_size(4096)
+            .buffer_alignment(512)
+            .open(&path)
+            .unwrap();
+        assert_eq!(reader.next().unwrap().unwrap().desc, b"d");
+        let mut reader = ReaderBuilder::new().open_paths(&[&path, &path]).unwrap();
+        assert_eq!(reader.count().unwrap(), 8);
+        fs::remove_file(&path).unwrap();
+        assert!(ReaderBuilder::new().open_paths(&[&path]).is_err());
+    }
+
+    #[test]
+    fn test_reader_cr_line_endings() {
+        let input: &[u8] = b"@r1 x\rACGT\r+\rIIII\r@r2\rAC\r+\rII";
+        let mut reader = Reader::from_reader(CrLineEndings::new(input, 3));
+        let record = reader.next().unwrap().unwrap();
+        assert_eq!((record.desc, record.qual), (&b"x"[..], Some(&b"IIII"[..])));
+        assert_eq!(reader.next().unwrap().unwrap().seq, b"AC");
+        let pos = reader.last_record_position();
+        assert_eq!((pos.offset, pos.line), (18, 5));
+        assert!(reader.next().is_none());
+
+        let path = temp_path(".fa");
+        fs::write(&path, b">a\rAC\rGT\r>b\r\nTT\r\n").unwrap();
+        let mut reader = ReaderBuilder::new()
+            .cr_line_endings(true)
+            .open(&path)
+            .unwrap();
+        assert_eq!(reader.next().unwrap().unwrap().seq, b"ACGT");
+        assert_eq!(reader.next().unwrap().unwrap().seq, b"TT");
+        assert!(reader.next().is_none());
+        let mut reader = Reader::new(&path).unwrap();
+        assert_eq!(reader.next().unwrap().unwrap().id, b"a\rAC\rGT\r>b");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_reader_comments() {
+        use std::sync::{Arc, Mutex};
+
+        let input =
+            ";file comment\n>a x\n;record comment\nAC\n; between lines\nGT\n;;\n>b\nTT\n;end";
+        for cap in [3, 64] {
+            let comments = Arc::new(Mutex::new(Vec::new()));
+            let sink = comments.clone();
+            let mut reader = ReaderBuilder::new()
+                .on_comment(move |c| {
+                    sink.lock()
+                        .unwrap()
+                        .push(String::from_utf8_lossy(c).to_string())
+                })
+                .from_reader(BufReader::with_capacity(cap, input.as_bytes()));
+            let mut records = Vec::new();
+            while let Some(res) = reader.next() {
+                let seq = res.unwrap();
+                records.push(String::from_utf8_lossy(seq.seq).to_string());
+            }
+            assert_eq!(records, ["ACGT", "TT"]);
+            assert_eq!(
+                *comments.lock().unwrap(),
+                [
+                    "file comment",
+                    "record comment",
+                    " between lines",
+                    ";",
+                    "end"
+                ]
+            );
+        }
+
+        // ';' is a quality character of FASTQ
+        let mut reader = Reader::from_bytes(b"@a\nAC\n+\n;;\n");
+        reader.skip_comments();
+        assert_eq!(reader.next().unwrap().unwrap().qual, Some(&b";;"[..]));
+
+        // not skipped by default
+        let mut reader = Reader::from_bytes(input.as_bytes());
+        assert!(matches!(reader.next(), Some(Err(FastxErr::InvalidFormat))));
+        let mut reader = Reader::from_bytes(b">a\nAC\n;x\n");
+        assert_eq!(reader.next().unwrap().unwrap().seq, b"AC;x");
+    }
+
+    #[test]
+    fn test_reader_blank_lines() {
+        fn read_all(input: &str, cap: usize, policy: BlankLinePolicy) -> Vec<Result<String, u64>> {
+            let mut reader = ReaderBuilder::new()
+                .blank_lines(policy)
+                .from_reader(BufReader::with_capacity(cap, input.as_bytes()));
+            let mut records = Vec::new();
+            while let Some(res) = reader.next() {
+                records.push(match res {
+                    Ok(seq) => Ok(String::from_utf8_lossy(seq.seq).to_string()),
+                    Err(FastxErr::BlankLine(line)) => Err(line),
+                    Err(e) => panic!("unexpected error: {e}"),
+                });
+            }
+            records
+        }
+
+        let fasta = "\n>a\nAC\n\nGT\n>b\nTT\n\n>c\nA\n";
+        let fastq = "@a\nAC\n+\nII\n\n@b\nAC\n\nGT\n+\nIIII\n@c\nA\n+\nI\n";
+        for cap in [3, 64] {
+            for policy in [BlankLinePolicy::Skip, BlankLinePolicy::SkipWithWarning] {
+                assert_eq!(
+                    read_all(fasta, cap, policy),
+                    [
+                        Ok("ACGT".to_string()),
+                        Ok("TT".to_string()),
+                        Ok("A".to_string())
+                    ]
+                );
+                assert_eq!(
+                    read_all(fastq, cap, policy),
+                    [
+                        Ok("AC".to_string()),
+                        Ok("ACGT".to_string()),
+                        Ok("A".to_string())
+                    ]
+                );
+            }
+
+            // the first blank line of a record is reported, and the next record is read normally
+            assert_eq!(
+                read_all(fasta, cap, BlankLinePolicy::Error),
+                [Err(1), Err(8), Ok("A".to_string())]
+            );
+            assert_eq!(
+                read_all(fastq, cap, BlankLinePolicy::Error),
+                [Ok("AC".to_string()), Err(5), Ok("A".to_string())]
+            );
+
+            // counted under any policy, warnings are only logged with the log feature
+            for (input, n) in [(fasta, 3), (fastq, 2)] {
+                let mut reader = ReaderBuilder::new()
+                    .blank_lines(BlankLinePolicy::SkipWithWarning)
+                    .from_reader(BufReader::with_capacity(cap, input.as_bytes()));
+                while let Some(res) = reader.next() {
+                    res.unwrap();
+                }
+                assert_eq!(reader.skipped_blank_lines(), n);
+            }
+        }
+
+        // skipped as malformed records in lenient mode
+        let mut reader = ReaderBuilder::new()
+            .blank_lines(BlankLinePolicy::Error)
+            .lenient(|_| {})
+            .from_reader(fasta.as_bytes());
+        assert_eq!(reader.next().unwrap().unwrap().seq, b"A");
+        assert!(reader.next().is_none());
+        assert_eq!(reader.malformed_records(), 2);
+    }
+
+    #[test]
+    fn test_reader_read_into() {
+        let mut reader = Reader::from_bytes(b"@r1 d\nACGT\n+\nIIII\n>s2\nAC\n");
+        let mut buf = RecordBuf::new();
+        assert!(reader.read_into(&mut buf).unwrap());
+        assert_eq!(buf.as_seq().qual, Some(&b"IIII"[..]));
+        assert_eq!(buf.desc, b"d");
+        let ptr = buf.seq.as_ptr();
+
+        let mut reader = Reader::from_bytes(b">s1\nAC\n>s2\nGG\n");
+        assert!(reader.read_into(&mut buf).unwrap());
+        assert_eq!(buf.seq.as_ptr(), ptr, "buffers should be reused");
+        let seq = buf.as_seq();
+        assert_eq!((seq.id, seq.desc, seq.qual), (&b"s1"[..], &b""[..], None));
+        assert_eq!(seq.seq, b"AC");
+        assert!(reader.read_into(&mut buf).unwrap());
+        assert_eq!(buf.seq, b"GG");
+        assert!(!reader.read_into(&mut buf).unwrap());
+
+        let mut reader = Reader::from_bytes(b"@r1\nACGT\n+\nII\n");
+        assert!(reader.read_into(&mut buf).is_err());
+    }
+
+    #[test]
+    fn test_reader_read_batch() {
+        let mut reader = Reader::from_bytes(b"@a\nAC\n+\nII\n@b\nG\n+\nI\n@c\nT\n+\nI\n");
+        let mut batch = Vec::new();
+        assert_eq!(reader.read_batch(2, &mut batch).unwrap(), 2);
+        assert_eq!(batch[1].id, b"b");
+        let ptr = batch[0].seq.as_ptr();
+
+        assert_eq!(reader.read_batch(2, &mut batch).unwrap(), 1);
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0].id, b"c");
+        assert_eq!(batch[0].qual.as_deref(), Some(&b"I"[..]));
+        assert_eq!(batch[0].seq.as_ptr(), ptr, "buffers should be reused");
+
+        assert_eq!(reader.read_batch(2, &mut batch).unwrap(), 0);
+        assert!(batch.is_empty());
+
+        let mut batch = vec![SeqOwned::default(); 3];
+        let mut reader = Reader::from_bytes(b">x\nAC\n");
+        assert_eq!(reader.read_batch(10, &mut batch).unwrap(), 1);
+        assert_eq!((batch.len(), batch[0].qual.is_none()), (1, true));
+    }
+
+    #[test]
+    fn test_reader_process_parallel() {
+        let data: String = (0..100).map(|i| format!(">r{i}\nACGT\n")).collect();
+        let mut ids = Vec::new();
+        let n = Reader::from_bytes(data.as_bytes())
+            .process_parallel(
+                2,
+                |seq, _| String::from_utf8_lossy(seq.id).to_string(),
+                |id| {
+                    ids.push(id);
+                    Ok(())
+                },
+            )
+            .unwrap();
+        assert_eq!(n, 100);
+        assert_eq!(ids[99], "r99");
+    }
+
+    #[test]
+    fn test_into_records() {
+        let records: Vec<SeqOwned> = Reader::from_bytes(b">s1 d\nAC\nGT\n>s2\nTT\n")
+            .into_records()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].desc, b"d");
+        assert_eq!(records[0].seq, b"ACGT");
+        assert_eq!(records[1].as_seq().id, b"s2");
+
+        let mut records = Reader::from_bytes(b"@r1\nACGT\n+\nII\n").into_records();
+        assert!(matches!(
+            records.next(),
+            Some(Err(FastxErr::UnequalSeqAndQual(4, 2)))
+        ));
+    }
+
+    #[test]
+    fn test_record_source_iterators() {
+        let data = b">s1\nACGT\n>s2\nAC\n>s3\nACGTAC\n";
+        let mut reader = Reader::from_bytes(data);
+
+        let long: Vec<String> = (&mut reader)
+            .map_records(|seq| (String::from_utf8_lossy(seq.id).to_string(), seq.len()))
+            .filter_map(|res| match res {
+                Ok((id, len)) if len >= 4 => Some(Ok(id)),
+                Ok(_) => None,
+                Err(e) => Some(Err(e)),
+            })
+            .collect::<Result<_, FastxErr>>()
+            .unwrap();
+        assert_eq!(long, vec!["s1", "s3"]);
+        assert!(reader.next().is_none());
+
+        let mut bases = 0;
+        Reader::from_bytes(data)
+            .for_each_record(|seq| {
+                bases += seq.len();
+                Ok::<(), FastxErr>(())
+            })
+            .unwrap();
+        assert_eq!(bases, 12);
+
+        let mut reader = Reader::from_bytes(b"@r1\nACGT\n+\nII\n");
+        let res: Result<(), FastxErr> = reader.for_each_record(|_| Ok(()));
+        assert!(matches!(res, Err(FastxErr::UnequalSeqAndQual(4, 2))));
+
+        let boxed: Box<dyn RecordSource> = Box::new(Reader::from_bytes(data));
+        assert_eq!(boxed.map_records(|seq| seq.len()).count(), 3);
+    }
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(
+            detect_format(&mut &b"\n>a\nACGT\n"[..]).unwrap(),
+            Format::Fasta
+        );
+        assert_eq!(
+            detect_format(&mut &b"@a\nA\n+\nI\n"[..]).unwrap(),
+            Format::Fastq
+        );
+        assert_eq!(detect_format(&mut &b"ACGT\n"[..]).unwrap(), Format::Unknown);
+        assert_eq!(detect_format(&mut &b""[..]).unwrap(), Format::Unknown);
+
+        // peeking does not consume the input
+        let mut reader = Reader::from_bytes(b"\r\n@a\nA\n+\nI\n");
+        assert_eq!(reader.format(), Format::Fastq);
+        assert_eq!(reader.next().unwrap().unwrap().id, b"a");
+        assert_eq!(reader.format(), Format::Fastq);
+        assert!(reader.next().is_none());
+        assert_eq!(reader.format(), Format::Fastq);
+
+        assert_eq!(Reader::from_bytes(b"").format(), Format::Unknown);
+    }
+
+    #[test]
+    fn test_reader_record_positions() {
+        let fasta = "\n>a\r\nAC\r\nGT\r\n\r\n>b desc\nA\n>c\n";
+        let fastq = "@a\nACGT\n+\nIIII\n\n@b\nA\n+b\nI\n";
+        for (input, expected) in [
+            (fasta, vec![(1, 2), (15, 6), (25, 8)]),
+            (fastq, vec![(0, 1), (16, 6)]),
+        ] {
+            for capacity in [3, 1 << 16] {
+                let r = BufReader::with_capacity(capacity, Cursor::new(input.as_bytes()));
+                let mut reader = Reader::from_reader(r);
+                let mut positions = Vec::new();
+                while let Some(res) = reader.next() {
+                    res.unwrap();
+                    let pos = reader.last_record_position();
+                    assert!(input[pos.offset as usize..].starts_with(['>', '@']));
+                    positions.push((pos.offset, pos.line));
+                }
+                assert_eq!(positions, expected);
+                assert_eq!(reader.position().offset, input.len() as u64);
+            }
+        }
+    }
+
+    #[test]
+    fn test_reader_lenient() {
+        use std::sync::{Arc, Mutex};
+
+        let input = "junk\n@a\nACGT\n+\nIIIII\n@b\nAC\n+\nII\n>c\nAC\n@d\nA\n+\nI\n@e\nACG\n+\nI";
+        let skipped = Arc::new(Mutex::new(Vec::new()));
+        let mut reader = Reader::from_bytes(input.as_bytes());
+        let log = skipped.clone();
+        reader.set_lenient(move |bad| {
+            log.lock().unwrap().push((
+                bad.position.line,
+                String::from_utf8_lossy(bad.bytes).to_string(),
+                bad.error.to_string(),
+            ));
+        });
+        let mut ids = Vec::new();
+        while let Some(res) = reader.next() {
+            ids.push(String::from_utf8_lossy(res.unwrap().id).to_string());
+        }
+        assert_eq!(ids, ["b", "d"]);
+        assert_eq!(reader.malformed_records(), 5);
+
+        let skipped = skipped.lock().unwrap();
+        assert_eq!(skipped[0].0, 1);
+        assert_eq!(skipped[0].1, "junk");
+        assert_eq!(skipped[0].2, "invalid FASTA/Q format");
+        assert_eq!((skipped[1].0, skipped[1].1.as_str()), (2, "aACGTIIIII"));
+        assert_eq!((skipped[2].0, skipped[2].1.as_str()), (10, ">c"));
+        assert_eq!((skipped[3].0, skipped[3].1.as_str()), (11, "AC"));
+        assert_eq!((skipped[4].0, skipped[4].1.as_str()), (16, "eACGI"));
+
+        // strict by default
+        let mut reader = Reader::from_bytes(input.as_bytes());
+        assert!(matches!(reader.next(), Some(Err(FastxErr::InvalidFormat))));
+    }
+
+    #[test]
+    fn test_reader_strict() {
+        let read_all = |input: &str, opts: StrictOptions| {
+            let mut reader = Reader::from_bytes(input.as_bytes());
+            reader.set_strict(opts);
+            let mut n = 0;
+            while let Some(res) = reader.next() {
+                res?;
+                n += 1;
+            }
+            Ok::<_, FastxErr>(n)
+        };
+        let opts = StrictOptions::default();
+        let valid = "@a x\nACGTN\n+a x\nIIII#\n@b\nRY\n+\nII\n";
+        assert_eq!(read_all(valid, opts).unwrap(), 2);
+
+        let dna = StrictOptions {
+            alphabet: Alphabet::Dna,
+            ..opts
+        };
+        assert_eq!(
+            read_all(valid, dna).unwrap_err().to_string(),
+            "invalid sequence character 'R' at position 0 of record b"
+        );
+        assert!(matches!(
+            read_all(">a\nAC GT\n", opts),
+            Err(FastxErr::InvalidSeqChar(_, ' ', 2))
+        ));
+        assert!(matches!(
+            read_all("@a x\nAC\n+a y\nII\n", opts),
+            Err(FastxErr::SeparatorMismatch(id, sep)) if id == "a" && sep == "a y"
+        ));
+        let no_sep_check = StrictOptions {
+            check_separator: false,
+            ..opts
+        };
+        assert_eq!(read_all("@a x\nAC\n+a y\nII\n", no_sep_check).unwrap(), 1);
+        assert!(matches!(
+            read_all("@a\nACG\n+\nI I\n", opts),
+            Err(FastxErr::InvalidQualChar(_, ' ', 1))
+        ));
+        let phred64 = StrictOptions {
+            qual_range: (b'@', b'h'),
+            ..opts
+        };
+        assert!(matches!(
+            read_all("@a\nAC\n+\nh5\n", phred64),
+            Err(FastxErr::InvalidQualChar(_, '5', 1))
+        ));
+
+        // strict and lenient
+        let mut reader = Reader::from_bytes(b">a\nAC\n>b\nA1\n>c\nG\n");
+        reader.set_strict(opts);
+        reader.set_lenient(|_| {});
+        assert_eq!(reader.next().unwrap().unwrap().id, b"a");
+        assert_eq!(reader.next().unwrap().unwrap().id, b"c");
+        assert_eq!(reader.malformed_records(), 1);
+    }
+
+    #[test]
+    fn test_reader_count() {
+        let inputs = [
+            "",
+            "\n\n",
+            ">a x>y\nAC\nGT\n\n>b\n>c\nA>C\n>d",
+            ">a\r\nACGT\r\n>b\r\nAC",
+            "@a\nAC\nGT\n+\n@@\nII\n\n@b\n\n+b\n\n@c\n@G\n+\n@I\n",
+            "@a\r\nACGT\r\n+\r\nIIII\r\n@b\r\nAC\r\n+\r\nII",
+        ];
+        for input in inputs {
+            let expected = read_to_owned(input).unwrap().len() as u64;
+            // buffers of 3 bytes split lines and line endings
+            for cap in [3, 65536] {
+                let mut reader =
+                    Reader::from_reader(BufReader::with_capacity(cap, input.as_bytes()));
+                assert_eq!(reader.count().unwrap(), expected, "{input:?}");
+            }
+        }
+
+        // the remaining records
+        let mut reader = Reader::from_bytes(b">a\nA\n>b\nC\n>c\nG\n");
+        reader.next().unwrap().unwrap();
+        assert_eq!(reader.count().unwrap(), 2);
+        assert!(reader.next().is_none());
+        let mut reader = Reader::from_bytes(b"@a\nA\n+\nI\n@b\nC\n+\nI\n");
+        reader.next().unwrap().unwrap();
+        assert_eq!(reader.count().unwrap(), 1);
+
+        let count = |input: &[u8]| Reader::from_bytes(input).count();
+        assert!(matches!(count(b"ACGT\n"), Err(FastxErr::InvalidFormat)));
+        assert!(matches!(
+            count(b"@a\nA\n+\nI\n@b\nACG\n+\nII"),
+            Err(FastxErr::UnequalSeqAndQual(3, 2))
+        ));
+        assert!(matches!(
+            count(b"@a\nA\n+\nI\nx\n"),
+            Err(FastxErr::InvalidFormat)
+        ));
+
+        let path = temp_path(".fa");
+        fs::write(&path, ">a\nA\n>b\nC\n").unwrap();
+        assert_eq!(count_records(&path).unwrap(), 2);
+        fs::remove_file(&path).unwrap();
+    }
+
+    // reads IDs of all records after seeking to every offset, compared with record starts
+    fn check_seek_to<R: BufRead + Seek>(mut reader: Reader<R>, input: &[u8]) {
+        let mut starts = Vec::new();
+        while let Some(res) = reader.next() {
+            let id = res.unwrap().id.to_vec();
+            starts.push((id, reader.last_record_position().offset));
+        }
+        for offset in 0..=input.len() as u64 + 1 {
+            reader.seek_to(offset).unwrap();
+            let mut ids = Vec::new();
+            while let Some(res) = reader.next() {
+                ids.push(res.unwrap().id.to_vec());
+                if ids.len() == 1 {
+                    let pos = reader.last_record_position();
+                    assert_eq!(pos.line, 1);
+                    assert!(pos.offset >= offset);
+                }
+            }
+            let expected: Vec<Vec<u8>> = starts
+                .iter()
+                .filter(|(_, start)| *start >= offset)
+                .map(|(id, _)| id.clone())
+                .collect();
+            assert_eq!(ids, expected, "offset {offset}");
+        }
+    }
+
+    #[test]
+    fn test_reader_seek_to() {
+        let fasta = b">a x>y\nAC\nGT\n\n>b\n>c\nA>C\nGG\n";
+        check_seek_to(Reader::from_reader(Cursor::new(&fasta[..])), fasta);
+        // quality lines starting with '@' and '+'
+        let fastq = b"@a\nACGT\n+a\n@III\n@b\nAC\n+\n+@\n@c\nACG\n+\nIII\n";
+        check_seek_to(Reader::from_reader(Cursor::new(&fastq[..])), fastq);
+
+        // positions from a full pass are usable as an index
+        let mut reader = Reader::from_reader(Cursor::new(&fastq[..]));
+        reader.next().unwrap().unwrap();
+        reader.next().unwrap().unwrap();
+        let pos = reader.last_record_position();
+        reader.next().unwrap().unwrap();
+        reader.seek_to(pos.offset).unwrap();
+        assert_eq!(reader.next().unwrap().unwrap().id, b"b");
+        assert_eq!(reader.position().offset, pos.offset + 11);
+
+        #[cfg(feature = "gzip")]
+        {
+            // BGZF blocks of 5 bytes, records span blocks
+            let mut data = Vec::new();
+            for chunk in fastq.chunks(5) {
+                let mut w = flate2::GzBuilder::new()
+                    .extra(vec![b'B', b'C', 2, 0, 0, 0])
+                    .write(Vec::new(), Compression::default());
+                w.write_all(chunk).unwrap();
+                let mut block = w.finish().unwrap();
+                let bsize = (block.len() - 1) as u16;
+                block[16..18].copy_from_slice(&bsize.to_le_bytes());
+                data.extend(block);
+            }
+            let bgzf = BgzfReader::new(Cursor::new(data)).unwrap();
+            check_seek_to(Reader::from_reader(bgzf), fastq);
+        }
+    }
+
+    #[test]
+    fn test_reader_size_limits() {
+        let limits = SizeLimits {
+            max_header_len: 5,
+            max_seq_len: 5,
+        };
+        let read_all = |input: &[u8], lenient: bool| {
+            let mut reader = Reader::from_bytes(input);
+            reader.set_limits(limits);
+            if lenient {
+                reader.set_lenient(|_| {});
+            }
+            let mut results = Vec::new();
+            while let Some(res) = reader.next() {
+                results.push(res.map(|seq| seq.id.to_vec()).map_err(|e| e.to_string()));
+            }
+            results
+        };
+
+        // records over the limits are skipped
+        let too_long = |what| Err(format!("{what} longer than the limit of 5 bytes"));
+        assert_eq!(
+            read_all(b">a\nACGT\nAC\n>b\nACGTA\n", false),
+            vec![too_long("sequence"), Ok(b"b".to_vec())]
+        );
+        assert_eq!(
+            read_all(b"@a\nACGTAC\n+\nIIIIII\n@b x\nAC\n+\nII\n", false),
+            vec![too_long("sequence"), Ok(b"b".to_vec())]
+        );
+        assert_eq!(
+            read_all(b">a 2345\nAC\n>b 234\nAC\n", false),
+            vec![too_long("header"), Ok(b"b".to_vec())]
+        );
+        assert_eq!(
+            read_all(b">a 2345\nAC\n>b 234\nAC\n", true),
+            vec![Ok(b"b".to_vec())]
+        );
+
+        // buffers don't grow with long lines beyond the input buffer
+        let mut input = b">a\n".to_vec();
+        input.extend(vec![b'A'; 3 << 20]);
+        input.extend(b"\n>");
+        input.extend(vec![b'b'; 1 << 20]);
+        input.extend(b"\nAC\n");
+        let mut reader = Reader::from_reader(BufReader::with_capacity(1 << 16, &input[..]));
+        reader.set_limits(limits);
+        assert!(matches!(
+            reader.next(),
+            Some(Err(FastxErr::SizeLimitExceeded("sequence", 5)))
+        ));
+        assert!(matches!(
+            reader.next(),
+            Some(Err(FastxErr::SizeLimitExceeded("header", 5)))
+        ));
+        assert!(reader.record_buf.capacity() <= 1 << 20);
+        assert!(reader.line_buf.capacity() <= 1 << 17);
+        assert!(reader.lookahead_line.capacity() <= 1 << 17);
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_reader_from_bytes() {
+        let data = b"@r1\nACGT\n+\nIIII\n@r2\nTT\n+\nII\n".to_vec();
+        let mut reader = Reader::from_bytes(&data);
+        assert_eq!(reader.next().unwrap().unwrap().qual, Some(&b"IIII"[..]));
+        assert_eq!(reader.next().unwrap().unwrap().id, b"r2");
+        assert!(reader.next().is_none());
+
+        let mut reader = Reader::from_reader(Cursor::new(data));
+        assert_eq!(reader.next().unwrap().unwrap().seq, b"ACGT");
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_reader_from_read_decompressed_gzip() {
+        let mut enc = GzEncoder::new(Vec::new(), Compression::default());
+        enc.write_all(b"@r1\nACGT\n+\nIIII\n").unwrap();
+        let gz = enc.finish().unwrap();
+
+        let mut reader = Reader::from_read_decompressed(Cursor::new(gz)).unwrap();
+        let record = reader.next().unwrap().unwrap();
+        assert_eq!(record.id, b"r1");
+        assert_eq!(record.qual, Some(&b"IIII"[..]));
+        assert!(reader.next().is_none());
+    }
+
     // --------------------------------------------------------------------------
     // empty file or invalid format edge cases
 
@@ -884,6 +2600,37 @@ IIIIIIII";
         assert_eq!(seqs[0].3, Some("IIIIIIII".to_string()));
     }
 
+    #[test]
+    fn test_fastq_wrapped_small_buffer() {
+        let input = "\
+@read1 desc
+ACGT
+AC
++
+@III
++I
+@read2
+AC\r
+GT\r
++read2\r
+II\r
+@I\r
+";
+        for cap in [3, 5, 64] {
+            let reader = BufReader::with_capacity(cap, Cursor::new(input.as_bytes()));
+            let seqs = read_to_owned_from_reader(reader).unwrap();
+            assert_eq!(seqs.len(), 2);
+            assert_eq!(seqs[0].2, "ACGTAC");
+            assert_eq!(seqs[0].3, Some("@III+I".to_string()));
+            assert_eq!(seqs[1].0, "read2");
+            assert_eq!(seqs[1].2, "ACGT");
+            assert_eq!(seqs[1].3, Some("II@I".to_string()));
+
+            let reader = BufReader::with_capacity(cap, Cursor::new(input.as_bytes()));
+            assert_eq!(Reader::from_reader(reader).count().unwrap(), 2);
+        }
+    }
+
     #[test]
     fn test_fasta_small_buffer_with_lookahead() {
         let input = "\
@@ -941,10 +2688,11 @@ TGCA";
         assert_eq!(seqs[1].2, "TGCA");
     }
 
+    #[cfg(feature = "gzip")]
     #[test]
     fn test_reader_new_reads_last_gzip_fasta_record_without_final_lf() {
         let path = temp_path(".fa.gz");
-        let file = File::create(&path).unwrap();
+        let file = fs::File::create(&path).unwrap();
         let mut writer = GzEncoder::new(file, Compression::default());
         writer.write_all(b">chr1\nACGT\n>chrM\nTGCA").unwrap();
         writer.finish().unwrap();
@@ -1009,6 +2757,7 @@ TGCA";
         );
     }
 
+    #[cfg(feature = "gzip")]
     #[test]
     fn test_reader_new_reads_gzip_from_stdin() {
         const HELPER_ENV: &str = "FASTX_TEST_GZIP_STDIN_HELPER";
@@ -1057,7 +2806,7 @@ TGCA";
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "gzip"))]
 mod bgzf_tests {
     use super::*;
     use flate2::Compression;
@@ -1072,7 +2821,10 @@ mod bgzf_tests {
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_nanos();
-        std::env::temp_dir().join(format!("fastseq-bgzf-{}-{nanos}{suffix}", std::process::id()))
+        std::env::temp_dir().join(format!(
+            "fastseq-bgzf-{}-{nanos}{suffix}",
+            std::process::id()
+        ))
     }
 
     #[test]
@@ -1090,8 +2842,8 @@ mod bgzf_tests {
             for i in 0..records_per_block {
                 let idx = block * records_per_block + i;
                 let len = lengths[idx % lengths.len()];
-                let seq: String = std::iter::repeat('A').take(len).collect();
-                let qual: String = std::iter::repeat('I').take(len).collect();
+                let seq: String = "A".repeat(len);
+                let qual: String = "I".repeat(len);
                 write!(block_data, "@read{}\n{}\n+\n{}\n", idx, seq, qual).unwrap();
             }
             // Each block is a separate gzip member
@@ -1112,6 +2864,10 @@ mod bgzf_tests {
             count += 1;
         }
         fs::remove_file(&path).unwrap();
-        assert_eq!(count, total_records, "expected {} records, got {}", total_records, count);
+        assert_eq!(
+            count, total_records,
+            "expected {} records, got {}",
+            total_records, count
+        );
     }
 }
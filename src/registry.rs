@@ -0,0 +1,295 @@
+use crate::digest::*;
+use crate::errors::*;
+use crate::reader::*;
+use crate::twobit::*;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A well-known reference genome.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RefGenome {
+    /// Canonical name, also used as the file name in the cache.
+    pub name: &'static str,
+    /// Other names resolving to the genome, matched case-insensitively.
+    pub aliases: &'static [&'static str],
+    pub description: &'static str,
+    /// URL of the (gzipped) FASTA file.
+    pub url: &'static str,
+    /// Lowercase hexadecimal SHA-256 checksum of the file at `url`, checked before converting.
+    /// None if no checksum is pinned, then downloads are only checked by parsing.
+    pub sha256: Option<&'static str>,
+}
+
+/// Reference genomes known to the registry.
+pub const GENOMES: &[RefGenome] = &[
+    RefGenome {
+        name: "GRCh38",
+        aliases: &["hg38", "GRCh38.p14", "GCA_000001405.15"],
+        description: "Human GRCh38 no-alt analysis set (NCBI, UCSC-style names)",
+        url: "https://ftp.ncbi.nlm.nih.gov/genomes/all/GCA/000/001/405/GCA_000001405.15_GRCh38/seqs_for_alignment_pipelines.ucsc_ids/GCA_000001405.15_GRCh38_no_alt_analysis_set.fna.gz",
+        sha256: None,
+    },
+    RefGenome {
+        name: "T2T-CHM13",
+        aliases: &["CHM13", "chm13v2.0", "hs1"],
+        description: "Human T2T-CHM13 v2.0 with chrY of HG002 (analysis set)",
+        url: "https://s3-us-west-2.amazonaws.com/human-pangenomics/T2T/CHM13/assemblies/analysis_set/chm13v2.0.fa.gz",
+        sha256: None,
+    },
+    RefGenome {
+        name: "GRCm39",
+        aliases: &["mm39"],
+        description: "Mouse GRCm39 primary assembly (Ensembl 110)",
+        url: "https://ftp.ensembl.org/pub/release-110/fasta/mus_musculus/dna/Mus_musculus.GRCm39.dna.primary_assembly.fa.gz",
+        sha256: None,
+    },
+];
+
+/// Resolves a genome name or alias, case-insensitively.
+pub fn resolve(name: &str) -> Option<&'static RefGenome> {
+    GENOMES.iter().find(|g| {
+        g.name.eq_ignore_ascii_case(name) || g.aliases.iter().any(|a| a.eq_ignore_ascii_case(name))
+    })
+}
+
+/// Returns the default cache directory: `$FASTSEQ_CACHE`, `$XDG_CACHE_HOME/fastseq`,
+/// or `$HOME/.cache/fastseq`, falling back to the system temporary directory.
+pub fn default_cache_dir() -> PathBuf {
+    if let Some(dir) = env::var_os("FASTSEQ_CACHE") {
+        return PathBuf::from(dir);
+    }
+    if let Some(dir) = env::var_os("XDG_CACHE_HOME") {
+        return PathBuf::from(dir).join("fastseq");
+    }
+    if let Some(dir) = env::var_os("HOME") {
+        return PathBuf::from(dir).join(".cache").join("fastseq");
+    }
+    env::temp_dir().join("fastseq")
+}
+
+/// A local cache of reference genomes.
+///
+/// Genomes are downloaded with `curl` (or `wget`) and stored as .2bit files,
+/// which are compact and support random access by sequence ID with `TwoBitReader`.
+/// Records are streamed to disk while converting, and cached files are read on demand,
+/// so genomes are never held in memory.
+///
+/// Downloads are checked against the SHA-256 checksum of the genome if one is pinned,
+/// and by parsing the FASTA file. Partial downloads and conversions are removed on errors,
+/// and never moved into the cache.
+pub struct Registry {
+    cache_dir: PathBuf,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Registry {
+    /// Creates a registry with the default cache directory.
+    pub fn new() -> Self {
+        Self::with_cache_dir(default_cache_dir())
+    }
+
+    /// Creates a registry with a cache directory.
+    pub fn with_cache_dir<P: Into<PathBuf>>(dir: P) -> Self {
+        Self {
+            cache_dir: dir.into(),
+        }
+    }
+
+    /// Returns the cache directory.
+    pub fn cache_dir(&self) -> &Path {
+        &self.cache_dir
+    }
+
+    /// Returns the path of the cached .2bit file of a genome.
+    pub fn path_of(&self, genome: &RefGenome) -> PathBuf {
+        self.cache_dir.join(format!("{}.2bit", genome.name))
+    }
+
+    /// Returns whether a genome is in the cache.
+    pub fn is_cached(&self, name: &str) -> bool {
+        resolve(name).is_some_and(|g| self.path_of(g).is_file())
+    }
+
+    /// Returns the path of the cached .2bit file of a genome, downloading it if needed.
+    pub fn ensure(&self, name: &str) -> Result<PathBuf, FastxErr> {
+        let genome = resolve(name).ok_or_else(|| FastxErr::UnknownReference(name.to_string()))?;
+        let path = self.path_of(genome);
+        if path.is_file() {
+            return Ok(path);
+        }
+
+        fs::create_dir_all(&self.cache_dir)?;
+        let fasta = self.cache_dir.join(format!("{}.fa.gz.part", genome.name));
+        let res = download(genome.url, &fasta)
+            .map_err(FastxErr::IOError)
+            .and_then(|_| match genome.sha256 {
+                Some(sha256) => verify_sha256(&fasta, sha256),
+                None => Ok(()),
+            })
+            .and_then(|_| self.convert(genome, &fasta));
+        let _ = fs::remove_file(&fasta);
+        res?;
+        Ok(path)
+    }
+
+    /// Returns a reader of a genome, downloading it if needed.
+    pub fn fetch(&self, name: &str) -> Result<TwoBitReader, FastxErr> {
+        let path = self.ensure(name)?;
+        TwoBitReader::new(path)
+    }
+
+    /// Adds a genome to the cache from a local FASTA file, e.g., one downloaded manually.
+    pub fn import<P: AsRef<Path>>(&self, name: &str, fasta: P) -> Result<PathBuf, FastxErr> {
+        let genome = resolve(name).ok_or_else(|| FastxErr::UnknownReference(name.to_string()))?;
+        fs::create_dir_all(&self.cache_dir)?;
+        self.convert(genome, fasta.as_ref())?;
+        Ok(self.path_of(genome))
+    }
+
+    // converts a FASTA file to .2bit in the cache
+    fn convert(&self, genome: &RefGenome, fasta: &Path) -> Result<(), FastxErr> {
+        let path = self.path_of(genome);
+        let part = path.with_extension("2bit.part");
+
+        if let Err(e) = write_twobit(fasta, &part) {
+            let _ = fs::remove_file(&part);
+            return Err(e);
+        }
+        fs::rename(&part, &path)?;
+        Ok(())
+    }
+}
+
+// converts a FASTA file to a .2bit file, streaming records to disk
+fn write_twobit(fasta: &Path, twobit: &Path) -> Result<(), FastxErr> {
+    let mut reader = Reader::new(fasta)?;
+    let mut writer = TwoBitWriter::new(twobit)?;
+    let mut n = 0;
+    while let Some(res) = reader.next() {
+        writer.write(&res?)?;
+        n += 1;
+    }
+    if n == 0 {
+        return Err(FastxErr::InvalidFormat);
+    }
+    writer.finish()?.sync_all()?;
+    Ok(())
+}
+
+// checks the SHA-256 checksum of a file, given in lowercase hexadecimal
+fn verify_sha256(file: &Path, expected: &str) -> Result<(), FastxErr> {
+    let mut f = File::open(file)?;
+    let mut h = Sha256::new();
+    let mut buf = vec![0u8; 1 << 16];
+    loop {
+        let n = f.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        h.update(&buf[..n]);
+    }
+    let actual = to_hex(&h.finalize());
+    if !actual.eq_ignore_ascii_case(expected) {
+        return Err(FastxErr::ChecksumMismatch(
+            file.display().to_string(),
+            expected.to_string(),
+            actual,
+        ));
+    }
+    Ok(())
+}
+
+// downloads a URL to a file with curl, or wget if curl is not available
+fn download(url: &str, dest: &Path) -> io::Result<()> {
+    let status = match Command::new("curl")
+        .args(["-fsSL", "--retry", "3", "-o"])
+        .arg(dest)
+        .arg(url)
+        .status()
+    {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Command::new("wget")
+            .args(["-q", "-O"])
+            .arg(dest)
+            .arg(url)
+            .status()?,
+        res => res?,
+    };
+    if !status.success() {
+        let _ = fs::remove_file(dest);
+        return Err(io::Error::other(format!(
+            "failed to download {url}: {status}"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(resolve("hg38").unwrap().name, "GRCh38");
+        assert_eq!(resolve("grch38").unwrap().name, "GRCh38");
+        assert_eq!(resolve("CHM13").unwrap().name, "T2T-CHM13");
+        assert_eq!(resolve("mm39").unwrap().name, "GRCm39");
+        assert!(resolve("hg19").is_none());
+    }
+
+    #[test]
+    fn test_registry_import_and_fetch() {
+        let dir = env::temp_dir().join(format!("fastseq_registry_{}", std::process::id()));
+        let fasta = dir.join("mm.fa");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&fasta, ">chr1\nACGTNNacgt\n>chr2\nGGGG\n").unwrap();
+
+        let registry = Registry::with_cache_dir(dir.join("cache"));
+        assert!(!registry.is_cached("mm39"));
+        registry.import("mm39", &fasta).unwrap();
+        assert!(registry.is_cached("GRCm39"));
+
+        // no download for a cached genome
+        let mut reader = registry.fetch("mm39").unwrap();
+        assert_eq!(reader.num_records(), 2);
+        assert_eq!(reader.get(b"chr1").unwrap().unwrap().seq, b"ACGTNNacgt");
+
+        assert!(matches!(
+            registry.fetch("hg19"),
+            Err(FastxErr::UnknownReference(_))
+        ));
+
+        // a failed conversion leaves nothing behind
+        // (a name too long for .2bit in the second record)
+        fs::write(&fasta, format!(">chr1\nACGT\n>{}\nAC\n", "x".repeat(300))).unwrap();
+        assert!(registry.import("hg38", &fasta).is_err());
+        let mut files: Vec<_> = fs::read_dir(dir.join("cache"))
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        files.sort();
+        assert_eq!(files, ["GRCm39.2bit"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_verify_sha256() {
+        let file = env::temp_dir().join(format!("fastseq_registry_sha_{}", std::process::id()));
+        fs::write(&file, b"abc").unwrap();
+        let abc = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        verify_sha256(&file, abc).unwrap();
+        verify_sha256(&file, &abc.to_uppercase()).unwrap();
+        assert!(matches!(
+            verify_sha256(&file, &abc.replace('b', "c")),
+            Err(FastxErr::ChecksumMismatch(..))
+        ));
+        fs::remove_file(&file).unwrap();
+    }
+}
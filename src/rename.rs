@@ -0,0 +1,190 @@
+use crate::errors::*;
+use crate::reader::*;
+use crate::seq::*;
+use crate::writer::*;
+use std::io::{BufRead, Write};
+
+/// Options of replacing read names with short sequential IDs.
+#[derive(Debug, Clone)]
+pub struct ShortNameOptions {
+    /// Prefix of new IDs, which are `<prefix><n>` numbered from 1.
+    pub prefix: String,
+    /// Whether to append the mate number of paired reads as "/1" or "/2",
+    /// taken from the "/1" or "/2" suffix of the ID or the "1:" or "2:" description (Casava 1.8+).
+    pub keep_mate: bool,
+    /// Whether to keep descriptions in headers.
+    pub keep_desc: bool,
+}
+
+impl Default for ShortNameOptions {
+    fn default() -> Self {
+        Self {
+            prefix: String::new(),
+            keep_mate: true,
+            keep_desc: false,
+        }
+    }
+}
+
+/// Replaces read names with short sequential IDs, writing records to the writer and a line of
+/// `<new ID>\t<original ID>\t<original description>` for every record to `mapping`, and returns
+/// the number of records. Mates in in-sync paired files get the same numbers.
+///
+/// The mapping can be compressed by opening it with `xwrite`, and names are restored
+/// with `restore_names`.
+pub fn shorten_names<R: BufRead, W: Write, M: Write>(
+    reader: &mut Reader<R>,
+    writer: &mut Writer<W>,
+    mapping: &mut M,
+    opts: &ShortNameOptions,
+) -> Result<u64, FastxErr> {
+    let mut n = 0u64;
+    let mut id = Vec::with_capacity(32);
+    while let Some(res) = reader.next() {
+        let seq = res?;
+        n += 1;
+        id.clear();
+        id.extend_from_slice(opts.prefix.as_bytes());
+        id.extend_from_slice(n.to_string().as_bytes());
+        if opts.keep_mate
+            && let (_, Some(mate)) = seq.mate_id()
+        {
+            id.extend_from_slice(&[b'/', b'0' + mate]);
+        }
+
+        mapping.write_all(&id)?;
+        mapping.write_all(b"\t")?;
+        mapping.write_all(seq.id)?;
+        mapping.write_all(b"\t")?;
+        mapping.write_all(seq.desc)?;
+        mapping.write_all(b"\n")?;
+
+        writer.write(&Seq {
+            id: &id,
+            desc: if opts.keep_desc { seq.desc } else { b"" },
+            ..seq
+        })?;
+    }
+    writer.flush()?;
+    mapping.flush()?;
+    Ok(n)
+}
+
+/// Restores original names and descriptions of records shortened by `shorten_names`, with the
+/// mapping, and returns the number of records.
+///
+/// Records must be in the original order, but may be a subset, e.g., after filtering.
+/// The mapping is streamed along with the records, so memory usage does not grow with the number
+/// of records. It fails with `InvalidNameMapping` if a record is not found in the rest of the mapping.
+pub fn restore_names<R: BufRead, W: Write, M: BufRead>(
+    reader: &mut Reader<R>,
+    writer: &mut Writer<W>,
+    mapping: &mut M,
+) -> Result<u64, FastxErr> {
+    let mut n = 0u64;
+    let mut line = Vec::with_capacity(256);
+    while let Some(res) = reader.next() {
+        let seq = res?;
+        // skip entries of removed records
+        let (id, desc) = loop {
+            line.clear();
+            if mapping.read_until(b'\n', &mut line)? == 0 {
+                return Err(FastxErr::InvalidNameMapping(format!(
+                    "{} not found",
+                    String::from_utf8_lossy(seq.id)
+                )));
+            }
+            if line.last() == Some(&b'\n') {
+                line.pop();
+            }
+            let mut fields = line.splitn(3, |&b| b == b'\t');
+            let (Some(short), Some(id)) = (fields.next(), fields.next()) else {
+                return Err(FastxErr::InvalidNameMapping(
+                    String::from_utf8_lossy(&line).to_string(),
+                ));
+            };
+            if short == seq.id {
+                break (id, fields.next().unwrap_or_default());
+            }
+        };
+        writer.write(&Seq { id, desc, ..seq })?;
+        n += 1;
+    }
+    writer.flush()?;
+    Ok(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUT: &[u8] = b"@A00123:8:H7:1:1101:1000:2000 1:N:0:ACGT\nAC\n+\nII\n\
+@A00123:8:H7:1:1101:1000:2001 1:N:0:ACGT\nG\n+\nI\n@read/1\nT\n+\nI\n";
+
+    #[test]
+    fn test_shorten_and_restore_names() {
+        let mut writer = Writer::from_writer(Vec::new());
+        let mut mapping = Vec::new();
+        let n = shorten_names(
+            &mut Reader::from_bytes(INPUT),
+            &mut writer,
+            &mut mapping,
+            &ShortNameOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(n, 3);
+        let short = writer.into_inner();
+        assert_eq!(short, b"@1/1\nAC\n+\nII\n@2/1\nG\n+\nI\n@3/1\nT\n+\nI\n");
+        assert_eq!(
+            mapping,
+            b"1/1\tA00123:8:H7:1:1101:1000:2000\t1:N:0:ACGT\n\
+2/1\tA00123:8:H7:1:1101:1000:2001\t1:N:0:ACGT\n3/1\tread/1\t\n"
+        );
+
+        let mut writer = Writer::from_writer(Vec::new());
+        let n = restore_names(
+            &mut Reader::from_bytes(&short),
+            &mut writer,
+            &mut mapping.as_slice(),
+        )
+        .unwrap();
+        assert_eq!(n, 3);
+        assert_eq!(writer.into_inner(), INPUT);
+
+        // a subset in order
+        let mut writer = Writer::from_writer(Vec::new());
+        restore_names(
+            &mut Reader::from_bytes(b"@3/1\nT\n+\nI\n"),
+            &mut writer,
+            &mut mapping.as_slice(),
+        )
+        .unwrap();
+        assert_eq!(writer.into_inner(), b"@read/1\nT\n+\nI\n");
+
+        // out of order
+        let res = restore_names(
+            &mut Reader::from_bytes(b">2/1\nG\n>1/1\nAC\n"),
+            &mut Writer::from_writer(Vec::new()),
+            &mut mapping.as_slice(),
+        );
+        assert!(matches!(res, Err(FastxErr::InvalidNameMapping(_))));
+    }
+
+    #[test]
+    fn test_shorten_names_options() {
+        let opts = ShortNameOptions {
+            prefix: "r".to_string(),
+            keep_mate: false,
+            keep_desc: true,
+        };
+        let mut writer = Writer::from_writer(Vec::new());
+        shorten_names(
+            &mut Reader::from_bytes(b">x/2 d\nA\n>y\nC\n"),
+            &mut writer,
+            &mut Vec::new(),
+            &opts,
+        )
+        .unwrap();
+        assert_eq!(writer.into_inner(), b">r1 d\nA\n>r2\nC\n");
+    }
+}
@@ -0,0 +1,330 @@
+use crate::errors::*;
+use crate::reader::*;
+use crate::writer::*;
+use flate2::bufread::GzDecoder;
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom, Write};
+use std::mem;
+
+/// Result of salvaging a corrupt gzip file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SalvageReport {
+    /// Number of gzip members decompressed without errors.
+    pub members: u64,
+    /// Number of records recovered.
+    pub records: u64,
+    /// Number of records dropped, e.g., truncated at a corruption point.
+    pub dropped_records: u64,
+    /// Compressed byte ranges (start, end) that could not be decompressed.
+    pub lost: Vec<(u64, u64)>,
+}
+
+impl SalvageReport {
+    /// Returns the number of compressed bytes lost.
+    pub fn lost_bytes(&self) -> u64 {
+        self.lost.iter().map(|(s, e)| e - s).sum()
+    }
+}
+
+enum State<R: BufRead> {
+    Idle(R),
+    Decoding(Box<GzDecoder<R>>),
+    Taken,
+}
+
+// decompresses gzip members one by one. On corruption, it skips to the next gzip member header
+// and returns EOF until `resume()` is called, so the caller can resynchronize its parser.
+struct MemberReader<R: BufRead + Seek> {
+    state: State<R>,
+    member_start: u64,
+    broken: bool,
+    members: u64,
+    lost: Vec<(u64, u64)>,
+}
+
+impl<R: BufRead + Seek> MemberReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            state: State::Idle(inner),
+            member_start: 0,
+            broken: false,
+            members: 0,
+            lost: Vec::new(),
+        }
+    }
+
+    fn resume(&mut self) {
+        self.broken = false;
+    }
+}
+
+impl<R: BufRead + Seek> Read for MemberReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.broken {
+                return Ok(0);
+            }
+            match mem::replace(&mut self.state, State::Taken) {
+                State::Idle(mut r) => {
+                    if r.fill_buf()?.is_empty() {
+                        self.state = State::Idle(r);
+                        return Ok(0);
+                    }
+                    self.member_start = r.stream_position()?;
+                    self.state = State::Decoding(Box::new(GzDecoder::new(r)));
+                }
+                State::Decoding(mut d) => match d.read(buf) {
+                    Ok(0) => {
+                        self.members += 1;
+                        self.state = State::Idle(d.into_inner());
+                    }
+                    Ok(n) => {
+                        self.state = State::Decoding(d);
+                        return Ok(n);
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                        self.state = State::Decoding(d);
+                    }
+                    Err(e)
+                        if matches!(
+                            e.kind(),
+                            io::ErrorKind::InvalidData
+                                | io::ErrorKind::InvalidInput
+                                | io::ErrorKind::UnexpectedEof
+                        ) =>
+                    {
+                        // the decoder may have read ahead, so search from the start of the member
+                        let mut r = d.into_inner();
+                        let next = find_member(&mut r, self.member_start + 1)?;
+                        warn!(
+                            "corrupt gzip member at bytes {}..{}: {e}, resuming at the next member",
+                            self.member_start, next
+                        );
+                        self.lost.push((self.member_start, next));
+                        self.state = State::Idle(r);
+                        self.broken = true;
+                        return Ok(0);
+                    }
+                    Err(e) => return Err(e),
+                },
+                State::Taken => unreachable!(),
+            }
+        }
+    }
+}
+
+// whether 4 bytes look like the start of a gzip member: magic, deflate, no reserved flags
+#[inline]
+fn is_member_header(win: u32) -> bool {
+    win >> 8 == 0x1f8b08 && win & 0xe0 == 0
+}
+
+// seeks to the next gzip member header from the offset, or to the end, and returns the position
+fn find_member<R: BufRead + Seek>(r: &mut R, from: u64) -> io::Result<u64> {
+    r.seek(SeekFrom::Start(from))?;
+    let mut win = 0u32;
+    let mut n = 0u64; // bytes scanned
+    loop {
+        let buf = r.fill_buf()?;
+        if buf.is_empty() {
+            return Ok(from + n);
+        }
+        let len = buf.len();
+        for &b in buf {
+            win = (win << 8) | b as u32;
+            n += 1;
+            if n >= 4 && is_member_header(win) {
+                let start = from + n - 4;
+                r.seek(SeekFrom::Start(start))?;
+                return Ok(start);
+            }
+        }
+        r.consume(len);
+    }
+}
+
+// skips bytes until the start of a record, and returns the bytes of the first lines of the record
+fn resync<B: BufRead>(r: &mut B, fastq: bool) -> io::Result<Vec<u8>> {
+    let mut lines: VecDeque<Vec<u8>> = VecDeque::with_capacity(4);
+    let trimmed_len = |line: &[u8]| line.trim_ascii_end().len();
+    loop {
+        let mut line = Vec::new();
+        if r.read_until(b'\n', &mut line)? == 0 {
+            return Ok(Vec::new());
+        }
+        if !fastq {
+            if line.first() == Some(&b'>') {
+                return Ok(line);
+            }
+            continue;
+        }
+        lines.push_back(line);
+        if lines.len() < 4 {
+            continue;
+        }
+        if lines[0].first() == Some(&b'@')
+            && lines[2].first() == Some(&b'+')
+            && trimmed_len(&lines[1]) == trimmed_len(&lines[3])
+        {
+            return Ok(lines.into_iter().flatten().collect());
+        }
+        lines.pop_front();
+    }
+}
+
+/// Recovers records from a corrupt gzip-compressed FASTA/Q file and writes them to the writer.
+///
+/// Gzip members are decompressed one by one. When a member is corrupt, the records decompressed
+/// before the corruption point are kept, the compressed data is scanned for the next gzip member
+/// header, and parsing resumes at the first complete record after it. Records truncated by
+/// corruption are dropped. Files made of many members (e.g. BGZF) lose little data,
+/// while everything after the corruption point is lost for single-member files.
+pub fn salvage_gzip<R: BufRead + Seek, W: Write>(
+    input: R,
+    writer: &mut Writer<W>,
+) -> Result<SalvageReport, FastxErr> {
+    let mut members = MemberReader::new(input);
+    let mut report = SalvageReport::default();
+    let mut fastq = None;
+    let mut resync_needed = false;
+    loop {
+        let mut segment = BufReader::with_capacity(1 << 16, &mut members);
+        let fastq = *fastq.get_or_insert_with(|| {
+            segment
+                .fill_buf()
+                .is_ok_and(|buf| buf.first() == Some(&b'@'))
+        });
+        let prefix = if resync_needed {
+            resync(&mut segment, fastq)?
+        } else {
+            Vec::new()
+        };
+
+        let mut reader = Reader::from_reader(Cursor::new(prefix).chain(segment));
+        let mut failed = false;
+        while let Some(res) = reader.next() {
+            match res {
+                Ok(seq) => {
+                    writer.write(&seq)?;
+                    report.records += 1;
+                }
+                Err(FastxErr::IOError(e)) => return Err(FastxErr::IOError(e)),
+                Err(e) => {
+                    debug!(
+                        "dropped a record after {} recovered ones: {e}",
+                        report.records
+                    );
+                    report.dropped_records += 1;
+                    failed = true;
+                    break;
+                }
+            }
+        }
+        drop(reader);
+
+        if members.broken {
+            members.resume();
+        } else if !failed {
+            break;
+        }
+        resync_needed = true;
+    }
+    writer.flush()?;
+
+    report.members = members.members;
+    report.lost = members.lost;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(data).unwrap();
+        e.finish().unwrap()
+    }
+
+    fn fastq(from: usize, to: usize) -> Vec<u8> {
+        let mut data = Vec::new();
+        for i in from..to {
+            let seq: String = (0..60)
+                .map(|j| b"ACGT"[(i * 7 + j * j) % 4] as char)
+                .collect();
+            data.extend(format!("@r{i}\n{seq}\n+\n{}\n", "I".repeat(60)).bytes());
+        }
+        data
+    }
+
+    fn ids(out: &[u8]) -> Vec<usize> {
+        let mut reader = Reader::from_reader(Cursor::new(out));
+        let mut ids = Vec::new();
+        while let Some(res) = reader.next() {
+            ids.push(
+                std::str::from_utf8(&res.unwrap().id[1..])
+                    .unwrap()
+                    .parse()
+                    .unwrap(),
+            );
+        }
+        ids
+    }
+
+    #[test]
+    fn test_salvage_gzip() {
+        // three members split in the middle of records
+        let data = fastq(0, 300);
+        let (a, b, c) = (&data[..10_000], &data[10_000..25_000], &data[25_000..]);
+        let mut input = gzip(a);
+        let corrupt_start = input.len();
+        let mut member2 = gzip(b);
+        let mid = member2.len() / 2;
+        member2[mid..mid + 64].fill(0xff);
+        input.extend(&member2);
+        let corrupt_end = input.len();
+        input.extend(gzip(c));
+
+        let mut writer = Writer::from_writer(Vec::new());
+        let report = salvage_gzip(Cursor::new(input), &mut writer).unwrap();
+        assert_eq!(report.members, 2);
+        assert_eq!(
+            report.lost,
+            vec![(corrupt_start as u64, corrupt_end as u64)]
+        );
+
+        // all records of the first and the third member are recovered in order
+        let ids = ids(&writer.into_inner());
+        assert_eq!(report.records as usize, ids.len());
+        assert!(ids.windows(2).all(|w| w[0] < w[1]));
+        let mut start = 0;
+        for i in 0..300 {
+            let end = start + fastq(i, i + 1).len();
+            if end <= 10_000 || start >= 25_000 {
+                assert!(ids.contains(&i), "record {i} not recovered");
+            }
+            start = end;
+        }
+    }
+
+    #[test]
+    fn test_salvage_intact_gzip() {
+        let data = fastq(0, 100);
+        let mut input = gzip(&data[..5000]);
+        input.extend(gzip(&data[5000..]));
+        let mut writer = Writer::from_writer(Vec::new());
+        let report = salvage_gzip(Cursor::new(input), &mut writer).unwrap();
+        assert_eq!(
+            report,
+            SalvageReport {
+                members: 2,
+                records: 100,
+                dropped_records: 0,
+                lost: vec![],
+            }
+        );
+        assert_eq!(writer.into_inner(), data);
+    }
+}
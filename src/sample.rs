@@ -0,0 +1,169 @@
+use crate::errors::*;
+use crate::reader::*;
+use crate::seq::*;
+use crate::util::*;
+use crate::writer::*;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+/// Returns the GC bin of a sequence, bins are of equal width in [0, 1].
+#[inline]
+pub fn gc_bin(seq: &Seq, bins: usize) -> usize {
+    ((seq.gc_content() as f64 * bins as f64) as usize).min(bins - 1)
+}
+
+/// Counts reads of all GC bins.
+pub fn gc_histogram<R: BufRead>(reader: &mut Reader<R>, bins: usize) -> Result<Vec<u64>, FastxErr> {
+    let bins = bins.max(1);
+    let mut hist = vec![0u64; bins];
+    while let Some(res) = reader.next() {
+        hist[gc_bin(&res?, bins)] += 1;
+    }
+    Ok(hist)
+}
+
+/// A sampler that equalizes the GC distribution of reads.
+///
+/// Given the GC histogram of the reads (the first pass), every bin is down-sampled to at most
+/// `cap` reads (the smallest non-empty bin by default), so over-represented GC bins are down-weighted.
+pub struct GcSampler {
+    bins: usize,
+    probs: Vec<f64>,
+    rng: Rng,
+}
+
+/// Statistics of a GC-balanced sampling.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GcSampleStats {
+    /// Read counts per GC bin of the input.
+    pub before: Vec<u64>,
+    /// Read counts per GC bin of the output.
+    pub after: Vec<u64>,
+}
+
+impl GcSampler {
+    /// Creates a sampler from a GC histogram, keeping at most `cap` reads per bin on average.
+    pub fn from_histogram(hist: &[u64], cap: Option<u64>, seed: u64) -> Self {
+        let cap = cap.unwrap_or_else(|| hist.iter().copied().filter(|&c| c > 0).min().unwrap_or(0));
+        let probs = hist
+            .iter()
+            .map(|&c| {
+                if c == 0 {
+                    0.0
+                } else {
+                    (cap as f64 / c as f64).min(1.0)
+                }
+            })
+            .collect();
+        Self {
+            bins: hist.len().max(1),
+            probs,
+            rng: Rng::new(seed),
+        }
+    }
+
+    /// Returns the keeping probability of every bin.
+    pub fn probabilities(&self) -> &[f64] {
+        &self.probs
+    }
+
+    /// Decides whether to keep a read.
+    pub fn keep(&mut self, seq: &Seq) -> bool {
+        let p = self
+            .probs
+            .get(gc_bin(seq, self.bins))
+            .copied()
+            .unwrap_or(0.0);
+        p >= 1.0 || self.rng.next_f64() < p
+    }
+
+    /// Samples the reads of the reader (the second pass) into the writer,
+    /// returns the read counts per bin of the output.
+    pub fn sample<R: BufRead, W: Write>(
+        &mut self,
+        reader: &mut Reader<R>,
+        writer: &mut Writer<W>,
+    ) -> Result<Vec<u64>, FastxErr> {
+        let mut after = vec![0u64; self.bins];
+        while let Some(res) = reader.next() {
+            let seq = res?;
+            if self.keep(&seq) {
+                after[gc_bin(&seq, self.bins)] += 1;
+                writer.write(&seq)?;
+            }
+        }
+        writer.flush()?;
+        Ok(after)
+    }
+}
+
+/// Samples reads of a file with an equalized GC distribution in two passes.
+pub fn sample_gc_balanced<P: AsRef<Path>, W: Write>(
+    file: P,
+    writer: &mut Writer<W>,
+    bins: usize,
+    cap: Option<u64>,
+    seed: u64,
+) -> Result<GcSampleStats, FastxErr> {
+    let before = gc_histogram(&mut Reader::new(&file)?, bins)?;
+    let mut sampler = GcSampler::from_histogram(&before, cap, seed);
+    let after = sampler.sample(&mut Reader::new(&file)?, writer)?;
+    Ok(GcSampleStats { before, after })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Cursor;
+
+    fn input() -> String {
+        let mut s = String::new();
+        for i in 0..1000 {
+            s.push_str(&format!(">at{i}\nAAAAATTTTT\n"));
+        }
+        for i in 0..100 {
+            s.push_str(&format!(">gc{i}\nGGGGGCCCCC\n"));
+        }
+        s
+    }
+
+    #[test]
+    fn test_gc_sampler() {
+        let data = input();
+        let hist =
+            gc_histogram(&mut Reader::from_reader(Cursor::new(data.as_bytes())), 10).unwrap();
+        assert_eq!(hist[0], 1000);
+        assert_eq!(hist[9], 100);
+
+        let mut sampler = GcSampler::from_histogram(&hist, None, 11);
+        assert_eq!(sampler.probabilities()[0], 0.1);
+        assert_eq!(sampler.probabilities()[9], 1.0);
+
+        let mut writer = Writer::from_writer(Vec::new());
+        let after = sampler
+            .sample(
+                &mut Reader::from_reader(Cursor::new(data.as_bytes())),
+                &mut writer,
+            )
+            .unwrap();
+        assert_eq!(after[9], 100);
+        assert!((70..=130).contains(&after[0]), "{}", after[0]);
+    }
+
+    #[test]
+    fn test_sample_gc_balanced_is_reproducible() {
+        let path = std::env::temp_dir().join(format!("fastseq-gcsample-{}.fa", std::process::id()));
+        fs::write(&path, input()).unwrap();
+
+        let mut w1 = Writer::from_writer(Vec::new());
+        let s1 = sample_gc_balanced(&path, &mut w1, 10, Some(50), 1).unwrap();
+        let mut w2 = Writer::from_writer(Vec::new());
+        let s2 = sample_gc_balanced(&path, &mut w2, 10, Some(50), 1).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(s1, s2);
+        assert_eq!(w1.into_inner(), w2.into_inner());
+        assert_eq!(s1.before[9], 100);
+    }
+}
@@ -0,0 +1,258 @@
+use crate::errors::*;
+use crate::xopen::*;
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::path::Path;
+
+/// A sample in a sample sheet.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Sample {
+    pub name: String,
+    /// The (i7) barcode.
+    pub barcode: Option<String>,
+    /// The second (i5) barcode of dual-indexed libraries.
+    pub barcode2: Option<String>,
+    /// Path of the read 1 (or single-end) file.
+    pub r1: Option<String>,
+    /// Path of the read 2 file.
+    pub r2: Option<String>,
+    /// Other columns, as (column name, value).
+    pub extra: Vec<(String, String)>,
+}
+
+impl Sample {
+    /// Returns the combined barcode, "i7+i5" for dual-indexed samples.
+    pub fn combined_barcode(&self) -> Option<String> {
+        match (&self.barcode, &self.barcode2) {
+            (Some(b1), Some(b2)) => Some(format!("{b1}+{b2}")),
+            (Some(b1), None) => Some(b1.clone()),
+            (None, Some(b2)) => Some(b2.clone()),
+            (None, None) => None,
+        }
+    }
+}
+
+/// A sample sheet parsed from a CSV or TSV file.
+///
+/// The delimiter is detected from the header line. Illumina sample sheets are supported by
+/// parsing the `[Data]` section only. Column names are case-insensitive, with common aliases:
+/// sample (`sample`, `sample_id`, `sample_name`), barcode (`barcode`, `index`, `i7`, `index1`),
+/// second barcode (`barcode2`, `index2`, `i5`), files (`r1`, `fastq_1`, `fq1`, `read1`, `file`)
+/// and (`r2`, `fastq_2`, `fq2`, `read2`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SampleSheet {
+    pub samples: Vec<Sample>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Column {
+    Name,
+    Barcode,
+    Barcode2,
+    R1,
+    R2,
+    Extra,
+}
+
+fn column_kind(name: &str) -> Column {
+    match name.to_ascii_lowercase().as_str() {
+        "sample" | "sample_id" | "sample_name" | "sampleid" | "samplename" => Column::Name,
+        "barcode" | "barcode1" | "index" | "index1" | "i7" => Column::Barcode,
+        "barcode2" | "index2" | "i5" => Column::Barcode2,
+        "r1" | "fastq_1" | "fq1" | "read1" | "file" => Column::R1,
+        "r2" | "fastq_2" | "fq2" | "read2" => Column::R2,
+        _ => Column::Extra,
+    }
+}
+
+impl SampleSheet {
+    /// Parses a sample sheet file, "-" for stdin.
+    pub fn from_path<P: AsRef<Path>>(file: P) -> Result<Self, FastxErr> {
+        Self::parse(xopen(file, 65536)?)
+    }
+
+    /// Parses a sample sheet from any BufRead.
+    pub fn parse<R: BufRead>(reader: R) -> Result<Self, FastxErr> {
+        let mut lines: Vec<(usize, String)> = Vec::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.trim_start().to_ascii_lowercase().starts_with("[data]") {
+                // an Illumina sample sheet, only keep the [Data] section
+                lines.clear();
+                continue;
+            }
+            lines.push((i + 1, line.to_string()));
+        }
+
+        let Some((_, header)) = lines.first() else {
+            return Ok(Self::default());
+        };
+        let delim = if header.contains('\t') { '\t' } else { ',' };
+        let columns: Vec<(String, Column)> = header
+            .split(delim)
+            .map(|c| {
+                let c = c.trim().to_string();
+                let kind = column_kind(&c);
+                (c, kind)
+            })
+            .collect();
+        if !columns.iter().any(|c| c.1 == Column::Name) {
+            return Err(FastxErr::InvalidSampleSheet(
+                "no sample name column in the header".to_string(),
+            ));
+        }
+
+        let mut samples = Vec::with_capacity(lines.len() - 1);
+        for (lineno, line) in &lines[1..] {
+            let fields: Vec<&str> = line.split(delim).map(|f| f.trim()).collect();
+            if fields.len() > columns.len() {
+                return Err(FastxErr::InvalidSampleSheet(format!(
+                    "line {lineno}: {} fields found, but the header has {}",
+                    fields.len(),
+                    columns.len()
+                )));
+            }
+            let mut sample = Sample::default();
+            for ((name, kind), &value) in columns.iter().zip(fields.iter()) {
+                let opt = || (!value.is_empty()).then(|| value.to_string());
+                match kind {
+                    Column::Name => sample.name = value.to_string(),
+                    Column::Barcode => sample.barcode = opt().map(|b| b.to_ascii_uppercase()),
+                    Column::Barcode2 => sample.barcode2 = opt().map(|b| b.to_ascii_uppercase()),
+                    Column::R1 => sample.r1 = opt(),
+                    Column::R2 => sample.r2 = opt(),
+                    Column::Extra => sample.extra.push((name.clone(), value.to_string())),
+                }
+            }
+            if sample.name.is_empty() {
+                return Err(FastxErr::InvalidSampleSheet(format!(
+                    "line {lineno}: empty sample name"
+                )));
+            }
+            samples.push(sample);
+        }
+        Ok(Self { samples })
+    }
+
+    /// Returns the sample with the given name.
+    pub fn get(&self, name: &str) -> Option<&Sample> {
+        self.samples.iter().find(|s| s.name == name)
+    }
+
+    /// Returns the smallest Hamming distance between combined barcodes of two samples,
+    /// as (distance, index of sample a, index of sample b).
+    pub fn min_barcode_distance(&self) -> Option<(usize, usize, usize)> {
+        let barcodes: Vec<(usize, String)> = self
+            .samples
+            .iter()
+            .enumerate()
+            .filter_map(|(i, s)| s.combined_barcode().map(|b| (i, b)))
+            .collect();
+        let mut best: Option<(usize, usize, usize)> = None;
+        for (x, (i, a)) in barcodes.iter().enumerate() {
+            for (j, b) in &barcodes[x + 1..] {
+                let d = hamming(a.as_bytes(), b.as_bytes());
+                if best.is_none_or(|(bd, _, _)| d < bd) {
+                    best = Some((d, *i, *j));
+                }
+            }
+        }
+        best
+    }
+
+    /// Checks for duplicate sample names, duplicate barcodes, and barcodes closer than
+    /// `min_distance` (the largest number of mismatches tolerated in demultiplexing is
+    /// `(min_distance - 1) / 2`).
+    pub fn validate(&self, min_distance: usize) -> Result<(), FastxErr> {
+        let mut names: HashMap<&str, usize> = HashMap::new();
+        for (i, s) in self.samples.iter().enumerate() {
+            if let Some(j) = names.insert(&s.name, i) {
+                return Err(FastxErr::InvalidSampleSheet(format!(
+                    "duplicate sample name: {} (records {} and {})",
+                    s.name,
+                    j + 1,
+                    i + 1
+                )));
+            }
+        }
+        if let Some((d, i, j)) = self.min_barcode_distance()
+            && (d == 0 || d < min_distance)
+        {
+            let (a, b) = (&self.samples[i], &self.samples[j]);
+            return Err(FastxErr::InvalidSampleSheet(format!(
+                "barcodes of samples {} ({}) and {} ({}) differ by {d} base(s), less than {}",
+                a.name,
+                a.combined_barcode().unwrap_or_default(),
+                b.name,
+                b.combined_barcode().unwrap_or_default(),
+                min_distance.max(1)
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Returns the Hamming distance of two sequences, extra bases of the longer one count as mismatches.
+pub fn hamming(a: &[u8], b: &[u8]) -> usize {
+    let common = a.iter().zip(b.iter()).filter(|(x, y)| x != y).count();
+    common + a.len().abs_diff(b.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_parse_csv_and_tsv() {
+        let csv = "sample_id,index,index2,fastq_1,fastq_2,project\n\
+                   s1,acgtacgt,TTTTAAAA,s1_R1.fq.gz,s1_R2.fq.gz,p1\n\
+                   s2,GGGGCCCC,,s2.fq.gz,,p1\n";
+        let sheet = SampleSheet::parse(Cursor::new(csv)).unwrap();
+        assert_eq!(sheet.samples.len(), 2);
+        let s1 = sheet.get("s1").unwrap();
+        assert_eq!(s1.combined_barcode().unwrap(), "ACGTACGT+TTTTAAAA");
+        assert_eq!(s1.r2.as_deref(), Some("s1_R2.fq.gz"));
+        assert_eq!(s1.extra, vec![("project".to_string(), "p1".to_string())]);
+        assert_eq!(sheet.samples[1].barcode2, None);
+
+        let tsv = "# comment\nSample\tBarcode\r\na\tACGT\r\nb\tACGA\r\n";
+        let sheet = SampleSheet::parse(Cursor::new(tsv)).unwrap();
+        assert_eq!(sheet.min_barcode_distance(), Some((1, 0, 1)));
+        assert!(sheet.validate(1).is_ok());
+        assert!(matches!(
+            sheet.validate(3),
+            Err(FastxErr::InvalidSampleSheet(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_illumina_sample_sheet() {
+        let sheet = "[Header]\nIEMFileVersion,4\n\n[Data]\nSample_ID,Sample_Name,index\nS1,x,AAAA\nS2,y,CCCC\n";
+        let sheet = SampleSheet::parse(Cursor::new(sheet)).unwrap();
+        assert_eq!(sheet.samples.len(), 2);
+        // the last alias wins for repeated kinds of columns
+        assert_eq!(sheet.samples[0].name, "x");
+        assert!(sheet.validate(3).is_ok());
+    }
+
+    #[test]
+    fn test_validate_duplicates() {
+        let sheet = SampleSheet::parse(Cursor::new("sample,barcode\na,AAAA\na,CCCC\n")).unwrap();
+        assert!(matches!(
+            sheet.validate(1),
+            Err(FastxErr::InvalidSampleSheet(_))
+        ));
+        let sheet = SampleSheet::parse(Cursor::new("sample,barcode\na,AAAA\nb,AAAA\n")).unwrap();
+        assert!(matches!(
+            sheet.validate(0),
+            Err(FastxErr::InvalidSampleSheet(_))
+        ));
+        assert!(SampleSheet::parse(Cursor::new("barcode\nAAAA\n")).is_err());
+        assert_eq!(hamming(b"ACGT", b"AC"), 2);
+    }
+}
@@ -0,0 +1,210 @@
+use crate::util::*;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// Profiles of characters allowed in sequence IDs by different consumers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SanitizeProfile {
+    /// Safe as a file name: alphanumerics and `._-`, not starting with `.`.
+    FileName,
+    /// Valid SAM reference name: no whitespace, `\,"'()[]{}<>`, and not starting with `*` or `=`.
+    SamRname,
+    /// Safe in Newick trees: no whitespace and `()[]':;,`.
+    Newick,
+}
+
+impl SanitizeProfile {
+    #[inline]
+    fn allows(&self, b: u8, first: bool) -> bool {
+        match self {
+            SanitizeProfile::FileName => {
+                (b.is_ascii_alphanumeric() || matches!(b, b'_' | b'-' | b'.'))
+                    && !(first && b == b'.')
+            }
+            SanitizeProfile::SamRname => {
+                b.is_ascii_graphic()
+                    && !matches!(
+                        b,
+                        b'\\'
+                            | b','
+                            | b'"'
+                            | b'\''
+                            | b'('
+                            | b')'
+                            | b'['
+                            | b']'
+                            | b'{'
+                            | b'}'
+                            | b'<'
+                            | b'>'
+                    )
+                    && !(first && matches!(b, b'*' | b'='))
+            }
+            SanitizeProfile::Newick => {
+                b.is_ascii_graphic()
+                    && !matches!(b, b'(' | b')' | b'[' | b']' | b'\'' | b':' | b';' | b',')
+            }
+        }
+    }
+
+    fn default_max_len(&self) -> usize {
+        match self {
+            SanitizeProfile::FileName => 255,
+            _ => 0,
+        }
+    }
+}
+
+/// Replaces characters not allowed by the profile with `_`, without collision handling.
+pub fn sanitize_id(profile: SanitizeProfile, id: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(id.len());
+    for (i, &b) in id.iter().enumerate() {
+        out.push(if profile.allows(b, i == 0) { b } else { b'_' });
+    }
+    if out.is_empty() {
+        out.push(b'_');
+    }
+    out
+}
+
+/// A stateful ID sanitizer that guarantees distinct input IDs stay distinct.
+///
+/// IDs are sanitized with `sanitize_id` and truncated to the maximum length. If the result collides
+/// with the output of a different input ID, it is truncated further and suffixed with a hash of the
+/// original ID. All altered IDs are recorded in a mapping table.
+pub struct Sanitizer {
+    profile: SanitizeProfile,
+    max_len: usize,
+    outputs: HashMap<Vec<u8>, Vec<u8>>, // sanitized -> original
+    mapping: Vec<(Vec<u8>, Vec<u8>)>,   // (sanitized, original) of altered IDs
+}
+
+impl Sanitizer {
+    /// Creates a new Sanitizer with the profile's default maximum length.
+    pub fn new(profile: SanitizeProfile) -> Self {
+        Self {
+            profile,
+            max_len: profile.default_max_len(),
+            outputs: HashMap::new(),
+            mapping: Vec::new(),
+        }
+    }
+
+    /// Sets the maximum length of output IDs, 0 for no limit.
+    /// Values shorter than 10 are raised to 10 to leave room for the hash suffix.
+    pub fn set_max_len(&mut self, max_len: usize) {
+        self.max_len = if max_len == 0 { 0 } else { max_len.max(10) }
+    }
+
+    /// Sanitizes an ID. Calling it again with the same ID returns the same output.
+    pub fn sanitize(&mut self, id: &[u8]) -> Vec<u8> {
+        let mut out = sanitize_id(self.profile, id);
+        if self.max_len > 0 {
+            out.truncate(self.max_len);
+        }
+
+        match self.outputs.get(&out) {
+            Some(orig) if orig == id => return out,
+            Some(_) => {
+                // collision: "<prefix>_<hash>", rehash with a counter until unique
+                let mut salt = 0u64;
+                loop {
+                    let hash = fnv1a64(id).wrapping_add(salt);
+                    let suffix = format!("_{:08x}", hash as u32);
+                    let keep = if self.max_len > 0 {
+                        out.len().min(self.max_len - suffix.len())
+                    } else {
+                        out.len()
+                    };
+                    let mut candidate = out[..keep].to_vec();
+                    candidate.extend_from_slice(suffix.as_bytes());
+                    match self.outputs.get(&candidate) {
+                        Some(orig) if orig == id => return candidate,
+                        Some(_) => salt += 1,
+                        None => {
+                            out = candidate;
+                            break;
+                        }
+                    }
+                }
+            }
+            None => {}
+        }
+
+        self.outputs.insert(out.clone(), id.to_vec());
+        if out != id {
+            self.mapping.push((out.clone(), id.to_vec()));
+        }
+        out
+    }
+
+    /// Returns the (sanitized, original) pairs of all altered IDs, in order of first occurrence.
+    pub fn mapping(&self) -> &[(Vec<u8>, Vec<u8>)] {
+        &self.mapping
+    }
+
+    /// Writes the mapping table as tab-delimited lines of sanitized and original IDs.
+    pub fn write_mapping<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        for (new, orig) in &self.mapping {
+            w.write_all(new)?;
+            w.write_all(b"\t")?;
+            w.write_all(orig)?;
+            w.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_id_profiles() {
+        assert_eq!(
+            sanitize_id(SanitizeProfile::FileName, b".chr1/x y:1"),
+            b"_chr1_x_y_1"
+        );
+        assert_eq!(
+            sanitize_id(SanitizeProfile::SamRname, b"*chr1 {x}|a:1"),
+            b"_chr1__x_|a:1"
+        );
+        assert_eq!(
+            sanitize_id(SanitizeProfile::Newick, b"sp(1):a,b|c"),
+            b"sp_1__a_b|c"
+        );
+        assert_eq!(sanitize_id(SanitizeProfile::Newick, b""), b"_");
+    }
+
+    #[test]
+    fn test_sanitizer_collisions() {
+        let mut s = Sanitizer::new(SanitizeProfile::FileName);
+        let a = s.sanitize(b"a/b");
+        let b = s.sanitize(b"a:b");
+        let c = s.sanitize(b"a_b");
+        assert_eq!(a, b"a_b");
+        assert_ne!(b, a);
+        assert!(b.starts_with(b"a_b_"));
+        assert_ne!(c, a);
+        assert_ne!(c, b);
+
+        // stable for repeated IDs
+        assert_eq!(s.sanitize(b"a:b"), b);
+        assert_eq!(s.mapping().len(), 3);
+
+        let mut out = Vec::new();
+        s.write_mapping(&mut out).unwrap();
+        assert!(out.starts_with(b"a_b\ta/b\n"));
+    }
+
+    #[test]
+    fn test_sanitizer_max_len() {
+        let mut s = Sanitizer::new(SanitizeProfile::Newick);
+        s.set_max_len(12);
+        let a = s.sanitize(b"abcdefghijklmn1");
+        let b = s.sanitize(b"abcdefghijklmn2");
+        assert_eq!(a, b"abcdefghijkl");
+        assert_eq!(b.len(), 12);
+        assert_ne!(a, b);
+    }
+}
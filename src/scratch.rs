@@ -0,0 +1,117 @@
+use crate::seq::*;
+
+/// Reusable buffers for record transforms, e.g., reverse complements, re-encoded IDs or
+/// translated sequences, so a loop or a worker thread allocates only while the buffers grow.
+///
+/// Keep one `Scratch` per thread: it is `Send` but meant to be used by one worker at a time.
+#[derive(Debug, Clone, Default)]
+pub struct Scratch {
+    /// A buffer for IDs or headers.
+    pub id: Vec<u8>,
+    /// A buffer for sequences, used by `revcomp`.
+    pub seq: Vec<u8>,
+    /// A buffer for quality strings, used by `reverse_qual`.
+    pub qual: Vec<u8>,
+    bufs: Vec<Vec<u8>>,
+}
+
+impl Scratch {
+    /// Creates empty scratch buffers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the i-th extra buffer, cleared, creating it if needed.
+    pub fn buf(&mut self, i: usize) -> &mut Vec<u8> {
+        if i >= self.bufs.len() {
+            self.bufs.resize_with(i + 1, Vec::new);
+        }
+        let buf = &mut self.bufs[i];
+        buf.clear();
+        buf
+    }
+
+    /// Returns the reverse complement of a sequence, computed in the `seq` buffer.
+    pub fn revcomp(&mut self, seq: &[u8]) -> &[u8] {
+        revcomp_into(seq, &mut self.seq);
+        &self.seq
+    }
+
+    /// Returns the reversed quality string, computed in the `qual` buffer.
+    pub fn reverse_qual(&mut self, qual: &[u8]) -> &[u8] {
+        self.qual.clear();
+        self.qual.extend(qual.iter().rev());
+        &self.qual
+    }
+
+    /// Returns the reverse complement of a record, with the sequence and quality in the buffers.
+    pub fn rc_record<'a>(&'a mut self, record: &Seq<'a>) -> Seq<'a> {
+        revcomp_into(record.seq, &mut self.seq);
+        let qual = match record.qual {
+            Some(q) => {
+                self.qual.clear();
+                self.qual.extend(q.iter().rev());
+                Some(&self.qual[..])
+            }
+            None => None,
+        };
+        Seq {
+            id: record.id,
+            desc: record.desc,
+            seq: &self.seq,
+            qual,
+        }
+    }
+
+    /// Clears all buffers, keeping the allocated memory.
+    pub fn clear(&mut self) {
+        self.id.clear();
+        self.seq.clear();
+        self.qual.clear();
+        self.bufs.iter_mut().for_each(|b| b.clear());
+    }
+
+    /// Releases memory of buffers larger than `max_bytes`, e.g., after an unusually long record.
+    pub fn shrink(&mut self, max_bytes: usize) {
+        for buf in [&mut self.id, &mut self.seq, &mut self.qual]
+            .into_iter()
+            .chain(self.bufs.iter_mut())
+        {
+            if buf.capacity() > max_bytes {
+                buf.clear();
+                buf.shrink_to(max_bytes);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scratch_reuse() {
+        let mut scratch = Scratch::new();
+        assert_eq!(scratch.revcomp(b"AACG"), b"CGTT");
+        let ptr = scratch.seq.as_ptr();
+        assert_eq!(scratch.revcomp(b"TTT"), b"AAA");
+        assert_eq!(scratch.seq.as_ptr(), ptr, "buffer should be reused");
+
+        scratch.buf(2).extend_from_slice(b"x");
+        assert_eq!(scratch.buf(2).len(), 0);
+        assert_eq!(scratch.reverse_qual(b"ABC"), b"CBA");
+
+        let record = Seq {
+            id: b"r",
+            desc: b"",
+            seq: b"ACC",
+            qual: Some(b"12#"),
+        };
+        let rc = scratch.rc_record(&record);
+        assert_eq!((rc.seq, rc.qual), (&b"GGT"[..], Some(&b"#21"[..])));
+
+        scratch.seq.reserve(1 << 20);
+        scratch.shrink(1024);
+        assert!(scratch.seq.capacity() <= 1024);
+    }
+}
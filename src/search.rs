@@ -0,0 +1,562 @@
+use crate::errors::*;
+use crate::hash::*;
+use crate::idnorm::*;
+use crate::reader::*;
+use crate::seq::*;
+use crate::writer::*;
+use std::collections::{HashSet, VecDeque};
+use std::io::{BufRead, Write};
+
+/// A match of a pattern in a text, `start..end` in the text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match {
+    pub pattern: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// An Aho-Corasick automaton for searching multiple patterns in one pass.
+///
+/// The automaton is compiled into a dense DFA (256 transitions per state),
+/// which is fast for the short query sets typical for vectors, adapters and spike-ins.
+pub struct AhoCorasick {
+    trans: Vec<[u32; 256]>,
+    outputs: Vec<Vec<usize>>, // pattern indexes ending at each state, including those via failure links
+    pattern_lens: Vec<usize>,
+    ignore_case: bool,
+}
+
+impl AhoCorasick {
+    /// Builds an automaton from the patterns. Empty patterns never match.
+    pub fn new<P: AsRef<[u8]>>(patterns: &[P], ignore_case: bool) -> Self {
+        let fold = |b: u8| {
+            if ignore_case {
+                b.to_ascii_uppercase()
+            } else {
+                b
+            }
+        };
+
+        // trie, 0 means no edge as the root can never be a child
+        let mut trans: Vec<[u32; 256]> = vec![[0; 256]];
+        let mut outputs: Vec<Vec<usize>> = vec![Vec::new()];
+        let mut pattern_lens = Vec::with_capacity(patterns.len());
+        for (i, p) in patterns.iter().enumerate() {
+            let p = p.as_ref();
+            pattern_lens.push(p.len());
+            if p.is_empty() {
+                continue;
+            }
+            let mut state = 0usize;
+            for &b in p {
+                let b = fold(b) as usize;
+                if trans[state][b] == 0 {
+                    trans.push([0; 256]);
+                    outputs.push(Vec::new());
+                    trans[state][b] = (trans.len() - 1) as u32;
+                }
+                state = trans[state][b] as usize;
+            }
+            outputs[state].push(i);
+        }
+
+        // BFS over the trie to compute failure links, turning the trie into a DFA
+        let mut fail = vec![0usize; trans.len()];
+        let mut queue = VecDeque::new();
+        for &s in trans[0].iter() {
+            if s != 0 {
+                queue.push_back(s as usize);
+            }
+        }
+        while let Some(state) = queue.pop_front() {
+            let inherited = outputs[fail[state]].clone();
+            outputs[state].extend(inherited);
+            let fail_trans = trans[fail[state]];
+            for (t, &f) in trans[state].iter_mut().zip(fail_trans.iter()) {
+                if *t != 0 {
+                    fail[*t as usize] = f as usize;
+                    queue.push_back(*t as usize);
+                } else {
+                    *t = f;
+                }
+            }
+        }
+
+        // folding is applied to the text on the fly, so add lowercase edges
+        if ignore_case {
+            for t in trans.iter_mut() {
+                for b in b'a'..=b'z' {
+                    t[b as usize] = t[b.to_ascii_uppercase() as usize];
+                }
+            }
+        }
+
+        Self {
+            trans,
+            outputs,
+            pattern_lens,
+            ignore_case,
+        }
+    }
+
+    /// Returns the number of patterns.
+    pub fn num_patterns(&self) -> usize {
+        self.pattern_lens.len()
+    }
+
+    /// Returns whether the automaton matches case-insensitively.
+    pub fn ignore_case(&self) -> bool {
+        self.ignore_case
+    }
+
+    /// Returns whether any pattern occurs in the text.
+    pub fn is_match(&self, text: &[u8]) -> bool {
+        let mut state = 0usize;
+        for &b in text {
+            state = self.trans[state][b as usize] as usize;
+            if !self.outputs[state].is_empty() {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Returns all (possibly overlapping) matches, ordered by end position.
+    pub fn find_all(&self, text: &[u8]) -> Vec<Match> {
+        let mut matches = Vec::new();
+        let mut state = 0usize;
+        for (i, &b) in text.iter().enumerate() {
+            state = self.trans[state][b as usize] as usize;
+            for &p in &self.outputs[state] {
+                matches.push(Match {
+                    pattern: p,
+                    start: i + 1 - self.pattern_lens[p],
+                    end: i + 1,
+                });
+            }
+        }
+        matches
+    }
+}
+
+/// Strand of a hit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strand {
+    Forward,
+    Reverse,
+}
+
+/// A hit of a query in a sequence. `start..end` is on the forward strand of the sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeqHit {
+    pub query: usize,
+    pub start: usize,
+    pub end: usize,
+    pub strand: Strand,
+}
+
+/// A substring searcher for sequences, optionally searching both strands.
+pub struct SeqGrep {
+    ac: AhoCorasick,
+    n_queries: usize,
+    // query index and strand of each pattern of the automaton
+    pattern_info: Vec<(usize, Strand)>,
+}
+
+impl SeqGrep {
+    /// Creates a searcher. With `both_strands`, reverse complements of queries are also searched,
+    /// palindromic queries are only reported once.
+    pub fn new<P: AsRef<[u8]>>(queries: &[P], both_strands: bool, ignore_case: bool) -> Self {
+        let mut patterns: Vec<Vec<u8>> = Vec::with_capacity(queries.len() * 2);
+        let mut pattern_info = Vec::with_capacity(queries.len() * 2);
+        for (i, q) in queries.iter().enumerate() {
+            patterns.push(q.as_ref().to_vec());
+            pattern_info.push((i, Strand::Forward));
+        }
+        if both_strands {
+            for (i, q) in queries.iter().enumerate() {
+                let q = q.as_ref();
+                let rc = revcomp(q);
+                let palindromic = if ignore_case {
+                    rc.eq_ignore_ascii_case(q)
+                } else {
+                    rc == q
+                };
+                if !palindromic {
+                    patterns.push(rc);
+                    pattern_info.push((i, Strand::Reverse));
+                }
+            }
+        }
+
+        Self {
+            ac: AhoCorasick::new(&patterns, ignore_case),
+            n_queries: queries.len(),
+            pattern_info,
+        }
+    }
+
+    /// Returns the number of queries.
+    pub fn num_queries(&self) -> usize {
+        self.n_queries
+    }
+
+    /// Returns whether any query occurs in the sequence.
+    pub fn is_match(&self, seq: &Seq) -> bool {
+        self.ac.is_match(seq.seq)
+    }
+
+    /// Returns all hits of all queries in the sequence.
+    pub fn find(&self, seq: &Seq) -> Vec<SeqHit> {
+        self.ac
+            .find_all(seq.seq)
+            .into_iter()
+            .map(|m| {
+                let (query, strand) = self.pattern_info[m.pattern];
+                SeqHit {
+                    query,
+                    start: m.start,
+                    end: m.end,
+                    strand,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Writes records matching (or, with `invert`, not matching) any query to the writer,
+/// and returns the number of written records.
+pub fn grep_records<R: BufRead, W: Write>(
+    reader: &mut Reader<R>,
+    writer: &mut Writer<W>,
+    grep: &SeqGrep,
+    invert: bool,
+) -> Result<u64, FastxErr> {
+    let mut n = 0u64;
+    while let Some(res) = reader.next() {
+        let seq = res?;
+        if grep.is_match(&seq) != invert {
+            writer.write(&seq)?;
+            n += 1;
+        }
+    }
+    writer.flush()?;
+    Ok(n)
+}
+
+/// A list of sequence IDs for grepping records by ID, e.g., host reads to remove.
+///
+/// The exact mode keeps all IDs in a hash set. The Bloom mode keeps bits of a Bloom filter only,
+/// trading false positives for bounded memory on lists of hundreds of millions of IDs. False
+/// positives can be removed with a second pass: collect matching IDs of the reads with
+/// `matching_ids`, keep those really in the list with `IdList::verify`, and grep again with the
+/// resulting exact list.
+///
+/// IDs of lists built with an `IdNormalizer` (the `*_with` functions) are normalized, so queries
+/// should be normalized with the same one, as `matching_ids_with` and `grep_ids_with` do.
+#[derive(Debug, Clone)]
+pub enum IdList {
+    Exact(HashSet<Vec<u8>>),
+    Bloom(BloomFilter),
+}
+
+impl IdList {
+    /// Reads an exact list, one ID per line. Empty lines are skipped, and only the first word of
+    /// a line is used, so headers with descriptions work too.
+    pub fn exact_from_reader<R: BufRead>(reader: R) -> Result<Self, FastxErr> {
+        Self::exact_from_reader_with(reader, &IdNormalizer::default())
+    }
+
+    /// Reads an exact list like `exact_from_reader`, normalizing the IDs.
+    pub fn exact_from_reader_with<R: BufRead>(
+        reader: R,
+        norm: &IdNormalizer,
+    ) -> Result<Self, FastxErr> {
+        let mut ids = HashSet::new();
+        for_each_id(reader, norm, |id| {
+            ids.insert(id.to_vec());
+        })?;
+        Ok(IdList::Exact(ids))
+    }
+
+    /// Reads a list into a Bloom filter sized for `expected_ids` IDs at the false-positive rate.
+    pub fn bloom_from_reader<R: BufRead>(
+        reader: R,
+        expected_ids: usize,
+        fp_rate: f64,
+    ) -> Result<Self, FastxErr> {
+        Self::bloom_from_reader_with(reader, expected_ids, fp_rate, &IdNormalizer::default())
+    }
+
+    /// Reads a list into a Bloom filter like `bloom_from_reader`, normalizing the IDs.
+    pub fn bloom_from_reader_with<R: BufRead>(
+        reader: R,
+        expected_ids: usize,
+        fp_rate: f64,
+        norm: &IdNormalizer,
+    ) -> Result<Self, FastxErr> {
+        let mut bloom = BloomFilter::with_rate(expected_ids, fp_rate);
+        for_each_id(reader, norm, |id| bloom.insert(id))?;
+        Ok(IdList::Bloom(bloom))
+    }
+
+    /// Returns whether the ID is in the list, with possible false positives in the Bloom mode.
+    #[inline]
+    pub fn contains(&self, id: &[u8]) -> bool {
+        match self {
+            IdList::Exact(ids) => ids.contains(id),
+            IdList::Bloom(bloom) => bloom.contains(id),
+        }
+    }
+
+    /// Returns an exact list of the candidate IDs that are in the list read from the reader,
+    /// removing false positives of a Bloom filter.
+    pub fn verify<R: BufRead>(candidates: &HashSet<Vec<u8>>, reader: R) -> Result<Self, FastxErr> {
+        Self::verify_with(candidates, reader, &IdNormalizer::default())
+    }
+
+    /// Verifies normalized candidate IDs like `verify`, normalizing the IDs of the list.
+    pub fn verify_with<R: BufRead>(
+        candidates: &HashSet<Vec<u8>>,
+        reader: R,
+        norm: &IdNormalizer,
+    ) -> Result<Self, FastxErr> {
+        let mut ids = HashSet::new();
+        for_each_id(reader, norm, |id| {
+            if candidates.contains(id) {
+                ids.insert(id.to_vec());
+            }
+        })?;
+        Ok(IdList::Exact(ids))
+    }
+}
+
+fn for_each_id<R: BufRead, F: FnMut(&[u8])>(
+    mut reader: R,
+    norm: &IdNormalizer,
+    mut f: F,
+) -> Result<(), FastxErr> {
+    let mut line = Vec::with_capacity(256);
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(());
+        }
+        let id = line
+            .split(|b| b.is_ascii_whitespace())
+            .find(|w| !w.is_empty());
+        if let Some(id) = id {
+            f(&norm.normalize(id));
+        }
+    }
+}
+
+/// Returns IDs of records in the list, the candidates for `IdList::verify` in the Bloom mode.
+pub fn matching_ids<R: BufRead>(
+    reader: &mut Reader<R>,
+    list: &IdList,
+) -> Result<HashSet<Vec<u8>>, FastxErr> {
+    matching_ids_with(reader, list, &IdNormalizer::default())
+}
+
+/// Returns normalized IDs of records in a list built with the same normalizer,
+/// the candidates for `IdList::verify_with`.
+pub fn matching_ids_with<R: BufRead>(
+    reader: &mut Reader<R>,
+    list: &IdList,
+    norm: &IdNormalizer,
+) -> Result<HashSet<Vec<u8>>, FastxErr> {
+    let mut ids = HashSet::new();
+    while let Some(res) = reader.next() {
+        let seq = res?;
+        let id = norm.normalize(seq.id);
+        if list.contains(&id) {
+            ids.insert(id.into_owned());
+        }
+    }
+    Ok(ids)
+}
+
+/// Writes records whose IDs are (or, with `invert`, are not) in the list to the writer,
+/// and returns the number of written records.
+pub fn grep_ids<R: BufRead, W: Write>(
+    reader: &mut Reader<R>,
+    writer: &mut Writer<W>,
+    list: &IdList,
+    invert: bool,
+) -> Result<u64, FastxErr> {
+    grep_ids_with(reader, writer, list, invert, &IdNormalizer::default())
+}
+
+/// Writes records like `grep_ids`, matching normalized IDs against a list built with the same
+/// normalizer.
+pub fn grep_ids_with<R: BufRead, W: Write>(
+    reader: &mut Reader<R>,
+    writer: &mut Writer<W>,
+    list: &IdList,
+    invert: bool,
+    norm: &IdNormalizer,
+) -> Result<u64, FastxErr> {
+    let mut n = 0u64;
+    while let Some(res) = reader.next() {
+        let seq = res?;
+        if list.contains(&norm.normalize(seq.id)) != invert {
+            writer.write(&seq)?;
+            n += 1;
+        }
+    }
+    writer.flush()?;
+    Ok(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn a_seq(seq: &'_ [u8]) -> Seq<'_> {
+        Seq {
+            id: b"",
+            desc: b"",
+            seq,
+            qual: None,
+        }
+    }
+
+    #[test]
+    fn test_aho_corasick_overlapping() {
+        let ac = AhoCorasick::new(&["he", "she", "his", "hers"], false);
+        let matches: Vec<(usize, usize)> = ac
+            .find_all(b"ushers")
+            .iter()
+            .map(|m| (m.pattern, m.start))
+            .collect();
+        assert_eq!(matches, vec![(1, 1), (0, 2), (3, 2)]);
+        assert!(ac.is_match(b"this"));
+        assert!(!ac.is_match(b"hi"));
+    }
+
+    #[test]
+    fn test_aho_corasick_ignore_case() {
+        let ac = AhoCorasick::new(&["acgt", ""], true);
+        assert_eq!(
+            ac.find_all(b"TTACGTacgt"),
+            vec![
+                Match {
+                    pattern: 0,
+                    start: 2,
+                    end: 6
+                },
+                Match {
+                    pattern: 0,
+                    start: 6,
+                    end: 10
+                }
+            ]
+        );
+        assert!(!AhoCorasick::new(&["acgt"], false).is_match(b"ACGT"));
+    }
+
+    #[test]
+    fn test_seq_grep_both_strands() {
+        let grep = SeqGrep::new(&["AAAC", "ACGT"], true, false);
+        let hits = grep.find(&a_seq(b"GTTTACGT"));
+        assert_eq!(
+            hits,
+            vec![
+                SeqHit {
+                    query: 0,
+                    start: 0,
+                    end: 4,
+                    strand: Strand::Reverse
+                },
+                SeqHit {
+                    query: 1,
+                    start: 4,
+                    end: 8,
+                    strand: Strand::Forward
+                },
+            ]
+        );
+
+        let forward_only = SeqGrep::new(&["AAAC"], false, false);
+        assert!(!forward_only.is_match(&a_seq(b"GTTTACGT")));
+    }
+
+    #[test]
+    fn test_grep_records() {
+        let input = ">s1\nAAAAAA\n>s2\nCCGTTTCC\n>s3\nGAAACG\n";
+        let grep = SeqGrep::new(&["AAAC"], true, false);
+
+        let mut reader = Reader::from_reader(Cursor::new(input.as_bytes()));
+        let mut writer = Writer::from_writer(Vec::new());
+        assert_eq!(
+            grep_records(&mut reader, &mut writer, &grep, false).unwrap(),
+            2
+        );
+        assert_eq!(writer.into_inner(), b">s2\nCCGTTTCC\n>s3\nGAAACG\n");
+
+        let mut reader = Reader::from_reader(Cursor::new(input.as_bytes()));
+        let mut writer = Writer::from_writer(Vec::new());
+        assert_eq!(
+            grep_records(&mut reader, &mut writer, &grep, true).unwrap(),
+            1
+        );
+        assert_eq!(writer.into_inner(), b">s1\nAAAAAA\n");
+    }
+
+    #[test]
+    fn test_grep_ids() {
+        let list = "r1 host\n\nr3\n";
+        let input = ">r1\nA\n>r2\nC\n>r3\nG\n";
+
+        let exact = IdList::exact_from_reader(Cursor::new(list)).unwrap();
+        let mut reader = Reader::from_reader(Cursor::new(input.as_bytes()));
+        let mut writer = Writer::from_writer(Vec::new());
+        assert_eq!(grep_ids(&mut reader, &mut writer, &exact, true).unwrap(), 1);
+        assert_eq!(writer.into_inner(), b">r2\nC\n");
+
+        let bloom = IdList::bloom_from_reader(Cursor::new(list), 2, 0.001).unwrap();
+        let mut reader = Reader::from_reader(Cursor::new(input.as_bytes()));
+        let candidates = matching_ids(&mut reader, &bloom).unwrap();
+        assert!(candidates.contains(&b"r1"[..]) && candidates.contains(&b"r3"[..]));
+
+        // a false positive is removed by verification
+        let mut candidates = candidates;
+        candidates.insert(b"r2".to_vec());
+        let verified = IdList::verify(&candidates, Cursor::new(list)).unwrap();
+        let mut reader = Reader::from_reader(Cursor::new(input.as_bytes()));
+        let mut writer = Writer::from_writer(Vec::new());
+        assert_eq!(
+            grep_ids(&mut reader, &mut writer, &verified, false).unwrap(),
+            2
+        );
+        assert_eq!(writer.into_inner(), b">r1\nA\n>r3\nG\n");
+    }
+
+    #[test]
+    fn test_grep_ids_normalized() {
+        let list = "NM_000546.6\nlcl|contig1\n";
+        let input = ">gi|4507341|ref|NM_000546.5|\nA\n>CONTIG1\nC\n>contig2\nG\n";
+        let norm = IdNormalizer::all();
+
+        let exact = IdList::exact_from_reader_with(Cursor::new(list), &norm).unwrap();
+        let mut reader = Reader::from_reader(Cursor::new(input.as_bytes()));
+        let mut writer = Writer::from_writer(Vec::new());
+        assert_eq!(
+            grep_ids_with(&mut reader, &mut writer, &exact, false, &norm).unwrap(),
+            2
+        );
+        assert_eq!(
+            writer.into_inner(),
+            b">gi|4507341|ref|NM_000546.5|\nA\n>CONTIG1\nC\n"
+        );
+
+        let bloom = IdList::bloom_from_reader_with(Cursor::new(list), 2, 0.001, &norm).unwrap();
+        let mut reader = Reader::from_reader(Cursor::new(input.as_bytes()));
+        let candidates = matching_ids_with(&mut reader, &bloom, &norm).unwrap();
+        let verified = IdList::verify_with(&candidates, Cursor::new(list), &norm).unwrap();
+        assert!(verified.contains(b"nm_000546") && verified.contains(b"contig1"));
+        assert!(!verified.contains(b"contig2"));
+    }
+}
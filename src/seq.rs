@@ -123,6 +123,259 @@ impl<'a> Seq<'a> {
         }
         gc as f32 / self.seq.len() as f32
     }
+
+    /// Returns the ID shared by both mates of a read pair and the mate number (1 or 2) if known.
+    /// Mates are recognized by a "/1" or "/2" suffix of the ID,
+    /// or a description starting with "1:" or "2:" (Casava 1.8+).
+    pub fn mate_id(&self) -> (&'a [u8], Option<u8>) {
+        if let [base @ .., b'/', n @ (b'1' | b'2')] = self.id {
+            return (base, Some(n - b'0'));
+        }
+        match self.desc {
+            [n @ (b'1' | b'2'), b':', ..] => (self.id, Some(n - b'0')),
+            _ => (self.id, None),
+        }
+    }
+
+    /// Normalizes the sequence into the buffer in a single pass, and returns the normalized sequence.
+    /// The buffer is cleared first and can be reused across records.
+    /// Quality scores are not touched, so gap removal only makes sense for FASTA records.
+    pub fn normalized<'b>(&self, opts: &NormalizeOptions, buf: &'b mut Vec<u8>) -> &'b [u8] {
+        buf.clear();
+        buf.reserve(self.seq.len());
+        for &b in self.seq {
+            if opts.remove_gaps && matches!(b, b'-' | b'.') {
+                continue;
+            }
+            let mut c = if opts.uppercase {
+                b.to_ascii_uppercase()
+            } else {
+                b
+            };
+            if opts.rna_to_dna {
+                c = match c {
+                    b'U' => b'T',
+                    b'u' => b't',
+                    _ => c,
+                };
+            }
+            if opts.non_iupac_to_n && !is_iupac(c) {
+                c = if c.is_ascii_lowercase() { b'n' } else { b'N' };
+            }
+            buf.push(c);
+        }
+        buf
+    }
+
+    /// Copies the record into an owned one, which can be stored or sent to other threads.
+    pub fn to_owned(&self) -> SeqOwned {
+        SeqOwned::from(self)
+    }
+}
+
+/// An owned sequence record, see `Seq` for the fields.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct SeqOwned {
+    pub id: Vec<u8>,
+    pub desc: Vec<u8>,
+    pub seq: Vec<u8>,
+    pub qual: Option<Vec<u8>>,
+}
+
+impl SeqOwned {
+    /// Returns a borrowed view of the record, for methods of `Seq` and writers.
+    pub fn as_seq(&self) -> Seq<'_> {
+        Seq::from(self)
+    }
+
+    /// Replaces the content with a record, reusing the buffers.
+    pub fn set(&mut self, seq: &Seq) {
+        self.id.clear();
+        self.id.extend_from_slice(seq.id);
+        self.desc.clear();
+        self.desc.extend_from_slice(seq.desc);
+        self.seq.clear();
+        self.seq.extend_from_slice(seq.seq);
+        match (seq.qual, &mut self.qual) {
+            (Some(q), Some(buf)) => {
+                buf.clear();
+                buf.extend_from_slice(q);
+            }
+            (Some(q), None) => self.qual = Some(q.to_vec()),
+            (None, _) => self.qual = None,
+        }
+    }
+}
+
+impl From<&Seq<'_>> for SeqOwned {
+    fn from(seq: &Seq) -> Self {
+        Self {
+            id: seq.id.to_vec(),
+            desc: seq.desc.to_vec(),
+            seq: seq.seq.to_vec(),
+            qual: seq.qual.map(|q| q.to_vec()),
+        }
+    }
+}
+
+impl From<Seq<'_>> for SeqOwned {
+    fn from(seq: Seq) -> Self {
+        Self::from(&seq)
+    }
+}
+
+impl<'a> From<&'a SeqOwned> for Seq<'a> {
+    fn from(seq: &'a SeqOwned) -> Self {
+        Seq {
+            id: &seq.id,
+            desc: &seq.desc,
+            seq: &seq.seq,
+            qual: seq.qual.as_deref(),
+        }
+    }
+}
+
+/// Reusable buffers of a record for allocation-free reading loops, see `Reader::read_into`.
+///
+/// Unlike `SeqOwned`, the quality buffer is kept for FASTA records, so no memory is released
+/// between records.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecordBuf {
+    pub id: Vec<u8>,
+    pub desc: Vec<u8>,
+    pub seq: Vec<u8>,
+    /// Empty for FASTA records.
+    pub qual: Vec<u8>,
+    /// Whether the record has quality scores.
+    pub is_fastq: bool,
+}
+
+impl RecordBuf {
+    /// Creates empty buffers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the content with a record, reusing the buffers.
+    pub fn set(&mut self, seq: &Seq) {
+        self.id.clear();
+        self.id.extend_from_slice(seq.id);
+        self.desc.clear();
+        self.desc.extend_from_slice(seq.desc);
+        self.seq.clear();
+        self.seq.extend_from_slice(seq.seq);
+        self.qual.clear();
+        if let Some(q) = seq.qual {
+            self.qual.extend_from_slice(q);
+        }
+        self.is_fastq = seq.qual.is_some();
+    }
+
+    /// Returns a borrowed view of the record.
+    pub fn as_seq(&self) -> Seq<'_> {
+        Seq {
+            id: &self.id,
+            desc: &self.desc,
+            seq: &self.seq,
+            qual: self.is_fastq.then_some(&self.qual[..]),
+        }
+    }
+}
+
+/// Options of `Seq::normalized`. The default enables all steps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NormalizeOptions {
+    /// Converts bases to uppercase.
+    pub uppercase: bool,
+    /// Converts U to T.
+    pub rna_to_dna: bool,
+    /// Removes gaps ('-' and '.').
+    pub remove_gaps: bool,
+    /// Replaces characters other than IUPAC nucleotide codes and gaps with N.
+    pub non_iupac_to_n: bool,
+}
+
+impl Default for NormalizeOptions {
+    fn default() -> Self {
+        Self {
+            uppercase: true,
+            rna_to_dna: true,
+            remove_gaps: true,
+            non_iupac_to_n: true,
+        }
+    }
+}
+
+#[inline]
+fn is_iupac(b: u8) -> bool {
+    matches!(
+        b.to_ascii_uppercase(),
+        b'A' | b'C'
+            | b'G'
+            | b'T'
+            | b'U'
+            | b'R'
+            | b'Y'
+            | b'S'
+            | b'W'
+            | b'K'
+            | b'M'
+            | b'B'
+            | b'D'
+            | b'H'
+            | b'V'
+            | b'N'
+            | b'-'
+            | b'.'
+    )
+}
+
+/// Sequence alphabets, matched case-insensitively.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alphabet {
+    /// `ACGTN`.
+    Dna,
+    /// `ACGUN`.
+    Rna,
+    /// IUPAC nucleotide codes (`ACGTURYSWKMBDHVN`) and gaps (`-` and `.`).
+    Iupac,
+    /// IUPAC amino acid codes, including `BJOUXZ`, stops (`*`) and gaps (`-`).
+    Protein,
+}
+
+impl Alphabet {
+    /// Returns whether a character belongs to the alphabet.
+    #[inline]
+    pub fn allows(&self, b: u8) -> bool {
+        match self {
+            Alphabet::Dna => matches!(b.to_ascii_uppercase(), b'A' | b'C' | b'G' | b'T' | b'N'),
+            Alphabet::Rna => matches!(b.to_ascii_uppercase(), b'A' | b'C' | b'G' | b'U' | b'N'),
+            Alphabet::Iupac => is_iupac(b),
+            Alphabet::Protein => b.is_ascii_alphabetic() || b == b'*' || b == b'-',
+        }
+    }
+
+    /// Returns the first character not in the alphabet, and its position.
+    pub fn find_invalid(&self, seq: &[u8]) -> Option<(usize, u8)> {
+        seq.iter()
+            .position(|&b| !self.allows(b))
+            .map(|i| (i, seq[i]))
+    }
+}
+
+/// Returns the reverse complement of a sequence.
+pub fn revcomp(seq: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(seq.len());
+    for &base in seq.iter().rev() {
+        result.push(RC_TABLE[base as usize]);
+    }
+    result
+}
+
+/// Writes the reverse complement of a sequence into the buffer, which is cleared first.
+pub fn revcomp_into(seq: &[u8], out: &mut Vec<u8>) {
+    out.clear();
+    out.extend(seq.iter().rev().map(|&b| RC_TABLE[b as usize]));
 }
 
 /// A lookup table for reverse complementing DNA/RNA sequences,
@@ -242,4 +495,84 @@ mod tests {
         let seq = b"";
         assert_eq!(a_seq(seq).gc_content(), 0.0);
     }
+
+    #[test]
+    fn test_mate_id() {
+        let mut seq = a_seq(b"A");
+        seq.id = b"r1/2";
+        assert_eq!(seq.mate_id(), (&b"r1"[..], Some(2)));
+        seq.id = b"r1";
+        seq.desc = b"1:N:0:ACGT";
+        assert_eq!(seq.mate_id(), (&b"r1"[..], Some(1)));
+        seq.desc = b"x";
+        assert_eq!(seq.mate_id(), (&b"r1"[..], None));
+    }
+
+    #[test]
+    fn test_alphabet() {
+        assert_eq!(Alphabet::Dna.find_invalid(b"ACGTNacgtn"), None);
+        assert_eq!(Alphabet::Dna.find_invalid(b"ACGU"), Some((3, b'U')));
+        assert_eq!(Alphabet::Rna.find_invalid(b"ACGT"), Some((3, b'T')));
+        assert_eq!(Alphabet::Iupac.find_invalid(b"ACRY-.n"), None);
+        assert_eq!(Alphabet::Iupac.find_invalid(b"ACE"), Some((2, b'E')));
+        assert_eq!(Alphabet::Protein.find_invalid(b"MKVL*-"), None);
+        assert_eq!(Alphabet::Protein.find_invalid(b"MK1"), Some((2, b'1')));
+    }
+
+    #[test]
+    fn test_revcomp_into() {
+        let mut buf = b"stale".to_vec();
+        revcomp_into(b"ACGTn", &mut buf);
+        assert_eq!(buf, revcomp(b"ACGTn"));
+    }
+
+    #[test]
+    fn test_seq_owned() {
+        let seq = Seq {
+            id: b"r1",
+            desc: b"d",
+            seq: b"ACGT",
+            qual: Some(b"IIII"),
+        };
+        let owned = seq.to_owned();
+        assert_eq!(owned.id, b"r1");
+        assert_eq!(owned.qual.as_deref(), Some(&b"IIII"[..]));
+        assert_eq!(owned, SeqOwned::from(seq));
+
+        let handle = std::thread::spawn(move || owned.as_seq().gc_content());
+        assert_eq!(handle.join().unwrap(), 0.5);
+
+        let owned = SeqOwned {
+            id: b"r2".to_vec(),
+            seq: b"AC".to_vec(),
+            ..Default::default()
+        };
+        let seq = Seq::from(&owned);
+        assert_eq!((seq.id, seq.desc, seq.qual), (&b"r2"[..], &b""[..], None));
+    }
+
+    #[test]
+    fn test_normalized() {
+        let mut buf = Vec::new();
+        let seq = a_seq(b"acgu-U.ryX*n");
+        assert_eq!(
+            seq.normalized(&NormalizeOptions::default(), &mut buf),
+            b"ACGTTRYNNN"
+        );
+
+        let opts = NormalizeOptions {
+            uppercase: false,
+            remove_gaps: false,
+            ..Default::default()
+        };
+        assert_eq!(seq.normalized(&opts, &mut buf), b"acgt-T.ryNNn");
+
+        let opts = NormalizeOptions {
+            uppercase: false,
+            rna_to_dna: false,
+            remove_gaps: false,
+            non_iupac_to_n: false,
+        };
+        assert_eq!(seq.normalized(&opts, &mut buf), seq.seq);
+    }
 }
@@ -0,0 +1,684 @@
+use crate::errors::*;
+use crate::kmer::*;
+use crate::reader::*;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, Read, Write};
+
+const CMS_MAGIC: &[u8; 4] = b"FSCM";
+const CBF_MAGIC: &[u8; 4] = b"FSCB";
+const FMH_MAGIC: &[u8; 4] = b"FSMH";
+
+/// A count-min sketch for approximate counting of u64 keys (e.g. k-mer codes) in bounded memory.
+///
+/// Estimates never undercount, and overcount by at most `e * total / width` with probability
+/// `1 - exp(-depth)`. Counters saturate at u32::MAX.
+pub struct CountMinSketch {
+    width: usize,
+    depth: usize,
+    table: Vec<u32>,
+}
+
+impl CountMinSketch {
+    /// Creates a sketch with `depth` rows of `width` counters.
+    pub fn new(width: usize, depth: usize) -> Self {
+        let width = width.max(1);
+        let depth = depth.max(1);
+        Self {
+            width,
+            depth,
+            table: vec![0; width * depth],
+        }
+    }
+
+    /// Returns the number of counters per row.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the number of rows.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Increments the count of a key.
+    #[inline]
+    pub fn add(&mut self, key: u64) {
+        for row in 0..self.depth {
+            let i = row * self.width + slot(row, key, self.width);
+            self.table[i] = self.table[i].saturating_add(1);
+        }
+    }
+
+    /// Returns the estimated count of a key.
+    #[inline]
+    pub fn estimate(&self, key: u64) -> u32 {
+        let mut min = u32::MAX;
+        for row in 0..self.depth {
+            min = min.min(self.table[row * self.width + slot(row, key, self.width)]);
+        }
+        min
+    }
+
+    /// Serializes the sketch.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(CMS_MAGIC)?;
+        w.write_all(&(self.width as u64).to_le_bytes())?;
+        w.write_all(&(self.depth as u64).to_le_bytes())?;
+        for c in &self.table {
+            w.write_all(&c.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Deserializes a sketch written by `write_to`.
+    pub fn read_from<R: Read>(r: &mut R) -> Result<Self, FastxErr> {
+        let (width, depth) = read_header(r, CMS_MAGIC)?;
+        let n = width
+            .checked_mul(depth)
+            .and_then(|n| n.checked_mul(4))
+            .ok_or(FastxErr::InvalidSketchFormat("dimensions too large"))?;
+        let data = read_bytes(r, n)?;
+        Ok(Self {
+            width,
+            depth,
+            table: data
+                .chunks_exact(4)
+                .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+                .collect(),
+        })
+    }
+}
+
+/// A counting Bloom filter with 8-bit saturating counters, supporting removal.
+pub struct CountingBloomFilter {
+    size: usize,
+    num_hashes: usize,
+    counters: Vec<u8>,
+}
+
+impl CountingBloomFilter {
+    /// Creates a filter with `size` counters and `num_hashes` hash functions.
+    pub fn new(size: usize, num_hashes: usize) -> Self {
+        let size = size.max(1);
+        Self {
+            size,
+            num_hashes: num_hashes.max(1),
+            counters: vec![0; size],
+        }
+    }
+
+    /// Adds a key.
+    #[inline]
+    pub fn add(&mut self, key: u64) {
+        for row in 0..self.num_hashes {
+            let i = slot(row, key, self.size);
+            self.counters[i] = self.counters[i].saturating_add(1);
+        }
+    }
+
+    /// Removes a key added before. Saturated counters are never decremented.
+    #[inline]
+    pub fn remove(&mut self, key: u64) {
+        if !self.contains(key) {
+            return;
+        }
+        for row in 0..self.num_hashes {
+            let i = slot(row, key, self.size);
+            if self.counters[i] < u8::MAX {
+                self.counters[i] -= 1;
+            }
+        }
+    }
+
+    /// Returns the estimated count of a key, capped at 255.
+    #[inline]
+    pub fn count(&self, key: u64) -> u8 {
+        let mut min = u8::MAX;
+        for row in 0..self.num_hashes {
+            min = min.min(self.counters[slot(row, key, self.size)]);
+        }
+        min
+    }
+
+    /// Returns whether a key may have been added.
+    #[inline]
+    pub fn contains(&self, key: u64) -> bool {
+        self.count(key) > 0
+    }
+
+    /// Serializes the filter.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(CBF_MAGIC)?;
+        w.write_all(&(self.size as u64).to_le_bytes())?;
+        w.write_all(&(self.num_hashes as u64).to_le_bytes())?;
+        w.write_all(&self.counters)
+    }
+
+    /// Deserializes a filter written by `write_to`.
+    pub fn read_from<R: Read>(r: &mut R) -> Result<Self, FastxErr> {
+        let (size, num_hashes) = read_header(r, CBF_MAGIC)?;
+        Ok(Self {
+            size,
+            num_hashes,
+            counters: read_bytes(r, size)?,
+        })
+    }
+}
+
+/// A streaming k-mer counter backed by a count-min sketch,
+/// which also tracks the most frequent k-mers (heavy hitters).
+pub struct KmerCounter {
+    k: usize,
+    canonical: bool,
+    sketch: CountMinSketch,
+    top_capacity: usize,
+    top: HashMap<u64, u32>,
+    top_min: u32, // a lower bound of the minimum count in `top` when it is full
+    total: u64,
+}
+
+impl KmerCounter {
+    /// Creates a counter for k-mers of size k (1..=32), tracking the `top_k` most frequent k-mers.
+    pub fn new(k: usize, canonical: bool, sketch: CountMinSketch, top_k: usize) -> Self {
+        assert!(
+            (1..=MAX_K).contains(&k),
+            "k should be in the range of 1..=32"
+        );
+        Self {
+            k,
+            canonical,
+            sketch,
+            top_capacity: top_k,
+            top: HashMap::with_capacity(top_k + 1),
+            top_min: 0,
+            total: 0,
+        }
+    }
+
+    /// Returns the k-mer size.
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// Returns the total number of counted k-mers.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Returns the underlying sketch.
+    pub fn sketch(&self) -> &CountMinSketch {
+        &self.sketch
+    }
+
+    /// Counts the k-mers of a sequence.
+    pub fn add_seq(&mut self, seq: &[u8]) {
+        for (_, code) in KmerIter::new(seq, self.k, self.canonical) {
+            self.add_kmer(code);
+        }
+    }
+
+    #[inline]
+    fn add_kmer(&mut self, code: u64) {
+        self.total += 1;
+        self.sketch.add(code);
+        if self.top_capacity == 0 {
+            return;
+        }
+        let est = self.sketch.estimate(code);
+        if let Some(c) = self.top.get_mut(&code) {
+            *c = est;
+            return;
+        }
+        if self.top.len() < self.top_capacity {
+            self.top.insert(code, est);
+            return;
+        }
+        if est <= self.top_min {
+            return;
+        }
+        // evict the current minimum
+        let (&min_key, &min_count) = self.top.iter().min_by_key(|&(k, c)| (*c, *k)).unwrap();
+        if est > min_count {
+            self.top.remove(&min_key);
+            self.top.insert(code, est);
+        }
+        self.top_min = self.top.values().copied().min().unwrap_or(0);
+    }
+
+    /// Counts the k-mers of all records of the reader, and returns the number of records.
+    pub fn add_reader<R: BufRead>(&mut self, reader: &mut Reader<R>) -> Result<u64, FastxErr> {
+        let mut n = 0u64;
+        while let Some(res) = reader.next() {
+            self.add_seq(res?.seq);
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    /// Returns the estimated count of a k-mer, 0 for invalid k-mers.
+    pub fn estimate(&self, kmer: &[u8]) -> u32 {
+        if kmer.len() != self.k {
+            return 0;
+        }
+        match encode_kmer(kmer) {
+            Some(code) => {
+                let code = if self.canonical {
+                    code.min(revcomp_kmer(code, self.k))
+                } else {
+                    code
+                };
+                self.sketch.estimate(code)
+            }
+            None => 0,
+        }
+    }
+
+    /// Returns the heavy hitters as (k-mer, estimated count), in descending order of counts.
+    pub fn top_k(&self) -> Vec<(Vec<u8>, u32)> {
+        let mut top: Vec<(u64, u32)> = self.top.iter().map(|(&k, &c)| (k, c)).collect();
+        top.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        top.into_iter()
+            .map(|(code, c)| (decode_kmer(code, self.k), c))
+            .collect()
+    }
+
+    /// Writes the heavy hitters as tab-delimited lines of k-mer and count.
+    pub fn write_top_k<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        for (kmer, count) in self.top_k() {
+            w.write_all(&kmer)?;
+            writeln!(w, "\t{count}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A FracMinHash sketch, keeping hashes of canonical k-mers below `u64::MAX / scaled`.
+///
+/// About one in `scaled` distinct k-mers is kept, and since all sketches keep the same hashes,
+/// containment between sequence sets is estimated directly from their sketches.
+pub struct ScaledSketch {
+    name: Vec<u8>,
+    k: usize,
+    scaled: u64,
+    max_hash: u64,
+    hashes: HashSet<u64>,
+}
+
+impl ScaledSketch {
+    /// Creates an empty sketch with k-mer size k (1..=32) and a scaled factor.
+    pub fn new(name: &[u8], k: usize, scaled: u64) -> Self {
+        assert!(
+            (1..=MAX_K).contains(&k),
+            "k should be in the range of 1..=32"
+        );
+        let scaled = scaled.max(1);
+        Self {
+            name: name.to_vec(),
+            k,
+            scaled,
+            max_hash: u64::MAX / scaled,
+            hashes: HashSet::new(),
+        }
+    }
+
+    /// Returns the name.
+    pub fn name(&self) -> &[u8] {
+        &self.name
+    }
+
+    /// Returns the k-mer size.
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// Returns the scaled factor.
+    pub fn scaled(&self) -> u64 {
+        self.scaled
+    }
+
+    /// Returns the number of hashes.
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    /// Returns whether the sketch has no hashes.
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    /// Adds k-mers of a sequence.
+    pub fn add_seq(&mut self, seq: &[u8]) {
+        for (_, code) in KmerIter::new(seq, self.k, true) {
+            let h = mix64(code);
+            if h <= self.max_hash {
+                self.hashes.insert(h);
+            }
+        }
+    }
+
+    /// Adds k-mers of all records of the reader, and returns the number of records.
+    pub fn add_reader<R: BufRead>(&mut self, reader: &mut Reader<R>) -> Result<u64, FastxErr> {
+        let mut n = 0;
+        while let Some(res) = reader.next() {
+            self.add_seq(res?.seq);
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    /// Returns the number of hashes shared with another sketch.
+    pub fn shared(&self, other: &ScaledSketch) -> usize {
+        let (small, large) = if self.len() <= other.len() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        small
+            .hashes
+            .iter()
+            .filter(|h| large.hashes.contains(h))
+            .count()
+    }
+
+    /// Returns the fraction of this sketch's hashes found in another sketch,
+    /// i.e., the estimated containment of this sequence set in the other one.
+    pub fn containment_in(&self, other: &ScaledSketch) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        self.shared(other) as f64 / self.len() as f64
+    }
+
+    /// Serializes the sketch, with hashes sorted.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(FMH_MAGIC)?;
+        w.write_all(&(self.k as u64).to_le_bytes())?;
+        w.write_all(&self.scaled.to_le_bytes())?;
+        w.write_all(&(self.name.len() as u64).to_le_bytes())?;
+        w.write_all(&self.name)?;
+        let mut hashes: Vec<u64> = self.hashes.iter().copied().collect();
+        hashes.sort_unstable();
+        w.write_all(&(hashes.len() as u64).to_le_bytes())?;
+        for h in hashes {
+            w.write_all(&h.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Deserializes a sketch written by `write_to`.
+    pub fn read_from<R: Read>(r: &mut R) -> Result<Self, FastxErr> {
+        let (k, scaled) = read_header(r, FMH_MAGIC)?;
+        if k > MAX_K {
+            return Err(FastxErr::InvalidSketchFormat("k-mer size out of range"));
+        }
+        let mut b = [0u8; 8];
+        r.read_exact(&mut b).map_err(truncated)?;
+        let name = read_bytes(r, u64::from_le_bytes(b) as usize)?;
+        let mut sketch = Self::new(&name, k, scaled as u64);
+        r.read_exact(&mut b).map_err(truncated)?;
+        let n = u64::from_le_bytes(b) as usize;
+        sketch.hashes.reserve(n.min(MAX_PREALLOC));
+        for _ in 0..n {
+            r.read_exact(&mut b).map_err(truncated)?;
+            sketch.hashes.insert(u64::from_le_bytes(b));
+        }
+        Ok(sketch)
+    }
+}
+
+/// Containment of a reference in a read set.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Containment {
+    /// Index of the reference sketch.
+    pub reference: usize,
+    /// Number of reference hashes found in the reads.
+    pub shared: usize,
+    /// Number of hashes of the reference.
+    pub reference_hashes: usize,
+    /// Fraction of the reference's k-mers present in the reads.
+    pub containment: f64,
+}
+
+/// Screens a read set against a panel of reference sketches (species, vectors, hosts, ...),
+/// and returns the containment of every reference in the reads, in descending order.
+/// All sketches must have the same k-mer size and scaled factor.
+pub fn screen(
+    reads: &ScaledSketch,
+    references: &[ScaledSketch],
+) -> Result<Vec<Containment>, FastxErr> {
+    if references
+        .iter()
+        .any(|r| r.k != reads.k || r.scaled != reads.scaled)
+    {
+        return Err(FastxErr::InvalidSketchFormat(
+            "k-mer sizes or scaled factors differ",
+        ));
+    }
+    let mut results: Vec<Containment> = references
+        .iter()
+        .enumerate()
+        .map(|(i, r)| {
+            let shared = r.shared(reads);
+            Containment {
+                reference: i,
+                shared,
+                reference_hashes: r.len(),
+                containment: if r.is_empty() {
+                    0.0
+                } else {
+                    shared as f64 / r.len() as f64
+                },
+            }
+        })
+        .collect();
+    results.sort_by(|a, b| b.containment.total_cmp(&a.containment));
+    Ok(results)
+}
+
+#[inline]
+fn slot(row: usize, key: u64, size: usize) -> usize {
+    (mix64(key ^ ROW_SEEDS[row % ROW_SEEDS.len()].wrapping_mul(row as u64 + 1)) % size as u64)
+        as usize
+}
+
+fn read_header<R: Read>(r: &mut R, magic: &[u8; 4]) -> Result<(usize, usize), FastxErr> {
+    let mut b = [0u8; 20];
+    r.read_exact(&mut b).map_err(truncated)?;
+    if &b[..4] != magic {
+        return Err(FastxErr::InvalidSketchFormat("bad magic bytes"));
+    }
+    let a = u64::from_le_bytes(b[4..12].try_into().unwrap()) as usize;
+    let c = u64::from_le_bytes(b[12..20].try_into().unwrap()) as usize;
+    if a == 0 || c == 0 {
+        return Err(FastxErr::InvalidSketchFormat("zero dimension"));
+    }
+    Ok((a, c))
+}
+
+// the most hashes reserved ahead of reading them, counts from the input are not trusted
+const MAX_PREALLOC: usize = 1 << 16;
+
+// reads n bytes, growing the buffer as data arrives, so a corrupt size fails as truncated data
+// instead of allocating it at once
+fn read_bytes<R: Read>(r: &mut R, n: usize) -> Result<Vec<u8>, FastxErr> {
+    let mut buf = Vec::new();
+    r.take(n as u64).read_to_end(&mut buf)?;
+    if buf.len() < n {
+        return Err(FastxErr::InvalidSketchFormat("truncated data"));
+    }
+    Ok(buf)
+}
+
+fn truncated(e: io::Error) -> FastxErr {
+    match e.kind() {
+        io::ErrorKind::UnexpectedEof => FastxErr::InvalidSketchFormat("truncated data"),
+        _ => FastxErr::IOError(e),
+    }
+}
+
+const ROW_SEEDS: [u64; 8] = [
+    0x9E37_79B9_7F4A_7C15,
+    0xC2B2_AE3D_27D4_EB4F,
+    0x1656_67B1_9E37_79F9,
+    0x85EB_CA77_C2B2_AE63,
+    0x27D4_EB2F_1656_67C5,
+    0x94D0_49BB_1331_11EB,
+    0xBF58_476D_1CE4_E5B9,
+    0xFF51_AFD7_ED55_8CCD,
+];
+
+// the finalizer of splitmix64, a cheap and well-mixing hash of u64
+#[inline]
+pub(crate) fn mix64(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_count_min_sketch() {
+        let mut cms = CountMinSketch::new(1 << 12, 4);
+        for key in 0..1000u64 {
+            for _ in 0..(key % 5) {
+                cms.add(key);
+            }
+        }
+        let mut exact = 0;
+        for key in 0..1000u64 {
+            let est = cms.estimate(key);
+            assert!(est >= (key % 5) as u32);
+            exact += (est == (key % 5) as u32) as usize;
+        }
+        assert!(exact > 950);
+        assert_eq!(cms.estimate(123_456_789), 0);
+    }
+
+    #[test]
+    fn test_sketch_serialization() {
+        let mut cms = CountMinSketch::new(100, 3);
+        cms.add(7);
+        cms.add(7);
+        let mut buf = Vec::new();
+        cms.write_to(&mut buf).unwrap();
+        let cms2 = CountMinSketch::read_from(&mut Cursor::new(&buf)).unwrap();
+        assert_eq!((cms2.width(), cms2.depth()), (100, 3));
+        assert_eq!(cms2.estimate(7), 2);
+
+        buf.truncate(buf.len() - 1);
+        assert!(matches!(
+            CountMinSketch::read_from(&mut Cursor::new(&buf)),
+            Err(FastxErr::InvalidSketchFormat(_))
+        ));
+
+        let mut cbf = CountingBloomFilter::new(1000, 3);
+        cbf.add(42);
+        let mut buf = Vec::new();
+        cbf.write_to(&mut buf).unwrap();
+        assert!(matches!(
+            CountMinSketch::read_from(&mut Cursor::new(&buf)),
+            Err(FastxErr::InvalidSketchFormat(_))
+        ));
+        let cbf2 = CountingBloomFilter::read_from(&mut Cursor::new(&buf)).unwrap();
+        assert!(cbf2.contains(42));
+
+        // corrupt dimensions fail without allocating them
+        let mut buf = Vec::new();
+        CountMinSketch::new(2, 2).write_to(&mut buf).unwrap();
+        for (width, depth) in [(u64::MAX, 2u64), (1 << 40, 1 << 20)] {
+            buf[4..12].copy_from_slice(&width.to_le_bytes());
+            buf[12..20].copy_from_slice(&depth.to_le_bytes());
+            assert!(matches!(
+                CountMinSketch::read_from(&mut Cursor::new(&buf)),
+                Err(FastxErr::InvalidSketchFormat(_))
+            ));
+        }
+        let mut buf = Vec::new();
+        CountingBloomFilter::new(8, 1).write_to(&mut buf).unwrap();
+        buf[4..12].copy_from_slice(&(1u64 << 62).to_le_bytes());
+        assert!(matches!(
+            CountingBloomFilter::read_from(&mut Cursor::new(&buf)),
+            Err(FastxErr::InvalidSketchFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_counting_bloom_filter() {
+        let mut cbf = CountingBloomFilter::new(1 << 12, 3);
+        cbf.add(1);
+        cbf.add(1);
+        cbf.add(2);
+        assert_eq!(cbf.count(1), 2);
+        assert!(cbf.contains(2));
+        cbf.remove(2);
+        assert!(!cbf.contains(2));
+        cbf.remove(3); // never added
+        assert_eq!(cbf.count(1), 2);
+    }
+
+    #[test]
+    fn test_kmer_counter_top_k() {
+        let input = ">a\nAAAAAAAA\n>b\nACGTACGT\n>c\nAAAAAC\n";
+        let mut counter = KmerCounter::new(4, true, CountMinSketch::new(1 << 10, 4), 2);
+        let n = counter
+            .add_reader(&mut Reader::from_reader(Cursor::new(input.as_bytes())))
+            .unwrap();
+        assert_eq!(n, 3);
+        assert_eq!(counter.total(), 5 + 5 + 3);
+        assert_eq!(counter.estimate(b"TTTT"), 7);
+        assert_eq!(
+            counter.top_k(),
+            vec![(b"AAAA".to_vec(), 7), (b"CGTA".to_vec(), 2)]
+        );
+
+        let mut out = Vec::new();
+        counter.write_top_k(&mut out).unwrap();
+        assert_eq!(out, b"AAAA\t7\nCGTA\t2\n");
+    }
+
+    #[test]
+    fn test_scaled_sketch_screen() {
+        let mut rng = crate::util::Rng::new(7);
+        let genome = |rng: &mut crate::util::Rng| -> Vec<u8> {
+            (0..20_000).map(|_| b"ACGT"[rng.below(4)]).collect()
+        };
+        let (g1, g2, g3) = (genome(&mut rng), genome(&mut rng), genome(&mut rng));
+
+        let mut refs = Vec::new();
+        for (name, g) in [(&b"g1"[..], &g1), (b"g2", &g2), (b"g3", &g3)] {
+            let mut s = ScaledSketch::new(name, 21, 10);
+            s.add_seq(g);
+            refs.push(s);
+        }
+        assert!(refs[0].len() > 1000 && refs[0].len() < 3000);
+
+        // reads covering all of g1 and the first half of g2, on both strands
+        let mut reads = ScaledSketch::new(b"reads", 21, 10);
+        for start in (0..g1.len()).step_by(100) {
+            reads.add_seq(&crate::seq::revcomp(
+                &g1[start..(start + 150).min(g1.len())],
+            ));
+        }
+        reads.add_seq(&g2[..10_000]);
+
+        let results = screen(&reads, &refs).unwrap();
+        assert_eq!(results[0].reference, 0);
+        assert!(results[0].containment > 0.95);
+        assert_eq!(results[1].reference, 1);
+        assert!((results[1].containment - 0.5).abs() < 0.1);
+        assert_eq!(results[2].containment, 0.0);
+
+        let mut buf = Vec::new();
+        refs[1].write_to(&mut buf).unwrap();
+        let s = ScaledSketch::read_from(&mut Cursor::new(buf)).unwrap();
+        assert_eq!(s.name(), b"g2");
+        assert_eq!(s.shared(&refs[1]), refs[1].len());
+        assert_eq!(s.containment_in(&refs[1]), 1.0);
+
+        assert!(screen(&ScaledSketch::new(b"x", 15, 10), &refs).is_err());
+    }
+}
@@ -0,0 +1,207 @@
+use crate::errors::*;
+use crate::kmercount::*;
+use crate::reader::*;
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+use std::path::Path;
+
+/// Default highest multiplicity of a spectrum, higher ones are added to the last bin,
+/// the same as `jellyfish histo`.
+pub const DEFAULT_MAX_COUNT: usize = 10_000;
+
+/// A k-mer spectrum: the histogram of multiplicities of distinct k-mers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KmerSpectrum {
+    k: usize,
+    hist: Vec<u64>, // hist[i]: number of distinct k-mers seen i times, the last bin for >= max
+}
+
+/// Estimates from a k-mer spectrum with GenomeScope-like heuristics for haploid genomes:
+/// k-mers below the first valley are erroneous, and the highest bin above it is the k-mer coverage.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpectrumEstimate {
+    /// Multiplicity of the valley between erroneous and genomic k-mers.
+    pub valley: usize,
+    /// Multiplicity of the coverage peak.
+    pub peak: usize,
+    /// K-mer coverage: the mean multiplicity of bins within half of the peak around it,
+    /// less noisy than the peak.
+    pub coverage: f64,
+    /// Total multiplicity of k-mers below the valley.
+    pub error_kmers: u64,
+    /// Total multiplicity of k-mers at and above the valley.
+    pub solid_kmers: u64,
+    /// Estimated genome size: solid k-mers divided by the coverage.
+    pub genome_size: u64,
+    /// Estimated per-base error rate, from the fraction of erroneous k-mers.
+    pub error_rate: f64,
+}
+
+impl KmerSpectrum {
+    /// Creates an empty spectrum of k-mers of size k, with bins of multiplicities 1..=max_count.
+    pub fn new(k: usize, max_count: usize) -> Self {
+        Self {
+            k,
+            hist: vec![0; max_count.max(2) + 1],
+        }
+    }
+
+    /// Builds the spectrum of all k-mers of a counter.
+    pub fn from_counter(
+        counter: &mut ExactKmerCounter,
+        max_count: usize,
+    ) -> Result<Self, FastxErr> {
+        let mut spectrum = Self::new(counter.k(), max_count);
+        counter.for_each(1, |_, c| {
+            spectrum.add(c as u64, 1);
+            Ok(())
+        })?;
+        Ok(spectrum)
+    }
+
+    /// Adds `n` distinct k-mers of the multiplicity.
+    pub fn add(&mut self, multiplicity: u64, n: u64) {
+        let max = self.hist.len() - 1;
+        self.hist[(multiplicity as usize).min(max)] += n;
+    }
+
+    /// Returns the k-mer size.
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// Returns the histogram, indexed by multiplicity. Bin 0 is always empty,
+    /// and the last bin holds all higher multiplicities.
+    pub fn histogram(&self) -> &[u64] {
+        &self.hist
+    }
+
+    /// Returns the number of distinct k-mers.
+    pub fn distinct(&self) -> u64 {
+        self.hist.iter().sum()
+    }
+
+    /// Returns the total number of k-mers.
+    pub fn total(&self) -> u64 {
+        self.hist
+            .iter()
+            .enumerate()
+            .map(|(i, &n)| i as u64 * n)
+            .sum()
+    }
+
+    /// Estimates the genome size and the error rate, None if the spectrum has no valley
+    /// followed by a peak, e.g., at low coverage.
+    pub fn estimate(&self) -> Option<SpectrumEstimate> {
+        // the last bin is an overflow bin, ignored as a peak
+        let last = self.hist.len() - 1;
+        let valley = (1..last).find(|&i| self.hist[i] < self.hist[i + 1])?;
+        let peak = (valley..last).max_by_key(|&i| (self.hist[i], std::cmp::Reverse(i)))?;
+        if peak == valley {
+            return None;
+        }
+
+        let sum = |range: Range<usize>| range.map(|i| i as u64 * self.hist[i]).sum::<u64>();
+        let window = (peak - peak / 2).max(valley)..(peak + peak / 2 + 1).min(last);
+        let coverage = sum(window.clone()) as f64 / self.hist[window].iter().sum::<u64>() as f64;
+        let error_kmers = sum(1..valley);
+        let solid_kmers = sum(valley..last + 1);
+        let error_fraction = error_kmers as f64 / (error_kmers + solid_kmers) as f64;
+        Some(SpectrumEstimate {
+            valley,
+            peak,
+            coverage,
+            error_kmers,
+            solid_kmers,
+            genome_size: (solid_kmers as f64 / coverage).round() as u64,
+            // a base error breaks k k-mers: 1 - e_kmer = (1 - e_base)^k
+            error_rate: 1.0 - (1.0 - error_fraction).powf(1.0 / self.k as f64),
+        })
+    }
+
+    /// Writes non-empty bins as "MULTIPLICITY COUNT" lines, the same as the output of
+    /// `jellyfish histo`, which can be plotted or fed to GenomeScope.
+    pub fn write_table<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        for (i, &n) in self.hist.iter().enumerate().skip(1) {
+            if n > 0 {
+                writeln!(w, "{i} {n}")?;
+            }
+        }
+        w.flush()
+    }
+}
+
+/// Counts canonical k-mers of all records of the reader and returns their spectrum.
+/// For k > 15, k-mers are spilled to a temporary directory in `tmp_dir`, see `ExactKmerCounter`.
+pub fn kmer_spectrum<R: BufRead>(
+    reader: &mut Reader<R>,
+    k: usize,
+    max_count: usize,
+    tmp_dir: &Path,
+) -> Result<KmerSpectrum, FastxErr> {
+    let mut counter = ExactKmerCounter::new(k, true, tmp_dir, 64, 1 << 24)?;
+    counter.add_reader(reader)?;
+    KmerSpectrum::from_counter(&mut counter, max_count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::Rng;
+    use std::fmt::Write as _;
+
+    #[test]
+    fn test_kmer_spectrum() {
+        let mut spectrum = KmerSpectrum::new(21, 5);
+        spectrum.add(1, 10);
+        spectrum.add(3, 2);
+        spectrum.add(7, 1);
+        spectrum.add(100, 1);
+        assert_eq!(spectrum.histogram(), &[0, 10, 0, 2, 0, 2]);
+        assert_eq!(spectrum.distinct(), 14);
+        assert_eq!(spectrum.total(), 26);
+
+        let mut out = Vec::new();
+        spectrum.write_table(&mut out).unwrap();
+        assert_eq!(out, b"1 10\n3 2\n5 2\n");
+
+        // monotonically decreasing: no coverage peak
+        let mut spectrum = KmerSpectrum::new(21, 100);
+        spectrum.add(1, 100);
+        spectrum.add(2, 10);
+        assert_eq!(spectrum.estimate(), None);
+    }
+
+    #[test]
+    fn test_kmer_spectrum_estimate() {
+        // reads of 100 bp at 30x of a random 20 kb genome, with 1% substitutions
+        let mut rng = Rng::new(3);
+        let genome: Vec<u8> = (0..20_000).map(|_| b"ACGT"[rng.below(4)]).collect();
+        let mut input = String::new();
+        for i in 0..6000 {
+            let start = rng.below(genome.len() - 100);
+            let mut read = genome[start..start + 100].to_vec();
+            for b in read.iter_mut() {
+                if rng.next_f64() < 0.01 {
+                    *b = b"ACGT"[(b"ACGT".iter().position(|x| x == b).unwrap() + 1) % 4];
+                }
+            }
+            write!(input, ">r{i}\n{}\n", String::from_utf8_lossy(&read)).unwrap();
+        }
+
+        let mut reader = Reader::from_bytes(input.as_bytes());
+        let spectrum =
+            kmer_spectrum(&mut reader, 17, DEFAULT_MAX_COUNT, &std::env::temp_dir()).unwrap();
+        assert_eq!(spectrum.total(), 6000 * 84);
+        let est = spectrum.estimate().unwrap();
+        assert!(est.valley > 2 && est.valley < 10, "{est:?}");
+        assert!(est.peak > 15 && est.peak < 27, "{est:?}");
+        // 25.2 x (1 - 0.01)^17
+        assert!(est.coverage > 19.0 && est.coverage < 23.0, "{est:?}");
+        assert!(
+            est.genome_size > 18_000 && est.genome_size < 22_000,
+            "{est:?}"
+        );
+        assert!(est.error_rate > 0.008 && est.error_rate < 0.012, "{est:?}");
+    }
+}
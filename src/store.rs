@@ -0,0 +1,651 @@
+use crate::binary::*;
+use crate::errors::*;
+use crate::reader::*;
+use crate::seq::*;
+use crate::util::*;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const STORE_MAGIC: &[u8; 4] = b"FSRS";
+const INDEX_MAGIC: &[u8; 4] = b"FSI2"; // FSRI indexes without lengths are rebuilt
+const STORE_VERSION: u8 = 1;
+const STORE_HEADER_LEN: u64 = 5;
+
+// block codecs
+const CODEC_RAW: u8 = 0;
+const CODEC_LZ4: u8 = 1;
+
+/// Default size of uncompressed blocks.
+pub const DEFAULT_BLOCK_SIZE: usize = 1 << 20;
+
+// location of a record: offset of its block in the data file, and offset in the decoded block,
+// with the sequence length for weighted sampling
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Loc {
+    block: u64,
+    offset: u32,
+    len: u64,
+}
+
+// block offset of records still in the pending block
+const PENDING: u64 = u64::MAX;
+
+// length of an index entry without the ID
+const INDEX_ENTRY_LEN: u64 = 4 + 8 + 4 + 8;
+
+/// How records are weighted in `RecordStore::sample`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleWeight {
+    /// Every record is equally likely to be drawn.
+    Uniform,
+    /// Records are drawn with probability proportional to their sequence lengths.
+    Length,
+}
+
+/// A disk-backed record store with random access by ID.
+///
+/// Records are appended to blocks in the binary record format, and each block is compressed
+/// (with LZ4 if the `lz4` feature is enabled) and appended to the data file.
+/// An index of ID to block offset and sequence length is kept in memory and saved to `<path>.idx`
+/// on `flush`, so only IDs, not the records, are kept in RAM. If the index is missing or behind the data file,
+/// e.g., after a crash, it is rebuilt from the data file on `open`.
+/// For duplicated IDs, `get` returns the last appended record.
+pub struct RecordStore {
+    path: PathBuf,
+    file: File,
+    data_len: u64, // length of the data file
+    index: HashMap<Vec<u8>, Loc>,
+    block_size: usize,
+    pending: BinaryWriter<Vec<u8>>, // the block being filled
+    pending_ids: Vec<Vec<u8>>,      // IDs of records in the pending block
+    pending_dirty: bool,
+    index_dirty: bool,
+    cache: (u64, Vec<u8>), // offset and data of the last decoded block
+}
+
+fn index_path(path: &Path) -> PathBuf {
+    let mut p = path.as_os_str().to_owned();
+    p.push(".idx");
+    PathBuf::from(p)
+}
+
+fn invalid(msg: &'static str) -> FastxErr {
+    FastxErr::InvalidRecordStore(msg)
+}
+
+fn truncated(e: io::Error) -> FastxErr {
+    match e.kind() {
+        io::ErrorKind::UnexpectedEof => invalid("truncated data"),
+        _ => FastxErr::IOError(e),
+    }
+}
+
+// a block starts with the binary stream header, so a decoded block is a valid binary record stream
+fn new_block() -> BinaryWriter<Vec<u8>> {
+    BinaryWriter::from_writer(Vec::with_capacity(DEFAULT_BLOCK_SIZE), false)
+        .expect("writing to a Vec never fails")
+}
+
+impl RecordStore {
+    /// Creates a new store at the path, truncating existing files.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, FastxErr> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)?;
+        file.write_all(STORE_MAGIC)?;
+        file.write_all(&[STORE_VERSION])?;
+        let _ = std::fs::remove_file(index_path(&path));
+        let mut store = Self::with_file(path, file, STORE_HEADER_LEN);
+        store.index_dirty = true;
+        Ok(store)
+    }
+
+    /// Opens an existing store for reading and appending.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, FastxErr> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new().read(true).write(true).open(&path)?;
+        let mut header = [0u8; STORE_HEADER_LEN as usize];
+        file.read_exact(&mut header).map_err(truncated)?;
+        if &header[..4] != STORE_MAGIC {
+            return Err(invalid("bad magic bytes"));
+        }
+        if header[4] != STORE_VERSION {
+            return Err(invalid("unsupported version"));
+        }
+        let data_len = file.metadata()?.len();
+        let mut store = Self::with_file(path, file, data_len);
+
+        // load the index, and index blocks written after it
+        let indexed = store.load_index().unwrap_or(STORE_HEADER_LEN);
+        if indexed < data_len {
+            info!(
+                "indexing records of {} from offset {indexed}",
+                store.path.display()
+            );
+            store.scan_blocks(indexed)?;
+            store.index_dirty = true;
+        }
+        Ok(store)
+    }
+
+    fn with_file(path: PathBuf, file: File, data_len: u64) -> Self {
+        Self {
+            path,
+            file,
+            data_len,
+            index: HashMap::new(),
+            block_size: DEFAULT_BLOCK_SIZE,
+            pending: new_block(),
+            pending_ids: Vec::new(),
+            pending_dirty: false,
+            index_dirty: false,
+            cache: (PENDING, Vec::new()),
+        }
+    }
+
+    /// Sets the size of uncompressed blocks, larger blocks compress better but make `get` slower.
+    pub fn set_block_size(&mut self, block_size: usize) {
+        self.block_size = block_size.max(1024);
+    }
+
+    /// Returns the number of distinct IDs.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Returns whether the store is empty.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Returns whether a record with the ID exists.
+    pub fn contains(&self, id: &[u8]) -> bool {
+        self.index.contains_key(id)
+    }
+
+    /// Appends a record.
+    pub fn append(&mut self, seq: &Seq) -> Result<(), FastxErr> {
+        let offset =
+            u32::try_from(self.pending.get_ref().len()).map_err(|_| invalid("block too large"))?;
+        self.pending.write(seq)?;
+        self.pending_ids.push(seq.id.to_vec());
+        self.index.insert(
+            seq.id.to_vec(),
+            Loc {
+                block: PENDING,
+                offset,
+                len: seq.seq.len() as u64,
+            },
+        );
+        self.pending_dirty = true;
+        self.index_dirty = true;
+        if self.pending.get_ref().len() >= self.block_size {
+            self.write_block()?;
+        }
+        Ok(())
+    }
+
+    /// Appends all records of the reader, and returns the number of records.
+    pub fn append_reader<R: BufRead>(&mut self, reader: &mut Reader<R>) -> Result<u64, FastxErr> {
+        let mut n = 0;
+        while let Some(res) = reader.next() {
+            self.append(&res?)?;
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    // compresses and writes the pending block
+    fn write_block(&mut self) -> Result<(), FastxErr> {
+        if !self.pending_dirty {
+            return Ok(());
+        }
+        let block_offset = self.data_len;
+        let (codec, data) = compress_block(self.pending.get_ref());
+        let stored_len = u32::try_from(data.len()).map_err(|_| invalid("block too large"))?;
+        self.file.seek(SeekFrom::Start(block_offset))?;
+        self.file.write_all(&[codec])?;
+        self.file.write_all(&stored_len.to_le_bytes())?;
+        self.file.write_all(&data)?;
+        self.data_len += 5 + data.len() as u64;
+
+        for id in self.pending_ids.drain(..) {
+            if let Some(loc) = self.index.get_mut(&id)
+                && loc.block == PENDING
+            {
+                loc.block = block_offset;
+            }
+        }
+        self.pending = new_block();
+        self.pending_dirty = false;
+        Ok(())
+    }
+
+    /// Writes the pending block and the index.
+    pub fn flush(&mut self) -> Result<(), FastxErr> {
+        self.write_block()?;
+        self.file.flush()?;
+        if self.index_dirty {
+            self.save_index()?;
+            self.index_dirty = false;
+        }
+        Ok(())
+    }
+
+    /// Returns the record with the ID.
+    pub fn get(&mut self, id: &[u8]) -> Result<Option<Seq<'_>>, FastxErr> {
+        let Some(&loc) = self.index.get(id) else {
+            return Ok(None);
+        };
+        self.get_at(loc).map(Some)
+    }
+
+    /// Samples `n` distinct records at random, all of them if there are fewer records.
+    ///
+    /// Records are drawn from the index without scanning the data file, and only blocks holding
+    /// sampled records are read, so sampling a few records of a huge store is near-instant.
+    /// With `SampleWeight::Length`, records are drawn with probability proportional to the length
+    /// of their sequences, and empty sequences are never drawn. Records are returned in the order
+    /// they were appended; for duplicated IDs, only the last appended record can be drawn.
+    pub fn sample(
+        &mut self,
+        n: usize,
+        weight: SampleWeight,
+        seed: u64,
+    ) -> Result<Vec<SeqOwned>, FastxErr> {
+        // sorted by location for reproducible draws and sequential block reads
+        let mut locs: Vec<Loc> = self.index.values().copied().collect();
+        locs.sort_unstable_by_key(|loc| (loc.block, loc.offset));
+
+        // weighted sampling without replacement (Efraimidis-Spirakis): the n largest ln(u)/w
+        let mut rng = Rng::new(seed);
+        let mut keys: Vec<(f64, Loc)> = Vec::with_capacity(locs.len());
+        for loc in locs {
+            let w = match weight {
+                SampleWeight::Uniform => 1.0,
+                SampleWeight::Length if loc.len == 0 => continue,
+                SampleWeight::Length => loc.len as f64,
+            };
+            keys.push(((1.0 - rng.next_f64()).ln() / w, loc));
+        }
+        if n < keys.len() {
+            keys.select_nth_unstable_by(n, |a, b| b.0.total_cmp(&a.0));
+            keys.truncate(n);
+        }
+        keys.sort_unstable_by_key(|(_, loc)| (loc.block, loc.offset));
+
+        let mut records = Vec::with_capacity(keys.len());
+        for (_, loc) in keys {
+            records.push(self.get_at(loc)?.to_owned());
+        }
+        Ok(records)
+    }
+
+    // returns the record at the location
+    fn get_at(&mut self, loc: Loc) -> Result<Seq<'_>, FastxErr> {
+        let block: &[u8] = if loc.block == PENDING {
+            self.pending.get_ref()
+        } else {
+            if self.cache.0 != loc.block {
+                let data = self.read_block(loc.block)?.1;
+                self.cache = (loc.block, data);
+            }
+            &self.cache.1
+        };
+        let (seq, _) = parse_record(&block[loc.offset as usize..])?;
+        Ok(seq)
+    }
+
+    /// Returns an iterator of all records in the order they were appended, including duplicates.
+    pub fn records(&mut self) -> Result<StoreRecords<'_>, FastxErr> {
+        self.write_block()?;
+        Ok(StoreRecords {
+            store: self,
+            next_block: STORE_HEADER_LEN,
+            block: Vec::new(),
+            pos: 0,
+        })
+    }
+
+    // reads and decodes the block at the offset, returns the offset of the next block and the data
+    fn read_block(&mut self, offset: u64) -> Result<(u64, Vec<u8>), FastxErr> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        let mut header = [0u8; 5];
+        self.file.read_exact(&mut header).map_err(truncated)?;
+        let stored_len = u32::from_le_bytes(header[1..5].try_into().unwrap()) as usize;
+        if offset + 5 + stored_len as u64 > self.data_len {
+            return Err(invalid("truncated block"));
+        }
+        let mut data = vec![0u8; stored_len];
+        self.file.read_exact(&mut data).map_err(truncated)?;
+        let block = decompress_block(header[0], data)?;
+        if block.len() < BINARY_HEADER_LEN {
+            return Err(invalid("truncated block"));
+        }
+        Ok((offset + 5 + stored_len as u64, block))
+    }
+
+    // indexes records of blocks starting from the offset
+    fn scan_blocks(&mut self, mut offset: u64) -> Result<(), FastxErr> {
+        while offset < self.data_len {
+            let (next, block) = self.read_block(offset)?;
+            let mut pos = BINARY_HEADER_LEN;
+            while pos < block.len() {
+                let (seq, n) = parse_record(&block[pos..])?;
+                self.index.insert(
+                    seq.id.to_vec(),
+                    Loc {
+                        block: offset,
+                        offset: pos as u32,
+                        len: seq.seq.len() as u64,
+                    },
+                );
+                pos += n;
+            }
+            offset = next;
+        }
+        Ok(())
+    }
+
+    // index file: magic, the data length covered, number of entries,
+    // and entries of ID length (u32), ID, block offset (u64), offset in the block (u32)
+    // and sequence length (u64)
+    fn save_index(&self) -> Result<(), FastxErr> {
+        let mut w = BufWriter::new(File::create(index_path(&self.path))?);
+        w.write_all(INDEX_MAGIC)?;
+        w.write_all(&self.data_len.to_le_bytes())?;
+        w.write_all(&(self.index.len() as u64).to_le_bytes())?;
+        for (id, loc) in &self.index {
+            w.write_all(&(id.len() as u32).to_le_bytes())?;
+            w.write_all(id)?;
+            w.write_all(&loc.block.to_le_bytes())?;
+            w.write_all(&loc.offset.to_le_bytes())?;
+            w.write_all(&loc.len.to_le_bytes())?;
+        }
+        w.flush()?;
+        Ok(())
+    }
+
+    // loads the index file, and returns the data length it covers.
+    // Lengths are checked against the file size, so corrupt indexes are rebuilt instead of
+    // allocating what they claim.
+    fn load_index(&mut self) -> Option<u64> {
+        let file = File::open(index_path(&self.path)).ok()?;
+        let mut remaining = file.metadata().ok()?.len().checked_sub(20)?;
+        let mut r = BufReader::new(file);
+        let mut b8 = [0u8; 8];
+        let mut b4 = [0u8; 4];
+        r.read_exact(&mut b4).ok()?;
+        if &b4 != INDEX_MAGIC {
+            return None;
+        }
+        r.read_exact(&mut b8).ok()?;
+        let covered = u64::from_le_bytes(b8);
+        if covered > self.data_len {
+            return None;
+        }
+        r.read_exact(&mut b8).ok()?;
+        let n = u64::from_le_bytes(b8);
+        if n.checked_mul(INDEX_ENTRY_LEN)? > remaining {
+            return None;
+        }
+        let mut index = HashMap::with_capacity(n as usize);
+        for _ in 0..n {
+            r.read_exact(&mut b4).ok()?;
+            let id_len = u32::from_le_bytes(b4) as u64;
+            remaining = remaining.checked_sub(INDEX_ENTRY_LEN + id_len)?;
+            let mut id = vec![0u8; id_len as usize];
+            r.read_exact(&mut id).ok()?;
+            r.read_exact(&mut b8).ok()?;
+            let block = u64::from_le_bytes(b8);
+            r.read_exact(&mut b4).ok()?;
+            r.read_exact(&mut b8).ok()?;
+            index.insert(
+                id,
+                Loc {
+                    block,
+                    offset: u32::from_le_bytes(b4),
+                    len: u64::from_le_bytes(b8),
+                },
+            );
+        }
+        self.index = index;
+        Some(covered)
+    }
+}
+
+impl Drop for RecordStore {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// An iterator of records of a RecordStore.
+pub struct StoreRecords<'a> {
+    store: &'a mut RecordStore,
+    next_block: u64,
+    block: Vec<u8>,
+    pos: usize,
+}
+
+impl StoreRecords<'_> {
+    // returns None if all records are read, otherwise returns Some(Ok(Seq)) or Some(Err(e))
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Result<Seq<'_>, FastxErr>> {
+        if self.pos >= self.block.len() {
+            if self.next_block >= self.store.data_len {
+                return None;
+            }
+            match self.store.read_block(self.next_block) {
+                Ok((next, block)) => {
+                    self.next_block = next;
+                    self.block = block;
+                    self.pos = BINARY_HEADER_LEN;
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        match parse_record(&self.block[self.pos..]) {
+            Ok((seq, n)) => {
+                self.pos += n;
+                Some(Ok(seq))
+            }
+            Err(e) => {
+                self.pos = self.block.len();
+                self.next_block = self.store.data_len;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(feature = "lz4")]
+fn compress_block(block: &[u8]) -> (u8, Vec<u8>) {
+    (CODEC_LZ4, lz4_flex::block::compress_prepend_size(block))
+}
+
+#[cfg(not(feature = "lz4"))]
+fn compress_block(block: &[u8]) -> (u8, Vec<u8>) {
+    (CODEC_RAW, block.to_vec())
+}
+
+fn decompress_block(codec: u8, data: Vec<u8>) -> Result<Vec<u8>, FastxErr> {
+    match codec {
+        CODEC_RAW => Ok(data),
+        #[cfg(feature = "lz4")]
+        CODEC_LZ4 => lz4_flex::block::decompress_size_prepended(&data)
+            .map_err(|_| invalid("corrupted block")),
+        #[cfg(not(feature = "lz4"))]
+        CODEC_LZ4 => Err(FastxErr::IOError(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "lz4 support is not enabled, rebuild fastseq with the \"lz4\" feature",
+        ))),
+        _ => Err(invalid("unknown block codec")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn tmp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("fastseq_store_{}_{}", std::process::id(), name))
+    }
+
+    fn cleanup(path: &Path) {
+        let _ = std::fs::remove_file(path);
+        let _ = std::fs::remove_file(index_path(path));
+    }
+
+    fn a_read(id: &[u8], seq: &[u8]) -> Vec<u8> {
+        let mut rec = b"@".to_vec();
+        rec.extend_from_slice(id);
+        rec.extend_from_slice(b" d\n");
+        rec.extend_from_slice(seq);
+        rec.extend_from_slice(b"\n+\n");
+        rec.extend(std::iter::repeat_n(b'I', seq.len()));
+        rec.push(b'\n');
+        rec
+    }
+
+    #[test]
+    fn test_record_store_get() {
+        let path = tmp_path("get");
+        let mut input = Vec::new();
+        for i in 0..2000 {
+            input.extend(a_read(format!("r{i}").as_bytes(), b"ACGTACGTAC"));
+        }
+        {
+            let mut store = RecordStore::create(&path).unwrap();
+            store.set_block_size(4096);
+            let mut reader = Reader::from_reader(Cursor::new(input));
+            assert_eq!(store.append_reader(&mut reader).unwrap(), 2000);
+            assert_eq!(store.len(), 2000);
+
+            // records in flushed blocks and the pending block
+            let seq = store.get(b"r1999").unwrap().unwrap();
+            assert_eq!(seq.desc, b"d");
+            assert_eq!(seq.qual, Some(&b"IIIIIIIIII"[..]));
+            assert_eq!(store.get(b"r5").unwrap().unwrap().id, b"r5");
+            assert!(store.get(b"r2000").unwrap().is_none());
+        }
+
+        let mut store = RecordStore::open(&path).unwrap();
+        assert_eq!(store.len(), 2000);
+        assert!(store.contains(b"r1000"));
+        assert_eq!(store.get(b"r1000").unwrap().unwrap().seq, b"ACGTACGTAC");
+        cleanup(&path);
+    }
+
+    #[test]
+    fn test_record_store_append_and_rebuild_index() {
+        let path = tmp_path("append");
+        {
+            let mut store = RecordStore::create(&path).unwrap();
+            store
+                .append(&Seq {
+                    id: b"a",
+                    desc: b"",
+                    seq: b"AAAA",
+                    qual: None,
+                })
+                .unwrap();
+        }
+        {
+            let mut store = RecordStore::open(&path).unwrap();
+            for (id, seq) in [(&b"b"[..], &b"CCCC"[..]), (b"a", b"GGGG")] {
+                store
+                    .append(&Seq {
+                        id,
+                        desc: b"",
+                        seq,
+                        qual: None,
+                    })
+                    .unwrap();
+            }
+            // the last record wins
+            assert_eq!(store.get(b"a").unwrap().unwrap().seq, b"GGGG");
+
+            let mut ids = Vec::new();
+            let mut records = store.records().unwrap();
+            while let Some(res) = records.next() {
+                ids.push(res.unwrap().id.to_vec());
+            }
+            assert_eq!(ids, vec![b"a".to_vec(), b"b".to_vec(), b"a".to_vec()]);
+        }
+
+        // rebuilt from the data file
+        std::fs::remove_file(index_path(&path)).unwrap();
+        let mut store = RecordStore::open(&path).unwrap();
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.get(b"a").unwrap().unwrap().seq, b"GGGG");
+        assert_eq!(store.get(b"b").unwrap().unwrap().seq, b"CCCC");
+        drop(store);
+
+        // lengths of corrupt indexes are not trusted, the index is rebuilt
+        let mut index = std::fs::read(index_path(&path)).unwrap();
+        index[20..24].copy_from_slice(&u32::MAX.to_le_bytes());
+        std::fs::write(index_path(&path), index).unwrap();
+        let mut store = RecordStore::open(&path).unwrap();
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.get(b"a").unwrap().unwrap().seq, b"GGGG");
+        drop(store);
+
+        std::fs::write(&path, b"FSRX\x01").unwrap();
+        assert!(matches!(
+            RecordStore::open(&path),
+            Err(FastxErr::InvalidRecordStore(_))
+        ));
+        cleanup(&path);
+    }
+
+    #[test]
+    fn test_record_store_sample() {
+        let path = tmp_path("sample");
+        let mut store = RecordStore::create(&path).unwrap();
+        store.set_block_size(1024);
+        let mut input = a_read(b"empty", b"");
+        for i in 0..100 {
+            input.extend(a_read(format!("r{i}").as_bytes(), b"ACGTACGTAC"));
+        }
+        input.extend(a_read(b"long", &[b'A'; 10000]));
+        store
+            .append_reader(&mut Reader::from_reader(Cursor::new(input)))
+            .unwrap();
+        store.flush().unwrap();
+
+        let draws = |store: &mut RecordStore, weight| {
+            (0..100)
+                .filter(|&seed| store.sample(1, weight, seed).unwrap()[0].id == b"long")
+                .count()
+        };
+        // p = 10000/11000 and 1/102
+        assert!(draws(&mut store, SampleWeight::Length) > 80);
+        assert!(draws(&mut store, SampleWeight::Uniform) < 10);
+
+        // distinct records in the order of appending
+        let records = store.sample(50, SampleWeight::Uniform, 1).unwrap();
+        assert_eq!(records.len(), 50);
+        let mut ids: Vec<&[u8]> = records.iter().map(|r| &r.id[..]).collect();
+        ids.dedup();
+        assert_eq!(ids.len(), 50);
+        let all = store.sample(200, SampleWeight::Length, 1).unwrap();
+        assert_eq!(all.len(), 101);
+        assert_eq!(all[0].id, b"r0");
+        assert_eq!(all[100].id, b"long");
+
+        // lengths are kept in the index file
+        drop(store);
+        let mut store = RecordStore::open(&path).unwrap();
+        assert_eq!(store.sample(200, SampleWeight::Length, 2).unwrap(), all);
+        assert_eq!(store.sample(5, SampleWeight::Uniform, 1).unwrap().len(), 5);
+        cleanup(&path);
+    }
+}
@@ -0,0 +1,397 @@
+//! Asynchronous reading of FASTA/Q records, e.g., of uploads streamed to a server,
+//! without blocking the runtime.
+//!
+//! Unlike an `AsyncBufRead`-based reader behind a `tokio` feature, `AsyncReader` reads any
+//! `futures_core::Stream` of byte chunks and only needs the `async` feature, so the crate is not
+//! tied to one runtime. With Tokio, wrap an `AsyncRead` in `tokio_util::io::ReaderStream`.
+
+use crate::errors::*;
+use crate::reader::*;
+use crate::seq::*;
+use futures_core::Stream;
+use memchr::memchr;
+use std::collections::VecDeque;
+use std::future::poll_fn;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// An asynchronous reader of FASTA/Q records from a stream of byte chunks.
+///
+/// Lines are scanned as chunks arrive, and bytes are passed to the parser once the records
+/// they hold are complete, i.e., the next FASTA header begins or the FASTQ quality is as long
+/// as the sequence, so every byte is scanned and parsed once. Besides the current chunk,
+/// at most one incomplete record is buffered. Input is not decompressed.
+/// Reading stops at the first error.
+pub struct AsyncReader<S> {
+    stream: S,
+    reader: Reader<VecDeque<u8>>,
+    // bytes not passed to the parser yet
+    buf: Vec<u8>,
+    // the start of the current line, and the number of bytes checked for its line feed
+    line_start: usize,
+    scanned: usize,
+    scanner: Scanner,
+    ready: VecDeque<SeqOwned>,
+    err: Option<FastxErr>,
+    done: bool,
+}
+
+impl<S, B> AsyncReader<S>
+where
+    S: Stream<Item = io::Result<B>> + Unpin,
+    B: AsRef<[u8]>,
+{
+    /// Creates a new AsyncReader of a stream of byte chunks, e.g., the body of an HTTP request.
+    /// Chunks may split records and lines anywhere.
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            reader: Reader::from_reader(VecDeque::new()),
+            buf: Vec::new(),
+            line_start: 0,
+            scanned: 0,
+            scanner: Scanner::default(),
+            ready: VecDeque::new(),
+            err: None,
+            done: false,
+        }
+    }
+
+    /// Returns the next record, None at the end of the stream.
+    pub async fn next(&mut self) -> Option<Result<SeqOwned, FastxErr>> {
+        poll_fn(|cx| self.poll_record(cx)).await
+    }
+
+    /// Polls for the next record, the building block of `next` and `Stream`.
+    pub fn poll_record(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<SeqOwned, FastxErr>>> {
+        loop {
+            if let Some(rec) = self.ready.pop_front() {
+                return Poll::Ready(Some(Ok(rec)));
+            }
+            if let Some(e) = self.err.take() {
+                return Poll::Ready(Some(Err(e)));
+            }
+            if self.done {
+                return Poll::Ready(None);
+            }
+            match Pin::new(&mut self.stream).poll_next(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Some(Ok(chunk))) => {
+                    self.buf.extend_from_slice(chunk.as_ref());
+                    let complete = self.scan();
+                    self.parse(complete);
+                }
+                Poll::Ready(Some(Err(e))) => {
+                    self.done = true;
+                    self.err = Some(e.into());
+                }
+                Poll::Ready(None) => {
+                    self.done = true;
+                    self.parse(self.buf.len());
+                }
+            }
+        }
+    }
+
+    /// Returns the underlying stream.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    // scans new complete lines, and returns the end of the last complete record in the buffer
+    fn scan(&mut self) -> usize {
+        let mut complete = 0;
+        while let Some(i) = memchr(b'\n', &self.buf[self.scanned..]) {
+            let (start, end) = (self.line_start, self.scanned + i + 1);
+            let mut len = end - 1 - start;
+            if len > 0 && self.buf[end - 2] == b'\r' {
+                len -= 1;
+            }
+            if let Some(pos) = self.scanner.on_line(self.buf[start], len, start, end) {
+                complete = pos;
+            }
+            (self.line_start, self.scanned) = (end, end);
+        }
+        self.scanned = self.buf.len();
+        complete
+    }
+
+    // passes the first n bytes of the buffer to the parser, and queues the records parsed
+    fn parse(&mut self, n: usize) {
+        if n == 0 && !self.done {
+            return;
+        }
+        self.reader.get_mut().extend(self.buf.drain(..n));
+        self.line_start -= n.min(self.line_start);
+        self.scanned -= n.min(self.scanned);
+        loop {
+            match self.reader.next() {
+                None => break,
+                Some(Ok(seq)) => self.ready.push_back(seq.to_owned()),
+                Some(Err(e)) => {
+                    self.err = Some(e);
+                    self.done = true;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+impl<S, B> Stream for AsyncReader<S>
+where
+    S: Stream<Item = io::Result<B>> + Unpin,
+    B: AsRef<[u8]>,
+{
+    type Item = Result<SeqOwned, FastxErr>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_record(cx)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum State {
+    #[default]
+    Start,
+    Fasta,
+    FastqHeader,
+    FastqSeq,
+    FastqQual,
+}
+
+// tracks where records end line by line, following the rules of the parser
+#[derive(Debug, Default)]
+struct Scanner {
+    state: State,
+    seq_len: usize,
+    qual_len: usize,
+}
+
+impl Scanner {
+    // takes the first byte and the length of a line without line endings, and the start and end
+    // of the line in the buffer; returns the end of a record if one is complete.
+    // Lines the parser rejects end records too, so errors are reported without waiting.
+    fn on_line(&mut self, first: u8, len: usize, start: usize, end: usize) -> Option<usize> {
+        if len == 0 {
+            return None; // blank lines
+        }
+        match self.state {
+            State::Start if first == b'>' => {
+                self.state = State::Fasta;
+                None
+            }
+            State::Start | State::FastqHeader if first == b'@' => {
+                self.state = State::FastqSeq;
+                self.seq_len = 0;
+                None
+            }
+            State::Start | State::FastqHeader => Some(end),
+            State::Fasta => (first == b'>').then_some(start),
+            State::FastqSeq if first == b'+' => {
+                if self.seq_len == 0 {
+                    self.state = State::FastqHeader;
+                    return Some(end);
+                }
+                self.qual_len = 0;
+                self.state = State::FastqQual;
+                None
+            }
+            State::FastqSeq => {
+                self.seq_len += len;
+                None
+            }
+            State::FastqQual => {
+                self.qual_len += len;
+                if self.qual_len < self.seq_len {
+                    return None;
+                }
+                self.state = State::FastqHeader;
+                Some(end)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::task::{Wake, Waker};
+
+    // yields the chunks in turn, pending once before each
+    struct Chunks {
+        chunks: VecDeque<io::Result<Vec<u8>>>,
+        pending: bool,
+    }
+
+    impl Stream for Chunks {
+        type Item = io::Result<Vec<u8>>;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            self.pending = !self.pending;
+            if self.pending {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            Poll::Ready(self.chunks.pop_front())
+        }
+    }
+
+    struct NoopWaker;
+
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    fn block_on<F: Future>(fut: F) -> F::Output {
+        let waker = Waker::from(Arc::new(NoopWaker));
+        let mut cx = Context::from_waker(&waker);
+        let mut fut = std::pin::pin!(fut);
+        loop {
+            if let Poll::Ready(v) = fut.as_mut().poll(&mut cx) {
+                return v;
+            }
+        }
+    }
+
+    fn read_all(data: &[u8], size: usize) -> Vec<Result<SeqOwned, FastxErr>> {
+        let chunks = data.chunks(size).map(|c| Ok(c.to_vec())).collect();
+        let mut r = AsyncReader::new(Chunks {
+            chunks,
+            pending: false,
+        });
+        block_on(async {
+            let mut records = Vec::new();
+            while let Some(rec) = r.next().await {
+                records.push(rec);
+            }
+            records
+        })
+    }
+
+    #[test]
+    fn test_async_reader() {
+        let fastq = b"@r1 desc\nACGT\n+\nIIII\n@r2\nGGC\n+\n###\n@r3\nT\n+\nI\n";
+        let fasta = b">s1\nACGT\nAC\n>s2\n\nGG\n>s3\nTTT";
+        let expected_fq: Vec<SeqOwned> = {
+            let r = Reader::from_bytes(fastq);
+            r.into_records().map(|r| r.unwrap()).collect()
+        };
+        let expected_fa: Vec<SeqOwned> = {
+            let r = Reader::from_bytes(fasta);
+            r.into_records().map(|r| r.unwrap()).collect()
+        };
+        assert_eq!(expected_fq.len(), 3);
+        assert_eq!(expected_fa.len(), 3);
+        for size in 1..=fastq.len() {
+            let records: Vec<SeqOwned> = read_all(fastq, size)
+                .into_iter()
+                .map(|r| r.unwrap())
+                .collect();
+            assert_eq!(records, expected_fq, "chunk size {size}");
+        }
+        for size in 1..=fasta.len() {
+            let records: Vec<SeqOwned> = read_all(fasta, size)
+                .into_iter()
+                .map(|r| r.unwrap())
+                .collect();
+            assert_eq!(records, expected_fa, "chunk size {size}");
+        }
+        assert!(read_all(b"", 4).is_empty());
+    }
+
+    #[test]
+    fn test_async_reader_long_record() {
+        let fasta: Vec<u8> = [
+            &b">chr1 long\r\n"[..],
+            &b"ACGTACGTAC\r\n".repeat(1000),
+            b">chr2\nGG\n",
+        ]
+        .concat();
+        let records: Vec<SeqOwned> = read_all(&fasta, 7)
+            .into_iter()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].desc, b"long");
+        assert_eq!(records[0].seq.len(), 10000);
+        assert_eq!(records[1].seq, b"GG");
+
+        let records: Vec<SeqOwned> = read_all(b"@r0\r\nA\r\n+\r\nI\r\n", 3)
+            .into_iter()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].qual.as_deref(), Some(&b"I"[..]));
+    }
+
+    #[test]
+    fn test_async_reader_yields_before_the_end() {
+        // a stream that stays pending after its chunks
+        struct Open(VecDeque<io::Result<Vec<u8>>>);
+
+        impl Stream for Open {
+            type Item = io::Result<Vec<u8>>;
+
+            fn poll_next(
+                mut self: Pin<&mut Self>,
+                _: &mut Context<'_>,
+            ) -> Poll<Option<Self::Item>> {
+                match self.0.pop_front() {
+                    Some(chunk) => Poll::Ready(Some(chunk)),
+                    None => Poll::Pending,
+                }
+            }
+        }
+
+        let mut r = AsyncReader::new(Open(VecDeque::from([
+            Ok(b"@r1\nAC".to_vec()),
+            Ok(b"GT\n+\nIIII\n@r2\nA".to_vec()),
+        ])));
+        let waker = Waker::from(Arc::new(NoopWaker));
+        let mut cx = Context::from_waker(&waker);
+        match r.poll_record(&mut cx) {
+            Poll::Ready(Some(Ok(rec))) => assert_eq!(rec.seq, b"ACGT"),
+            _ => panic!("r1 should be ready"),
+        }
+        assert!(r.poll_record(&mut cx).is_pending());
+    }
+
+    #[test]
+    fn test_async_reader_errors() {
+        // truncated at the end
+        let records = read_all(b"@r1\nACGT\n+\nIIII\n@r2\nAC", 5);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].as_ref().unwrap().id, b"r1");
+        assert!(records[1].is_err());
+
+        // malformed in the middle, reading stops
+        let records = read_all(b"@r1\nACGT\n+\nII\n@r2\nAC\n+\nII\n@r3\nA\n+\nI\n", 8);
+        assert_eq!(records.len(), 1);
+        assert!(records[0].is_err());
+
+        // errors of the stream
+        let mut r = AsyncReader::new(Chunks {
+            chunks: VecDeque::from([
+                Ok(b">s1\nAC\n>s2\nG".to_vec()),
+                Err(io::Error::other("connection reset")),
+            ]),
+            pending: false,
+        });
+        let records = block_on(async {
+            let mut records = Vec::new();
+            while let Some(rec) = r.next().await {
+                records.push(rec);
+            }
+            records
+        });
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].as_ref().unwrap().id, b"s1");
+        assert!(matches!(records[1], Err(FastxErr::IOError(_))));
+    }
+}
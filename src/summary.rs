@@ -0,0 +1,620 @@
+use crate::errors::*;
+use crate::reader::*;
+use crate::seq::*;
+use crate::util::*;
+use crate::xopen::*;
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Number of records probed by `summarize`.
+pub const DEFAULT_PROBE_RECORDS: usize = 1000;
+
+/// Encodings of FASTQ quality scores.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QualityEncoding {
+    /// Sanger and Illumina 1.8+, ASCII offset 33.
+    Phred33,
+    /// Solexa scores with ASCII offset 64, which can be negative.
+    Solexa64,
+    /// Illumina 1.3-1.7, ASCII offset 64.
+    Phred64,
+}
+
+/// A summary of a sequence file from probing the first records.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileSummary {
+    /// None if the file has no records.
+    pub format: Option<Format>,
+    pub compression: CompressionFormat,
+    /// Size of the file on disk, None for stdin.
+    pub file_size: Option<u64>,
+    /// Number of records probed.
+    pub probed_records: u64,
+    /// Whether the whole file was read, which makes `estimated_records` exact.
+    pub complete: bool,
+    /// Estimated number of records, extrapolated from the bytes consumed by the probe.
+    pub estimated_records: u64,
+    pub min_len: usize,
+    pub max_len: usize,
+    /// The most frequent sequence length, the shortest one for ties.
+    pub len_mode: usize,
+    /// None for FASTA files.
+    pub quality_encoding: Option<QualityEncoding>,
+    /// Whether probed records are sorted by ID.
+    pub sorted: bool,
+    /// Whether probed records are mates of read pairs in turn.
+    pub interleaved: bool,
+    /// The file of the other mates, if the file name looks like a mate file and the other file exists.
+    pub mate_file: Option<PathBuf>,
+}
+
+// counts bytes read from the underlying file
+struct CountingRead<R> {
+    inner: R,
+    count: Rc<Cell<u64>>,
+}
+
+impl<R: Read> Read for CountingRead<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(out)?;
+        self.count.set(self.count.get() + n as u64);
+        Ok(n)
+    }
+}
+
+/// Summarizes a sequence file by probing the first 1000 records, "-" for stdin.
+pub fn summarize<P: AsRef<Path>>(file: P) -> Result<FileSummary, FastxErr> {
+    summarize_with(file, DEFAULT_PROBE_RECORDS)
+}
+
+/// Summarizes a sequence file by probing the first `max_records` records, "-" for stdin.
+pub fn summarize_with<P: AsRef<Path>>(
+    file: P,
+    max_records: usize,
+) -> Result<FileSummary, FastxErr> {
+    let file = file.as_ref();
+    let count = Rc::new(Cell::new(0u64));
+    let (file_size, compression, r) = if IoPath::new(file).is_std() {
+        let r = xopen(file, 4096)?;
+        (None, CompressionFormat::None, r)
+    } else {
+        let mut f = File::open(long_path(file))?;
+        let size = f.metadata()?.len();
+        let mut magic = [0u8; 6];
+        let n = read_prefix(&mut f, &mut magic)?;
+        f.seek(SeekFrom::Start(0))?;
+        let r = xopen_reader(
+            CountingRead {
+                inner: f,
+                count: Rc::clone(&count),
+            },
+            4096,
+        )?;
+        (Some(size), detect_compression(&magic[..n]), r)
+    };
+
+    let mut reader = Reader::from_reader(r);
+    let mut summary = FileSummary {
+        format: None,
+        compression,
+        file_size,
+        probed_records: 0,
+        complete: false,
+        estimated_records: 0,
+        min_len: 0,
+        max_len: 0,
+        len_mode: 0,
+        quality_encoding: None,
+        sorted: true,
+        interleaved: false,
+        mate_file: None,
+    };
+
+    let mut lens: HashMap<usize, u64> = HashMap::new();
+    let (mut qmin, mut qmax) = (u8::MAX, 0u8);
+    let mut last_id: Vec<u8> = Vec::new();
+    let mut last_mate: (Vec<u8>, Option<u8>) = (Vec::new(), None);
+    let mut mates_in_turn = true;
+    let mut complete = true;
+    while let Some(res) = reader.next() {
+        let seq = res?;
+        let n = summary.probed_records;
+        if n == 0 {
+            summary.format = Some(if seq.is_fastq() {
+                Format::Fastq
+            } else {
+                Format::Fasta
+            });
+            summary.min_len = seq.len();
+        }
+        summary.probed_records += 1;
+
+        summary.min_len = summary.min_len.min(seq.len());
+        summary.max_len = summary.max_len.max(seq.len());
+        *lens.entry(seq.len()).or_insert(0) += 1;
+        if let Some(qual) = seq.qual {
+            for &q in qual {
+                qmin = qmin.min(q);
+                qmax = qmax.max(q);
+            }
+        }
+
+        if n > 0 && seq.id < last_id.as_slice() {
+            summary.sorted = false;
+        }
+        last_id.clear();
+        last_id.extend_from_slice(seq.id);
+
+        // the 2nd record of each pair should be the mate of the 1st one
+        let (base, mate) = seq.mate_id();
+        if n % 2 == 1
+            && (base != last_mate.0.as_slice()
+                || !matches!((last_mate.1, mate), (Some(1), Some(2))))
+        {
+            mates_in_turn = false;
+        }
+        last_mate.0.clear();
+        last_mate.0.extend_from_slice(base);
+        last_mate.1 = mate;
+
+        if summary.probed_records as usize >= max_records {
+            complete = false;
+            break;
+        }
+    }
+    if !complete {
+        // a probe ending exactly at the last record
+        complete = reader.next().is_none();
+    }
+
+    summary.complete = complete;
+    summary.interleaved =
+        mates_in_turn && summary.probed_records >= 2 && summary.probed_records.is_multiple_of(2);
+    summary.len_mode = lens
+        .iter()
+        .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)))
+        .map(|(&len, _)| len)
+        .unwrap_or(0);
+    if qmax > 0 {
+        summary.quality_encoding = Some(if qmin < b';' {
+            QualityEncoding::Phred33
+        } else if qmin < b'@' {
+            QualityEncoding::Solexa64
+        } else {
+            QualityEncoding::Phred64
+        });
+    }
+    summary.estimated_records = match (complete, file_size) {
+        (false, Some(size)) if count.get() > 0 => {
+            (summary.probed_records as f64 * size as f64 / count.get() as f64).round() as u64
+        }
+        _ => summary.probed_records,
+    };
+    if file != "-" {
+        summary.mate_file = find_mate_file(Path::new(file));
+    }
+
+    Ok(summary)
+}
+
+/// Minimum fraction of in-order neighboring records for `detect_order` to call a file sorted.
+pub const SORTED_MIN_FRACTION: f64 = 0.99;
+
+/// Orders of records in a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordOrder {
+    /// Sorted by ID in byte order, e.g., by `sort` or `seqkit sort -n`.
+    Name,
+    /// Sorted by ID in natural order, where numbers are compared by value ("r2" before "r10").
+    Natural,
+    /// Grouped and sorted by coordinates in IDs: lane and tile of Illumina read names,
+    /// or sequence name and start of region IDs like "chr1:100-200".
+    Coordinate,
+    Unsorted,
+}
+
+/// Result of `detect_order`. Fractions are of neighboring record pairs in order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderReport {
+    /// Number of records sampled.
+    pub records: u64,
+    /// The detected order, sorting by name is preferred over natural and coordinate order.
+    pub order: RecordOrder,
+    pub name_sorted: f64,
+    pub natural_sorted: f64,
+    /// None if IDs contain no coordinates.
+    pub coordinate_sorted: Option<f64>,
+    /// Fraction of record pairs (1st and 2nd, 3rd and 4th, ...) which are mates of read pairs.
+    pub mates_in_turn: f64,
+    /// Whether the file looks like interleaved paired reads.
+    pub interleaved: bool,
+}
+
+/// Samples up to `max_records` records of a source and reports whether they appear name-sorted,
+/// coordinate-sorted, interleaved or unsorted, so tools can choose algorithms, e.g., streaming
+/// pairing of interleaved files versus re-pairing.
+///
+/// The detection tolerates a few out-of-order records (see `SORTED_MIN_FRACTION`), and pairs of
+/// interleaved files are compared by their shared IDs, without mate suffixes.
+pub fn detect_order<S: RecordSource>(
+    source: &mut S,
+    max_records: usize,
+) -> Result<OrderReport, FastxErr> {
+    let mut n = 0u64;
+    let (mut name_ok, mut natural_ok) = (0u64, 0u64);
+    let (mut coord_pairs, mut coord_ok) = (0u64, 0u64);
+    let mut coord_parsed = true;
+    let mut mates = 0u64;
+
+    let mut last_mate: (Vec<u8>, Option<u8>) = (Vec::new(), None);
+    let mut last_coord: Option<(Vec<u8>, u64)> = None;
+    let mut groups: HashSet<Vec<u8>> = HashSet::new();
+
+    while let Some(res) = source.next_record() {
+        let seq = res?;
+        let (base, mate) = seq.mate_id();
+
+        if n > 0 {
+            if base >= last_mate.0.as_slice() {
+                name_ok += 1;
+            }
+            if natural_cmp_bytes(base, &last_mate.0) != Ordering::Less {
+                natural_ok += 1;
+            }
+        }
+        if n % 2 == 1 && base == last_mate.0.as_slice() && (last_mate.1, mate) == (Some(1), Some(2))
+        {
+            mates += 1;
+        }
+
+        if coord_parsed {
+            match parse_coordinate(base) {
+                Some((group, pos)) => {
+                    if let Some((last_group, last_pos)) = &last_coord {
+                        coord_pairs += 1;
+                        if group == last_group.as_slice() {
+                            coord_ok += (pos >= *last_pos) as u64;
+                        } else if !groups.contains(group) {
+                            coord_ok += 1;
+                        }
+                    }
+                    groups.insert(group.to_vec());
+                    last_coord = Some((group.to_vec(), pos));
+                }
+                None => coord_parsed = false,
+            }
+        }
+
+        last_mate.0.clear();
+        last_mate.0.extend_from_slice(base);
+        last_mate.1 = mate;
+
+        n += 1;
+        if n as usize >= max_records {
+            break;
+        }
+    }
+
+    let fraction = |ok: u64, total: u64| {
+        if total == 0 {
+            1.0
+        } else {
+            ok as f64 / total as f64
+        }
+    };
+    let pairs = n.saturating_sub(1);
+    let name_sorted = fraction(name_ok, pairs);
+    let natural_sorted = fraction(natural_ok, pairs);
+    let coordinate_sorted = (coord_parsed && n > 0).then(|| fraction(coord_ok, coord_pairs));
+    let mates_in_turn = fraction(mates, n / 2);
+
+    let order = if name_sorted >= SORTED_MIN_FRACTION {
+        RecordOrder::Name
+    } else if natural_sorted >= SORTED_MIN_FRACTION {
+        RecordOrder::Natural
+    } else if coordinate_sorted.is_some_and(|f| f >= SORTED_MIN_FRACTION) {
+        RecordOrder::Coordinate
+    } else {
+        RecordOrder::Unsorted
+    };
+
+    Ok(OrderReport {
+        records: n,
+        order,
+        name_sorted,
+        natural_sorted,
+        coordinate_sorted,
+        mates_in_turn,
+        interleaved: n >= 2 && mates_in_turn >= SORTED_MIN_FRACTION,
+    })
+}
+
+/// The first position where two streams differ in record order, see `compare_order`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderDivergence {
+    /// 1-based index of the record.
+    pub index: u64,
+    /// ID of the record in the first stream, None if the stream ended.
+    pub id1: Option<Vec<u8>>,
+    /// ID of the record in the second stream, None if the stream ended.
+    pub id2: Option<Vec<u8>>,
+}
+
+/// Result of `compare_order`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OrderComparison {
+    /// Number of records with the same IDs before the first divergence.
+    pub matched: u64,
+    /// The first divergence, None if both streams have the same IDs in the same order.
+    pub divergence: Option<OrderDivergence>,
+}
+
+impl OrderComparison {
+    /// Returns whether both streams have the same IDs in the same order.
+    pub fn is_same(&self) -> bool {
+        self.divergence.is_none()
+    }
+}
+
+/// Verifies that two streams have records of the same IDs in the same order, e.g., R1 and R2
+/// files, or the input and output of a tool supposed to keep the order, stopping at the first
+/// divergence. Records are compared one by one, so memory usage is constant.
+///
+/// With `mates`, IDs are compared without mate suffixes ("/1", "/2"), as for R1 and R2 files.
+pub fn compare_order<S1: RecordSource, S2: RecordSource>(
+    s1: &mut S1,
+    s2: &mut S2,
+    mates: bool,
+) -> Result<OrderComparison, FastxErr> {
+    let mut cmp = OrderComparison::default();
+    loop {
+        let (a, b) = match (s1.next_record(), s2.next_record()) {
+            (None, None) => return Ok(cmp),
+            (a, b) => (a.transpose()?, b.transpose()?),
+        };
+        let key = |seq: &Option<Seq>| {
+            seq.as_ref()
+                .map(|s| if mates { s.mate_id().0 } else { s.id }.to_vec())
+        };
+        if a.is_none() || b.is_none() || key(&a) != key(&b) {
+            cmp.divergence = Some(OrderDivergence {
+                index: cmp.matched + 1,
+                id1: a.map(|s| s.id.to_vec()),
+                id2: b.map(|s| s.id.to_vec()),
+            });
+            return Ok(cmp);
+        }
+        cmp.matched += 1;
+    }
+}
+
+// returns the group and position of an ID with coordinates:
+// lane and tile of Illumina read names (instrument:run:flowcell:lane:tile:x:y),
+// or name and start of region IDs (name:start-end)
+fn parse_coordinate(id: &[u8]) -> Option<(&[u8], u64)> {
+    let num = |s: &[u8]| -> Option<u64> {
+        if s.is_empty() || !s.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        std::str::from_utf8(s).ok()?.parse().ok()
+    };
+
+    let fields: Vec<&[u8]> = id.split(|&b| b == b':').collect();
+    if fields.len() == 7 && fields[3..].iter().all(|f| num(f).is_some()) {
+        return Some((fields[3], num(fields[4])?));
+    }
+
+    let colon = id.iter().rposition(|&b| b == b':')?;
+    let (start, end) = id[colon + 1..].split_at(id[colon + 1..].iter().position(|&b| b == b'-')?);
+    num(&end[1..])?;
+    Some((&id[..colon], num(start)?))
+}
+
+fn read_prefix(f: &mut File, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match f.read(&mut buf[n..])? {
+            0 => break,
+            m => n += m,
+        }
+    }
+    Ok(n)
+}
+
+// mate markers in file names, with the counterpart
+const MATE_MARKERS: [(&str, &str); 8] = [
+    ("_R1", "_R2"),
+    ("_R2", "_R1"),
+    (".R1", ".R2"),
+    (".R2", ".R1"),
+    ("_1.", "_2."),
+    ("_2.", "_1."),
+    ("_r1", "_r2"),
+    ("_r2", "_r1"),
+];
+
+/// Returns the mate file of a read 1 or read 2 file that exists,
+/// by swapping the last mate marker (e.g., "_R1" and "_R2", "_1." and "_2.") in the file name.
+pub fn find_mate_file(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    let (pos, from, to) = MATE_MARKERS
+        .iter()
+        .filter_map(|&(from, to)| name.rfind(from).map(|pos| (pos, from, to)))
+        .max_by_key(|x| x.0)?;
+    let mate = format!("{}{}{}", &name[..pos], to, &name[pos + from.len()..]);
+    let mate = path.with_file_name(mate);
+    mate.exists().then_some(mate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn temp_dir() -> PathBuf {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let dir = std::env::temp_dir().join(format!(
+            "fastseq-summary-test-{}-{nanos}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_summarize_fastq() {
+        let dir = temp_dir();
+        let r1 = dir.join("s_R1.fq");
+        let mut data = String::new();
+        for i in 0..12 {
+            data.push_str(&format!("@r{i}/1\nACGT\n+\nII5I\n@r{i}/2\nACG\n+\nIII\n"));
+        }
+        fs::write(&r1, &data).unwrap();
+        fs::write(dir.join("s_R2.fq"), "").unwrap();
+
+        let s = summarize(r1.to_str().unwrap()).unwrap();
+        assert_eq!(s.format, Some(Format::Fastq));
+        assert_eq!(s.compression, CompressionFormat::None);
+        assert!(s.complete);
+        assert_eq!((s.probed_records, s.estimated_records), (24, 24));
+        assert_eq!((s.min_len, s.max_len, s.len_mode), (3, 4, 3));
+        assert_eq!(s.quality_encoding, Some(QualityEncoding::Phred33));
+        assert!(s.interleaved);
+        assert!(!s.sorted); // r9/2 > r10/1
+        assert_eq!(s.mate_file, Some(dir.join("s_R2.fq")));
+
+        let s = summarize_with(r1.to_str().unwrap(), 5).unwrap();
+        assert!(!s.complete);
+        assert!(!s.interleaved);
+        assert!(s.estimated_records >= 5);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_summarize_gzip_fasta() {
+        let dir = temp_dir();
+        let path = dir.join("seqs.fa.gz");
+        {
+            let mut w = xwrite(path.to_str().unwrap(), 4096).unwrap();
+            w.write_all(b">a\nACGT\n>b\nAC\n>c\nACGT\n").unwrap();
+        }
+        let s = summarize(path.to_str().unwrap()).unwrap();
+        assert_eq!(s.format, Some(Format::Fasta));
+        assert_eq!(s.compression, CompressionFormat::Gzip);
+        assert_eq!(s.len_mode, 4);
+        assert_eq!(s.quality_encoding, None);
+        assert!(s.sorted);
+        assert!(!s.interleaved);
+        assert_eq!(s.mate_file, None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn order(input: &str) -> OrderReport {
+        detect_order(&mut Reader::from_bytes(input.as_bytes()), 1000).unwrap()
+    }
+
+    #[test]
+    fn test_detect_order() {
+        let r = order(">a\nA\n>b\nA\n>c\nA\n");
+        assert_eq!(
+            (r.records, r.order, r.interleaved),
+            (3, RecordOrder::Name, false)
+        );
+
+        let input: String = (1..=12).map(|i| format!(">r{i}\nA\n")).collect();
+        let r = order(&input);
+        assert_eq!(r.order, RecordOrder::Natural);
+        assert!(r.name_sorted < 1.0);
+        assert_eq!(r.coordinate_sorted, None);
+
+        let input: String = (1..=12)
+            .map(|i| format!("@r{i}/1\nA\n+\nI\n@r{i}/2\nA\n+\nI\n"))
+            .collect();
+        let r = order(&input);
+        assert_eq!(r.order, RecordOrder::Natural);
+        assert_eq!(r.mates_in_turn, 1.0);
+        assert!(r.interleaved);
+
+        let r = order(">b\nA\n>a\nA\n>c\nA\n");
+        assert_eq!(r.order, RecordOrder::Unsorted);
+        assert_eq!(r.name_sorted, 0.5);
+
+        assert_eq!(order("").order, RecordOrder::Name);
+    }
+
+    #[test]
+    fn test_compare_order() {
+        let cmp = |a: &str, b: &str, mates: bool| {
+            compare_order(
+                &mut Reader::from_bytes(a.as_bytes()),
+                &mut Reader::from_bytes(b.as_bytes()),
+                mates,
+            )
+            .unwrap()
+        };
+
+        let c = cmp(">a\nA\n>b\nC\n", ">a x\nT\n>b\nG\n", false);
+        assert!(c.is_same());
+        assert_eq!(c.matched, 2);
+
+        let c = cmp(">a\nA\n>b\nC\n>c\nC\n", ">a\nA\n>c\nC\n>b\nC\n", false);
+        assert_eq!(
+            c,
+            OrderComparison {
+                matched: 1,
+                divergence: Some(OrderDivergence {
+                    index: 2,
+                    id1: Some(b"b".to_vec()),
+                    id2: Some(b"c".to_vec()),
+                }),
+            }
+        );
+
+        let c = cmp(">a\nA\n", ">a\nA\n>b\nC\n", false);
+        assert_eq!(c.divergence.unwrap().id1, None);
+
+        assert!(!cmp(">a/1\nA\n", ">a/2\nA\n", false).is_same());
+        assert!(cmp(">a/1\nA\n", ">a/2\nA\n", true).is_same());
+        assert!(cmp("", "", false).is_same());
+    }
+
+    #[test]
+    fn test_detect_order_coordinates() {
+        // region IDs in reference order, not name order
+        let r = order(">chr2:5-10\nA\n>chr2:20-30\nA\n>chr1:1-5\nA\n>chr1:8-9\nA\n");
+        assert_eq!(r.order, RecordOrder::Coordinate);
+        assert_eq!(r.coordinate_sorted, Some(1.0));
+
+        let r = order(">chr2:5-10\nA\n>chr1:1-5\nA\n>chr2:1-3\nA\n");
+        assert_eq!(r.order, RecordOrder::Unsorted);
+
+        // Illumina read names by lane and tile
+        let r = order(
+            "@M1:5:FC:2:1101:9:9 1:N\nA\n+\nI\n@M1:5:FC:2:1102:1:1 1:N\nA\n+\nI\n\
+             @M1:5:FC:1:1101:5:5 1:N\nA\n+\nI\n",
+        );
+        assert_eq!(r.order, RecordOrder::Coordinate);
+    }
+
+    #[test]
+    fn test_detect_quality_encoding() {
+        let dir = temp_dir();
+        let path = dir.join("q64.fq");
+        fs::write(&path, "@a\nACGT\n+\nhhh@\n").unwrap();
+        let s = summarize(path.to_str().unwrap()).unwrap();
+        assert_eq!(s.quality_encoding, Some(QualityEncoding::Phred64));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
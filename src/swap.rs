@@ -0,0 +1,432 @@
+use crate::errors::*;
+use crate::reader::*;
+use crate::samplesheet::*;
+use crate::seq::*;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufRead, Write};
+
+/// Options of barcode swap detection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapOptions {
+    /// Maximum number of mismatches of an observed barcode to a barcode of the sample sheet.
+    pub max_mismatches: usize,
+    /// Maximum number of reads checked per file, 0 for all.
+    pub max_reads: u64,
+}
+
+impl Default for SwapOptions {
+    fn default() -> Self {
+        Self {
+            max_mismatches: 1,
+            max_reads: 0,
+        }
+    }
+}
+
+/// Counts of reads by the barcodes observed in their headers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BarcodeCounts {
+    /// Number of reads checked.
+    pub reads: u64,
+    /// Number of reads without a barcode in the header.
+    pub no_barcode: u64,
+    /// Number of reads with the barcodes of the expected sample.
+    pub expected: u64,
+    /// Number of reads with the barcodes of another sample.
+    pub other_sample: u64,
+    /// Number of reads whose i7 and i5 barcodes belong to different samples, i.e., index hopping.
+    pub hopped: u64,
+    /// Number of reads with barcodes of no sample.
+    pub unknown: u64,
+}
+
+impl BarcodeCounts {
+    /// Returns the fraction of reads with barcodes of other samples.
+    pub fn other_sample_rate(&self) -> f64 {
+        rate(self.other_sample, self.reads)
+    }
+
+    /// Returns the fraction of reads with hopped indexes.
+    pub fn hopping_rate(&self) -> f64 {
+        rate(self.hopped, self.reads)
+    }
+
+    fn add(&mut self, other: &BarcodeCounts) {
+        self.reads += other.reads;
+        self.no_barcode += other.no_barcode;
+        self.expected += other.expected;
+        self.other_sample += other.other_sample;
+        self.hopped += other.hopped;
+        self.unknown += other.unknown;
+    }
+}
+
+fn rate(n: u64, total: u64) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    n as f64 / total as f64
+}
+
+/// The result of checking the reads of a sample.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SampleSwapReport {
+    /// Name of the sample.
+    pub sample: String,
+    /// Counts of all reads of the sample.
+    pub counts: BarcodeCounts,
+    /// Counts by lane, lane 0 for reads without a lane in the header.
+    pub lanes: BTreeMap<u32, BarcodeCounts>,
+    /// Number of reads by the other sample whose barcodes they carry, most frequent first.
+    pub other_samples: Vec<(String, u64)>,
+}
+
+impl SampleSwapReport {
+    /// Returns the sample whose barcodes are carried by more reads than the expected ones,
+    /// i.e., the file likely belongs to that sample.
+    pub fn likely_swap(&self) -> Option<&str> {
+        self.other_samples
+            .first()
+            .filter(|(_, n)| *n > self.counts.expected)
+            .map(|(s, _)| s.as_str())
+    }
+}
+
+/// The result of checking samples of a sample sheet.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SwapReport {
+    pub samples: Vec<SampleSwapReport>,
+}
+
+impl SwapReport {
+    /// Returns the counts of all samples by lane.
+    pub fn lanes(&self) -> BTreeMap<u32, BarcodeCounts> {
+        let mut lanes: BTreeMap<u32, BarcodeCounts> = BTreeMap::new();
+        for s in &self.samples {
+            for (&lane, c) in &s.lanes {
+                lanes.entry(lane).or_default().add(c);
+            }
+        }
+        lanes
+    }
+
+    /// Writes a tab-delimited summary per lane.
+    pub fn write_lane_summary<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(
+            w,
+            "lane\treads\tno_barcode\texpected\tother_sample\thopped\tunknown\tother_sample_rate\thopping_rate"
+        )?;
+        for (lane, c) in self.lanes() {
+            writeln!(
+                w,
+                "{lane}\t{}\t{}\t{}\t{}\t{}\t{}\t{:.6}\t{:.6}",
+                c.reads,
+                c.no_barcode,
+                c.expected,
+                c.other_sample,
+                c.hopped,
+                c.unknown,
+                c.other_sample_rate(),
+                c.hopping_rate()
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes a tab-delimited summary per sample, with the likely sample of swapped files,
+    /// "-" for others.
+    pub fn write_sample_summary<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(
+            w,
+            "sample\treads\tno_barcode\texpected\tother_sample\thopped\tunknown\tlikely_swap"
+        )?;
+        for s in &self.samples {
+            let c = &s.counts;
+            writeln!(
+                w,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                s.sample,
+                c.reads,
+                c.no_barcode,
+                c.expected,
+                c.other_sample,
+                c.hopped,
+                c.unknown,
+                s.likely_swap().unwrap_or("-")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// the class of an observed barcode relative to a sample
+#[derive(Clone, Copy)]
+enum Class {
+    Expected,
+    Other(usize),
+    Hopped,
+    Unknown,
+}
+
+/// A checker of barcodes in read headers against the assignments of a sample sheet, e.g., for
+/// finding swapped files of samples, or estimating index hopping rates of lanes.
+///
+/// Barcodes are taken from the last field of Casava 1.8+ descriptions (`1:N:0:ACGT+TTGA`),
+/// or following '#' in older read names (`...:941:1973#ACGT/1`), and lanes from read names.
+pub struct SwapChecker<'a> {
+    sheet: &'a SampleSheet,
+    opts: SwapOptions,
+    cache: HashMap<Vec<u8>, Vec<Class>>, // observed barcode -> classes relative to each sample
+}
+
+impl<'a> SwapChecker<'a> {
+    /// Creates a checker of the samples with barcodes in the sheet.
+    pub fn new(sheet: &'a SampleSheet, opts: SwapOptions) -> Self {
+        Self {
+            sheet,
+            opts,
+            cache: HashMap::new(),
+        }
+    }
+
+    /// Checks the reads of a sample, returning None if the sample is not in the sheet.
+    pub fn check_reader<R: BufRead>(
+        &mut self,
+        sample: &str,
+        reader: &mut Reader<R>,
+    ) -> Result<Option<SampleSwapReport>, FastxErr> {
+        let Some(idx) = self.sheet.samples.iter().position(|s| s.name == sample) else {
+            return Ok(None);
+        };
+        let mut report = SampleSwapReport {
+            sample: sample.to_string(),
+            ..Default::default()
+        };
+        let mut others = vec![0u64; self.sheet.samples.len()];
+        while self.opts.max_reads == 0 || report.counts.reads < self.opts.max_reads {
+            let Some(res) = reader.next() else {
+                break;
+            };
+            let seq = res?;
+            let lane = read_lane(seq.id).unwrap_or(0);
+            let class = read_barcode(&seq).map(|b| self.classify(b, idx));
+            for c in [&mut report.counts, report.lanes.entry(lane).or_default()] {
+                c.reads += 1;
+                match class {
+                    None => c.no_barcode += 1,
+                    Some(Class::Expected) => c.expected += 1,
+                    Some(Class::Other(_)) => c.other_sample += 1,
+                    Some(Class::Hopped) => c.hopped += 1,
+                    Some(Class::Unknown) => c.unknown += 1,
+                }
+            }
+            if let Some(Class::Other(j)) = class {
+                others[j] += 1;
+            }
+        }
+        report.other_samples = others
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| **n > 0)
+            .map(|(j, &n)| (self.sheet.samples[j].name.clone(), n))
+            .collect();
+        report
+            .other_samples
+            .sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        Ok(Some(report))
+    }
+
+    /// Checks the read 1 (or single-end) files of all samples with barcodes and files
+    /// in the sheet.
+    pub fn check_files(&mut self) -> Result<SwapReport, FastxErr> {
+        let mut report = SwapReport::default();
+        for sample in &self.sheet.samples {
+            let Some(file) = sample.r1.as_ref() else {
+                continue;
+            };
+            if sample.barcode.is_none() && sample.barcode2.is_none() {
+                continue;
+            }
+            let mut reader = Reader::new(file)?;
+            if let Some(r) = self.check_reader(&sample.name, &mut reader)? {
+                report.samples.push(r);
+            }
+        }
+        Ok(report)
+    }
+
+    fn classify(&mut self, observed: &[u8], sample: usize) -> Class {
+        if !self.cache.contains_key(observed) {
+            let classes = self.classify_all(observed);
+            self.cache.insert(observed.to_vec(), classes);
+        }
+        self.cache[observed][sample]
+    }
+
+    // classes of an observed barcode relative to every sample
+    fn classify_all(&self, observed: &[u8]) -> Vec<Class> {
+        let (i7, i5) = match observed.iter().position(|&b| b == b'+') {
+            Some(p) => (&observed[..p], Some(&observed[p + 1..])),
+            None => (observed, None),
+        };
+        let matches =
+            |expected: &Option<String>, observed: Option<&[u8]>| match (expected, observed) {
+                (Some(e), Some(o)) => hamming(e.as_bytes(), o) <= self.opts.max_mismatches,
+                (None, _) => true,
+                (Some(_), None) => false,
+            };
+        let samples = &self.sheet.samples;
+        let i7_matches: Vec<bool> = samples
+            .iter()
+            .map(|s| s.barcode.is_some() && matches(&s.barcode, Some(i7)))
+            .collect();
+        let i5_matches: Vec<bool> = samples
+            .iter()
+            .map(|s| s.barcode2.is_some() && matches(&s.barcode2, i5))
+            .collect();
+        let full: Vec<bool> = samples
+            .iter()
+            .map(|s| {
+                (s.barcode.is_some() || s.barcode2.is_some())
+                    && matches(&s.barcode, Some(i7))
+                    && matches(&s.barcode2, i5)
+            })
+            .collect();
+        let other = full.iter().position(|&m| m);
+        let hopped = i5.is_some() && i7_matches.contains(&true) && i5_matches.contains(&true);
+        (0..samples.len())
+            .map(|i| {
+                if full[i] {
+                    Class::Expected
+                } else if let Some(j) = other {
+                    Class::Other(j)
+                } else if hopped {
+                    Class::Hopped
+                } else {
+                    Class::Unknown
+                }
+            })
+            .collect()
+    }
+}
+
+// the barcode of a read: the last field of a Casava 1.8+ description, or following '#' in the ID
+fn read_barcode<'a>(seq: &Seq<'a>) -> Option<&'a [u8]> {
+    let desc = seq.desc;
+    if desc.len() > 2 && desc[1] == b':' {
+        let b = desc.rsplit(|&b| b == b':').next()?;
+        return (!b.is_empty() && !b.iter().all(|b| b.is_ascii_digit())).then_some(b);
+    }
+    let p = seq.id.iter().rposition(|&b| b == b'#')?;
+    let b = &seq.id[p + 1..];
+    let end = b.iter().position(|&b| b == b'/').unwrap_or(b.len());
+    (end > 0 && b[..end] != *b"0").then_some(&b[..end])
+}
+
+// the lane of a read: "instrument:run:flowcell:lane:..." (Casava 1.8+) or "instrument:lane:..."
+fn read_lane(id: &[u8]) -> Option<u32> {
+    let fields: Vec<&[u8]> = id.split(|&b| b == b':').collect();
+    let lane = match fields.len() {
+        7 => fields[3],
+        5 => fields[1],
+        _ => return None,
+    };
+    std::str::from_utf8(lane).ok()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn fastq(reads: &[(u32, &str)]) -> String {
+        let mut out = String::new();
+        for (i, (lane, barcode)) in reads.iter().enumerate() {
+            out.push_str(&format!(
+                "@A1:8:FC:{lane}:1101:{i}:1 1:N:0:{barcode}\nACGT\n+\nIIII\n"
+            ));
+        }
+        out
+    }
+
+    #[test]
+    fn test_read_barcode_and_lane() {
+        let seq = |id, desc| Seq {
+            id,
+            desc,
+            seq: b"",
+            qual: None,
+        };
+        assert_eq!(
+            read_barcode(&seq(b"A:1:F:2:3:4:5", b"1:N:0:ACGT+TTGA")),
+            Some(&b"ACGT+TTGA"[..])
+        );
+        assert_eq!(read_barcode(&seq(b"A:1:F:2:3:4:5", b"1:N:0:2")), None);
+        assert_eq!(
+            read_barcode(&seq(b"HWUSI:6:73:941:1973#ACGT/1", b"")),
+            Some(&b"ACGT"[..])
+        );
+        assert_eq!(read_barcode(&seq(b"HWUSI:6:73:941:1973#0/1", b"")), None);
+        assert_eq!(read_lane(b"A:1:F:2:3:4:5"), Some(2));
+        assert_eq!(read_lane(b"HWUSI:6:73:941:1973#0/1"), Some(6));
+        assert_eq!(read_lane(b"read1"), None);
+    }
+
+    #[test]
+    fn test_swap_checker() {
+        let sheet = "sample,i7,i5\na,AAAAAAAA,CCCCCCCC\nb,GGGGGGGG,TTTTTTTT\n";
+        let sheet = SampleSheet::parse(Cursor::new(sheet)).unwrap();
+        let mut checker = SwapChecker::new(&sheet, SwapOptions::default());
+
+        let input = fastq(&[
+            (1, "AAAAAAAA+CCCCCCCC"),
+            (1, "AAAAAAAT+CCCCCCCC"), // a mismatch
+            (1, "AAAAAAAA+TTTTTTTT"), // hopped
+            (2, "GGGGGGGG+TTTTTTTT"),
+            (2, "ACACACAC+CCCCCCCC"),
+        ]);
+        let mut reader = Reader::from_bytes(input.as_bytes());
+        let a = checker.check_reader("a", &mut reader).unwrap().unwrap();
+        assert_eq!(
+            a.counts,
+            BarcodeCounts {
+                reads: 5,
+                no_barcode: 0,
+                expected: 2,
+                other_sample: 1,
+                hopped: 1,
+                unknown: 1,
+            }
+        );
+        assert_eq!(a.lanes[&1].hopping_rate(), 1.0 / 3.0);
+        assert_eq!(a.other_samples, vec![("b".to_string(), 1)]);
+        assert_eq!(a.likely_swap(), None);
+
+        // the reads of sample b are of sample a
+        let input = fastq(&[(1, "AAAAAAAA+CCCCCCCC"), (2, "AAAAAAAA+CCCCCCCC")]);
+        let mut reader = Reader::from_bytes(input.as_bytes());
+        let b = checker.check_reader("b", &mut reader).unwrap().unwrap();
+        assert_eq!(b.likely_swap(), Some("a"));
+
+        let mut reader = Reader::from_bytes(input.as_bytes());
+        assert!(checker.check_reader("c", &mut reader).unwrap().is_none());
+
+        let report = SwapReport {
+            samples: vec![a, b],
+        };
+        let lanes = report.lanes();
+        assert_eq!((lanes[&1].reads, lanes[&2].reads), (4, 3));
+        let mut out = Vec::new();
+        report.write_sample_summary(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap().lines().nth(2),
+            Some("b\t2\t0\t0\t2\t0\t0\ta")
+        );
+        let mut out = Vec::new();
+        report.write_lane_summary(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap().lines().nth(1),
+            Some("1\t4\t0\t2\t1\t1\t0\t0.250000\t0.250000")
+        );
+    }
+}
@@ -0,0 +1,654 @@
+use crate::errors::*;
+use crate::reader::*;
+use crate::seq::*;
+use crate::util::*;
+use crate::writer::*;
+use std::io::{self, BufRead, Write};
+
+/// A step of quality trimming or filtering, steps of a `Trimmer` are applied in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrimStep {
+    /// Trimmomatic `LEADING:q`, removes leading bases with quality below q.
+    Leading(u8),
+    /// Trimmomatic `TRAILING:q`, removes trailing bases with quality below q.
+    Trailing(u8),
+    /// Trimmomatic `SLIDINGWINDOW:w:q`, cuts the read once the mean quality of a window
+    /// falls below q, and then removes trailing bases with quality below q.
+    SlidingWindow { window: usize, quality: u8 },
+    /// cutadapt `-q five,three`, the BWA algorithm applied to both ends, 0 to skip an end.
+    Cutadapt { five: u8, three: u8 },
+    /// `bwa aln -q`, see `bwa_trim`.
+    Bwa(u8),
+    /// Trimmomatic `MINLEN:n` or fastp `-l n`, discards reads shorter than n.
+    MinLen(usize),
+    /// fastp `-q q -u percent`, discards reads with more than `percent`% bases of quality below q.
+    LowQualityFilter { quality: u8, percent: u8 },
+    /// fastp `-n n`, discards reads with more than n `N` bases.
+    NFilter(usize),
+}
+
+/// Quality-trimming presets replicating popular tools.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrimPreset {
+    /// The recommended Trimmomatic steps: `LEADING:3 TRAILING:3 SLIDINGWINDOW:4:15 MINLEN:36`.
+    Trimmomatic,
+    /// cutadapt `-q q`, trimming the 3' end only.
+    Cutadapt(u8),
+    /// Quality filters of fastp with default options: `-q 15 -u 40 -n 5 -l 15`.
+    /// Adapter and polyG trimming of fastp are not included.
+    Fastp,
+}
+
+impl TrimPreset {
+    /// Returns the steps of the preset.
+    pub fn steps(&self) -> Vec<TrimStep> {
+        match self {
+            TrimPreset::Trimmomatic => vec![
+                TrimStep::Leading(3),
+                TrimStep::Trailing(3),
+                TrimStep::SlidingWindow {
+                    window: 4,
+                    quality: 15,
+                },
+                TrimStep::MinLen(36),
+            ],
+            TrimPreset::Cutadapt(q) => vec![TrimStep::Cutadapt { five: 0, three: *q }],
+            TrimPreset::Fastp => vec![
+                TrimStep::LowQualityFilter {
+                    quality: 15,
+                    percent: 40,
+                },
+                TrimStep::NFilter(5),
+                TrimStep::MinLen(15),
+            ],
+        }
+    }
+}
+
+/// Why a read was discarded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiscardReason {
+    /// No bases left after trimming.
+    Empty,
+    /// Shorter than the minimum length.
+    TooShort,
+    /// Too many low-quality bases.
+    LowQuality,
+    /// Too many `N` bases.
+    TooManyN,
+}
+
+impl DiscardReason {
+    /// Returns the name of the reason.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiscardReason::Empty => "empty",
+            DiscardReason::TooShort => "too_short",
+            DiscardReason::LowQuality => "low_quality",
+            DiscardReason::TooManyN => "too_many_n",
+        }
+    }
+}
+
+/// The result of trimming a read: the kept region `start..end`,
+/// and the reason if the read is discarded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trimmed {
+    pub start: usize,
+    pub end: usize,
+    pub discarded: Option<DiscardReason>,
+}
+
+impl Trimmed {
+    /// Returns the kept region of the read.
+    pub fn apply<'a>(&self, seq: &Seq<'a>) -> Seq<'a> {
+        Seq {
+            id: seq.id,
+            desc: seq.desc,
+            seq: &seq.seq[self.start..self.end],
+            qual: seq.qual.map(|q| &q[self.start..self.end]),
+        }
+    }
+}
+
+/// A quality trimmer applying a series of steps.
+///
+/// Steps on quality scores are skipped for FASTA records.
+#[derive(Debug, Clone)]
+pub struct Trimmer {
+    steps: Vec<TrimStep>,
+    offset: u8,
+}
+
+impl Trimmer {
+    /// Creates a trimmer with steps, quality scores are Phred+33 encoded.
+    pub fn new(steps: Vec<TrimStep>) -> Self {
+        Self { steps, offset: 33 }
+    }
+
+    /// Creates a trimmer from a preset.
+    pub fn from_preset(preset: TrimPreset) -> Self {
+        Self::new(preset.steps())
+    }
+
+    /// Sets the offset of quality scores, e.g., 64 for old Illumina data.
+    pub fn set_phred_offset(&mut self, offset: u8) {
+        self.offset = offset;
+    }
+
+    /// Returns the steps.
+    pub fn steps(&self) -> &[TrimStep] {
+        &self.steps
+    }
+
+    /// Trims a read.
+    pub fn trim(&self, seq: &Seq) -> Trimmed {
+        let mut start = 0;
+        let mut end = seq.len();
+        for step in &self.steps {
+            let reason = match (*step, seq.qual) {
+                (TrimStep::MinLen(n), _) => (end - start < n).then_some(DiscardReason::TooShort),
+                (TrimStep::NFilter(n), _) => {
+                    let ns = seq.seq[start..end]
+                        .iter()
+                        .filter(|b| b.eq_ignore_ascii_case(&b'N'))
+                        .count();
+                    (ns > n).then_some(DiscardReason::TooManyN)
+                }
+                (_, None) => None,
+                (step, Some(qual)) => {
+                    let quals = &qual[start..end];
+                    match step {
+                        TrimStep::Leading(q) => {
+                            let n = quals.iter().take_while(|&&b| self.phred(b) < q).count();
+                            start += n;
+                            None
+                        }
+                        TrimStep::Trailing(q) => {
+                            let n = quals
+                                .iter()
+                                .rev()
+                                .take_while(|&&b| self.phred(b) < q)
+                                .count();
+                            end -= n;
+                            None
+                        }
+                        TrimStep::SlidingWindow { window, quality } => {
+                            match self.sliding_window(quals, window, quality) {
+                                Some(n) => {
+                                    end = start + n;
+                                    None
+                                }
+                                None => {
+                                    end = start;
+                                    Some(DiscardReason::Empty)
+                                }
+                            }
+                        }
+                        TrimStep::Cutadapt { five, three } => {
+                            let (s, e) = self.bwa_trim_index(quals, five, three);
+                            end = start + e;
+                            start += s;
+                            None
+                        }
+                        TrimStep::Bwa(q) => {
+                            end = start + bwa_trim(quals, q, self.offset);
+                            None
+                        }
+                        TrimStep::LowQualityFilter { quality, percent } => {
+                            let low = quals.iter().filter(|&&b| self.phred(b) < quality).count();
+                            (low as f64 > percent as f64 * quals.len() as f64 / 100.0)
+                                .then_some(DiscardReason::LowQuality)
+                        }
+                        TrimStep::MinLen(_) | TrimStep::NFilter(_) => unreachable!(),
+                    }
+                }
+            };
+            if reason.is_some() {
+                return Trimmed {
+                    start,
+                    end,
+                    discarded: reason,
+                };
+            }
+            // Trimmomatic drops reads once all bases are trimmed
+            if start >= end
+                && matches!(
+                    step,
+                    TrimStep::Leading(_) | TrimStep::Trailing(_) | TrimStep::SlidingWindow { .. }
+                )
+            {
+                return Trimmed {
+                    start,
+                    end: start,
+                    discarded: Some(DiscardReason::Empty),
+                };
+            }
+        }
+        Trimmed {
+            start,
+            end,
+            discarded: None,
+        }
+    }
+
+    #[inline]
+    fn phred(&self, b: u8) -> u8 {
+        b.saturating_sub(self.offset)
+    }
+
+    // the length to keep by Trimmomatic SLIDINGWINDOW, None for dropping the read
+    fn sliding_window(&self, quals: &[u8], window: usize, quality: u8) -> Option<usize> {
+        let window = window.max(1);
+        if quals.len() < window {
+            return None;
+        }
+        let required = quality as u32 * window as u32;
+        let mut total: u32 = quals[..window].iter().map(|&b| self.phred(b) as u32).sum();
+        if total < required {
+            return None;
+        }
+        let mut keep = quals.len();
+        for i in 0..quals.len() - window {
+            total = total + self.phred(quals[i + window]) as u32 - self.phred(quals[i]) as u32;
+            if total < required {
+                keep = i + window;
+                break;
+            }
+        }
+        while keep > 1 && self.phred(quals[keep - 1]) < quality {
+            keep -= 1;
+        }
+        Some(keep)
+    }
+
+    // the region to keep by the BWA algorithm as implemented in cutadapt:
+    // from each end, find the position maximizing the partial sum of (cutoff - quality),
+    // stopping once the sum drops below zero
+    fn bwa_trim_index(&self, quals: &[u8], five: u8, three: u8) -> (usize, usize) {
+        let mut start = 0;
+        let mut stop = quals.len();
+        if five > 0 {
+            let (mut s, mut max) = (0i64, 0i64);
+            for (i, &b) in quals.iter().enumerate() {
+                s += five as i64 - self.phred(b) as i64;
+                if s < 0 {
+                    break;
+                }
+                if s > max {
+                    max = s;
+                    start = i + 1;
+                }
+            }
+        }
+        if three > 0 {
+            let (mut s, mut max) = (0i64, 0i64);
+            for (i, &b) in quals.iter().enumerate().rev() {
+                s += three as i64 - self.phred(b) as i64;
+                if s < 0 {
+                    break;
+                }
+                if s > max {
+                    max = s;
+                    stop = i;
+                }
+            }
+        }
+        if start >= stop { (0, 0) } else { (start, stop) }
+    }
+}
+
+/// Formats of per-read trimming reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// Tab-delimited values with a header line.
+    Tsv,
+    /// One JSON object per line.
+    Jsonl,
+}
+
+/// An entry of a per-read trimming report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrimReportEntry<'a> {
+    /// Read ID.
+    pub id: &'a [u8],
+    /// Number of bases trimmed from the 5' end.
+    pub trimmed_5p: usize,
+    /// Number of bases trimmed from the 3' end.
+    pub trimmed_3p: usize,
+    /// Name of the adapter found, if any.
+    pub adapter: Option<&'a [u8]>,
+    /// Why the read was discarded, None for kept reads.
+    pub discarded: Option<DiscardReason>,
+}
+
+impl<'a> TrimReportEntry<'a> {
+    /// Creates an entry from the trimming result of a read.
+    pub fn new(seq: &Seq<'a>, trimmed: &Trimmed) -> Self {
+        Self {
+            id: seq.id,
+            trimmed_5p: trimmed.start,
+            trimmed_3p: seq.len() - trimmed.end,
+            adapter: None,
+            discarded: trimmed.discarded,
+        }
+    }
+}
+
+/// A writer of per-read trimming reports, for auditing what happened to every read.
+///
+/// In TSV, columns are `id`, `trimmed_5p`, `trimmed_3p`, `adapter` and `discarded`,
+/// with `-` for missing adapters and kept reads. In JSONL, they are `null`.
+pub struct TrimReport<W: Write> {
+    w: W,
+    format: ReportFormat,
+    header: bool,
+}
+
+impl<W: Write> TrimReport<W> {
+    /// Creates a report writer.
+    pub fn new(w: W, format: ReportFormat) -> Self {
+        Self {
+            w,
+            format,
+            header: format == ReportFormat::Tsv,
+        }
+    }
+
+    /// Writes an entry.
+    pub fn write(&mut self, entry: &TrimReportEntry) -> io::Result<()> {
+        let w = &mut self.w;
+        match self.format {
+            ReportFormat::Tsv => {
+                if self.header {
+                    w.write_all(b"id\ttrimmed_5p\ttrimmed_3p\tadapter\tdiscarded\n")?;
+                    self.header = false;
+                }
+                w.write_all(entry.id)?;
+                write!(w, "\t{}\t{}\t", entry.trimmed_5p, entry.trimmed_3p)?;
+                w.write_all(entry.adapter.unwrap_or(b"-"))?;
+                writeln!(w, "\t{}", entry.discarded.map_or("-", |r| r.as_str()))
+            }
+            ReportFormat::Jsonl => {
+                w.write_all(b"{\"id\":")?;
+                write_json_str(w, entry.id)?;
+                write!(
+                    w,
+                    ",\"trimmed_5p\":{},\"trimmed_3p\":{},\"adapter\":",
+                    entry.trimmed_5p, entry.trimmed_3p
+                )?;
+                match entry.adapter {
+                    Some(a) => write_json_str(w, a)?,
+                    None => w.write_all(b"null")?,
+                }
+                match entry.discarded {
+                    Some(r) => writeln!(w, ",\"discarded\":\"{}\"}}", r.as_str()),
+                    None => w.write_all(b",\"discarded\":null}\n"),
+                }
+            }
+        }
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.w
+    }
+}
+
+/// Minimum read length of quality trimming in BWA, `BWA_MIN_RDLEN`.
+pub const BWA_MIN_READ_LEN: usize = 35;
+
+/// Returns the length of a read to keep after trimming its 3' end like `bwa aln -q`,
+/// a port of `bwa_trim_read` of BWA.
+///
+/// From the 3' end, the partial sums of `trim_qual - quality` are computed until a sum drops
+/// below zero, and the read is cut where the sum is the largest. Reads are never trimmed
+/// shorter than `BWA_MIN_READ_LEN`, and `trim_qual` 0 disables trimming.
+/// BWA reads qualities with the offset 33.
+pub fn bwa_trim(qual: &[u8], trim_qual: u8, offset: u8) -> usize {
+    let len = qual.len();
+    if trim_qual < 1 {
+        return len;
+    }
+    let (mut s, mut max, mut max_l) = (0i32, 0i32, len);
+    for l in (BWA_MIN_READ_LEN..len).rev() {
+        s += trim_qual as i32 - (qual[l] as i32 - offset as i32);
+        if s < 0 {
+            break;
+        }
+        if s > max {
+            max = s;
+            max_l = l;
+        }
+    }
+    max_l
+}
+
+/// Statistics of trimming.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrimStats {
+    /// Number of input reads.
+    pub reads: u64,
+    /// Number of discarded reads.
+    pub discarded: u64,
+    /// Number of input bases.
+    pub bases_in: u64,
+    /// Number of output bases.
+    pub bases_out: u64,
+}
+
+/// Trims all reads of the reader, and writes kept ones to the writer.
+pub fn trim_records<R: BufRead, W: Write>(
+    reader: &mut Reader<R>,
+    writer: &mut Writer<W>,
+    trimmer: &Trimmer,
+) -> Result<TrimStats, FastxErr> {
+    trim_records_with_report::<R, W, io::Sink>(reader, writer, trimmer, None)
+}
+
+/// Like `trim_records`, and writes an entry of every read to the report if given.
+pub fn trim_records_with_report<R: BufRead, W: Write, T: Write>(
+    reader: &mut Reader<R>,
+    writer: &mut Writer<W>,
+    trimmer: &Trimmer,
+    mut report: Option<&mut TrimReport<T>>,
+) -> Result<TrimStats, FastxErr> {
+    let mut stats = TrimStats::default();
+    while let Some(res) = reader.next() {
+        let seq = res?;
+        stats.reads += 1;
+        stats.bases_in += seq.len() as u64;
+        let t = trimmer.trim(&seq);
+        if let Some(report) = report.as_deref_mut() {
+            report.write(&TrimReportEntry::new(&seq, &t))?;
+        }
+        if t.discarded.is_some() {
+            stats.discarded += 1;
+            continue;
+        }
+        stats.bases_out += (t.end - t.start) as u64;
+        writer.write(&t.apply(&seq))?;
+    }
+    writer.flush()?;
+    if let Some(report) = report {
+        report.flush()?;
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn phred33(quals: &[u8]) -> Vec<u8> {
+        quals.iter().map(|q| q + 33).collect()
+    }
+
+    fn a_read<'a>(seq: &'a [u8], qual: &'a [u8]) -> Seq<'a> {
+        Seq {
+            id: b"r",
+            desc: b"",
+            seq,
+            qual: Some(qual),
+        }
+    }
+
+    #[test]
+    fn test_trimmomatic_steps() {
+        let qual = phred33(&[30, 30, 2, 30, 30, 30, 30, 30, 10, 10, 10, 10, 30, 2]);
+        let seq = vec![b'A'; qual.len()];
+        let trimmer = Trimmer::new(vec![
+            TrimStep::Leading(3),
+            TrimStep::Trailing(3),
+            TrimStep::SlidingWindow {
+                window: 4,
+                quality: 15,
+            },
+        ]);
+        let t = trimmer.trim(&a_read(&seq, &qual));
+        assert_eq!((t.start, t.end, t.discarded), (0, 8, None));
+
+        let qual = phred33(&[2, 2, 20, 20, 20, 20, 2]);
+        let t = trimmer.trim(&a_read(&seq[..7], &qual));
+        assert_eq!((t.start, t.end), (2, 6));
+
+        // MINLEN:36 of the preset
+        let t = Trimmer::from_preset(TrimPreset::Trimmomatic).trim(&a_read(&seq[..7], &qual));
+        assert_eq!(t.discarded, Some(DiscardReason::TooShort));
+
+        // nothing left
+        let qual = phred33(&[2, 2, 2]);
+        let t = trimmer.trim(&a_read(&seq[..3], &qual));
+        assert_eq!(t.discarded, Some(DiscardReason::Empty));
+    }
+
+    #[test]
+    fn test_cutadapt_quality_trimming() {
+        // the example of the cutadapt documentation
+        let qual = phred33(&[42, 40, 26, 27, 8, 7, 11, 4, 2, 3]);
+        let seq = vec![b'A'; qual.len()];
+        let t = Trimmer::from_preset(TrimPreset::Cutadapt(10)).trim(&a_read(&seq, &qual));
+        assert_eq!((t.start, t.end, t.discarded), (0, 4, None));
+
+        let trimmer = Trimmer::new(vec![TrimStep::Cutadapt {
+            five: 10,
+            three: 10,
+        }]);
+        let qual = phred33(&[3, 2, 40, 40, 2]);
+        let t = trimmer.trim(&a_read(&seq[..5], &qual));
+        assert_eq!((t.start, t.end), (2, 4));
+
+        // trimmed reads are kept by cutadapt
+        let qual = phred33(&[2, 2]);
+        let t = trimmer.trim(&a_read(&seq[..2], &qual));
+        assert_eq!((t.start, t.end, t.discarded), (0, 0, None));
+    }
+
+    #[test]
+    fn test_fastp_filters() {
+        let trimmer = Trimmer::from_preset(TrimPreset::Fastp);
+        let seq = b"ACGTACGTACGTACGTACGT";
+        let mut quals = [30u8; 20];
+        quals[..8].fill(10);
+        let qual = phred33(&quals);
+        assert_eq!(trimmer.trim(&a_read(seq, &qual)).discarded, None);
+        quals[8] = 10;
+        let qual = phred33(&quals);
+        assert_eq!(
+            trimmer.trim(&a_read(seq, &qual)).discarded,
+            Some(DiscardReason::LowQuality)
+        );
+
+        let qual = phred33(&[30; 20]);
+        let t = trimmer.trim(&a_read(b"NNNNNNACGTACGTACGTAC", &qual));
+        assert_eq!(t.discarded, Some(DiscardReason::TooManyN));
+    }
+
+    #[test]
+    fn test_trim_records() {
+        let input = "@r1\nACGTAC\n+\nIIII##\n@r2\nAC\n+\n##\n>r3\nACGT\n";
+        let mut reader = Reader::from_reader(Cursor::new(input.as_bytes()));
+        let mut writer = Writer::from_writer(Vec::new());
+        let trimmer = Trimmer::new(vec![TrimStep::Trailing(3), TrimStep::MinLen(1)]);
+        let stats = trim_records(&mut reader, &mut writer, &trimmer).unwrap();
+        assert_eq!(
+            stats,
+            TrimStats {
+                reads: 3,
+                discarded: 1,
+                bases_in: 12,
+                bases_out: 8,
+            }
+        );
+        assert_eq!(writer.into_inner(), b"@r1\nACGT\n+\nIIII\n>r3\nACGT\n");
+    }
+
+    #[test]
+    fn test_trim_report() {
+        let input = "@r1\nACGTAC\n+\n#IIII#\n@r2\nAC\n+\n##\n";
+        let trimmer = Trimmer::new(vec![
+            TrimStep::Leading(3),
+            TrimStep::Trailing(3),
+            TrimStep::MinLen(1),
+        ]);
+        for (format, expected) in [
+            (
+                ReportFormat::Tsv,
+                "id\ttrimmed_5p\ttrimmed_3p\tadapter\tdiscarded\nr1\t1\t1\t-\t-\nr2\t2\t0\t-\tempty\n",
+            ),
+            (
+                ReportFormat::Jsonl,
+                concat!(
+                    r#"{"id":"r1","trimmed_5p":1,"trimmed_3p":1,"adapter":null,"discarded":null}"#,
+                    "\n",
+                    r#"{"id":"r2","trimmed_5p":2,"trimmed_3p":0,"adapter":null,"discarded":"empty"}"#,
+                    "\n"
+                ),
+            ),
+        ] {
+            let mut reader = Reader::from_reader(Cursor::new(input.as_bytes()));
+            let mut writer = Writer::from_writer(Vec::new());
+            let mut report = TrimReport::new(Vec::new(), format);
+            trim_records_with_report(&mut reader, &mut writer, &trimmer, Some(&mut report))
+                .unwrap();
+            assert_eq!(String::from_utf8(report.into_inner()).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_bwa_trim() {
+        // 36 bases of Q30 and 4 of Q2
+        let mut qual = vec![b'?'; 36];
+        qual.extend(b"####");
+        assert_eq!(bwa_trim(&qual, 20, 33), 36);
+        assert_eq!(bwa_trim(&qual, 0, 33), 40);
+
+        // a good base in the tail is trimmed when the sum stays positive
+        let mut qual = vec![b'?'; 36];
+        qual.extend(b"#5##");
+        assert_eq!(bwa_trim(&qual, 20, 33), 36);
+
+        // never shorter than 35 bases
+        let mut qual = vec![b'?'; 33];
+        qual.extend(b"#####");
+        assert_eq!(bwa_trim(&qual, 20, 33), 35);
+        assert_eq!(bwa_trim(&qual[..35], 20, 33), 35);
+
+        // a high-quality tail stops the scan
+        let mut qual = vec![b'#'; 36];
+        qual.extend(b"II");
+        assert_eq!(bwa_trim(&qual, 20, 33), 38);
+
+        let seq = vec![b'A'; 40];
+        let mut qual = vec![b'?'; 36];
+        qual.extend(b"####");
+        let t = Trimmer::new(vec![TrimStep::Bwa(20)]).trim(&a_read(&seq, &qual));
+        assert_eq!((t.start, t.end), (0, 36));
+    }
+}
@@ -0,0 +1,569 @@
+use crate::errors::*;
+use crate::seq::*;
+use crate::xopen::*;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Signature of a UCSC .2bit file.
+pub const TWOBIT_SIGNATURE: u32 = 0x1A41_2743;
+
+// T=0, C=1, A=2, G=3
+const TWOBIT_BASES: [u8; 4] = [b'T', b'C', b'A', b'G'];
+
+/// A reader of UCSC .2bit files.
+///
+/// Files opened with `new()` are read on demand: only the index is loaded, and records are
+/// read with seeks, so whole genomes are never held in memory. Records can be read
+/// sequentially with `next()` or randomly with `get()`. N-blocks are decoded as `N` and
+/// mask-blocks as lowercase bases unless masking is disabled with `skip_mask()`.
+pub struct TwoBitReader {
+    src: Source,
+    big_endian: bool,
+    names: Vec<Vec<u8>>,
+    offsets: Vec<u64>,
+    name2idx: HashMap<Vec<u8>, usize>,
+    next_idx: usize,
+    mask: bool,
+    seq_buf: Vec<u8>,
+    buf: Vec<u8>, // raw bytes of the record being read
+}
+
+// the content of a .2bit file, in memory or read from a file on demand
+enum Source {
+    Bytes(Vec<u8>),
+    File(File, u64),
+}
+
+impl Source {
+    fn len(&self) -> u64 {
+        match self {
+            Source::Bytes(data) => data.len() as u64,
+            Source::File(_, len) => *len,
+        }
+    }
+
+    // reads n bytes at the position into the buffer, failing with the message if they are
+    // beyond the end of the file
+    fn read_at(
+        &mut self,
+        pos: u64,
+        n: u64,
+        buf: &mut Vec<u8>,
+        msg: &'static str,
+    ) -> Result<(), FastxErr> {
+        if pos.saturating_add(n) > self.len() {
+            return Err(FastxErr::InvalidTwoBitFormat(msg));
+        }
+        buf.clear();
+        match self {
+            Source::Bytes(data) => buf.extend_from_slice(&data[pos as usize..(pos + n) as usize]),
+            Source::File(file, _) => {
+                buf.resize(n as usize, 0);
+                file.seek(SeekFrom::Start(pos))?;
+                file.read_exact(buf)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TwoBitReader {
+    /// Creates a new TwoBitReader from a file path, reading only the index.
+    pub fn new<P: AsRef<Path>>(file: P) -> Result<Self, FastxErr> {
+        let file = File::open(long_path(file.as_ref()))?;
+        let len = file.metadata()?.len();
+        Self::from_source(Source::File(file, len))
+    }
+
+    /// Creates a new TwoBitReader from any Read, the data is read into memory.
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Self, FastxErr> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Self::from_bytes(data)
+    }
+
+    /// Creates a new TwoBitReader from the content of a .2bit file.
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, FastxErr> {
+        Self::from_source(Source::Bytes(data))
+    }
+
+    fn from_source(src: Source) -> Result<Self, FastxErr> {
+        let mut r = Self {
+            src,
+            big_endian: false,
+            names: Vec::new(),
+            offsets: Vec::new(),
+            name2idx: HashMap::new(),
+            next_idx: 0,
+            mask: true,
+            seq_buf: Vec::with_capacity(1 << 20),
+            buf: Vec::new(),
+        };
+
+        let mut header = Vec::new();
+        r.src
+            .read_at(0, 16, &mut header, "unexpected end of file")?;
+        let sig = u32_of(false, &header[0..4]);
+        if sig != TWOBIT_SIGNATURE {
+            if sig.swap_bytes() != TWOBIT_SIGNATURE {
+                return Err(FastxErr::InvalidTwoBitFormat("bad signature"));
+            }
+            r.big_endian = true;
+        }
+        if u32_of(r.big_endian, &header[4..8]) != 0 {
+            return Err(FastxErr::InvalidTwoBitFormat("unsupported version"));
+        }
+        let n = u32_of(r.big_endian, &header[8..12]) as usize;
+
+        let (names, offsets) = (&mut r.names, &mut r.offsets);
+        match &mut r.src {
+            Source::Bytes(data) => read_index(&data[16..], n, r.big_endian, names, offsets),
+            Source::File(file, _) => {
+                file.seek(SeekFrom::Start(16))?;
+                read_index(BufReader::new(file), n, r.big_endian, names, offsets)
+            }
+        }?;
+        for (idx, name) in r.names.iter().enumerate() {
+            r.name2idx.insert(name.clone(), idx);
+        }
+        Ok(r)
+    }
+
+    /// Do not apply mask-blocks, all bases are returned in uppercase.
+    pub fn skip_mask(&mut self) {
+        self.mask = false
+    }
+
+    /// Returns the number of records.
+    pub fn num_records(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Returns the IDs of all records, in the order of the file index.
+    pub fn ids(&self) -> &[Vec<u8>] {
+        &self.names
+    }
+
+    /// Returns the record with the given ID, or None if it does not exist.
+    pub fn get(&mut self, id: &[u8]) -> Option<Result<Seq<'_>, FastxErr>> {
+        let idx = *self.name2idx.get(id)?;
+        Some(self.read_record(idx))
+    }
+
+    // returns None if all records are read, otherwise returns Some(Ok(Seq)) or Some(Err(e))
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Result<Seq<'_>, FastxErr>> {
+        if self.next_idx >= self.names.len() {
+            return None;
+        }
+        self.next_idx += 1;
+        Some(self.read_record(self.next_idx - 1))
+    }
+
+    fn read_record(&mut self, idx: usize) -> Result<Seq<'_>, FastxErr> {
+        let mut pos = self.offsets[idx];
+        let dna_size = self.u32_at(pos)? as usize;
+        pos += 4;
+        let (n_blocks, p) = self.read_blocks(pos)?;
+        let (mask_blocks, p) = self.read_blocks(p)?;
+        pos = p + 4; // reserved
+
+        self.src.read_at(
+            pos,
+            dna_size.div_ceil(4) as u64,
+            &mut self.buf,
+            "truncated sequence",
+        )?;
+
+        self.seq_buf.clear();
+        self.seq_buf.reserve(dna_size);
+        for i in 0..dna_size {
+            let byte = self.buf[i >> 2];
+            self.seq_buf
+                .push(TWOBIT_BASES[((byte >> (6 - 2 * (i & 3))) & 3) as usize]);
+        }
+        for (start, size) in n_blocks {
+            let end = (start + size).min(dna_size);
+            self.seq_buf[start.min(end)..end].fill(b'N');
+        }
+        if self.mask {
+            for (start, size) in mask_blocks {
+                let end = (start + size).min(dna_size);
+                self.seq_buf[start.min(end)..end].make_ascii_lowercase();
+            }
+        }
+
+        Ok(Seq {
+            id: &self.names[idx],
+            desc: &[],
+            seq: &self.seq_buf,
+            qual: None,
+        })
+    }
+
+    // read a block list: count, starts, sizes
+    fn read_blocks(&mut self, pos: u64) -> Result<(Vec<(usize, usize)>, u64), FastxErr> {
+        let count = self.u32_at(pos)? as u64;
+        // the count comes from the file, it is checked before allocating
+        self.src
+            .read_at(pos + 4, 8 * count, &mut self.buf, "truncated block list")?;
+        let (starts, sizes) = self.buf.split_at(4 * count as usize);
+        let blocks = starts
+            .chunks_exact(4)
+            .zip(sizes.chunks_exact(4))
+            .map(|(start, size)| {
+                (
+                    u32_of(self.big_endian, start) as usize,
+                    u32_of(self.big_endian, size) as usize,
+                )
+            })
+            .collect();
+        Ok((blocks, pos + 4 + 8 * count))
+    }
+
+    #[inline]
+    fn u32_at(&mut self, pos: u64) -> Result<u32, FastxErr> {
+        self.src
+            .read_at(pos, 4, &mut self.buf, "unexpected end of file")?;
+        Ok(u32_of(self.big_endian, &self.buf))
+    }
+}
+
+#[inline]
+fn u32_of(big_endian: bool, b: &[u8]) -> u32 {
+    let b: [u8; 4] = b[..4].try_into().unwrap();
+    if big_endian {
+        u32::from_be_bytes(b)
+    } else {
+        u32::from_le_bytes(b)
+    }
+}
+
+// reads n index entries: the name length, the name and the record offset
+fn read_index<R: Read>(
+    mut r: R,
+    n: usize,
+    big_endian: bool,
+    names: &mut Vec<Vec<u8>>,
+    offsets: &mut Vec<u64>,
+) -> Result<(), FastxErr> {
+    let truncated = |e: io::Error| match e.kind() {
+        io::ErrorKind::UnexpectedEof => FastxErr::InvalidTwoBitFormat("truncated index"),
+        _ => FastxErr::IOError(e),
+    };
+    let mut b4 = [0u8; 4];
+    for _ in 0..n {
+        let mut name_len = [0u8; 1];
+        r.read_exact(&mut name_len).map_err(truncated)?;
+        let mut name = vec![0u8; name_len[0] as usize];
+        r.read_exact(&mut name).map_err(truncated)?;
+        r.read_exact(&mut b4).map_err(truncated)?;
+        names.push(name);
+        offsets.push(u32_of(big_endian, &b4) as u64);
+    }
+    Ok(())
+}
+
+/// A writer of UCSC .2bit files.
+///
+/// Because the file index stores the offsets of all records, records are packed first and
+/// written out after the index by `finish()`. Writers created with `new()` keep packed records
+/// in a temporary file next to the output (`<file>.tmp`), so only names and sizes are held in
+/// memory, while writers created with `from_writer()` keep them in memory.
+pub struct TwoBitWriter<W: Write> {
+    writer: W,
+    names: Vec<Vec<u8>>,
+    name_set: HashSet<Vec<u8>>,
+    sizes: Vec<u64>,
+    records: Records,
+}
+
+// packed records waiting for the index
+enum Records {
+    Mem(Vec<u8>),
+    File(SpillFile),
+}
+
+// a temporary file, removed on drop
+struct SpillFile {
+    path: PathBuf,
+    file: BufWriter<File>,
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl TwoBitWriter<File> {
+    /// Creates a new TwoBitWriter to a file path.
+    pub fn new<P: AsRef<Path>>(file: P) -> Result<Self, FastxErr> {
+        let path = long_path(file.as_ref());
+        let mut tmp = OsString::from(path.as_os_str());
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        let spill = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&tmp)?;
+        let mut w = Self::from_writer(File::create(&path)?);
+        w.records = Records::File(SpillFile {
+            path: tmp,
+            file: BufWriter::new(spill),
+        });
+        Ok(w)
+    }
+}
+
+impl<W: Write> TwoBitWriter<W> {
+    /// Creates a new TwoBitWriter from any Write.
+    pub fn from_writer(writer: W) -> Self {
+        Self {
+            writer,
+            names: Vec::new(),
+            name_set: HashSet::new(),
+            sizes: Vec::new(),
+            records: Records::Mem(Vec::new()),
+        }
+    }
+    /// Adds a record. Non-ACGT bases are stored as N-blocks, lowercase bases as mask-blocks.
+    /// Sequence names must be unique, as records are looked up by name.
+    pub fn write(&mut self, seq: &Seq) -> Result<(), FastxErr> {
+        if seq.id.len() > 255 {
+            return Err(FastxErr::InvalidTwoBitFormat(
+                "sequence name longer than 255",
+            ));
+        }
+        if self.name_set.contains(seq.id) {
+            return Err(FastxErr::InvalidTwoBitFormat("duplicate sequence name"));
+        }
+        let dna_size = u32::try_from(seq.seq.len())
+            .map_err(|_| FastxErr::InvalidTwoBitFormat("sequence longer than 4 Gb"))?;
+
+        let n_blocks = find_blocks(seq.seq, |b| {
+            !matches!(b, b'A' | b'C' | b'G' | b'T' | b'a' | b'c' | b'g' | b't')
+        });
+        let mask_blocks = find_blocks(seq.seq, |b| b.is_ascii_lowercase());
+
+        let mut rec = Vec::with_capacity(16 + seq.seq.len().div_ceil(4));
+        rec.extend_from_slice(&dna_size.to_le_bytes());
+        push_blocks(&mut rec, &n_blocks);
+        push_blocks(&mut rec, &mask_blocks);
+        rec.extend_from_slice(&0u32.to_le_bytes()); // reserved
+        for chunk in seq.seq.chunks(4) {
+            let mut byte = 0u8;
+            for (i, &b) in chunk.iter().enumerate() {
+                let code = match b {
+                    b'C' | b'c' => 1,
+                    b'A' | b'a' => 2,
+                    b'G' | b'g' => 3,
+                    _ => 0,
+                };
+                byte |= code << (6 - 2 * i);
+            }
+            rec.push(byte);
+        }
+
+        match &mut self.records {
+            Records::Mem(data) => data.extend_from_slice(&rec),
+            Records::File(spill) => spill.file.write_all(&rec)?,
+        }
+        self.names.push(seq.id.to_vec());
+        self.name_set.insert(seq.id.to_vec());
+        self.sizes.push(rec.len() as u64);
+        Ok(())
+    }
+
+    /// Writes the header, the index and all records, and returns the underlying writer.
+    /// Nothing is written if the file would be too large for the 32-bit offsets of the index.
+    pub fn finish(self) -> Result<W, FastxErr> {
+        let too_large = || FastxErr::InvalidTwoBitFormat("file larger than 4 GB");
+        let Self {
+            mut writer,
+            names,
+            sizes,
+            records,
+            ..
+        } = self;
+
+        let count = u32::try_from(names.len()).map_err(|_| too_large())?;
+        let index_size: u64 = names.iter().map(|n| 1 + n.len() as u64 + 4).sum();
+        let mut offset = 16 + index_size;
+        let mut offsets = Vec::with_capacity(sizes.len());
+        for size in &sizes {
+            offsets.push(u32::try_from(offset).map_err(|_| too_large())?);
+            offset += size;
+        }
+        u32::try_from(offset).map_err(|_| too_large())?;
+
+        let w = &mut writer;
+        w.write_all(&TWOBIT_SIGNATURE.to_le_bytes())?;
+        w.write_all(&0u32.to_le_bytes())?;
+        w.write_all(&count.to_le_bytes())?;
+        w.write_all(&0u32.to_le_bytes())?;
+        for (name, offset) in names.iter().zip(offsets) {
+            w.write_all(&[name.len() as u8])?;
+            w.write_all(name)?;
+            w.write_all(&offset.to_le_bytes())?;
+        }
+        match records {
+            Records::Mem(data) => w.write_all(&data)?,
+            Records::File(mut spill) => {
+                spill.file.flush()?;
+                let file = spill.file.get_mut();
+                file.seek(SeekFrom::Start(0))?;
+                io::copy(file, w)?;
+            }
+        }
+        w.flush()?;
+        Ok(writer)
+    }
+}
+
+// find runs of bases matching the predicate, as (start, size)
+fn find_blocks<F: Fn(u8) -> bool>(seq: &[u8], f: F) -> Vec<(u32, u32)> {
+    let mut blocks = Vec::new();
+    let mut start: Option<usize> = None;
+    for (i, &b) in seq.iter().enumerate() {
+        match (f(b), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                blocks.push((s as u32, (i - s) as u32));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        blocks.push((s as u32, (seq.len() - s) as u32));
+    }
+    blocks
+}
+
+fn push_blocks(rec: &mut Vec<u8>, blocks: &[(u32, u32)]) {
+    rec.extend_from_slice(&(blocks.len() as u32).to_le_bytes());
+    for (start, _) in blocks {
+        rec.extend_from_slice(&start.to_le_bytes());
+    }
+    for (_, size) in blocks {
+        rec.extend_from_slice(&size.to_le_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn a_seq<'a>(id: &'a [u8], seq: &'a [u8]) -> Seq<'a> {
+        Seq {
+            id,
+            desc: b"",
+            seq,
+            qual: None,
+        }
+    }
+
+    fn to_twobit(records: &[Seq]) -> Vec<u8> {
+        let mut w = TwoBitWriter::from_writer(Vec::new());
+        for r in records {
+            w.write(r).unwrap();
+        }
+        w.finish().unwrap()
+    }
+
+    #[test]
+    fn test_twobit_roundtrip() {
+        let records = [
+            a_seq(b"chr1", b"ACGTNNNNacgtnACG"),
+            a_seq(b"chr2", b"TTTTT"),
+            a_seq(b"chrEmpty", b""),
+        ];
+        let mut r = TwoBitReader::from_bytes(to_twobit(&records)).unwrap();
+        assert_eq!(r.num_records(), 3);
+
+        let mut i = 0;
+        while let Some(res) = r.next() {
+            let seq = res.unwrap();
+            assert_eq!(seq.id, records[i].id);
+            assert_eq!(seq.seq, records[i].seq);
+            i += 1;
+        }
+        assert_eq!(i, 3);
+    }
+
+    #[test]
+    fn test_twobit_random_access_and_mask() {
+        let records = [a_seq(b"chr1", b"ACGT"), a_seq(b"chr2", b"acgTTn")];
+        let mut r = TwoBitReader::from_bytes(to_twobit(&records)).unwrap();
+        assert_eq!(r.get(b"chr2").unwrap().unwrap().seq, b"acgTTn");
+        assert!(r.get(b"chr3").is_none());
+
+        r.skip_mask();
+        assert_eq!(r.get(b"chr2").unwrap().unwrap().seq, b"ACGTTN");
+    }
+
+    #[test]
+    fn test_twobit_file() {
+        let path = std::env::temp_dir().join(format!("fastseq_twobit_{}.2bit", std::process::id()));
+        let mut w = TwoBitWriter::new(&path).unwrap();
+        w.write(&a_seq(b"chr1", b"ACGTNNacgt")).unwrap();
+        w.write(&a_seq(b"chr2", b"GGGG")).unwrap();
+        w.finish().unwrap();
+        assert!(!path.with_extension("2bit.tmp").exists());
+
+        // the same content as written in memory, read on demand
+        let records = [a_seq(b"chr1", b"ACGTNNacgt"), a_seq(b"chr2", b"GGGG")];
+        assert_eq!(fs::read(&path).unwrap(), to_twobit(&records));
+        let mut r = TwoBitReader::new(&path).unwrap();
+        assert_eq!(r.ids(), [b"chr1".to_vec(), b"chr2".to_vec()]);
+        assert_eq!(r.get(b"chr2").unwrap().unwrap().seq, b"GGGG");
+        assert_eq!(r.next().unwrap().unwrap().seq, b"ACGTNNacgt");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_twobit_known_encoding() {
+        // "TCAG" packs into a single byte 0b00_01_10_11
+        let data = to_twobit(&[a_seq(b"s", b"TCAG")]);
+        assert_eq!(&data[..4], &[0x43, 0x27, 0x41, 0x1A]);
+        assert_eq!(*data.last().unwrap(), 0b0001_1011);
+    }
+
+    #[test]
+    fn test_twobit_duplicate_names() {
+        let mut w = TwoBitWriter::from_writer(Vec::new());
+        w.write(&a_seq(b"chr1", b"ACGT")).unwrap();
+        assert!(matches!(
+            w.write(&a_seq(b"chr1", b"GG")),
+            Err(FastxErr::InvalidTwoBitFormat("duplicate sequence name"))
+        ));
+        w.write(&a_seq(b"chr2", b"GG")).unwrap();
+        let mut r = TwoBitReader::from_bytes(w.finish().unwrap()).unwrap();
+        assert_eq!(r.num_records(), 2);
+        assert_eq!(r.get(b"chr1").unwrap().unwrap().seq, b"ACGT");
+    }
+
+    #[test]
+    fn test_twobit_invalid() {
+        assert!(matches!(
+            TwoBitReader::from_bytes(b">seq\nACGT\n".to_vec()),
+            Err(FastxErr::InvalidTwoBitFormat(_))
+        ));
+
+        // a corrupt N-block count: header, index entry of "s", and the DNA size before it
+        let mut data = to_twobit(&[a_seq(b"s", b"ACGT")]);
+        let i = 16 + 1 + 1 + 4 + 4;
+        data[i..i + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let mut r = TwoBitReader::from_bytes(data).unwrap();
+        assert!(matches!(
+            r.next(),
+            Some(Err(FastxErr::InvalidTwoBitFormat("truncated block list")))
+        ));
+    }
+}
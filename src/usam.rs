@@ -0,0 +1,486 @@
+use crate::errors::*;
+use crate::pair::*;
+use crate::reader::*;
+use crate::seq::*;
+#[cfg(feature = "gzip")]
+use flate2::{Compression, Crc, write::DeflateEncoder};
+use std::io::{BufRead, Write};
+
+/// SAM flag of unpaired unmapped reads.
+pub const FLAG_UNPAIRED: u16 = 0x4;
+/// SAM flag of read 1 of unmapped pairs: paired, unmapped, mate unmapped, first segment.
+pub const FLAG_READ1: u16 = 0x1 | 0x4 | 0x8 | 0x40;
+/// SAM flag of read 2 of unmapped pairs: paired, unmapped, mate unmapped, last segment.
+pub const FLAG_READ2: u16 = 0x1 | 0x4 | 0x8 | 0x80;
+
+/// Read-group metadata, written as an @RG header line, and as RG tags of the records of the group.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReadGroup {
+    /// Read group identifier, `ID`.
+    pub id: String,
+    /// Sample, `SM`.
+    pub sample: Option<String>,
+    /// Library, `LB`.
+    pub library: Option<String>,
+    /// Platform, `PL`, e.g., ILLUMINA.
+    pub platform: Option<String>,
+    /// Platform unit, `PU`, e.g., `{flowcell}.{lane}.{barcode}`.
+    pub platform_unit: Option<String>,
+    /// Sequencing center, `CN`.
+    pub center: Option<String>,
+}
+
+impl ReadGroup {
+    /// Creates a read group with an ID.
+    pub fn new(id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Creates a read group from a Casava 1.8+ read name (`instrument:run:flowcell:lane:tile:x:y`)
+    /// and description (`read:filtered:control:barcode`), like GATK best practices: ID is
+    /// `{flowcell}.{lane}`, PU is `{flowcell}.{lane}.{barcode}`, and PL is ILLUMINA.
+    /// Returns None if the name is not in the format.
+    pub fn from_illumina(id: &[u8], desc: &[u8]) -> Option<Self> {
+        let id = std::str::from_utf8(id).ok()?;
+        let fields: Vec<&str> = id.split(':').collect();
+        if fields.len() != 7 || fields[3].parse::<u32>().is_err() {
+            return None;
+        }
+        let rg = format!("{}.{}", fields[2], fields[3]);
+        let barcode = std::str::from_utf8(desc)
+            .ok()
+            .and_then(|d| d.split(':').nth(3))
+            .filter(|b| !b.is_empty());
+        Some(Self {
+            platform_unit: Some(match barcode {
+                Some(b) => format!("{rg}.{b}"),
+                None => rg.clone(),
+            }),
+            id: rg,
+            platform: Some("ILLUMINA".to_string()),
+            ..Default::default()
+        })
+    }
+
+    /// Returns the @RG header line, with a trailing newline.
+    pub fn header_line(&self) -> String {
+        let mut line = format!("@RG\tID:{}", self.id);
+        for (tag, value) in [
+            ("SM", &self.sample),
+            ("LB", &self.library),
+            ("PL", &self.platform),
+            ("PU", &self.platform_unit),
+            ("CN", &self.center),
+        ] {
+            if let Some(v) = value {
+                line.push_str(&format!("\t{tag}:{v}"));
+            }
+        }
+        line.push('\n');
+        line
+    }
+}
+
+// the output of a UsamWriter
+enum Output<W: Write> {
+    Sam(W),
+    #[cfg(feature = "gzip")]
+    Bam(BgzfWriter<W>),
+}
+
+/// A writer of unaligned SAM (uSAM) or BAM (uBAM) records with read-group metadata, e.g., for
+/// ingesting FASTQ files into pipelines starting from unaligned BAM files like GATK.
+///
+/// Records are written unmapped, with read names without mate suffixes, quality scores
+/// converted from phred+33, and an RG tag if given.
+pub struct UsamWriter<W: Write> {
+    out: Output<W>,
+    groups: Vec<String>,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> UsamWriter<W> {
+    /// Creates a uSAM writer, and writes the header with the read groups.
+    pub fn sam(writer: W, groups: &[ReadGroup]) -> Result<Self, FastxErr> {
+        let mut w = Self {
+            out: Output::Sam(writer),
+            groups: groups.iter().map(|g| g.id.clone()).collect(),
+            buf: Vec::with_capacity(1024),
+        };
+        let header = header_text(groups);
+        w.write_out(header.as_bytes())?;
+        Ok(w)
+    }
+
+    /// Creates a uBAM writer, compressing with BGZF, and writes the header with the read groups.
+    #[cfg(feature = "gzip")]
+    pub fn bam(writer: W, groups: &[ReadGroup]) -> Result<Self, FastxErr> {
+        let mut w = Self {
+            out: Output::Bam(BgzfWriter::new(writer)),
+            groups: groups.iter().map(|g| g.id.clone()).collect(),
+            buf: Vec::with_capacity(1024),
+        };
+        let header = header_text(groups);
+        let mut data = Vec::with_capacity(header.len() + 12);
+        data.extend_from_slice(b"BAM\x01");
+        data.extend_from_slice(&(header.len() as i32).to_le_bytes());
+        data.extend_from_slice(header.as_bytes());
+        data.extend_from_slice(&0i32.to_le_bytes()); // no reference sequences
+        w.write_out(&data)?;
+        Ok(w)
+    }
+
+    /// Writes an unpaired read, with the RG tag of a read group given at creation.
+    pub fn write(&mut self, seq: &Seq, rg: Option<&str>) -> Result<(), FastxErr> {
+        self.write_record(seq, FLAG_UNPAIRED, rg)
+    }
+
+    /// Writes both mates of a read pair.
+    pub fn write_pair(&mut self, r1: &Seq, r2: &Seq, rg: Option<&str>) -> Result<(), FastxErr> {
+        self.write_record(r1, FLAG_READ1, rg)?;
+        self.write_record(r2, FLAG_READ2, rg)
+    }
+
+    /// Flushes and finishes the output (the end-of-file block of BAM), and returns the writer.
+    pub fn finish(self) -> Result<W, FastxErr> {
+        match self.out {
+            Output::Sam(mut w) => {
+                w.flush()?;
+                Ok(w)
+            }
+            #[cfg(feature = "gzip")]
+            Output::Bam(w) => Ok(w.finish()?),
+        }
+    }
+
+    fn write_out(&mut self, data: &[u8]) -> Result<(), FastxErr> {
+        match &mut self.out {
+            Output::Sam(w) => w.write_all(data)?,
+            #[cfg(feature = "gzip")]
+            Output::Bam(w) => w.write_all(data)?,
+        }
+        Ok(())
+    }
+
+    fn write_record(&mut self, seq: &Seq, flag: u16, rg: Option<&str>) -> Result<(), FastxErr> {
+        if let Some(rg) = rg
+            && !self.groups.iter().any(|g| g == rg)
+        {
+            return Err(FastxErr::InvalidSamRecord(format!(
+                "unknown read group: {rg}"
+            )));
+        }
+        let name = seq.mate_id().0;
+        if name.is_empty() || name.len() > 254 {
+            return Err(FastxErr::InvalidSamRecord(format!(
+                "invalid read name: {}",
+                String::from_utf8_lossy(name)
+            )));
+        }
+        if let Some(qual) = seq.qual
+            && qual.iter().any(|&q| q < 33)
+        {
+            return Err(FastxErr::InvalidSamRecord(format!(
+                "quality scores of {} are not phred+33",
+                String::from_utf8_lossy(name)
+            )));
+        }
+
+        let mut buf = std::mem::take(&mut self.buf);
+        buf.clear();
+        match self.out {
+            Output::Sam(_) => sam_record(&mut buf, name, seq, flag, rg),
+            #[cfg(feature = "gzip")]
+            Output::Bam(_) => bam_record(&mut buf, name, seq, flag, rg),
+        }
+        let res = self.write_out(&buf);
+        self.buf = buf;
+        res
+    }
+}
+
+/// Writes all records of a reader as unpaired reads, and returns the number of records.
+pub fn reads_to_usam<R: BufRead, W: Write>(
+    reader: &mut Reader<R>,
+    writer: &mut UsamWriter<W>,
+    rg: Option<&str>,
+) -> Result<u64, FastxErr> {
+    let mut n = 0u64;
+    while let Some(res) = reader.next() {
+        writer.write(&res?, rg)?;
+        n += 1;
+    }
+    Ok(n)
+}
+
+/// Writes all pairs of a paired reader, and returns the number of pairs.
+pub fn pairs_to_usam<R1: BufRead, R2: BufRead, W: Write>(
+    reader: &mut PairedReader<R1, R2>,
+    writer: &mut UsamWriter<W>,
+    rg: Option<&str>,
+) -> Result<u64, FastxErr> {
+    let mut n = 0u64;
+    while let Some(res) = reader.next() {
+        let (r1, r2) = res?;
+        writer.write_pair(&r1, &r2, rg)?;
+        n += 1;
+    }
+    Ok(n)
+}
+
+fn header_text(groups: &[ReadGroup]) -> String {
+    let mut header = String::from("@HD\tVN:1.6\tSO:unsorted\n");
+    for g in groups {
+        header.push_str(&g.header_line());
+    }
+    header.push_str(&format!(
+        "@PG\tID:fastseq\tPN:fastseq\tVN:{}\n",
+        env!("CARGO_PKG_VERSION")
+    ));
+    header
+}
+
+fn sam_record(buf: &mut Vec<u8>, name: &[u8], seq: &Seq, flag: u16, rg: Option<&str>) {
+    buf.extend_from_slice(name);
+    buf.extend_from_slice(format!("\t{flag}\t*\t0\t0\t*\t*\t0\t0\t").as_bytes());
+    if seq.seq.is_empty() {
+        buf.extend_from_slice(b"*\t*");
+    } else {
+        buf.extend_from_slice(seq.seq);
+        buf.push(b'\t');
+        match seq.qual {
+            Some(qual) => buf.extend_from_slice(qual),
+            None => buf.push(b'*'),
+        }
+    }
+    if let Some(rg) = rg {
+        buf.extend_from_slice(b"\tRG:Z:");
+        buf.extend_from_slice(rg.as_bytes());
+    }
+    buf.push(b'\n');
+}
+
+#[cfg(feature = "gzip")]
+fn bam_record(buf: &mut Vec<u8>, name: &[u8], seq: &Seq, flag: u16, rg: Option<&str>) {
+    const CODES: &[u8; 16] = b"=ACMGRSVTWYHKDBN";
+    let code = |b: u8| {
+        let b = b.to_ascii_uppercase();
+        CODES.iter().position(|&c| c == b).unwrap_or(15) as u8
+    };
+
+    buf.extend_from_slice(&[0; 4]); // block_size, set at the end
+    buf.extend_from_slice(&(-1i32).to_le_bytes()); // refID
+    buf.extend_from_slice(&(-1i32).to_le_bytes()); // pos
+    buf.push(name.len() as u8 + 1);
+    buf.push(0); // mapq
+    buf.extend_from_slice(&4680u16.to_le_bytes()); // bin of unmapped reads
+    buf.extend_from_slice(&0u16.to_le_bytes()); // n_cigar_op
+    buf.extend_from_slice(&flag.to_le_bytes());
+    buf.extend_from_slice(&(seq.seq.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&(-1i32).to_le_bytes()); // next_refID
+    buf.extend_from_slice(&(-1i32).to_le_bytes()); // next_pos
+    buf.extend_from_slice(&0i32.to_le_bytes()); // tlen
+    buf.extend_from_slice(name);
+    buf.push(0);
+    for pair in seq.seq.chunks(2) {
+        let low = pair.get(1).map_or(0, |&b| code(b));
+        buf.push(code(pair[0]) << 4 | low);
+    }
+    match seq.qual {
+        Some(qual) => buf.extend(qual.iter().map(|q| q - 33)),
+        None => buf.extend(std::iter::repeat_n(0xff, seq.seq.len())),
+    }
+    if let Some(rg) = rg {
+        buf.extend_from_slice(b"RGZ");
+        buf.extend_from_slice(rg.as_bytes());
+        buf.push(0);
+    }
+    let size = (buf.len() - 4) as i32;
+    buf[..4].copy_from_slice(&size.to_le_bytes());
+}
+
+/// Maximum size of uncompressed data of a BGZF block, as htslib.
+#[cfg(feature = "gzip")]
+const BGZF_BLOCK_SIZE: usize = 0xff00;
+
+/// The empty block marking the end of a BGZF file.
+#[cfg(feature = "gzip")]
+const BGZF_EOF: [u8; 28] = [
+    0x1f, 0x8b, 8, 4, 0, 0, 0, 0, 0, 0xff, 6, 0, b'B', b'C', 2, 0, 0x1b, 0, 3, 0, 0, 0, 0, 0, 0, 0,
+    0, 0,
+];
+
+// a writer of BGZF, gzip members of at most 64 KiB with their sizes in the BC extra field
+#[cfg(feature = "gzip")]
+struct BgzfWriter<W: Write> {
+    inner: W,
+    buf: Vec<u8>,
+}
+
+#[cfg(feature = "gzip")]
+impl<W: Write> BgzfWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            buf: Vec::with_capacity(BGZF_BLOCK_SIZE),
+        }
+    }
+
+    fn write_block(&mut self) -> std::io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&self.buf)?;
+        let cdata = encoder.finish()?;
+        let mut crc = Crc::new();
+        crc.update(&self.buf);
+
+        let bsize = (18 + cdata.len() + 8 - 1) as u16;
+        let mut header = [
+            0x1f, 0x8b, 8, 4, 0, 0, 0, 0, 0, 0xff, 6, 0, b'B', b'C', 2, 0, 0, 0,
+        ];
+        header[16..].copy_from_slice(&bsize.to_le_bytes());
+        self.inner.write_all(&header)?;
+        self.inner.write_all(&cdata)?;
+        self.inner.write_all(&crc.sum().to_le_bytes())?;
+        self.inner
+            .write_all(&(self.buf.len() as u32).to_le_bytes())?;
+        self.buf.clear();
+        Ok(())
+    }
+
+    fn finish(mut self) -> std::io::Result<W> {
+        self.write_block()?;
+        self.inner.write_all(&BGZF_EOF)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl<W: Write> Write for BgzfWriter<W> {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        let n = data.len().min(BGZF_BLOCK_SIZE - self.buf.len());
+        self.buf.extend_from_slice(&data[..n]);
+        if self.buf.len() == BGZF_BLOCK_SIZE {
+            self.write_block()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.write_block()?;
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const R1: &[u8] = b"@A00123:8:H7KJ2DSXY:2:1101:1000:2000 1:N:0:ACGTACGT\nACGTN\n+\nIIII#\n";
+    const R2: &[u8] = b"@A00123:8:H7KJ2DSXY:2:1101:1000:2000 2:N:0:ACGTACGT\nTTG\n+\n?I5\n";
+
+    fn sample_group() -> ReadGroup {
+        let mut rg =
+            ReadGroup::from_illumina(b"A00123:8:H7KJ2DSXY:2:1101:1000:2000", b"1:N:0:ACGTACGT")
+                .unwrap();
+        rg.sample = Some("NA12878".to_string());
+        rg.library = Some("lib1".to_string());
+        rg
+    }
+
+    #[test]
+    fn test_read_group() {
+        let rg = sample_group();
+        assert_eq!(rg.id, "H7KJ2DSXY.2");
+        assert_eq!(
+            rg.header_line(),
+            "@RG\tID:H7KJ2DSXY.2\tSM:NA12878\tLB:lib1\tPL:ILLUMINA\tPU:H7KJ2DSXY.2.ACGTACGT\n"
+        );
+        assert_eq!(ReadGroup::new("x").header_line(), "@RG\tID:x\n");
+        assert!(ReadGroup::from_illumina(b"read1", b"").is_none());
+    }
+
+    #[test]
+    fn test_usam_writer() {
+        let rg = sample_group();
+        let mut w = UsamWriter::sam(Vec::new(), std::slice::from_ref(&rg)).unwrap();
+        let mut pr = PairedReader::from_readers(Reader::from_bytes(R1), Reader::from_bytes(R2));
+        assert_eq!(pairs_to_usam(&mut pr, &mut w, Some(&rg.id)).unwrap(), 1);
+        let n = reads_to_usam(&mut Reader::from_bytes(b">r/1\nAC\n>e\n\n"), &mut w, None).unwrap();
+        assert_eq!(n, 2);
+        let out = String::from_utf8(w.finish().unwrap()).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "@HD\tVN:1.6\tSO:unsorted");
+        assert!(lines[1].starts_with("@RG\tID:H7KJ2DSXY.2\t"));
+        assert!(lines[2].starts_with("@PG\tID:fastseq"));
+        assert_eq!(
+            lines[3],
+            "A00123:8:H7KJ2DSXY:2:1101:1000:2000\t77\t*\t0\t0\t*\t*\t0\t0\tACGTN\tIIII#\tRG:Z:H7KJ2DSXY.2"
+        );
+        assert!(lines[4].starts_with("A00123:8:H7KJ2DSXY:2:1101:1000:2000\t141\t"));
+        assert_eq!(lines[5], "r\t4\t*\t0\t0\t*\t*\t0\t0\tAC\t*");
+        assert_eq!(lines[6], "e\t4\t*\t0\t0\t*\t*\t0\t0\t*\t*");
+
+        let mut w = UsamWriter::sam(Vec::new(), &[]).unwrap();
+        let seq = Seq {
+            id: b"r",
+            desc: b"",
+            seq: b"A",
+            qual: None,
+        };
+        assert!(matches!(
+            w.write(&seq, Some("x")),
+            Err(FastxErr::InvalidSamRecord(_))
+        ));
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_ubam_writer() {
+        use flate2::read::MultiGzDecoder;
+        use std::io::Read;
+
+        let rg = sample_group();
+        let mut w = UsamWriter::bam(Vec::new(), std::slice::from_ref(&rg)).unwrap();
+        let mut pr = PairedReader::from_readers(Reader::from_bytes(R1), Reader::from_bytes(R2));
+        pairs_to_usam(&mut pr, &mut w, Some(&rg.id)).unwrap();
+        let bam = w.finish().unwrap();
+
+        let info = crate::util::sniff_reader(std::io::Cursor::new(&bam)).unwrap();
+        assert!(info.bgzf);
+        assert!(bam.ends_with(&BGZF_EOF));
+
+        let mut data = Vec::new();
+        MultiGzDecoder::new(bam.as_slice())
+            .read_to_end(&mut data)
+            .unwrap();
+        assert_eq!(&data[..4], b"BAM\x01");
+        let l_text = i32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+        let text = std::str::from_utf8(&data[8..8 + l_text]).unwrap();
+        assert!(text.contains("@RG\tID:H7KJ2DSXY.2\t"));
+
+        let rec = &data[8 + l_text + 4..];
+        let block_size = i32::from_le_bytes(rec[..4].try_into().unwrap()) as usize;
+        let r = &rec[4..4 + block_size];
+        assert_eq!(r[8] as usize, 36); // l_read_name
+        assert_eq!(u16::from_le_bytes([r[14], r[15]]), FLAG_READ1);
+        assert_eq!(u32::from_le_bytes(r[16..20].try_into().unwrap()), 5);
+        let seq_start = 32 + 36;
+        assert_eq!(
+            &r[32..seq_start - 1],
+            b"A00123:8:H7KJ2DSXY:2:1101:1000:2000"
+        );
+        assert_eq!(&r[seq_start..seq_start + 3], &[0x12, 0x48, 0xf0]); // AC GT N
+        assert_eq!(&r[seq_start + 3..seq_start + 8], &[40, 40, 40, 40, 2]);
+        assert_eq!(&r[seq_start + 8..], b"RGZH7KJ2DSXY.2\0");
+
+        let r2 = &rec[4 + block_size..];
+        assert_eq!(u16::from_le_bytes([r2[18], r2[19]]), FLAG_READ2);
+    }
+}
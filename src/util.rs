@@ -1,3 +1,8 @@
+use crate::xopen::*;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
 // pub(crate) fn trim_cr(line: &[u8]) -> &[u8] {
 //     if let Some((&b'\r', remaining)) = line.split_last() {
 //         remaining
@@ -26,3 +31,647 @@ pub(crate) fn trim_crlf(line: &[u8]) -> &[u8] {
     }
     &line[..end]
 }
+
+pub(crate) const FNV1A64_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+// 64-bit FNV-1a hash, stable across runs and platforms
+#[inline]
+pub(crate) fn fnv1a64(data: &[u8]) -> u64 {
+    fnv1a64_with_basis(FNV1A64_BASIS, data)
+}
+
+#[inline]
+pub(crate) fn fnv1a64_with_basis(basis: u64, data: &[u8]) -> u64 {
+    let mut h = basis;
+    for &b in data {
+        h ^= b as u64;
+        h = h.wrapping_mul(0x0100_0000_01b3);
+    }
+    h
+}
+
+/// A small, fast and seedable pseudo-random number generator (xoshiro256**),
+/// so sampling results are reproducible across runs and platforms.
+pub struct Rng {
+    s: [u64; 4],
+}
+
+impl Rng {
+    /// Creates a generator from a seed.
+    pub fn new(seed: u64) -> Self {
+        // expand the seed with splitmix64
+        let mut x = seed;
+        let mut s = [0u64; 4];
+        for v in s.iter_mut() {
+            x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = x;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            *v = z ^ (z >> 31);
+        }
+        Self { s }
+    }
+
+    /// Returns a random u64.
+    #[inline]
+    pub fn next_u64(&mut self) -> u64 {
+        let result = self.s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = self.s[1] << 17;
+        self.s[2] ^= self.s[0];
+        self.s[3] ^= self.s[1];
+        self.s[1] ^= self.s[2];
+        self.s[0] ^= self.s[3];
+        self.s[2] ^= t;
+        self.s[3] = self.s[3].rotate_left(45);
+        result
+    }
+
+    /// Returns a random f64 in [0, 1).
+    #[inline]
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+
+    /// Returns a random usize in [0, n), n must be > 0.
+    #[inline]
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+/// Matches a file name against a glob pattern supporting `*`, `?` and character classes
+/// like `[abc]`, `[a-z]` and `[!0-9]`. Path separators are not treated specially.
+pub fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // positions to restart from after the last `*`
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() {
+            match pattern[p] {
+                b'*' => {
+                    star = Some((p + 1, n));
+                    p += 1;
+                    continue;
+                }
+                b'?' => {
+                    p += 1;
+                    n += 1;
+                    continue;
+                }
+                b'[' => {
+                    if let Some((matched, end)) = match_class(&pattern[p..], name[n])
+                        && matched
+                    {
+                        p += end;
+                        n += 1;
+                        continue;
+                    }
+                }
+                c if c == name[n] => {
+                    p += 1;
+                    n += 1;
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        match star {
+            Some((sp, sn)) => {
+                p = sp;
+                n = sn + 1;
+                star = Some((sp, sn + 1));
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+// matches a byte against a character class at the start of the pattern,
+// returns whether it matches and the length of the class, or None for an unclosed class
+fn match_class(pattern: &[u8], b: u8) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negated = matches!(pattern.get(i), Some(b'!') | Some(b'^'));
+    if negated {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    while i < pattern.len() {
+        let c = pattern[i];
+        if c == b']' && !first {
+            return Some((matched != negated, i + 1));
+        }
+        first = false;
+        if i + 2 < pattern.len() && pattern[i + 1] == b'-' && pattern[i + 2] != b']' {
+            matched |= (c..=pattern[i + 2]).contains(&b);
+            i += 3;
+        } else {
+            matched |= c == b;
+            i += 1;
+        }
+    }
+    None
+}
+
+/// Compares two strings in natural order, where runs of digits are compared by numeric value,
+/// so "r2.fq" sorts before "r10.fq".
+pub fn natural_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+    natural_cmp_bytes(a.as_bytes(), b.as_bytes())
+}
+
+// natural_cmp on bytes, for IDs which are not necessarily UTF-8
+pub(crate) fn natural_cmp_bytes(a: &[u8], b: &[u8]) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i].is_ascii_digit() && b[j].is_ascii_digit() {
+            let si = i;
+            let sj = j;
+            while i < a.len() && a[i].is_ascii_digit() {
+                i += 1;
+            }
+            while j < b.len() && b[j].is_ascii_digit() {
+                j += 1;
+            }
+            // compare without leading zeros by length, then digit by digit
+            let da = &a[si..i];
+            let db = &b[sj..j];
+            let ta = &da[da.iter().take_while(|&&c| c == b'0').count()..];
+            let tb = &db[db.iter().take_while(|&&c| c == b'0').count()..];
+            let ord = ta.len().cmp(&tb.len()).then_with(|| ta.cmp(tb));
+            if ord != Ordering::Equal {
+                return ord;
+            }
+        } else {
+            if a[i] != b[j] {
+                return a[i].cmp(&b[j]);
+            }
+            i += 1;
+            j += 1;
+        }
+    }
+    (a.len() - i).cmp(&(b.len() - j)).then_with(|| a.cmp(b))
+}
+
+// writes bytes as a JSON string, invalid UTF-8 is replaced
+pub(crate) fn write_json_str<W: std::io::Write>(w: &mut W, s: &[u8]) -> std::io::Result<()> {
+    w.write_all(b"\"")?;
+    for c in String::from_utf8_lossy(s).chars() {
+        match c {
+            '"' => w.write_all(b"\\\"")?,
+            '\\' => w.write_all(b"\\\\")?,
+            '\n' => w.write_all(b"\\n")?,
+            '\r' => w.write_all(b"\\r")?,
+            '\t' => w.write_all(b"\\t")?,
+            c if (c as u32) < 0x20 => write!(w, "\\u{:04x}", c as u32)?,
+            c => write!(w, "{c}")?,
+        }
+    }
+    w.write_all(b"\"")
+}
+
+/// Details of the compression container of a file, from `sniff`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerInfo {
+    pub compression: CompressionFormat,
+    /// Size of the file.
+    pub compressed_size: u64,
+    /// Number of gzip members, zstd or lz4 frames, or xz streams, None if unknown.
+    pub members: Option<u64>,
+    /// Whether it is BGZF, i.e., gzip made of blocks with their sizes in headers,
+    /// which can be decompressed in parallel or randomly accessed.
+    pub bgzf: bool,
+    /// Uncompressed size from ISIZE fields, frame headers or the xz index, None if unknown.
+    pub uncompressed_size: Option<u64>,
+    /// Whether `uncompressed_size` is exact. The ISIZE of a plain gzip file is the size of
+    /// its last member modulo 2^32, so it is only an estimate.
+    pub exact_size: bool,
+}
+
+impl ContainerInfo {
+    /// Returns whether the file has multiple members or frames, None if unknown.
+    pub fn is_multi_member(&self) -> Option<bool> {
+        self.members.map(|n| n > 1)
+    }
+}
+
+/// Inspects the compression container of a file without decompressing it,
+/// so tools can choose between memory mapping, parallel decompression or streaming.
+///
+/// Only headers, footers and block boundaries are read: all blocks of BGZF files,
+/// zstd and lz4 frames are walked, and the index of xz streams is parsed.
+pub fn sniff<P: AsRef<Path>>(path: P) -> io::Result<ContainerInfo> {
+    sniff_reader(File::open(long_path(path.as_ref()))?)
+}
+
+/// Like `sniff`, but reads from any seekable reader.
+pub fn sniff_reader<R: Read + Seek>(mut r: R) -> io::Result<ContainerInfo> {
+    let size = r.seek(SeekFrom::End(0))?;
+    let mut magic = [0u8; 6];
+    let n = read_at(&mut r, 0, &mut magic)?;
+    let mut info = ContainerInfo {
+        compression: detect_compression(&magic[..n]),
+        compressed_size: size,
+        members: None,
+        bgzf: false,
+        uncompressed_size: None,
+        exact_size: false,
+    };
+    match info.compression {
+        CompressionFormat::None => {
+            info.members = Some(1);
+            info.uncompressed_size = Some(size);
+            info.exact_size = true;
+        }
+        CompressionFormat::Gzip => sniff_gzip(&mut r, &mut info)?,
+        CompressionFormat::Zstd => sniff_frames(&mut r, &mut info, zstd_frame)?,
+        CompressionFormat::Lz4 => sniff_frames(&mut r, &mut info, lz4_frame)?,
+        CompressionFormat::Xz => sniff_xz(&mut r, &mut info)?,
+        CompressionFormat::Bzip2 => {}
+    }
+    Ok(info)
+}
+
+// reads as many bytes as possible at the offset
+fn read_at<R: Read + Seek>(r: &mut R, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+    r.seek(SeekFrom::Start(offset))?;
+    let mut n = 0;
+    while n < buf.len() {
+        match r.read(&mut buf[n..])? {
+            0 => break,
+            m => n += m,
+        }
+    }
+    Ok(n)
+}
+
+fn read_exact_at<R: Read + Seek>(r: &mut R, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+    r.seek(SeekFrom::Start(offset))?;
+    r.read_exact(buf)
+}
+
+// the block size of a BGZF block header
+pub(crate) fn bgzf_block_size(h: &[u8; 18]) -> Option<u64> {
+    let is_bgzf = h[..4] == [0x1f, 0x8b, 8, 4]
+        && u16::from_le_bytes([h[10], h[11]]) == 6
+        && h[12..16] == [b'B', b'C', 2, 0];
+    is_bgzf.then(|| u16::from_le_bytes([h[16], h[17]]) as u64 + 1)
+}
+
+fn sniff_gzip<R: Read + Seek>(r: &mut R, info: &mut ContainerInfo) -> io::Result<()> {
+    let size = info.compressed_size;
+    let mut h = [0u8; 18];
+    if read_at(r, 0, &mut h)? == h.len() && bgzf_block_size(&h).is_some() {
+        // walk all blocks, summing up their ISIZE
+        let (mut offset, mut blocks, mut total) = (0u64, 0u64, 0u64);
+        while offset < size {
+            let bsize = match read_at(r, offset, &mut h)? {
+                18 => bgzf_block_size(&h),
+                _ => None,
+            };
+            let Some(bsize) = bsize.filter(|&b| b >= 26 && offset + b <= size) else {
+                // not BGZF after all, keep what is known
+                info.bgzf = false;
+                return Ok(());
+            };
+            let mut isize = [0u8; 4];
+            read_exact_at(r, offset + bsize - 4, &mut isize)?;
+            total += u32::from_le_bytes(isize) as u64;
+            blocks += 1;
+            offset += bsize;
+        }
+        info.bgzf = true;
+        info.members = Some(blocks);
+        info.uncompressed_size = Some(total);
+        info.exact_size = true;
+    } else if size >= 18 {
+        let mut isize = [0u8; 4];
+        read_exact_at(r, size - 4, &mut isize)?;
+        info.uncompressed_size = Some(u32::from_le_bytes(isize) as u64);
+    }
+    Ok(())
+}
+
+// walks a frame at the offset, returning its size and content size if known
+type FrameWalker<R> = fn(&mut R, u64) -> io::Result<Option<(u64, Option<u64>)>>;
+
+fn sniff_frames<R: Read + Seek>(
+    r: &mut R,
+    info: &mut ContainerInfo,
+    walk: FrameWalker<R>,
+) -> io::Result<()> {
+    let (mut offset, mut frames, mut total) = (0u64, 0u64, Some(0u64));
+    while offset < info.compressed_size {
+        let Some((len, content)) = walk(r, offset)? else {
+            return Ok(());
+        };
+        // skippable frames have no content
+        if content != Some(u64::MAX) {
+            frames += 1;
+            total = total.zip(content).map(|(a, b)| a + b);
+        }
+        offset += len;
+    }
+    info.members = Some(frames);
+    info.exact_size = total.is_some();
+    info.uncompressed_size = total;
+    Ok(())
+}
+
+fn read_u32_le<R: Read + Seek>(r: &mut R, offset: u64) -> io::Result<u32> {
+    let mut b = [0u8; 4];
+    read_exact_at(r, offset, &mut b)?;
+    Ok(u32::from_le_bytes(b))
+}
+
+// content size u64::MAX marks skippable frames of zstd and lz4
+fn skippable_frame<R: Read + Seek>(
+    r: &mut R,
+    offset: u64,
+) -> io::Result<Option<(u64, Option<u64>)>> {
+    let len = read_u32_le(r, offset + 4)? as u64;
+    Ok(Some((8 + len, Some(u64::MAX))))
+}
+
+fn zstd_frame<R: Read + Seek>(r: &mut R, offset: u64) -> io::Result<Option<(u64, Option<u64>)>> {
+    let magic = read_u32_le(r, offset)?;
+    if magic & 0xFFFF_FFF0 == 0x184D_2A50 {
+        return skippable_frame(r, offset);
+    }
+    if magic != 0xFD2F_B528 {
+        return Ok(None);
+    }
+    let mut fhd = [0u8; 1];
+    read_exact_at(r, offset + 4, &mut fhd)?;
+    let fhd = fhd[0];
+    let single_segment = fhd & 0x20 != 0;
+    let fcs_len = match fhd >> 6 {
+        0 => single_segment as u64,
+        1 => 2,
+        2 => 4,
+        _ => 8,
+    };
+    let dict_len = [0, 1, 2, 4][(fhd & 3) as usize];
+    let fcs_offset = offset + 5 + (!single_segment) as u64 + dict_len;
+    let content = if fcs_len == 0 {
+        None
+    } else {
+        let mut b = [0u8; 8];
+        read_exact_at(r, fcs_offset, &mut b[..fcs_len as usize])?;
+        let v = u64::from_le_bytes(b);
+        Some(if fcs_len == 2 { v + 256 } else { v })
+    };
+    let mut pos = fcs_offset + fcs_len;
+    loop {
+        let mut h = [0u8; 4];
+        read_exact_at(r, pos, &mut h[..3])?;
+        let h = u32::from_le_bytes(h);
+        let block_size = match (h >> 1) & 3 {
+            1 => 1, // RLE
+            3 => return Ok(None),
+            _ => (h >> 3) as u64,
+        };
+        pos += 3 + block_size;
+        if h & 1 == 1 {
+            break;
+        }
+    }
+    if fhd & 0x04 != 0 {
+        pos += 4; // checksum
+    }
+    Ok(Some((pos - offset, content)))
+}
+
+fn lz4_frame<R: Read + Seek>(r: &mut R, offset: u64) -> io::Result<Option<(u64, Option<u64>)>> {
+    let magic = read_u32_le(r, offset)?;
+    if magic & 0xFFFF_FFF0 == 0x184D_2A50 {
+        return skippable_frame(r, offset);
+    }
+    if magic != 0x184D_2204 {
+        return Ok(None);
+    }
+    let mut flg = [0u8; 1];
+    read_exact_at(r, offset + 4, &mut flg)?;
+    let flg = flg[0];
+    let mut pos = offset + 6; // magic, FLG and BD
+    let content = if flg & 0x08 != 0 {
+        let mut b = [0u8; 8];
+        read_exact_at(r, pos, &mut b)?;
+        pos += 8;
+        Some(u64::from_le_bytes(b))
+    } else {
+        None
+    };
+    if flg & 0x01 != 0 {
+        pos += 4; // dictionary ID
+    }
+    pos += 1; // header checksum
+    loop {
+        let v = read_u32_le(r, pos)?;
+        pos += 4;
+        if v == 0 {
+            break;
+        }
+        pos += (v & 0x7FFF_FFFF) as u64;
+        if flg & 0x10 != 0 {
+            pos += 4; // block checksum
+        }
+    }
+    if flg & 0x04 != 0 {
+        pos += 4; // content checksum
+    }
+    Ok(Some((pos - offset, content)))
+}
+
+// reads a variable-length integer of xz at the position of the buffer
+fn xz_varint(buf: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut v = 0u64;
+    for i in 0..9 {
+        let b = *buf.get(*pos)?;
+        *pos += 1;
+        v |= ((b & 0x7F) as u64) << (7 * i);
+        if b & 0x80 == 0 {
+            return Some(v);
+        }
+    }
+    None
+}
+
+// walks xz streams backwards from the end via their footers and indexes
+fn sniff_xz<R: Read + Seek>(r: &mut R, info: &mut ContainerInfo) -> io::Result<()> {
+    let (mut end, mut streams, mut total) = (info.compressed_size, 0u64, 0u64);
+    while end > 0 {
+        // stream padding
+        let mut word = [0u8; 4];
+        read_exact_at(r, end - 4, &mut word)?;
+        if word == [0; 4] {
+            end -= 4;
+            continue;
+        }
+        let mut footer = [0u8; 12];
+        if end < 24 || read_at(r, end - 12, &mut footer)? != 12 || &footer[10..] != b"YZ" {
+            return Ok(());
+        }
+        let index_size = (u32::from_le_bytes(footer[4..8].try_into().unwrap()) as u64 + 1) * 4;
+        let Some(index_start) = (end - 12).checked_sub(index_size) else {
+            return Ok(());
+        };
+        let mut index = vec![0u8; index_size as usize];
+        read_exact_at(r, index_start, &mut index)?;
+        let mut pos = 1;
+        let (Some(0), Some(n)) = (
+            index.first().map(|&b| b as u64),
+            xz_varint(&index, &mut pos),
+        ) else {
+            return Ok(());
+        };
+        let mut blocks = 0u64;
+        for _ in 0..n {
+            let (Some(unpadded), Some(uncompressed)) =
+                (xz_varint(&index, &mut pos), xz_varint(&index, &mut pos))
+            else {
+                return Ok(());
+            };
+            blocks += unpadded.div_ceil(4) * 4;
+            total += uncompressed;
+        }
+        let Some(start) = index_start.checked_sub(blocks + 12) else {
+            return Ok(());
+        };
+        let mut header = [0u8; 6];
+        read_exact_at(r, start, &mut header)?;
+        if header != [0xFD, b'7', b'z', b'X', b'Z', 0x00] {
+            return Ok(());
+        }
+        streams += 1;
+        end = start;
+    }
+    info.members = Some(streams);
+    info.uncompressed_size = Some(total);
+    info.exact_size = true;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_json_str() {
+        let mut out = Vec::new();
+        write_json_str(&mut out, b"a\"b\\c\td\x01").unwrap();
+        assert_eq!(out, br#""a\"b\\c\td\u0001""#);
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(b"*.fq.gz", b"a.fq.gz"));
+        assert!(glob_match(b"*.fq.gz", b".fq.gz"));
+        assert!(!glob_match(b"*.fq.gz", b"a.fq.gz.tmp"));
+        assert!(glob_match(b"r?_*.f[aq]", b"r1_x.fq"));
+        assert!(!glob_match(b"r?_*.f[aq]", b"r1_x.fz"));
+        assert!(glob_match(b"s[0-9][!0-9]*", b"s1a"));
+        assert!(!glob_match(b"s[0-9][!0-9]*", b"s12"));
+        assert!(glob_match(b"*a*b*", b"xxaybzb"));
+        assert!(!glob_match(b"a[bc", b"ab"));
+        assert!(glob_match(b"", b""));
+        assert!(!glob_match(b"", b"a"));
+    }
+
+    #[test]
+    fn test_natural_cmp() {
+        let mut names = vec!["r10.fq", "r2.fq", "r1.fq", "a.fq", "r02.fq", "r2a.fq"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            vec!["a.fq", "r1.fq", "r02.fq", "r2.fq", "r2a.fq", "r10.fq"]
+        );
+    }
+
+    #[test]
+    fn test_sniff_plain_and_gzip() {
+        let info = sniff_reader(io::Cursor::new(b">a\nACGT\n")).unwrap();
+        assert_eq!(info.compression, CompressionFormat::None);
+        assert_eq!((info.uncompressed_size, info.exact_size), (Some(8), true));
+        assert_eq!(info.is_multi_member(), Some(false));
+
+        #[cfg(feature = "gzip")]
+        {
+            use flate2::{Compression, GzBuilder};
+            use std::io::Write;
+
+            let member = |data: &[u8], bgzf: bool| {
+                let builder = if bgzf {
+                    GzBuilder::new().extra(vec![b'B', b'C', 2, 0, 0, 0])
+                } else {
+                    GzBuilder::new()
+                };
+                let mut w = builder.write(Vec::new(), Compression::default());
+                w.write_all(data).unwrap();
+                let mut out = w.finish().unwrap();
+                if bgzf {
+                    let bsize = (out.len() - 1) as u16;
+                    out[16..18].copy_from_slice(&bsize.to_le_bytes());
+                }
+                out
+            };
+
+            let mut data = member(b">a\nACGT\n", true);
+            data.extend(member(b">b\nAC\n", true));
+            data.extend(member(b"", true)); // EOF block
+            let info = sniff_reader(io::Cursor::new(&data)).unwrap();
+            assert!(info.bgzf);
+            assert_eq!(info.members, Some(3));
+            assert_eq!((info.uncompressed_size, info.exact_size), (Some(14), true));
+
+            let data = member(b">a\nACGT\n", false);
+            let info = sniff_reader(io::Cursor::new(&data)).unwrap();
+            assert!(!info.bgzf);
+            assert_eq!(info.members, None);
+            assert_eq!((info.uncompressed_size, info.exact_size), (Some(8), false));
+        }
+    }
+
+    #[cfg(all(feature = "zstd", feature = "lz4", feature = "xz"))]
+    #[test]
+    fn test_sniff_frames() {
+        use std::io::Write;
+
+        let mut data = zstd::bulk::compress(b">a\nACGT\n", 3).unwrap();
+        data.extend(zstd::bulk::compress(&[b'A'; 1000], 3).unwrap());
+        let info = sniff_reader(io::Cursor::new(&data)).unwrap();
+        assert_eq!(info.compression, CompressionFormat::Zstd);
+        assert_eq!(info.members, Some(2));
+        assert_eq!(info.uncompressed_size, Some(1008));
+
+        // no content size in streaming frames
+        let data = zstd::encode_all(&b">a\nACGT\n"[..], 3).unwrap();
+        let info = sniff_reader(io::Cursor::new(&data)).unwrap();
+        assert_eq!((info.members, info.uncompressed_size), (Some(1), None));
+
+        let frame_info = lz4_flex::frame::FrameInfo::new().content_size(Some(8));
+        let mut w = lz4_flex::frame::FrameEncoder::with_frame_info(frame_info, Vec::new());
+        w.write_all(b">a\nACGT\n").unwrap();
+        let data = w.finish().unwrap();
+        let info = sniff_reader(io::Cursor::new(&data)).unwrap();
+        assert_eq!(info.compression, CompressionFormat::Lz4);
+        assert_eq!((info.members, info.uncompressed_size), (Some(1), Some(8)));
+
+        let xz = |d: &[u8]| {
+            let mut w = liblzma::write::XzEncoder::new(Vec::new(), 6);
+            w.write_all(d).unwrap();
+            w.finish().unwrap()
+        };
+        let mut data = xz(b">a\nACGT\n");
+        data.extend([0u8; 4]); // stream padding
+        data.extend(xz(&[b'A'; 3000]));
+        let info = sniff_reader(io::Cursor::new(&data)).unwrap();
+        assert_eq!(info.compression, CompressionFormat::Xz);
+        assert_eq!(info.members, Some(2));
+        assert_eq!(
+            (info.uncompressed_size, info.exact_size),
+            (Some(3008), true)
+        );
+    }
+}
@@ -0,0 +1,222 @@
+use crate::errors::*;
+use crate::sanitize::*;
+use crate::seq::*;
+use crate::xopen::*;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// A FASTA/Q writer that can write to any Write.
+/// Records with quality scores are written in FASTQ format, others in FASTA format.
+pub struct Writer<W: Write> {
+    writer: W,
+    line_width: usize,
+    sanitizer: Option<Sanitizer>,
+}
+
+impl Writer<Box<dyn FinishWrite>> {
+    /// Creates a new Writer to a file path.
+    /// Stdout is supported by passing "-" as the file path.
+    /// The compression format is detected from the file extension.
+    pub fn new<P: AsRef<Path>>(file: P) -> Result<Self, FastxErr> {
+        let w = xwrite(file, 65536).map_err(FastxErr::IOError)?;
+        Ok(Self::from_writer(w))
+    }
+
+    /// Creates a new Writer to a file path with options, e.g., syncing the file to disk on `finish`.
+    pub fn with_options<P: AsRef<Path>>(file: P, opts: &WriteOptions) -> Result<Self, FastxErr> {
+        Ok(Self::from_writer(xwrite_with_options(file, opts)?))
+    }
+
+    /// Creates a new Writer to a file compressed with zstd at the level using a dictionary,
+    /// e.g., trained by `zdict::train_dictionary`.
+    #[cfg(feature = "zstd")]
+    pub fn with_zstd_dictionary<P: AsRef<Path>>(
+        file: P,
+        level: i32,
+        dict: &[u8],
+    ) -> Result<Self, FastxErr> {
+        Ok(Self::from_writer(xwrite_zstd_with_dictionary(
+            file,
+            level,
+            dict,
+            &WriteOptions::default(),
+        )?))
+    }
+
+    /// Creates a new Writer to STDOUT or STDERR, without compression.
+    /// Writing to a closed pipe fails with an error for which `FastxErr::is_broken_pipe` is true.
+    pub fn to_std(stream: StdStream) -> Result<Self, FastxErr> {
+        Ok(Self::from_writer(xwrite_std(stream, 65536)?))
+    }
+}
+
+impl<W: Write> Writer<W> {
+    /// Creates a new Writer from any Write. The writer will take ownership of the provided Write.
+    pub fn from_writer(writer: W) -> Self {
+        Self {
+            writer,
+            line_width: 0,
+            sanitizer: None,
+        }
+    }
+
+    /// Sets the line width of FASTA sequences, 0 for no wrapping (default).
+    pub fn set_line_width(&mut self, line_width: usize) {
+        self.line_width = line_width
+    }
+
+    /// Sanitizes the IDs of all written records with the given Sanitizer.
+    pub fn set_id_sanitizer(&mut self, sanitizer: Sanitizer) {
+        self.sanitizer = Some(sanitizer)
+    }
+
+    /// Returns the ID Sanitizer, which holds the mapping table of altered IDs.
+    pub fn id_sanitizer(&self) -> Option<&Sanitizer> {
+        self.sanitizer.as_ref()
+    }
+
+    /// Writes a record.
+    pub fn write(&mut self, seq: &Seq) -> Result<(), FastxErr> {
+        if let Some(sanitizer) = self.sanitizer.as_mut() {
+            let id = sanitizer.sanitize(seq.id);
+            return self.write_record(&Seq { id: &id, ..*seq });
+        }
+        self.write_record(seq)
+    }
+
+    fn write_record(&mut self, seq: &Seq) -> Result<(), FastxErr> {
+        match seq.qual {
+            Some(qual) => {
+                if qual.len() != seq.seq.len() {
+                    return Err(FastxErr::UnequalSeqAndQual(seq.seq.len(), qual.len()));
+                }
+                write_fastq(&mut self.writer, seq)?
+            }
+            None => write_fasta(&mut self.writer, seq, self.line_width)?,
+        }
+        Ok(())
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&mut self) -> Result<(), FastxErr> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: FinishWrite> Writer<W> {
+    /// Flushes and finalizes the output, e.g., the end of a compressed stream.
+    /// Dropping a Writer finalizes the output too, but errors are lost.
+    pub fn finish(&mut self) -> Result<(), FastxErr> {
+        self.writer.finish()?;
+        Ok(())
+    }
+}
+
+#[inline]
+fn write_header<W: Write>(w: &mut W, prefix: u8, seq: &Seq) -> io::Result<()> {
+    w.write_all(&[prefix])?;
+    w.write_all(seq.id)?;
+    if !seq.desc.is_empty() {
+        w.write_all(b" ")?;
+        w.write_all(seq.desc)?;
+    }
+    w.write_all(b"\n")
+}
+
+/// Writes a record in FASTA format, wrapping the sequence at `line_width` (0 for no wrapping).
+pub fn write_fasta<W: Write>(w: &mut W, seq: &Seq, line_width: usize) -> io::Result<()> {
+    write_header(w, b'>', seq)?;
+    if seq.seq.is_empty() {
+        return Ok(());
+    }
+    if line_width == 0 {
+        w.write_all(seq.seq)?;
+        return w.write_all(b"\n");
+    }
+    for line in seq.seq.chunks(line_width) {
+        w.write_all(line)?;
+        w.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// Writes a record in FASTQ format. Records without quality scores are written with an empty quality line.
+pub fn write_fastq<W: Write>(w: &mut W, seq: &Seq) -> io::Result<()> {
+    write_header(w, b'@', seq)?;
+    w.write_all(seq.seq)?;
+    w.write_all(b"\n+\n")?;
+    w.write_all(seq.qual.unwrap_or_default())?;
+    w.write_all(b"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_fasta_and_fastq() {
+        let mut w = Writer::from_writer(Vec::new());
+        w.set_line_width(4);
+        w.write(&Seq {
+            id: b"seq1",
+            desc: b"desc",
+            seq: b"ACGTACGTA",
+            qual: None,
+        })
+        .unwrap();
+        w.write(&Seq {
+            id: b"seq2",
+            desc: b"",
+            seq: b"",
+            qual: None,
+        })
+        .unwrap();
+        w.write(&Seq {
+            id: b"read1",
+            desc: b"",
+            seq: b"ACGTACGTA",
+            qual: Some(b"IIIIIIIII"),
+        })
+        .unwrap();
+
+        assert_eq!(
+            w.into_inner(),
+            b">seq1 desc\nACGT\nACGT\nA\n>seq2\n@read1\nACGTACGTA\n+\nIIIIIIIII\n"
+        );
+    }
+
+    #[test]
+    fn test_write_with_id_sanitizer() {
+        let mut w = Writer::from_writer(Vec::new());
+        w.set_id_sanitizer(Sanitizer::new(SanitizeProfile::Newick));
+        w.write(&Seq {
+            id: b"sp(1)",
+            desc: b"",
+            seq: b"ACGT",
+            qual: None,
+        })
+        .unwrap();
+        assert_eq!(w.id_sanitizer().unwrap().mapping().len(), 1);
+        assert_eq!(w.into_inner(), b">sp_1_\nACGT\n");
+    }
+
+    #[test]
+    fn test_write_unequal_seq_and_qual() {
+        let mut w = Writer::from_writer(Vec::new());
+        assert!(matches!(
+            w.write(&Seq {
+                id: b"read1",
+                desc: b"",
+                seq: b"ACGT",
+                qual: Some(b"II"),
+            }),
+            Err(FastxErr::UnequalSeqAndQual(4, 2))
+        ));
+    }
+}
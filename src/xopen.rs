@@ -1,21 +1,40 @@
-use bzip2::read::{BzDecoder, BzEncoder};
+#[cfg(feature = "bzip2")]
+use bzip2::read::BzDecoder;
+#[cfg(feature = "bzip2")]
+use bzip2::write::BzEncoder;
+#[cfg(feature = "gzip")]
 use flate2::Compression;
 // Use the bufread variant of MultiGzDecoder so it can directly consume the
 // underlying BufRead's buffer instead of going through an extra internal one.
 // MultiGzDecoder (rather than GzDecoder) is required to handle concatenated
 // gzip files, which are common in bioinformatics.
+#[cfg(feature = "gzip")]
 use flate2::bufread::MultiGzDecoder;
+#[cfg(feature = "gzip")]
 use gzp::ZWriter;
+#[cfg(feature = "gzip")]
 use gzp::deflate::Gzip;
+#[cfg(feature = "gzip")]
 use gzp::par::compress::{ParCompress, ParCompressBuilder};
-use liblzma::read::{XzDecoder, XzEncoder};
+#[cfg(feature = "xz")]
+use liblzma::read::XzDecoder;
+#[cfg(feature = "xz")]
+use liblzma::write::XzEncoder;
 use std::alloc::{Layout, alloc, dealloc};
+use std::borrow::Cow;
+use std::collections::VecDeque;
 use std::fs::File;
 use std::io::IsTerminal;
 use std::io::{self, BufRead, Read, Write};
+#[cfg(feature = "gzip")]
+use std::io::{Seek, SeekFrom};
+use std::path::{Path, PathBuf};
 use std::ptr::NonNull;
 use std::slice;
+use std::sync::{Arc, RwLock};
+#[cfg(feature = "zstd")]
 use zstd::stream::read::Decoder as ZstdDecoder;
+#[cfg(feature = "zstd")]
 use zstd::stream::write::Encoder as ZstdEncoder;
 
 pub const DEFAULT_IO_BUFFER_ALIGNMENT: usize = 4096;
@@ -155,6 +174,33 @@ impl<R: Read> BufRead for AlignedBufReader<R> {
     }
 }
 
+/// A writer whose output needs finalizing, e.g., writing the footer of a compressed stream.
+///
+/// Writers returned by `xwrite` finalize their output on drop too, but errors are lost there,
+/// so call `finish` to make sure the output is complete. Nothing should be written after it.
+pub trait FinishWrite: Write {
+    /// Flushes buffered data and finalizes the output.
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+impl<W: FinishWrite + ?Sized> FinishWrite for Box<W> {
+    fn finish(&mut self) -> io::Result<()> {
+        (**self).finish()
+    }
+}
+
+impl FinishWrite for Vec<u8> {
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl FinishWrite for File {
+    fn finish(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
 /// AlignedBufWriter is a buffered writer that uses an aligned buffer for efficient I/O operations.
 struct AlignedBufWriter<W: Write> {
     inner: W, // the underlying writer that will receive the data when the buffer is flushed
@@ -199,6 +245,13 @@ impl<W: Write> AlignedBufWriter<W> {
     }
 }
 
+impl<W: FinishWrite> FinishWrite for AlignedBufWriter<W> {
+    fn finish(&mut self) -> io::Result<()> {
+        self.flush_buf()?;
+        self.inner.finish()
+    }
+}
+
 impl<W: Write> Drop for AlignedBufWriter<W> {
     fn drop(&mut self) {
         let _ = self.flush();
@@ -228,196 +281,1075 @@ impl<W: Write> Write for AlignedBufWriter<W> {
     }
 }
 
+// the file written by xwrite, which is synced to disk on finish if asked
+struct OutputFile {
+    file: File,
+    fsync: bool,
+}
+
+impl Write for OutputFile {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl FinishWrite for OutputFile {
+    fn finish(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.fsync {
+            self.file.sync_all()?;
+        }
+        Ok(())
+    }
+}
+
+// finishes the inner writer on drop if `finish` was not called, ignoring errors
+struct AutoFinish<W: FinishWrite> {
+    inner: W,
+    finished: bool,
+}
+
+impl<W: FinishWrite> AutoFinish<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            finished: false,
+        }
+    }
+}
+
+// some encoders panic when used after finishing
+impl<W: FinishWrite> Write for AutoFinish<W> {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.finished {
+            return Err(io::Error::other("writing to a finished stream"));
+        }
+        self.inner.write(buf)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.inner.flush()
+    }
+}
+
+impl<W: FinishWrite> FinishWrite for AutoFinish<W> {
+    fn finish(&mut self) -> io::Result<()> {
+        if !self.finished {
+            self.finished = true;
+            self.inner.finish()?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: FinishWrite> Drop for AutoFinish<W> {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+#[cfg(feature = "xz")]
+impl FinishWrite for XzEncoder<OutputFile> {
+    fn finish(&mut self) -> io::Result<()> {
+        self.try_finish()?;
+        self.get_mut().finish()
+    }
+}
+
+#[cfg(feature = "bzip2")]
+impl FinishWrite for BzEncoder<OutputFile> {
+    fn finish(&mut self) -> io::Result<()> {
+        self.try_finish()?;
+        self.get_mut().finish()
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl FinishWrite for ZstdEncoder<'static, OutputFile> {
+    fn finish(&mut self) -> io::Result<()> {
+        self.do_finish()?;
+        self.get_mut().finish()
+    }
+}
+
+#[cfg(feature = "lz4")]
+impl FinishWrite for lz4_flex::frame::FrameEncoder<OutputFile> {
+    fn finish(&mut self) -> io::Result<()> {
+        self.try_finish().map_err(io::Error::other)?;
+        self.get_mut().finish()
+    }
+}
+
+#[cfg(feature = "gzip")]
 /// GzpGzipWriter wraps a parallel gzip encoder from `gzp` and ensures
 /// `finish()` is called on drop. Without `finish()`, gzp would leave the
 /// gzip stream truncated (no footer, worker threads not joined).
 struct GzpGzipWriter<W: Write + Send + 'static> {
     inner: Option<ParCompress<'static, Gzip, W>>,
+    // a handle of the output file for syncing, as gzp does not give the file back
+    fsync: Option<File>,
 }
 
+#[cfg(feature = "gzip")]
+impl<W: Write + Send + 'static> FinishWrite for GzpGzipWriter<W> {
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some(mut w) = self.inner.take() {
+            w.finish().map_err(io::Error::other)?;
+            if let Some(file) = &self.fsync {
+                file.sync_all()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "gzip")]
 impl<W: Write + Send + 'static> Write for GzpGzipWriter<W> {
     #[inline]
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.inner
-            .as_mut()
-            .expect("gzp writer used after finish")
-            .write(buf)
+        match self.inner.as_mut() {
+            Some(w) => w.write(buf),
+            None => Err(io::Error::other("writing to a finished stream")),
+        }
     }
 
     #[inline]
     fn flush(&mut self) -> io::Result<()> {
-        self.inner
-            .as_mut()
-            .expect("gzp writer used after finish")
-            .flush()
+        match self.inner.as_mut() {
+            Some(w) => w.flush(),
+            None => Ok(()),
+        }
     }
 }
 
+#[cfg(feature = "gzip")]
 impl<W: Write + Send + 'static> Drop for GzpGzipWriter<W> {
     fn drop(&mut self) {
-        if let Some(mut w) = self.inner.take() {
-            let _ = w.finish();
+        let _ = self.finish();
+    }
+}
+
+// The codec of every compression format is behind a cargo feature of the same name, all enabled
+// by default. gzip (zlib-rs), bzip2 (libbz2-rs-sys) and lz4 (lz4_flex) are pure Rust, while xz and
+// zstd link C libraries, so building with `default-features = false` and
+// `features = ["gzip", "bzip2", "lz4"]` builds without C libraries.
+// Opening a file in a disabled format returns an `Unsupported` error.
+
+#[cfg(not(all(
+    feature = "gzip",
+    feature = "xz",
+    feature = "bzip2",
+    feature = "zstd",
+    feature = "lz4"
+)))]
+fn disabled(format: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("{format} support is not enabled, rebuild fastseq with the \"{format}\" feature"),
+    )
+}
+
+// opens a decompressing reader on a stream starting with the magic number of the format
+type Decoder = fn(Box<dyn BufRead>) -> io::Result<Box<dyn Read>>;
+
+#[cfg(feature = "gzip")]
+fn gzip_decoder(r: Box<dyn BufRead>) -> io::Result<Box<dyn Read>> {
+    Ok(Box::new(MultiGzDecoder::new(r)))
+}
+
+#[cfg(not(feature = "gzip"))]
+fn gzip_decoder(_r: Box<dyn BufRead>) -> io::Result<Box<dyn Read>> {
+    Err(disabled("gzip"))
+}
+
+#[cfg(feature = "xz")]
+fn xz_decoder(r: Box<dyn BufRead>) -> io::Result<Box<dyn Read>> {
+    Ok(Box::new(XzDecoder::new(r)))
+}
+
+#[cfg(not(feature = "xz"))]
+fn xz_decoder(_r: Box<dyn BufRead>) -> io::Result<Box<dyn Read>> {
+    Err(disabled("xz"))
+}
+
+#[cfg(feature = "bzip2")]
+fn bzip2_decoder(r: Box<dyn BufRead>) -> io::Result<Box<dyn Read>> {
+    Ok(Box::new(BzDecoder::new(r)))
+}
+
+#[cfg(not(feature = "bzip2"))]
+fn bzip2_decoder(_r: Box<dyn BufRead>) -> io::Result<Box<dyn Read>> {
+    Err(disabled("bzip2"))
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_decoder(r: Box<dyn BufRead>) -> io::Result<Box<dyn Read>> {
+    Ok(Box::new(ZstdDecoder::new(r)?))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd_decoder(_r: Box<dyn BufRead>) -> io::Result<Box<dyn Read>> {
+    Err(disabled("zstd"))
+}
+
+#[cfg(feature = "lz4")]
+fn lz4_decoder(r: Box<dyn BufRead>) -> io::Result<Box<dyn Read>> {
+    Ok(Box::new(lz4_flex::frame::FrameDecoder::new(r)))
+}
+
+#[cfg(not(feature = "lz4"))]
+fn lz4_decoder(_r: Box<dyn BufRead>) -> io::Result<Box<dyn Read>> {
+    Err(disabled("lz4"))
+}
+
+#[cfg(feature = "gzip")]
+fn gzip_encoder(file: OutputFile) -> io::Result<Box<dyn FinishWrite>> {
+    let fsync = if file.fsync {
+        Some(file.file.try_clone()?)
+    } else {
+        None
+    };
+    let parz = ParCompressBuilder::<Gzip>::new()
+        .compression_level(Compression::default())
+        .from_writer(file.file);
+    Ok(Box::new(GzpGzipWriter {
+        inner: Some(parz),
+        fsync,
+    }))
+}
+
+#[cfg(not(feature = "gzip"))]
+fn gzip_encoder(_file: OutputFile) -> io::Result<Box<dyn FinishWrite>> {
+    Err(disabled("gzip"))
+}
+
+#[cfg(feature = "xz")]
+fn xz_encoder(file: OutputFile) -> io::Result<Box<dyn FinishWrite>> {
+    Ok(Box::new(AutoFinish::new(XzEncoder::new(file, 6))))
+}
+
+#[cfg(not(feature = "xz"))]
+fn xz_encoder(_file: OutputFile) -> io::Result<Box<dyn FinishWrite>> {
+    Err(disabled("xz"))
+}
+
+#[cfg(feature = "bzip2")]
+fn bzip2_encoder(file: OutputFile) -> io::Result<Box<dyn FinishWrite>> {
+    Ok(Box::new(AutoFinish::new(BzEncoder::new(
+        file,
+        bzip2::Compression::default(),
+    ))))
+}
+
+#[cfg(not(feature = "bzip2"))]
+fn bzip2_encoder(_file: OutputFile) -> io::Result<Box<dyn FinishWrite>> {
+    Err(disabled("bzip2"))
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_encoder(file: OutputFile) -> io::Result<Box<dyn FinishWrite>> {
+    let encoder = ZstdEncoder::new(file, 0)?; // level 0 = default
+    Ok(Box::new(AutoFinish::new(encoder)))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd_encoder(_file: OutputFile) -> io::Result<Box<dyn FinishWrite>> {
+    Err(disabled("zstd"))
+}
+
+#[cfg(feature = "lz4")]
+fn lz4_encoder(file: OutputFile) -> io::Result<Box<dyn FinishWrite>> {
+    Ok(Box::new(AutoFinish::new(
+        lz4_flex::frame::FrameEncoder::new(file),
+    )))
+}
+
+#[cfg(not(feature = "lz4"))]
+fn lz4_encoder(_file: OutputFile) -> io::Result<Box<dyn FinishWrite>> {
+    Err(disabled("lz4"))
+}
+
+/// A compression codec plugged into xopen and xwrite with `register_codec`, e.g., lz4 variants,
+/// brotli or proprietary formats.
+///
+/// Registered codecs are checked before the built-in formats, in the order of registration:
+/// xopen decompresses streams for which `sniff` returns true, and xwrite compresses files whose
+/// names end with one of the `extensions`.
+pub trait Codec: Send + Sync {
+    /// Returns the name of the codec.
+    fn name(&self) -> &str;
+
+    /// Returns whether a stream starting with the bytes is in this format, e.g., by its magic number.
+    /// The bytes are the first buffer of the stream, which may be short for tiny inputs.
+    fn sniff(&self, buf: &[u8]) -> bool;
+
+    /// Returns the file extensions of the format in lowercase, e.g., `[".br"]`.
+    fn extensions(&self) -> &[&str];
+
+    /// Wraps a compressed stream in a decompressing reader.
+    fn decoder(&self, r: Box<dyn BufRead>) -> io::Result<Box<dyn Read>>;
+
+    /// Wraps the output file in a compressing writer, whose `finish` must write the end of the
+    /// stream and then finish the inner writer. xwrite calls `finish` on drop if the user did not.
+    fn encoder(&self, w: Box<dyn FinishWrite>) -> io::Result<Box<dyn FinishWrite>>;
+}
+
+static CODECS: RwLock<Vec<Arc<dyn Codec>>> = RwLock::new(Vec::new());
+
+/// Registers a custom codec for xopen and xwrite in the whole process. A codec of the same name
+/// is replaced.
+pub fn register_codec<C: Codec + 'static>(codec: C) {
+    let mut codecs = CODECS.write().unwrap_or_else(|e| e.into_inner());
+    codecs.retain(|c| c.name() != codec.name());
+    codecs.push(Arc::new(codec));
+}
+
+/// Removes a registered codec by name, and returns whether it was registered.
+pub fn unregister_codec(name: &str) -> bool {
+    let mut codecs = CODECS.write().unwrap_or_else(|e| e.into_inner());
+    let n = codecs.len();
+    codecs.retain(|c| c.name() != name);
+    codecs.len() < n
+}
+
+/// Returns the names of registered codecs.
+pub fn registered_codecs() -> Vec<String> {
+    let codecs = CODECS.read().unwrap_or_else(|e| e.into_inner());
+    codecs.iter().map(|c| c.name().to_string()).collect()
+}
+
+fn find_codec<F: Fn(&dyn Codec) -> bool>(f: F) -> Option<Arc<dyn Codec>> {
+    let codecs = CODECS.read().unwrap_or_else(|e| e.into_inner());
+    codecs.iter().find(|c| f(c.as_ref())).cloned()
+}
+
+/// Compression formats supported by xopen and xwrite.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionFormat {
+    None,
+    Gzip,
+    Xz,
+    Bzip2,
+    Zstd,
+    Lz4,
+}
+
+impl CompressionFormat {
+    /// Returns the name of the format.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CompressionFormat::None => "none",
+            CompressionFormat::Gzip => "gzip",
+            CompressionFormat::Xz => "xz",
+            CompressionFormat::Bzip2 => "bzip2",
+            CompressionFormat::Zstd => "zstd",
+            CompressionFormat::Lz4 => "lz4",
+        }
+    }
+}
+
+/// Detects the compression format from the magic number at the start of a stream.
+pub fn detect_compression(buf: &[u8]) -> CompressionFormat {
+    if buf.starts_with(&[0x1f, 0x8b]) {
+        CompressionFormat::Gzip
+    } else if buf.starts_with(&[0xFD, b'7', b'z', b'X', b'Z', 0x00]) {
+        CompressionFormat::Xz
+    } else if buf.starts_with(b"BZh") {
+        CompressionFormat::Bzip2
+    } else if buf.starts_with(&[0x28, 0xB5, 0x2F, 0xFD]) {
+        CompressionFormat::Zstd
+    } else if buf.starts_with(&[0x04, 0x22, 0x4D, 0x18])
+        || buf.starts_with(&[0x02, 0x21, 0x4C, 0x18])
+    {
+        CompressionFormat::Lz4
+    } else {
+        CompressionFormat::None
+    }
+}
+
+/// A source or destination of data: the standard streams, written as "-", or a file path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IoPath {
+    /// STDIN for reading, or STDOUT for writing.
+    Std,
+    /// A file path, which may be non-UTF-8.
+    File(PathBuf),
+}
+
+impl IoPath {
+    /// Creates an IoPath from a path, "-" means the standard streams.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref();
+        if path == Path::new("-") {
+            IoPath::Std
+        } else {
+            IoPath::File(path.to_path_buf())
         }
     }
+
+    /// Returns whether it is the standard streams.
+    pub fn is_std(&self) -> bool {
+        matches!(self, IoPath::Std)
+    }
+}
+
+/// Returns the path for opening a file. On Windows, long absolute paths are converted to
+/// extended-length paths (`\\?\`), which are not limited to 260 characters.
+#[cfg(windows)]
+pub(crate) fn long_path(path: &Path) -> Cow<'_, Path> {
+    use std::ffi::OsString;
+    use std::path::{Component, Prefix};
+
+    if path.as_os_str().len() < 260 {
+        return Cow::Borrowed(path);
+    }
+    let Ok(abs) = std::path::absolute(path) else {
+        return Cow::Borrowed(path);
+    };
+    let mut components = abs.components();
+    let mut out = match components.next() {
+        Some(Component::Prefix(p)) => match p.kind() {
+            Prefix::Disk(_) => OsString::from(r"\\?\"),
+            Prefix::UNC(server, share) => {
+                let mut s = OsString::from(r"\\?\UNC\");
+                s.push(server);
+                s.push(r"\");
+                s.push(share);
+                components.next(); // the root
+                for c in components {
+                    s.push(r"\");
+                    s.push(c.as_os_str());
+                }
+                return Cow::Owned(PathBuf::from(s));
+            }
+            // already verbatim or a device path
+            _ => return Cow::Owned(abs),
+        },
+        _ => return Cow::Owned(abs),
+    };
+    out.push(abs.as_os_str());
+    Cow::Owned(PathBuf::from(out))
+}
+
+/// Returns the path for opening a file, which is used as is on platforms other than Windows.
+#[cfg(not(windows))]
+#[inline]
+pub(crate) fn long_path(path: &Path) -> Cow<'_, Path> {
+    Cow::Borrowed(path)
 }
 
 /// xopen is a helper function that opens a file for reading and returns a buffered reader that automatically detects compression formats.
 /// It supports gzip, xz, bzip2, zstd, and lz4 compression formats based on the file's magic numbers.
-pub fn xopen(file: &str, buf_size: usize) -> io::Result<Box<dyn BufRead>> {
+pub fn xopen<P: AsRef<Path>>(file: P, buf_size: usize) -> io::Result<Box<dyn BufRead>> {
     xopen_with_alignment(file, buf_size, DEFAULT_IO_BUFFER_ALIGNMENT)
 }
 
 /// xopen_with_alignment is a helper function that opens a file for reading and returns a buffered reader with
 /// the specified buffer size and alignment, automatically detecting compression formats.
-pub fn xopen_with_alignment(
-    file: &str,
+pub fn xopen_with_alignment<P: AsRef<Path>>(
+    file: P,
+    buf_size: usize,
+    buf_align: usize,
+) -> io::Result<Box<dyn BufRead>> {
+    match IoPath::new(file) {
+        IoPath::Std => {
+            if io::stdin().is_terminal() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "no data detected in STDIN",
+                ));
+            }
+            // if reading from STDIN, we can directly wrap the locked STDIN in the aligned buffer reader
+            // without an additional layer of buffering
+            debug!("reading from STDIN");
+            xopen_reader_with_alignment(io::stdin().lock(), buf_size, buf_align)
+        }
+        IoPath::File(file) => {
+            debug!("opening {} for reading", file.display());
+            // if reading from a file, we can open the file and wrap it in an aligned buffer reader to ensure proper alignment for efficient I/O operations,
+            // and we can directly wrap the file in the aligned buffer reader without an additional layer of buffering since the aligned buffer reader already provides buffering functionality
+            xopen_reader_with_alignment(File::open(long_path(&file))?, buf_size, buf_align)
+        }
+    }
+}
+
+/// xopen_file is like xopen, but reads from an already-open File.
+pub fn xopen_file(file: File, buf_size: usize) -> io::Result<Box<dyn BufRead>> {
+    xopen_reader_with_alignment(file, buf_size, DEFAULT_IO_BUFFER_ALIGNMENT)
+}
+
+/// xopen_fd is like xopen, but reads from a raw file descriptor, e.g., from process substitution or a socket.
+///
+/// # Safety
+///
+/// The file descriptor must be open and owned by the caller, it is closed when the reader is dropped.
+#[cfg(unix)]
+pub unsafe fn xopen_fd(
+    fd: std::os::unix::io::RawFd,
+    buf_size: usize,
+) -> io::Result<Box<dyn BufRead>> {
+    use std::os::unix::io::FromRawFd;
+    xopen_file(unsafe { File::from_raw_fd(fd) }, buf_size)
+}
+
+/// xopen_reader is like xopen, but reads from any reader, e.g., a locked STDIN or an in-memory buffer.
+pub fn xopen_reader<R: Read + 'static>(inner: R, buf_size: usize) -> io::Result<Box<dyn BufRead>> {
+    xopen_reader_with_alignment(inner, buf_size, DEFAULT_IO_BUFFER_ALIGNMENT)
+}
+
+/// xopen_reader_with_alignment is like xopen_with_alignment, but reads from any reader.
+pub fn xopen_reader_with_alignment<R: Read + 'static>(
+    inner: R,
     buf_size: usize,
     buf_align: usize,
 ) -> io::Result<Box<dyn BufRead>> {
     let buf_size = buf_size.max(4096);
 
-    let mut r: Box<dyn BufRead> = if file == "-" {
-        if io::stdin().is_terminal() {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "no data detected in STDIN",
-            ));
-        }
-        // if reading from STDIN, we need to use an aligned buffer reader to ensure proper alignment for efficient I/O operations,
-        // and we can directly wrap the locked STDIN in the aligned buffer reader without an additional
-        Box::new(AlignedBufReader::with_capacity_and_alignment(
-            buf_size,
-            buf_align,
-            io::stdin().lock(),
-        )?)
-    } else {
-        // if reading from a file, we can open the file and wrap it in an aligned buffer reader to ensure proper alignment for efficient I/O operations,
-        // and we can directly wrap the file in the aligned buffer reader without an additional layer of buffering since the aligned buffer reader already provides buffering functionality
-        Box::new(AlignedBufReader::with_capacity_and_alignment(
-            buf_size,
-            buf_align,
-            File::open(file)?,
-        )?)
-    };
+    let mut r: Box<dyn BufRead> = Box::new(AlignedBufReader::with_capacity_and_alignment(
+        buf_size, buf_align, inner,
+    )?);
 
     // check compression formats
     let buf = r.fill_buf()?; // peek without consuming
 
-    let reader: Box<dyn BufRead> = if buf.starts_with(&[0x1f, 0x8b]) {
-        // gzip
-        Box::new(AlignedBufReader::with_capacity_and_alignment(
-            buf_size,
-            buf_align,
-            MultiGzDecoder::new(r),
-        )?)
-    } else if buf.starts_with(&[0xFD, b'7', b'z', b'X', b'Z', 0x00]) {
-        // xz
-        Box::new(AlignedBufReader::with_capacity_and_alignment(
+    if let Some(codec) = find_codec(|c| c.sniff(buf)) {
+        debug!("detected compression format: {}", codec.name());
+        return Ok(Box::new(AlignedBufReader::with_capacity_and_alignment(
             buf_size,
             buf_align,
-            XzDecoder::new(r),
-        )?)
-    } else if buf.starts_with(b"BZh") {
-        // bzip2
-        Box::new(AlignedBufReader::with_capacity_and_alignment(
-            buf_size,
-            buf_align,
-            BzDecoder::new(r),
-        )?)
-    } else if buf.starts_with(&[0x28, 0xB5, 0x2F, 0xFD]) {
-        // zstd
-        Box::new(AlignedBufReader::with_capacity_and_alignment(
-            buf_size,
-            buf_align,
-            ZstdDecoder::new(r)?,
-        )?)
-    } else if buf.starts_with(&[0x04, 0x22, 0x4D, 0x18])
-        || buf.starts_with(&[0x02, 0x21, 0x4C, 0x18])
-    {
-        // lz4
-        Box::new(AlignedBufReader::with_capacity_and_alignment(
+            codec.decoder(r)?,
+        )?));
+    }
+
+    let format = detect_compression(buf);
+    debug!("detected compression format: {}", format.as_str());
+    let decoder: Decoder = match format {
+        CompressionFormat::Gzip => gzip_decoder,
+        CompressionFormat::Xz => xz_decoder,
+        CompressionFormat::Bzip2 => bzip2_decoder,
+        CompressionFormat::Zstd => zstd_decoder,
+        CompressionFormat::Lz4 => lz4_decoder,
+        CompressionFormat::None => return Ok(r),
+    };
+
+    Ok(Box::new(AlignedBufReader::with_capacity_and_alignment(
+        buf_size,
+        buf_align,
+        decoder(r)?,
+    )?))
+}
+
+/// A reader of the data of multiple files in turn, each opened with xopen when the previous one
+/// ends, so files can be compressed differently. A newline is inserted after a file not ending
+/// with one, so records of different files are never joined.
+pub struct ChainReader {
+    files: VecDeque<PathBuf>,
+    buf_size: usize,
+    current: Option<Box<dyn BufRead>>,
+    last: u8, // the last byte of the current file returned by fill_buf
+    pending_newline: bool,
+}
+
+impl ChainReader {
+    /// Creates a reader of the files, "-" for STDIN.
+    pub fn new<P: AsRef<Path>>(files: &[P], buf_size: usize) -> Self {
+        Self {
+            files: files.iter().map(|f| f.as_ref().to_path_buf()).collect(),
             buf_size,
-            buf_align,
-            lz4_flex::frame::FrameDecoder::new(r),
-        )?)
-    } else {
-        // no compression
-        r
+            current: None,
+            last: b'\n',
+            pending_newline: false,
+        }
+    }
+}
+
+impl Read for ChainReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(out.len());
+        out[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl BufRead for ChainReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        loop {
+            if self.pending_newline {
+                return Ok(b"\n");
+            }
+            match self.current.as_mut() {
+                None => match self.files.pop_front() {
+                    None => return Ok(&[]),
+                    Some(file) => self.current = Some(xopen(file, self.buf_size)?),
+                },
+                Some(r) => match r.fill_buf()?.last() {
+                    Some(&b) => {
+                        self.last = b;
+                        break;
+                    }
+                    None => {
+                        self.current = None;
+                        self.pending_newline = self.last != b'\n';
+                        self.last = b'\n';
+                    }
+                },
+            }
+        }
+        // the data is buffered, so this does not read again
+        self.current.as_mut().unwrap().fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if self.pending_newline {
+            self.pending_newline = amt == 0;
+        } else if let Some(r) = self.current.as_mut() {
+            r.consume(amt);
+        }
+    }
+}
+
+/// A reader translating old Mac line endings, i.e., bare '\r', into '\n', for parsing files
+/// written by some legacy tools. CRLF line endings are kept. Every byte is mapped to one byte,
+/// so offsets in the output are offsets in the input.
+pub struct CrLineEndings<R: Read> {
+    inner: R,
+    buf: Vec<u8>,
+    pos: usize,
+    filled: usize,
+    pending_cr: bool, // a '\r' ending the last read, held back until the next byte is known
+}
+
+impl<R: Read> CrLineEndings<R> {
+    /// Creates a reader with a buffer of `buf_size` bytes.
+    pub fn new(inner: R, buf_size: usize) -> Self {
+        Self {
+            inner,
+            buf: vec![0; buf_size.max(2)],
+            pos: 0,
+            filled: 0,
+            pending_cr: false,
+        }
+    }
+}
+
+impl<R: Read> Read for CrLineEndings<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(out.len());
+        out[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<R: Read> BufRead for CrLineEndings<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.pos >= self.filled {
+            let start = self.pending_cr as usize;
+            self.buf[0] = b'\r';
+            let n = self.inner.read(&mut self.buf[start..])?;
+            self.pos = 0;
+            if n == 0 {
+                // a '\r' at the end of the input ends the last line
+                self.buf[0] = b'\n';
+                self.filled = start;
+                self.pending_cr = false;
+                break;
+            }
+            let end = start + n;
+            for i in 0..end - 1 {
+                if self.buf[i] == b'\r' && self.buf[i + 1] != b'\n' {
+                    self.buf[i] = b'\n';
+                }
+            }
+            self.pending_cr = self.buf[end - 1] == b'\r';
+            self.filled = end - self.pending_cr as usize;
+        }
+        Ok(&self.buf[self.pos..self.filled])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.filled);
+    }
+}
+
+/// A reader of BGZF files seeking to offsets in the decompressed data, e.g., for
+/// `Reader::seek_to`. Blocks are located on creation by walking their headers,
+/// and only the block holding the position is decompressed on seeking.
+#[cfg(feature = "gzip")]
+pub struct BgzfReader<R: Read + Seek> {
+    inner: R,
+    blocks: Vec<(u64, u64)>, // compressed and decompressed offsets of blocks, and of the end
+    next: usize,             // index of the next block to decompress
+    start: u64,              // decompressed offset of data
+    data: Vec<u8>,           // the current block
+    pos: usize,
+    compressed: Vec<u8>,
+}
+
+#[cfg(feature = "gzip")]
+impl BgzfReader<File> {
+    /// Opens a BGZF file.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::new(File::open(long_path(path.as_ref()))?)
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl<R: Read + Seek> BgzfReader<R> {
+    /// Creates a reader of BGZF data, failing with `InvalidData` if it is not BGZF.
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let size = inner.seek(SeekFrom::End(0))?;
+        let not_bgzf = || io::Error::new(io::ErrorKind::InvalidData, "not a BGZF file");
+        let mut blocks = Vec::new();
+        let (mut offset, mut total) = (0u64, 0u64);
+        let mut h = [0u8; 18];
+        while offset < size {
+            inner.seek(SeekFrom::Start(offset))?;
+            inner.read_exact(&mut h).map_err(|_| not_bgzf())?;
+            let bsize = crate::util::bgzf_block_size(&h)
+                .filter(|&b| b >= 26 && offset + b <= size)
+                .ok_or_else(not_bgzf)?;
+            let mut isize = [0u8; 4];
+            inner.seek(SeekFrom::Start(offset + bsize - 4))?;
+            inner.read_exact(&mut isize)?;
+            blocks.push((offset, total));
+            total += u32::from_le_bytes(isize) as u64;
+            offset += bsize;
+        }
+        blocks.push((size, total));
+        Ok(Self {
+            inner,
+            blocks,
+            next: 0,
+            start: 0,
+            data: Vec::new(),
+            pos: 0,
+            compressed: Vec::new(),
+        })
+    }
+
+    /// Returns the size of the decompressed data.
+    pub fn uncompressed_size(&self) -> u64 {
+        self.blocks[self.blocks.len() - 1].1
+    }
+
+    // decompresses the block i into data
+    fn load(&mut self, i: usize) -> io::Result<()> {
+        let (offset, start) = self.blocks[i];
+        self.compressed
+            .resize((self.blocks[i + 1].0 - offset) as usize, 0);
+        self.inner.seek(SeekFrom::Start(offset))?;
+        self.inner.read_exact(&mut self.compressed)?;
+        self.data.clear();
+        flate2::read::GzDecoder::new(&self.compressed[..]).read_to_end(&mut self.data)?;
+        self.start = start;
+        self.pos = 0;
+        self.next = i + 1;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl<R: Read + Seek> Read for BgzfReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(out.len());
+        out[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl<R: Read + Seek> BufRead for BgzfReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // skip empty blocks, e.g., the EOF marker
+        while self.pos >= self.data.len() && self.next + 1 < self.blocks.len() {
+            self.load(self.next)?;
+        }
+        Ok(&self.data[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.data.len());
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl<R: Read + Seek> Seek for BgzfReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let current = self.start + self.pos as u64;
+        let target = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::Current(n) => current.checked_add_signed(n),
+            SeekFrom::End(n) => self.uncompressed_size().checked_add_signed(n),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek position"))?;
+
+        // the last block starting at or before the target, past the end at EOF
+        let i = self.blocks.partition_point(|b| b.1 <= target) - 1;
+        if i + 1 >= self.blocks.len() {
+            self.data.clear();
+            (self.start, self.pos, self.next) = (self.uncompressed_size(), 0, i);
+        } else {
+            self.load(i)?;
+            self.pos = (target - self.start) as usize;
+        }
+        Ok(target)
+    }
+}
+
+/// xopen_zstd_with_dictionary is like xopen, but decompresses zstd streams with a dictionary,
+/// e.g., trained by `zdict::train_dictionary`. Streams in other formats are opened as by xopen.
+#[cfg(feature = "zstd")]
+pub fn xopen_zstd_with_dictionary<P: AsRef<Path>>(
+    file: P,
+    dict: &[u8],
+    buf_size: usize,
+) -> io::Result<Box<dyn BufRead>> {
+    let buf_size = buf_size.max(4096);
+    let inner: Box<dyn Read> = match IoPath::new(file) {
+        IoPath::Std => Box::new(io::stdin().lock()),
+        IoPath::File(file) => Box::new(File::open(long_path(&file))?),
     };
+    let mut r: Box<dyn BufRead> = Box::new(AlignedBufReader::with_capacity_and_alignment(
+        buf_size,
+        DEFAULT_IO_BUFFER_ALIGNMENT,
+        inner,
+    )?);
+    if detect_compression(r.fill_buf()?) != CompressionFormat::Zstd {
+        return xopen_reader(r, buf_size);
+    }
+    Ok(Box::new(AlignedBufReader::with_capacity_and_alignment(
+        buf_size,
+        DEFAULT_IO_BUFFER_ALIGNMENT,
+        ZstdDecoder::with_dictionary(r, dict)?,
+    )?))
+}
 
-    Ok(reader)
+/// Standard output streams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StdStream {
+    Stdout,
+    Stderr,
+}
+
+/// A writer to a pipe that stops writing once the reader end is closed.
+///
+/// The first broken-pipe error is returned as is, and all later writes and flushes
+/// fail with a broken-pipe error without touching the pipe, so buffered data is not
+/// retried on flush or drop.
+pub struct PipeWriter<W: Write> {
+    inner: W,
+    broken: bool,
+}
+
+impl<W: Write> PipeWriter<W> {
+    /// Creates a PipeWriter.
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            broken: false,
+        }
+    }
+
+    /// Returns whether the pipe is closed.
+    pub fn is_broken(&self) -> bool {
+        self.broken
+    }
+
+    fn check<T>(&mut self, res: io::Result<T>) -> io::Result<T> {
+        if let Err(e) = &res
+            && e.kind() == io::ErrorKind::BrokenPipe
+        {
+            debug!("output pipe closed");
+            self.broken = true;
+        }
+        res
+    }
+}
+
+impl<W: Write> FinishWrite for PipeWriter<W> {
+    fn finish(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+impl<W: Write> Write for PipeWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if self.broken {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        let res = self.inner.write(data);
+        self.check(res)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.broken {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        let res = self.inner.flush();
+        self.check(res)
+    }
+}
+
+/// xwrite_std returns a buffered writer to STDOUT or STDERR, without compression.
+/// Closed pipes are handled by `PipeWriter`.
+pub fn xwrite_std(stream: StdStream, buf_size: usize) -> io::Result<Box<dyn FinishWrite>> {
+    let buf_size = buf_size.max(4096);
+    Ok(match stream {
+        StdStream::Stdout => Box::new(AlignedBufWriter::with_capacity_and_alignment(
+            buf_size,
+            DEFAULT_IO_BUFFER_ALIGNMENT,
+            PipeWriter::new(io::stdout().lock()),
+        )?),
+        StdStream::Stderr => Box::new(AlignedBufWriter::with_capacity_and_alignment(
+            buf_size,
+            DEFAULT_IO_BUFFER_ALIGNMENT,
+            PipeWriter::new(io::stderr().lock()),
+        )?),
+    })
 }
 
 /// xwrite is a helper function that opens a file for writing
 /// and returns a buffered writer that automatically detects compression formats based on the file extension.
 /// It supports gzip, xz, bzip2, zstd, and lz4 compression formats based on the file extension.
-pub fn xwrite(path: &str, buf_size: usize) -> io::Result<Box<dyn Write>> {
+pub fn xwrite<P: AsRef<Path>>(path: P, buf_size: usize) -> io::Result<Box<dyn FinishWrite>> {
     xwrite_with_alignment(path, buf_size, DEFAULT_IO_BUFFER_ALIGNMENT)
 }
 
+/// Options of xwrite_with_options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteOptions {
+    /// Buffer size, at least 4096.
+    pub buf_size: usize,
+    /// Buffer alignment, a power of two.
+    pub buf_align: usize,
+    /// Whether to sync the file to disk on `finish`, ignored for STDOUT.
+    pub fsync: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self {
+            buf_size: 65536,
+            buf_align: DEFAULT_IO_BUFFER_ALIGNMENT,
+            fsync: false,
+        }
+    }
+}
+
 /// xwrite_with_alignment is a helper function that opens a file for writing and returns a buffered writer with
 /// the specified buffer size and alignment, automatically detecting compression formats based on the file extension.
-pub fn xwrite_with_alignment(
-    path: &str,
+pub fn xwrite_with_alignment<P: AsRef<Path>>(
+    path: P,
     buf_size: usize,
     buf_align: usize,
-) -> io::Result<Box<dyn Write>> {
-    let buf_size = buf_size.max(4096);
-
-    if path == "-" {
-        return Ok(Box::new(AlignedBufWriter::with_capacity_and_alignment(
+) -> io::Result<Box<dyn FinishWrite>> {
+    xwrite_with_options(
+        path,
+        &WriteOptions {
             buf_size,
             buf_align,
-            io::stdout().lock(),
-        )?));
-    }
+            fsync: false,
+        },
+    )
+}
 
-    let file = File::create(path)?;
-    let path_lc = path.to_ascii_lowercase();
+/// xwrite_with_options is like xwrite_with_alignment, and optionally syncs the file to disk on `finish`.
+pub fn xwrite_with_options<P: AsRef<Path>>(
+    path: P,
+    opts: &WriteOptions,
+) -> io::Result<Box<dyn FinishWrite>> {
+    let buf_size = opts.buf_size.max(4096);
+    let buf_align = opts.buf_align;
 
-    let writer: Box<dyn Write> = if path_lc.ends_with(".gz") {
-        let parz = ParCompressBuilder::<Gzip>::new()
-            .compression_level(Compression::default())
-            .from_writer(file);
-        Box::new(AlignedBufWriter::with_capacity_and_alignment(
-            buf_size,
-            buf_align,
-            GzpGzipWriter { inner: Some(parz) },
-        )?)
-    } else if path_lc.ends_with(".xz") {
-        Box::new(AlignedBufWriter::with_capacity_and_alignment(
-            buf_size,
-            buf_align,
-            XzEncoder::new(file, 6),
-        )?)
-    } else if path_lc.ends_with(".bz2") {
-        Box::new(AlignedBufWriter::with_capacity_and_alignment(
-            buf_size,
-            buf_align,
-            BzEncoder::new(file, bzip2::Compression::default()),
-        )?)
-    } else if path_lc.ends_with(".zst") || path_lc.ends_with(".zstd") {
-        let encoder = ZstdEncoder::new(file, 0)?; // level 0 = default
-        Box::new(AlignedBufWriter::with_capacity_and_alignment(
-            buf_size,
-            buf_align,
-            encoder.auto_finish(),
-        )?)
-    } else if path_lc.ends_with(".lz4") {
-        let encoder = lz4_flex::frame::FrameEncoder::new(file);
-        Box::new(AlignedBufWriter::with_capacity_and_alignment(
-            buf_size,
-            buf_align,
-            encoder.auto_finish(),
-        )?)
+    let path = match IoPath::new(path) {
+        IoPath::Std => {
+            return Ok(Box::new(AlignedBufWriter::with_capacity_and_alignment(
+                buf_size,
+                buf_align,
+                PipeWriter::new(io::stdout().lock()),
+            )?));
+        }
+        IoPath::File(path) => path,
+    };
+
+    debug!("opening {} for writing", path.display());
+    let file = OutputFile {
+        file: File::create(long_path(&path))?,
+        fsync: opts.fsync,
+    };
+    // compare bytes, so non-UTF-8 paths work too
+    let path_lc = path.as_os_str().as_encoded_bytes().to_ascii_lowercase();
+
+    let custom = find_codec(|c| {
+        c.extensions()
+            .iter()
+            .any(|ext| path_lc.ends_with(ext.as_bytes()))
+    });
+
+    let writer: Box<dyn FinishWrite> = if let Some(codec) = custom {
+        Box::new(AutoFinish::new(codec.encoder(Box::new(file))?))
+    } else if path_lc.ends_with(b".gz") {
+        gzip_encoder(file)?
+    } else if path_lc.ends_with(b".xz") {
+        xz_encoder(file)?
+    } else if path_lc.ends_with(b".bz2") {
+        bzip2_encoder(file)?
+    } else if path_lc.ends_with(b".zst") || path_lc.ends_with(b".zstd") {
+        zstd_encoder(file)?
+    } else if path_lc.ends_with(b".lz4") {
+        lz4_encoder(file)?
     } else {
         // no compression
-        Box::new(AlignedBufWriter::with_capacity_and_alignment(
-            buf_size, buf_align, file,
-        )?)
+        Box::new(file)
     };
 
-    Ok(writer)
+    Ok(Box::new(AlignedBufWriter::with_capacity_and_alignment(
+        buf_size, buf_align, writer,
+    )?))
+}
+
+/// xwrite_zstd_with_dictionary is like xwrite_with_options, but always compresses the file with
+/// zstd at the level (0 for the default) using a dictionary, whatever the file extension.
+/// The same dictionary is needed for reading, see `xopen_zstd_with_dictionary`.
+#[cfg(feature = "zstd")]
+pub fn xwrite_zstd_with_dictionary<P: AsRef<Path>>(
+    path: P,
+    level: i32,
+    dict: &[u8],
+    opts: &WriteOptions,
+) -> io::Result<Box<dyn FinishWrite>> {
+    let path = path.as_ref();
+    debug!(
+        "opening {} for writing with a zstd dictionary",
+        path.display()
+    );
+    let file = OutputFile {
+        file: File::create(long_path(path))?,
+        fsync: opts.fsync,
+    };
+    let encoder = ZstdEncoder::with_dictionary(file, level, dict)?;
+    Ok(Box::new(AlignedBufWriter::with_capacity_and_alignment(
+        opts.buf_size.max(4096),
+        opts.buf_align,
+        AutoFinish::new(encoder),
+    )?))
 }
 
 #[cfg(test)]
@@ -441,10 +1373,10 @@ mod xwrite_drop_tests {
     fn roundtrip(suffix: &str, data: &[u8]) {
         let path = temp_path(suffix);
         {
-            let mut writer = xwrite(path.to_str().unwrap(), 8192).unwrap();
+            let mut writer = xwrite(&path, 8192).unwrap();
             writer.write_all(data).unwrap();
         }
-        let mut reader = xopen(path.to_str().unwrap(), 8192).unwrap();
+        let mut reader = xopen(&path, 8192).unwrap();
         let mut content = Vec::new();
         reader.read_to_end(&mut content).unwrap();
         fs::remove_file(&path).unwrap();
@@ -466,6 +1398,7 @@ mod xwrite_drop_tests {
         assert_eq!(content, b">chr1\nACGT\n>chrM\nTGCA\n");
     }
 
+    #[cfg(feature = "gzip")]
     #[test]
     fn test_xwrite_flushes_on_drop_for_gzip_file() {
         let path = temp_path(".fa.gz");
@@ -486,30 +1419,275 @@ mod xwrite_drop_tests {
         roundtrip(".fa", FASTA);
     }
 
+    #[cfg(feature = "gzip")]
     #[test]
     fn test_roundtrip_gzip() {
         roundtrip(".fa.gz", FASTA);
     }
 
+    #[cfg(feature = "xz")]
     #[test]
     fn test_roundtrip_xz() {
         roundtrip(".fa.xz", FASTA);
     }
 
+    #[cfg(feature = "bzip2")]
     #[test]
     fn test_roundtrip_bzip2() {
         roundtrip(".fa.bz2", FASTA);
     }
 
+    #[cfg(feature = "zstd")]
     #[test]
     fn test_roundtrip_zstd() {
         roundtrip(".fa.zst", FASTA);
     }
 
+    #[cfg(feature = "lz4")]
     #[test]
     fn test_roundtrip_lz4() {
         roundtrip(".fa.lz4", FASTA);
     }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_xopen_file_and_reader() {
+        let path = temp_path(".fa.gz");
+        {
+            let mut writer = xwrite(path.to_str().unwrap(), 8192).unwrap();
+            writer.write_all(FASTA).unwrap();
+        }
+
+        let mut content = Vec::new();
+        xopen_file(File::open(&path).unwrap(), 8192)
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        assert_eq!(content, FASTA);
+
+        let gz = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        content.clear();
+        xopen_reader(io::Cursor::new(gz), 8192)
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        assert_eq!(content, FASTA);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_xopen_fd() {
+        use std::os::unix::io::IntoRawFd;
+        let path = temp_path(".fa");
+        fs::write(&path, FASTA).unwrap();
+        let fd = File::open(&path).unwrap().into_raw_fd();
+        let mut content = Vec::new();
+        unsafe { xopen_fd(fd, 8192) }
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(content, FASTA);
+    }
+
+    #[test]
+    fn test_xwrite_finish_and_fsync() {
+        let opts = WriteOptions {
+            fsync: true,
+            ..Default::default()
+        };
+        for (suffix, format, enabled) in [
+            (".fa", CompressionFormat::None, true),
+            (".fa.gz", CompressionFormat::Gzip, cfg!(feature = "gzip")),
+            (".fa.xz", CompressionFormat::Xz, cfg!(feature = "xz")),
+            (".fa.bz2", CompressionFormat::Bzip2, cfg!(feature = "bzip2")),
+            (".fa.zst", CompressionFormat::Zstd, cfg!(feature = "zstd")),
+            (".fa.lz4", CompressionFormat::Lz4, cfg!(feature = "lz4")),
+        ] {
+            if !enabled {
+                continue;
+            }
+            let path = temp_path(suffix);
+            let mut writer = xwrite_with_options(&path, &opts).unwrap();
+            writer.write_all(FASTA).unwrap();
+            writer.finish().unwrap();
+            // complete before the writer is dropped
+            let data = fs::read(&path).unwrap();
+            assert_eq!(detect_compression(&data), format, "{suffix}");
+            let mut content = Vec::new();
+            xopen(&path, 8192)
+                .unwrap()
+                .read_to_end(&mut content)
+                .unwrap();
+            assert_eq!(content, FASTA, "{suffix}");
+            drop(writer);
+            assert_eq!(fs::read(&path).unwrap(), data, "{suffix}");
+            fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[cfg(all(unix, feature = "gzip"))]
+    #[test]
+    fn test_non_utf8_path() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        let name = OsStr::from_bytes(b"fastseq-xopen-test-\xff.fa.GZ");
+        let path = std::env::temp_dir().join(name);
+        {
+            let mut writer = xwrite(&path, 8192).unwrap();
+            writer.write_all(FASTA).unwrap();
+        }
+        assert_eq!(fs::read(&path).unwrap()[..2], [0x1f, 0x8b]);
+        let mut content = Vec::new();
+        xopen(&path, 8192)
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(content, FASTA);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_pipe_writer_broken_pipe() {
+        use crate::errors::*;
+        use std::process::{Command, Stdio};
+        let mut child = Command::new("head")
+            .args(["-c", "1"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+        let mut w = PipeWriter::new(child.stdin.take().unwrap());
+        let chunk = [b'A'; 65536];
+        let err = loop {
+            if let Err(e) = w.write_all(&chunk) {
+                break e;
+            }
+        };
+        child.wait().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        assert!(w.is_broken());
+        assert_eq!(w.flush().unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+
+        let err = FastxErr::IOError(err);
+        assert!(err.is_broken_pipe());
+        assert!(ok_on_broken_pipe(Err(err)).is_ok());
+        assert!(ok_on_broken_pipe(Err(FastxErr::InvalidFormat)).is_err());
+    }
+
+    // a toy codec xor-ing bytes after a magic number
+    struct XorCodec;
+
+    const XOR_MAGIC: &[u8] = b"XOR\x01";
+
+    struct XorReader(Box<dyn BufRead>);
+
+    impl Read for XorReader {
+        fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+            let n = self.0.read(out)?;
+            out[..n].iter_mut().for_each(|b| *b ^= 0x5a);
+            Ok(n)
+        }
+    }
+
+    struct XorWriter(Box<dyn FinishWrite>);
+
+    impl Write for XorWriter {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            let buf: Vec<u8> = data.iter().map(|b| b ^ 0x5a).collect();
+            self.0.write_all(&buf)?;
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.0.flush()
+        }
+    }
+
+    impl FinishWrite for XorWriter {
+        fn finish(&mut self) -> io::Result<()> {
+            self.0.finish()
+        }
+    }
+
+    impl Codec for XorCodec {
+        fn name(&self) -> &str {
+            "xor"
+        }
+
+        fn sniff(&self, buf: &[u8]) -> bool {
+            buf.starts_with(XOR_MAGIC)
+        }
+
+        fn extensions(&self) -> &[&str] {
+            &[".xor"]
+        }
+
+        fn decoder(&self, mut r: Box<dyn BufRead>) -> io::Result<Box<dyn Read>> {
+            r.consume(XOR_MAGIC.len());
+            Ok(Box::new(XorReader(r)))
+        }
+
+        fn encoder(&self, mut w: Box<dyn FinishWrite>) -> io::Result<Box<dyn FinishWrite>> {
+            w.write_all(XOR_MAGIC)?;
+            Ok(Box::new(XorWriter(w)))
+        }
+    }
+
+    #[test]
+    fn test_custom_codec() {
+        register_codec(XorCodec);
+        register_codec(XorCodec);
+        assert_eq!(
+            registered_codecs().iter().filter(|c| *c == "xor").count(),
+            1
+        );
+
+        let path = temp_path(".fa.XOR");
+        {
+            let mut writer = xwrite(&path, 8192).unwrap();
+            writer.write_all(FASTA).unwrap();
+        }
+        let data = fs::read(&path).unwrap();
+        assert!(data.starts_with(XOR_MAGIC));
+        assert_eq!(data.len(), XOR_MAGIC.len() + FASTA.len());
+        roundtrip(".xor", FASTA);
+
+        let mut content = Vec::new();
+        xopen_reader(io::Cursor::new(data), 8192)
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(content, FASTA);
+
+        assert!(unregister_codec("xor"));
+        assert!(!unregister_codec("xor"));
+    }
+
+    #[test]
+    fn test_io_path() {
+        assert!(IoPath::new("-").is_std());
+        assert!(IoPath::new(Path::new("-")).is_std());
+        assert_eq!(IoPath::new("a.fq"), IoPath::File(PathBuf::from("a.fq")));
+        assert_eq!(long_path(Path::new("a.fq")), Path::new("a.fq"));
+    }
+
+    #[cfg(not(feature = "xz"))]
+    #[test]
+    fn test_disabled_format_is_unsupported() {
+        let path = temp_path(".fa.xz");
+        assert_eq!(
+            xwrite(path.to_str().unwrap(), 8192).err().unwrap().kind(),
+            io::ErrorKind::Unsupported
+        );
+        fs::write(&path, [0xFD, b'7', b'z', b'X', b'Z', 0x00]).unwrap();
+        let res = xopen(path.to_str().unwrap(), 8192);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(res.err().unwrap().kind(), io::ErrorKind::Unsupported);
+    }
 }
 
 #[cfg(test)]
@@ -566,6 +1744,72 @@ mod tests {
         }
     }
 
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_bgzf_reader_seek() {
+        let block = |data: &[u8]| {
+            let mut w = flate2::GzBuilder::new()
+                .extra(vec![b'B', b'C', 2, 0, 0, 0])
+                .write(Vec::new(), Compression::default());
+            w.write_all(data).unwrap();
+            let mut out = w.finish().unwrap();
+            let bsize = (out.len() - 1) as u16;
+            out[16..18].copy_from_slice(&bsize.to_le_bytes());
+            out
+        };
+        let mut data = block(b"0123");
+        data.extend(block(b""));
+        data.extend(block(b"4567"));
+        data.extend(block(b"89"));
+        data.extend(block(b"")); // EOF block
+
+        let mut r = BgzfReader::new(Cursor::new(data)).unwrap();
+        assert_eq!(r.uncompressed_size(), 10);
+        let mut out = String::new();
+        r.read_to_string(&mut out).unwrap();
+        assert_eq!(out, "0123456789");
+
+        assert_eq!(r.seek(SeekFrom::Start(3)).unwrap(), 3);
+        assert_eq!(r.fill_buf().unwrap(), b"3");
+        r.consume(1);
+        assert_eq!(r.fill_buf().unwrap(), b"4567");
+        assert_eq!(r.seek(SeekFrom::Current(3)).unwrap(), 7);
+        assert_eq!(r.seek(SeekFrom::End(-2)).unwrap(), 8);
+        out.clear();
+        r.read_to_string(&mut out).unwrap();
+        assert_eq!(out, "89");
+        assert_eq!(r.seek(SeekFrom::Start(20)).unwrap(), 20);
+        assert_eq!(r.fill_buf().unwrap(), b"");
+
+        // plain gzip
+        let mut w = flate2::write::GzEncoder::new(Vec::new(), Compression::default());
+        w.write_all(b"0123").unwrap();
+        let err = BgzfReader::new(Cursor::new(w.finish().unwrap()))
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_cr_line_endings() {
+        let input = b"\r>a\rAC\r\nGT\r\r>b\n\rTT\r";
+        for buf_size in [1, 2, 3, 5, 64] {
+            let mut out = Vec::new();
+            CrLineEndings::new(&input[..], buf_size)
+                .read_to_end(&mut out)
+                .unwrap();
+            assert_eq!(out, b"\n>a\nAC\r\nGT\n\n>b\n\nTT\n", "{buf_size}");
+        }
+
+        let mut out = Vec::new();
+        CrLineEndings::new(&b"\r"[..], 2)
+            .read_to_end(&mut out)
+            .unwrap();
+        assert_eq!(out, b"\n");
+        let mut r = CrLineEndings::new(&b""[..], 2);
+        assert!(r.fill_buf().unwrap().is_empty());
+    }
+
     #[test]
     fn test_aligned_buf_reader_large_read_bypasses_internal_buffer() {
         let read_sizes = Rc::new(RefCell::new(Vec::new()));
@@ -0,0 +1,153 @@
+use crate::errors::*;
+use crate::reader::*;
+use crate::writer::*;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Default maximum size of trained dictionaries, 110 KiB as the zstd CLI.
+pub const DEFAULT_DICT_SIZE: usize = 112_640;
+
+/// Extension appended to output file names for their dictionary files.
+pub const DICT_EXTENSION: &str = ".dict";
+
+/// Trains a zstd dictionary of at most `max_size` bytes on the first `max_records` records of
+/// the source, each record being a sample in the format it is written in.
+///
+/// Dictionaries capture what records share, e.g., the instrument and run prefixes of read names
+/// and quality patterns, which gives much better ratios on collections of small FASTQ files.
+/// Training fails if there are too few records, zstd needs about 100 times the dictionary size.
+pub fn train_dictionary<S: RecordSource>(
+    source: &mut S,
+    max_records: usize,
+    max_size: usize,
+) -> Result<Vec<u8>, FastxErr> {
+    let mut samples: Vec<Vec<u8>> = Vec::new();
+    while samples.len() < max_records {
+        let Some(res) = source.next_record() else {
+            break;
+        };
+        let seq = res?;
+        let mut sample = Vec::with_capacity(seq.id.len() + seq.desc.len() + 2 * seq.seq.len() + 8);
+        match seq.qual {
+            Some(_) => write_fastq(&mut sample, &seq)?,
+            None => write_fasta(&mut sample, &seq, 0)?,
+        }
+        samples.push(sample);
+    }
+    debug!("training a zstd dictionary on {} records", samples.len());
+    Ok(zstd::dict::from_samples(&samples, max_size)?)
+}
+
+/// Returns the path of the dictionary file of an output file: `<path>.dict`.
+pub fn dictionary_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let mut s = OsString::from(path.as_ref().as_os_str());
+    s.push(DICT_EXTENSION);
+    PathBuf::from(s)
+}
+
+/// Saves a dictionary alongside an output file, and returns the path of the dictionary file.
+pub fn save_dictionary<P: AsRef<Path>>(path: P, dict: &[u8]) -> Result<PathBuf, FastxErr> {
+    let dict_path = dictionary_path(path);
+    fs::write(&dict_path, dict)?;
+    Ok(dict_path)
+}
+
+/// Loads the dictionary saved alongside a file by `save_dictionary`.
+pub fn load_dictionary<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, FastxErr> {
+    Ok(fs::read(dictionary_path(path))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::seq::*;
+
+    // short reads sharing a name prefix, with pseudo-random bases and qualities
+    fn reads(n: usize, seed: u64) -> Vec<u8> {
+        let mut x = seed;
+        let mut next = move || {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x
+        };
+        let mut w = Writer::from_writer(Vec::new());
+        for i in 0..n {
+            let seq: Vec<u8> = (0..100).map(|_| b"ACGT"[(next() % 4) as usize]).collect();
+            let qual: Vec<u8> = (0..100).map(|_| b"FF:F,"[(next() % 5) as usize]).collect();
+            let id = format!("A00123:45:HXXXXDSXY:1:1101:{}:{}", 1000 + i, next() % 30000);
+            w.write(&Seq {
+                id: id.as_bytes(),
+                desc: b"1:N:0:ACGTACGT+TTGCAAGC",
+                seq: &seq,
+                qual: Some(&qual),
+            })
+            .unwrap();
+        }
+        w.into_inner()
+    }
+
+    fn temp_path(suffix: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("fastseq-zdict-{}{suffix}", std::process::id()))
+    }
+
+    #[test]
+    fn test_zstd_dictionary() {
+        let training = reads(2000, 7);
+        let dict = train_dictionary(&mut Reader::from_bytes(&training), 2000, 8192).unwrap();
+        assert!(!dict.is_empty() && dict.len() <= 8192);
+
+        let data = reads(20, 11);
+        let write = |path: &Path, dict: Option<&[u8]>| {
+            let mut w = match dict {
+                Some(dict) => Writer::with_zstd_dictionary(path, 0, dict).unwrap(),
+                None => Writer::new(path).unwrap(),
+            };
+            let mut reader = Reader::from_bytes(&data);
+            while let Some(res) = reader.next() {
+                w.write(&res.unwrap()).unwrap();
+            }
+            w.finish().unwrap();
+            fs::metadata(path).unwrap().len()
+        };
+
+        let plain = temp_path(".fq.zst");
+        let with_dict = temp_path(".dict.fq.zst");
+        let size_plain = write(&plain, None);
+        let size_dict = write(&with_dict, Some(&dict));
+        assert!(size_dict < size_plain, "{size_dict} >= {size_plain}");
+
+        let dict_path = save_dictionary(&with_dict, &dict).unwrap();
+        assert_eq!(dict_path, dictionary_path(&with_dict));
+        let loaded = load_dictionary(&with_dict).unwrap();
+
+        let mut reader = Reader::with_zstd_dictionary(&with_dict, &loaded).unwrap();
+        let mut expected = Reader::from_bytes(&data);
+        let mut n = 0;
+        while let Some(res) = reader.next() {
+            assert_eq!(
+                res.unwrap().to_owned(),
+                expected.next().unwrap().unwrap().to_owned()
+            );
+            n += 1;
+        }
+        assert_eq!(n, 20);
+
+        // files in other formats are read as usual, but the dictionary is required
+        assert!(
+            Reader::with_zstd_dictionary(&plain, &loaded)
+                .unwrap()
+                .next()
+                .unwrap()
+                .is_ok()
+        );
+        let mut reader = Reader::new(&with_dict).unwrap();
+        assert!(reader.next().unwrap().is_err());
+
+        for path in [plain, with_dict, dict_path] {
+            fs::remove_file(path).unwrap();
+        }
+        assert!(load_dictionary(temp_path(".none")).is_err());
+    }
+}